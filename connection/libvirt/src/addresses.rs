@@ -0,0 +1,243 @@
+/* Copyright 2024 Marco Köpcke
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ *
+ * SPDX-License-Identifier: GPL-3.0-or-later
+ */
+//! Addresses of the network interfaces of domains.
+//!
+//! libvirt knows addresses from the DHCP leases of its own networks, from the ARP table of the
+//! host and from the QEMU guest agent running inside the domain.
+
+use std::borrow::Cow;
+use std::net::IpAddr;
+
+use adw::prelude::*;
+use anyhow::anyhow;
+use futures::future::LocalBoxFuture;
+use gettextrs::gettext;
+use gtk::gdk;
+
+use libfieldmonitor::adapter::types::Adapter;
+use libfieldmonitor::adapter::vte_pty::{VtePtyAdapter, SSH_ADAPTER_TAG, SSH_DRIVER_BIN};
+use libfieldmonitor::connection::{ConnectionError, ConnectionResult, ServerAction};
+use libfieldmonitor::gtk::{show_toast, FieldMonitorActionParametersDialog};
+use libfieldmonitor::i18n::gettext_f;
+use log::debug;
+use virt::domain::Domain;
+use virt::sys::{
+    VIR_DOMAIN_INTERFACE_ADDRESSES_SRC_AGENT, VIR_DOMAIN_INTERFACE_ADDRESSES_SRC_ARP,
+    VIR_DOMAIN_INTERFACE_ADDRESSES_SRC_LEASE,
+};
+use which::which_global;
+
+use crate::agent::GuestAgent;
+use crate::connection::{run_in_thread, LibvirtServer, VirtArc};
+
+/// Loads the addresses of a running domain from all sources. Blocks.
+///
+/// The guest agent is only asked if it is connected, libvirt would otherwise wait for it to
+/// respond.
+pub(crate) fn guest_addresses(name: &str, domain: &Domain, ask_agent: bool) -> Vec<IpAddr> {
+    let mut sources = vec![
+        VIR_DOMAIN_INTERFACE_ADDRESSES_SRC_LEASE,
+        VIR_DOMAIN_INTERFACE_ADDRESSES_SRC_ARP,
+    ];
+    if ask_agent {
+        // The agent also knows addresses of networks not managed by libvirt.
+        sources.insert(0, VIR_DOMAIN_INTERFACE_ADDRESSES_SRC_AGENT);
+    }
+
+    let mut addresses = Vec::new();
+    for source in sources {
+        match domain.interface_addresses(source, 0) {
+            Ok(interfaces) => addresses.extend(
+                interfaces
+                    .into_iter()
+                    .flat_map(|interface| interface.addrs)
+                    .map(|address| address.addr),
+            ),
+            // Most likely the domain is not connected to a network this source knows.
+            Err(err) => debug!("failed to load addresses of {name} from source {source}: {err}"),
+        }
+    }
+    reachable_addresses(addresses.iter().map(String::as_str))
+}
+
+/// The distinct addresses that can be reached from outside of the guest, ie. without loopback
+/// and link-local addresses. IPv4 addresses first.
+pub(crate) fn reachable_addresses<'a>(addresses: impl IntoIterator<Item = &'a str>) -> Vec<IpAddr> {
+    let mut reachable: Vec<IpAddr> = Vec::new();
+    for address in addresses
+        .into_iter()
+        .filter_map(|address| address.parse::<IpAddr>().ok())
+    {
+        let is_reachable = !address.is_loopback()
+            && !address.is_unspecified()
+            && match address {
+                IpAddr::V4(address) => !address.is_link_local(),
+                IpAddr::V6(address) => address.segments()[0] & 0xffc0 != 0xfe80,
+            };
+        if is_reachable && !reachable.contains(&address) {
+            reachable.push(address);
+        }
+    }
+    reachable.sort_by_key(IpAddr::is_ipv6);
+    reachable
+}
+
+/// Looks up the addresses of a domain when acting on them. Servers of listings do not know the
+/// addresses the guest agent reports, so it is asked then if needed.
+#[derive(Clone)]
+struct AddressLookup {
+    name: String,
+    domain: VirtArc<Domain>,
+    known: Vec<IpAddr>,
+    ask_agent: bool,
+}
+
+impl AddressLookup {
+    async fn addresses(self) -> Vec<IpAddr> {
+        let Self {
+            name,
+            domain,
+            known,
+            ask_agent,
+        } = self;
+        if !ask_agent {
+            return known;
+        }
+        run_in_thread(move || guest_addresses(&name, &domain, true))
+            .await
+            .unwrap_or(known)
+    }
+}
+
+impl LibvirtServer {
+    /// The address of the domain to show and connect to, if known.
+    pub(crate) fn primary_address(&self) -> Option<IpAddr> {
+        self.addresses.first().copied()
+    }
+
+    /// Whether the guest agent may know addresses that were not loaded yet.
+    pub(crate) fn may_ask_agent(&self) -> bool {
+        !self.agent_asked && self.guest_agent == Some(GuestAgent::Connected)
+    }
+
+    fn address_lookup(&self) -> AddressLookup {
+        AddressLookup {
+            name: self.name.clone(),
+            domain: self.domain.clone(),
+            known: self.addresses.clone(),
+            ask_agent: self.may_ask_agent(),
+        }
+    }
+
+    pub(crate) fn address_actions(&self) -> Vec<(Cow<'static, str>, Cow<'static, str>)> {
+        if self.primary_address().is_some() || self.may_ask_agent() {
+            vec![("copyip".into(), gettext("Copy IP Address").into())]
+        } else {
+            vec![]
+        }
+    }
+
+    pub(crate) fn act_copy_ip<'a>(&self) -> ServerAction<'a> {
+        let name = self.name.clone();
+        let lookup = self.address_lookup();
+        ServerAction::new(
+            Box::new(()),
+            Box::new(move |_params, window, toov| {
+                let name = name.clone();
+                let lookup = lookup.clone();
+                Box::pin(async move {
+                    let addresses: Vec<String> = lookup
+                        .addresses()
+                        .await
+                        .iter()
+                        .map(ToString::to_string)
+                        .collect();
+                    let address = match addresses.as_slice() {
+                        [] => {
+                            show_toast(
+                                toov.as_ref(),
+                                &gettext("The address of the domain is not known."),
+                            );
+                            return false;
+                        }
+                        [address] => address.clone(),
+                        _ => {
+                            let dialog = FieldMonitorActionParametersDialog::new(
+                                &gettext_f("Copy IP Address of {domain}", &[("domain", &name)]),
+                                None,
+                                &gettext("Copy"),
+                            );
+                            let choices: Vec<&str> = addresses.iter().map(String::as_str).collect();
+                            let address_row =
+                                dialog.add_combo_row(&gettext("Address"), &choices, 0);
+                            if !dialog.run(window.as_ref()).await {
+                                return false;
+                            }
+                            match addresses.get(address_row.selected() as usize) {
+                                Some(address) => address.clone(),
+                                None => return false,
+                            }
+                        }
+                    };
+
+                    let Some(display) = gdk::Display::default() else {
+                        return false;
+                    };
+                    display.clipboard().set_text(&address);
+                    show_toast(
+                        toov.as_ref(),
+                        &gettext_f(
+                            "Copied {address} to the clipboard.",
+                            &[("address", &address)],
+                        ),
+                    );
+                    false
+                })
+            }),
+        )
+    }
+
+    /// Adapter opening an SSH session to the primary address of the domain.
+    pub(crate) fn create_ssh_adapter(&self) -> LocalBoxFuture<ConnectionResult<Box<dyn Adapter>>> {
+        let lookup = self.address_lookup();
+        let connection_name = self.connection_name.clone();
+        let name = self.name.clone();
+        Box::pin(async move {
+            let address = lookup.addresses().await.first().copied().ok_or_else(|| {
+                ConnectionError::General(
+                    Some(gettext("The address of the domain is not known.")),
+                    anyhow!("no interface address"),
+                )
+            })?;
+            let driver = which_global(SSH_DRIVER_BIN).map_err(|err| {
+                ConnectionError::General(
+                    Some(gettext("Field Monitor is not correctly installed.")),
+                    anyhow!(err),
+                )
+            })?;
+            let adapter: Box<dyn Adapter> = Box::new(VtePtyAdapter::new(
+                connection_name,
+                name,
+                SSH_ADAPTER_TAG.to_string(),
+                driver,
+                vec![address.to_string(), String::new(), String::new()],
+            ));
+            Ok(adapter)
+        })
+    }
+}
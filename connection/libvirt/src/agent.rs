@@ -0,0 +1,488 @@
+/* Copyright 2024 Marco Köpcke
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ *
+ * SPDX-License-Identifier: GPL-3.0-or-later
+ */
+//! Actions run inside of domains by the QEMU guest agent.
+//!
+//! The commands are sent to the agent as-is with `virDomainQemuAgentCommand`. libvirt marks
+//! domains it was used on as tainted, this has no effect besides a note in the domain log.
+
+use std::borrow::Cow;
+use std::time::{Duration, Instant};
+
+use adw::prelude::*;
+use anyhow::anyhow;
+use async_std::task::sleep;
+use base64::prelude::*;
+use gettextrs::gettext;
+use libfieldmonitor::connection::ServerAction;
+use libfieldmonitor::gtk::{show_toast, FieldMonitorActionParametersDialog};
+use libfieldmonitor::i18n::gettext_f;
+use log::warn;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use virt::domain::Domain;
+
+use crate::connection::{run_in_thread, LibvirtServer, VirtArc};
+
+/// Seconds to wait for the agent to respond. It may not be running in the guest after all.
+const AGENT_TIMEOUT: i32 = 5;
+/// How long the output of a command run with `guest-exec` is waited for.
+const EXEC_TIMEOUT: Duration = Duration::from_secs(60);
+const EXEC_POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// State of the QEMU guest agent of a domain.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum GuestAgent {
+    /// The agent in the guest has its channel open.
+    Connected,
+    /// The domain has a channel for the agent, but the agent is not running.
+    Disconnected,
+}
+
+/// Builds a guest agent command.
+pub(crate) fn agent_request(execute: &str, arguments: Option<Value>) -> String {
+    let mut request = json!({ "execute": execute });
+    if let Some(arguments) = arguments {
+        request["arguments"] = arguments;
+    }
+    request.to_string()
+}
+
+/// The return value of a guest agent response.
+pub(crate) fn agent_response(response: &str) -> anyhow::Result<Value> {
+    let mut response: Value = serde_json::from_str(response)?;
+    if let Some(error) = response.get("error") {
+        let desc = error.get("desc").and_then(Value::as_str);
+        return Err(anyhow!("{}", desc.unwrap_or("unknown guest agent error")));
+    }
+    Ok(response
+        .get_mut("return")
+        .map(Value::take)
+        .unwrap_or_default())
+}
+
+/// Runs a guest agent command in a thread.
+async fn agent_command(
+    domain: &VirtArc<Domain>,
+    execute: &'static str,
+    arguments: Option<Value>,
+) -> anyhow::Result<Value> {
+    let domain = domain.clone();
+    let request = agent_request(execute, arguments);
+    let response = run_in_thread(move || domain.qemu_agent_command(&request, AGENT_TIMEOUT, 0))
+        .await
+        .map_err(|err| anyhow!("{err}"))??;
+    agent_response(&response)
+}
+
+/// Rows of the information returned by `guest-get-osinfo`, as title and value.
+pub(crate) fn os_info_rows(info: &Value) -> Vec<(String, String)> {
+    let field = |key: &str| info.get(key).and_then(Value::as_str).map(str::to_string);
+    [
+        (
+            gettext("Operating System"),
+            field("pretty-name").or_else(|| field("name")),
+        ),
+        (gettext("Version"), field("version")),
+        (gettext("Kernel"), field("kernel-release")),
+        (gettext("Kernel Version"), field("kernel-version")),
+        (gettext("Architecture"), field("machine")),
+    ]
+    .into_iter()
+    .filter_map(|(title, value)| Some((title, value.filter(|v| !v.is_empty())?)))
+    .collect()
+}
+
+#[derive(Debug, Deserialize)]
+struct GuestExecStatus {
+    exited: bool,
+    #[serde(default)]
+    exitcode: Option<i64>,
+    #[serde(rename = "out-data", default)]
+    out_data: Option<String>,
+    #[serde(rename = "err-data", default)]
+    err_data: Option<String>,
+    #[serde(rename = "out-truncated", default)]
+    out_truncated: bool,
+    #[serde(rename = "err-truncated", default)]
+    err_truncated: bool,
+}
+
+/// Output of a command that was run with `guest-exec`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct ExecOutput {
+    /// `None` if the command was killed by a signal.
+    pub(crate) exit_code: Option<i64>,
+    pub(crate) stdout: String,
+    pub(crate) stderr: String,
+    /// The guest agent only keeps the first few megabytes of output.
+    pub(crate) truncated: bool,
+}
+
+/// The output of the command from a `guest-exec-status` response, `None` if it is still
+/// running.
+pub(crate) fn exec_output(status: Value) -> anyhow::Result<Option<ExecOutput>> {
+    let status: GuestExecStatus = serde_json::from_value(status)?;
+    if !status.exited {
+        return Ok(None);
+    }
+    let decode = |data: Option<String>| -> anyhow::Result<String> {
+        let bytes = BASE64_STANDARD.decode(data.unwrap_or_default())?;
+        Ok(String::from_utf8_lossy(&bytes).into_owned())
+    };
+    Ok(Some(ExecOutput {
+        exit_code: status.exitcode,
+        stdout: decode(status.out_data)?,
+        stderr: decode(status.err_data)?,
+        truncated: status.out_truncated || status.err_truncated,
+    }))
+}
+
+/// Runs a command in the shell of the guest and waits for it to exit.
+async fn guest_exec(domain: &VirtArc<Domain>, command: &str) -> anyhow::Result<ExecOutput> {
+    let started = agent_command(
+        domain,
+        "guest-exec",
+        Some(json!({
+            "path": "/bin/sh",
+            "arg": ["-c", command],
+            "capture-output": true,
+        })),
+    )
+    .await?;
+    let pid = started
+        .get("pid")
+        .and_then(Value::as_i64)
+        .ok_or_else(|| anyhow!("guest agent did not return a pid"))?;
+
+    let start = Instant::now();
+    loop {
+        let status =
+            agent_command(domain, "guest-exec-status", Some(json!({ "pid": pid }))).await?;
+        if let Some(output) = exec_output(status)? {
+            return Ok(output);
+        }
+        if start.elapsed() > EXEC_TIMEOUT {
+            return Err(anyhow!("command did not exit in time"));
+        }
+        sleep(EXEC_POLL_INTERVAL).await;
+    }
+}
+
+/// Runs a guest agent command and shows the outcome as a toast. Returns the return value on
+/// success.
+async fn agent_action(
+    domain: &VirtArc<Domain>,
+    execute: &'static str,
+    arguments: Option<Value>,
+    success_msg: impl FnOnce(&Value) -> String,
+    err_msg: impl FnOnce(&anyhow::Error) -> String,
+    toov: Option<&adw::ToastOverlay>,
+) -> Option<Value> {
+    match agent_command(domain, execute, arguments).await {
+        Ok(value) => {
+            show_toast(toov, &success_msg(&value));
+            Some(value)
+        }
+        Err(err) => {
+            warn!("guest agent command {execute} failed: {err:?}");
+            show_toast(toov, &err_msg(&err));
+            None
+        }
+    }
+}
+
+impl LibvirtServer {
+    pub(crate) fn agent_actions(&self) -> Vec<(Cow<'static, str>, Cow<'static, str>)> {
+        match self.guest_agent {
+            Some(GuestAgent::Connected) => vec![
+                ("agentping".into(), gettext("Ping Guest Agent").into()),
+                (
+                    "agentosinfo".into(),
+                    gettext("Show Operating System").into(),
+                ),
+                ("agentfsfreeze".into(), gettext("Freeze Filesystems").into()),
+                ("agentfsthaw".into(), gettext("Thaw Filesystems").into()),
+                (
+                    "agentsetuserpassword".into(),
+                    gettext("Set User Password…").into(),
+                ),
+                ("agentexec".into(), gettext("Run Command…").into()),
+            ],
+            // The agent may have been started since the domain was loaded.
+            Some(GuestAgent::Disconnected) => {
+                vec![("agentping".into(), gettext("Ping Guest Agent").into())]
+            }
+            None => vec![],
+        }
+    }
+
+    pub(crate) fn act_agent_ping<'a>(&self) -> ServerAction<'a> {
+        ServerAction::new(
+            Box::new(self.domain.clone()),
+            Box::new(|params, _window, toov| {
+                Box::pin(async move {
+                    let domain = params.downcast::<VirtArc<Domain>>().unwrap();
+                    agent_action(
+                        &domain,
+                        "guest-ping",
+                        None,
+                        |_| gettext("The guest agent is responding."),
+                        |err| {
+                            gettext_f(
+                                "The guest agent is not responding: {err}",
+                                &[("err", &err.to_string())],
+                            )
+                        },
+                        toov.as_ref(),
+                    )
+                    .await
+                    // Reload, the agent may have been started since the domain was loaded.
+                    .is_some()
+                })
+            }),
+        )
+    }
+
+    pub(crate) fn act_agent_os_info<'a>(&self) -> ServerAction<'a> {
+        let name = self.name.clone();
+        ServerAction::new(
+            Box::new(self.domain.clone()),
+            Box::new(move |params, window, toov| {
+                let name = name.clone();
+                Box::pin(async move {
+                    let domain = params.downcast::<VirtArc<Domain>>().unwrap();
+                    let info = match agent_command(&domain, "guest-get-osinfo", None).await {
+                        Ok(info) => info,
+                        Err(err) => {
+                            warn!("failed to load os info: {err:?}");
+                            show_toast(
+                                toov.as_ref(),
+                                &gettext_f(
+                                    "Failed to load the operating system: {err}",
+                                    &[("err", &err.to_string())],
+                                ),
+                            );
+                            return false;
+                        }
+                    };
+                    let rows = os_info_rows(&info);
+
+                    let dialog = FieldMonitorActionParametersDialog::new_informational(
+                        &gettext_f("Operating System of {vm}", &[("vm", &name)]),
+                        rows.is_empty()
+                            .then(|| gettext("The guest agent did not report any details."))
+                            .as_deref(),
+                    );
+                    for (title, value) in &rows {
+                        dialog.add_info_row(title, value);
+                    }
+                    dialog.run(window.as_ref()).await;
+                    false
+                })
+            }),
+        )
+    }
+
+    pub(crate) fn act_agent_fsfreeze<'a>(&self) -> ServerAction<'a> {
+        ServerAction::new(
+            Box::new(self.domain.clone()),
+            Box::new(|params, _window, toov| {
+                Box::pin(async move {
+                    let domain = params.downcast::<VirtArc<Domain>>().unwrap();
+                    agent_action(
+                        &domain,
+                        "guest-fsfreeze-freeze",
+                        None,
+                        |_| gettext("The filesystems of the domain are now frozen."),
+                        |err| {
+                            gettext_f(
+                                "Failed to freeze the filesystems: {err}",
+                                &[("err", &err.to_string())],
+                            )
+                        },
+                        toov.as_ref(),
+                    )
+                    .await;
+                    false
+                })
+            }),
+        )
+    }
+
+    pub(crate) fn act_agent_fsthaw<'a>(&self) -> ServerAction<'a> {
+        ServerAction::new(
+            Box::new(self.domain.clone()),
+            Box::new(|params, _window, toov| {
+                Box::pin(async move {
+                    let domain = params.downcast::<VirtArc<Domain>>().unwrap();
+                    agent_action(
+                        &domain,
+                        "guest-fsfreeze-thaw",
+                        None,
+                        |_| gettext("The filesystems of the domain were thawed."),
+                        |err| {
+                            gettext_f(
+                                "Failed to thaw the filesystems: {err}",
+                                &[("err", &err.to_string())],
+                            )
+                        },
+                        toov.as_ref(),
+                    )
+                    .await;
+                    false
+                })
+            }),
+        )
+    }
+
+    pub(crate) fn act_agent_set_user_password<'a>(&self) -> ServerAction<'a> {
+        let name = self.name.clone();
+        ServerAction::new(
+            Box::new(self.domain.clone()),
+            Box::new(move |params, window, toov| {
+                let name = name.clone();
+                Box::pin(async move {
+                    let dialog = FieldMonitorActionParametersDialog::new(
+                        &gettext_f("Set User Password in {vm}", &[("vm", &name)]),
+                        Some(&gettext(
+                            "The password is set by the guest agent running inside of the domain.",
+                        )),
+                        &gettext("Set Password"),
+                    );
+                    let username_row = dialog.add_entry_row(&gettext("Username"), "root");
+                    let password_row = adw::PasswordEntryRow::builder()
+                        .title(gettext("Password"))
+                        .build();
+                    dialog.add_row(&password_row);
+
+                    if !dialog.run(window.as_ref()).await {
+                        return false;
+                    }
+
+                    let username = username_row.text().to_string();
+                    let password = password_row.text().to_string();
+                    if username.is_empty() || password.is_empty() {
+                        show_toast(
+                            toov.as_ref(),
+                            &gettext("Username and password must not be empty."),
+                        );
+                        return false;
+                    }
+
+                    let domain = params.downcast::<VirtArc<Domain>>().unwrap();
+                    agent_action(
+                        &domain,
+                        "guest-set-user-password",
+                        Some(json!({
+                            "username": username,
+                            "password": BASE64_STANDARD.encode(password),
+                            "crypted": false,
+                        })),
+                        |_| gettext("The password was set."),
+                        |err| {
+                            gettext_f(
+                                "Failed to set the password: {err}",
+                                &[("err", &err.to_string())],
+                            )
+                        },
+                        toov.as_ref(),
+                    )
+                    .await;
+                    false
+                })
+            }),
+        )
+    }
+
+    pub(crate) fn act_agent_exec<'a>(&self) -> ServerAction<'a> {
+        let name = self.name.clone();
+        ServerAction::new(
+            Box::new(self.domain.clone()),
+            Box::new(move |params, window, toov| {
+                let name = name.clone();
+                Box::pin(async move {
+                    let dialog = FieldMonitorActionParametersDialog::new(
+                        &gettext_f("Run Command in {vm}?", &[("vm", &name)]),
+                        Some(&gettext(
+                            "The command is run with /bin/sh as the user of the guest agent, usually root.",
+                        )),
+                        &gettext("Run"),
+                    );
+                    dialog.set_destructive();
+                    let command_row = dialog.add_entry_row(&gettext("Command"), "");
+
+                    if !dialog.run(window.as_ref()).await {
+                        return false;
+                    }
+
+                    let command = command_row.text().trim().to_string();
+                    if command.is_empty() {
+                        show_toast(toov.as_ref(), &gettext("A command is required."));
+                        return false;
+                    }
+
+                    let domain = params.downcast::<VirtArc<Domain>>().unwrap();
+                    let output = match guest_exec(&domain, &command).await {
+                        Ok(output) => output,
+                        Err(err) => {
+                            warn!("failed to run command in guest: {err:?}");
+                            show_toast(
+                                toov.as_ref(),
+                                &gettext_f(
+                                    "Failed to run the command: {err}",
+                                    &[("err", &err.to_string())],
+                                ),
+                            );
+                            return false;
+                        }
+                    };
+
+                    let status = match output.exit_code {
+                        Some(code) => gettext_f(
+                            "The command exited with status {code}.",
+                            &[("code", &code.to_string())],
+                        ),
+                        None => gettext("The command was terminated."),
+                    };
+                    let dialog = FieldMonitorActionParametersDialog::new_informational(
+                        &command,
+                        Some(&status),
+                    );
+                    for (title, text) in [
+                        (gettext("Output"), &output.stdout),
+                        (gettext("Errors"), &output.stderr),
+                    ] {
+                        if !text.is_empty() {
+                            let row = dialog.add_info_row(&title, text.trim_end());
+                            row.set_subtitle_selectable(true);
+                            row.add_css_class("monospace");
+                        }
+                    }
+                    if output.truncated {
+                        dialog.add_info_row(
+                            &gettext("Output Truncated"),
+                            &gettext("The guest agent only returns the beginning of long output."),
+                        );
+                    }
+                    dialog.run(window.as_ref()).await;
+                    false
+                })
+            }),
+        )
+    }
+}
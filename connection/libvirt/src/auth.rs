@@ -0,0 +1,108 @@
+/* Copyright 2024 Marco Köpcke
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ *
+ * SPDX-License-Identifier: GPL-3.0-or-later
+ */
+//! Answers to the credential requests of libvirt while opening connections, eg. for
+//! passphrases of SSH keys or SASL authentication.
+//!
+//! libvirt asks for credentials through a plain callback without user data. The credentials
+//! are therefore passed to it via a thread local, connections are opened in their own thread.
+
+use std::cell::RefCell;
+
+use secure_string::SecureString;
+use virt::connect::{Connect, ConnectAuth, ConnectCredential};
+use virt::sys::{VIR_CRED_AUTHNAME, VIR_CRED_NOECHOPROMPT, VIR_CRED_PASSPHRASE};
+
+use libfieldmonitor::connection::{ConnectionError, ConnectionResult};
+
+use crate::connection::virt_err;
+
+/// Credentials to answer the requests of libvirt with.
+#[derive(Clone, Default)]
+pub(crate) struct Credentials {
+    /// Username for SASL.
+    pub(crate) username: Option<String>,
+    /// Passphrase of SSH keys or password for SASL.
+    pub(crate) password: Option<SecureString>,
+}
+
+struct PendingOpen {
+    credentials: Credentials,
+    /// Whether libvirt asked for any credentials.
+    asked: bool,
+}
+
+thread_local! {
+    static PENDING_OPEN: RefCell<Option<PendingOpen>> = const { RefCell::new(None) };
+}
+
+/// Opens a connection to `uri`. Blocks until the connection is established.
+///
+/// If libvirt asked for credentials and opening the connection failed, the credentials were
+/// missing or wrong and [`ConnectionError::AuthFailed`] is returned.
+pub(crate) fn open(uri: &str, credentials: Credentials) -> ConnectionResult<Connect> {
+    PENDING_OPEN.with_borrow_mut(|pending| {
+        *pending = Some(PendingOpen {
+            credentials,
+            asked: false,
+        })
+    });
+    let mut auth = ConnectAuth::new(
+        vec![
+            VIR_CRED_AUTHNAME,
+            VIR_CRED_PASSPHRASE,
+            VIR_CRED_NOECHOPROMPT,
+        ],
+        answer_credentials,
+    );
+    let result = Connect::open_auth(Some(uri), &mut auth, 0);
+    let asked = PENDING_OPEN
+        .with_borrow_mut(Option::take)
+        .is_some_and(|pending| pending.asked);
+
+    result.map_err(|err| {
+        if asked {
+            ConnectionError::AuthFailed(Some(err.message().to_string()), err.into())
+        } else {
+            virt_err(err)
+        }
+    })
+}
+
+fn answer_credentials(creds: &mut Vec<ConnectCredential>) {
+    PENDING_OPEN.with_borrow_mut(|pending| {
+        let Some(pending) = pending else {
+            return;
+        };
+        pending.asked = true;
+        for cred in creds {
+            cred.result = if cred.typed == VIR_CRED_AUTHNAME as i32 {
+                pending.credentials.username.clone()
+            } else if cred.typed == VIR_CRED_PASSPHRASE as i32
+                || cred.typed == VIR_CRED_NOECHOPROMPT as i32
+            {
+                pending
+                    .credentials
+                    .password
+                    .as_ref()
+                    .map(|v| v.unsecure().to_string())
+            } else {
+                None
+            };
+        }
+    });
+}
@@ -16,7 +16,8 @@
  * SPDX-License-Identifier: GPL-3.0-or-later
  */
 use std::borrow::Cow;
-use std::num::NonZeroU32;
+use std::cell::RefCell;
+use std::net::IpAddr;
 use std::ops::Deref;
 use std::sync::Arc;
 use std::thread;
@@ -24,17 +25,18 @@ use std::time::Duration;
 
 use anyhow::anyhow;
 use async_std::task::sleep;
+use futures::channel::mpsc::UnboundedReceiver;
 use futures::channel::oneshot;
 use futures::future::LocalBoxFuture;
+use futures::stream::LocalBoxStream;
 use futures::{stream, StreamExt, TryStreamExt};
 use gettextrs::gettext;
 use log::{debug, error, warn};
 use quick_xml::de::from_str;
-use quick_xml::impl_deserialize_for_internally_tagged_enum;
-use secure_string::SecureString;
 use serde::Deserialize;
 use virt::connect::Connect;
 use virt::domain::Domain;
+use virt::error::ErrorNumber;
 use virt::sys::{
     VIR_CONNECT_LIST_DOMAINS_ACTIVE, VIR_CONNECT_LIST_DOMAINS_INACTIVE,
     VIR_DOMAIN_DESTROY_GRACEFUL, VIR_DOMAIN_PAUSED, VIR_DOMAIN_REBOOT_ACPI_POWER_BTN,
@@ -46,11 +48,23 @@ use libfieldmonitor::adapter::rdp::RdpAdapter;
 use libfieldmonitor::adapter::spice::SpiceAdapter;
 use libfieldmonitor::adapter::types::Adapter;
 use libfieldmonitor::adapter::vnc::VncAdapter;
-use libfieldmonitor::adapter::vte_pty::VtePtyAdapter;
+use libfieldmonitor::adapter::vte_pty::{VtePtyAdapter, SSH_ADAPTER_TAG};
 use libfieldmonitor::connection::*;
+use libfieldmonitor::gtk::show_toast;
 use libfieldmonitor::i18n::gettext_f;
 
+use crate::addresses::guest_addresses;
+use crate::agent::GuestAgent;
+use crate::auth::{self, Credentials};
+use crate::console::{
+    console_alias, consoles, guest_agent, ConsoleDevice, ConsoleKind, LibvirtXmlCharDevice,
+};
+use crate::events::{start_event_loop, DomainEvents};
+use crate::graphics::{LibvirtGraphics, LibvirtXmlGraphics};
+
 pub const PTY_DRIVER_BIN: &str = "de.capypara.FieldMonitor.PtyDrv.Libvirt";
+/// How many domains are loaded at the same time when listing the servers.
+const CONCURRENT_DOMAIN_LOADS: usize = 8;
 
 #[derive(Debug, Clone)]
 pub(crate) struct VirtArc<T>(Arc<()>, T, Connect);
@@ -68,6 +82,17 @@ impl VirtArc<Connect> {
             .map(|domain| VirtArc(self.0.clone(), domain, self.2.clone()))
             .collect::<Vec<_>>())
     }
+
+    /// Looks up a domain by its UUID. `None` if there is no such domain.
+    pub fn lookup_domain(&self, uuid: &str) -> Result<Option<VirtArc<Domain>>, virt::error::Error> {
+        match Domain::lookup_by_uuid_string(&self.1, uuid) {
+            Ok(domain) => Ok(Some(VirtArc(self.0.clone(), domain, self.2.clone()))),
+            Err(err) if matches!(err.code(), ErrorNumber::NoDomain | ErrorNumber::InvalidArg) => {
+                Ok(None)
+            }
+            Err(err) => Err(err),
+        }
+    }
 }
 
 impl Deref for VirtArc<Domain> {
@@ -95,8 +120,24 @@ pub struct LibvirtConnection {
     id: String,
     title: String,
     hostname: String,
+    /// Must be dropped before the connection is closed.
+    _events: Option<DomainEvents>,
+    changes: RefCell<Option<UnboundedReceiver<ServerChange>>>,
     connection: VirtArc<Connect>,
     icon: Cow<'static, str>,
+    adapter_options: AdapterOptions,
+}
+
+/// Settings of a connection for the adapters of its servers.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct AdapterOptions {
+    /// CA for graphics that use TLS.
+    pub(crate) tls_ca: Option<String>,
+    /// Take over consoles that other clients have open.
+    pub(crate) console_force: bool,
+    /// Whether graphics can be connected to through sockets opened by libvirt, see
+    /// `Hypervisor::passes_fds`.
+    pub(crate) passes_fds: bool,
 }
 
 impl LibvirtConnection {
@@ -106,21 +147,67 @@ impl LibvirtConnection {
         uri: &str,
         title: &str,
         icon: Cow<'static, str>,
+        credentials: Credentials,
+        adapter_options: AdapterOptions,
     ) -> ConnectionResult<Self> {
         let uri = uri.to_string();
         debug!(
             "Opening libvirt connection to {uri} [hostname for adapter connections: {hostname}]"
         );
-        let connection =
-            run_in_thread(move || Connect::open(Some(&uri)).map_err(virt_err)).await??;
+        start_event_loop();
+        let (connection, events) = run_in_thread(move || {
+            let connection = auth::open(&uri, credentials)?;
+            let events = DomainEvents::register(&connection)
+                .inspect_err(|err| warn!("Failed to register for libvirt domain events: {err}"))
+                .ok();
+            Ok::<_, ConnectionError>((connection, events))
+        })
+        .await??;
+        let (events, changes) = events.unzip();
         Ok(Self {
             id: connection_id.to_string(),
             title: title.to_string(),
             hostname: hostname.to_string(),
+            _events: events,
+            changes: RefCell::new(changes),
             connection: VirtArc::new(connection),
             icon,
+            adapter_options,
         })
     }
+
+    /// Loads the server of a domain, together with its ID. The guest agent is only asked for
+    /// the addresses of the domain if `ask_agent` is set.
+    async fn load_server(
+        &self,
+        domain: VirtArc<Domain>,
+        ask_agent: bool,
+    ) -> ConnectionResult<(String, LibvirtServer)> {
+        let hostname = self.hostname.clone();
+        let adapter_options = self.adapter_options.clone();
+        let connection_name = self.id.clone();
+        // Loading the devices and addresses of the domain blocks.
+        run_in_thread(move || {
+            let domain_id = domain.get_uuid()?.to_string();
+            let name = domain
+                .get_name()
+                .unwrap_or_else(|_| gettext("(Unable to load server name)"));
+            let state = DomainState::of(&domain);
+            let server = LibvirtServer::new(
+                &hostname,
+                domain,
+                domain_id.clone(),
+                connection_name,
+                name,
+                state,
+                adapter_options,
+                ask_agent,
+            );
+            Ok((domain_id, server))
+        })
+        .await?
+        .map_err(virt_err)
+    }
 }
 
 impl Actionable for LibvirtConnection {}
@@ -134,43 +221,27 @@ impl Connection for LibvirtConnection {
             .unwrap()
     }
 
+    fn changes(&self) -> Option<LocalBoxStream<'static, ServerChange>> {
+        self.changes
+            .take()
+            .map(|changes| Box::pin(changes) as LocalBoxStream<'static, ServerChange>)
+    }
+
     fn servers(&self) -> LocalBoxFuture<ConnectionResult<ServerMap>> {
         Box::pin(async move {
             let connection = self.connection.clone();
             let domains =
                 run_in_thread(move || connection.list_all_domains().map_err(virt_err)).await??;
 
-            let hostname = self.hostname.clone();
-
+            // Asking the guest agents of all domains would take too long, it is only asked for
+            // single domains and when acting on the addresses, see `AddressLookup`.
             let mut servers: ServerMap = stream::iter(domains.into_iter())
-                .then(|domain| {
-                    let hostname_cln = hostname.clone();
-                    async move {
-                        let domain_cln = domain.clone();
-                        let (domain_id, name, is_active) = run_in_thread(move || {
-                            let domain_id = domain_cln.get_uuid()?;
-                            let name = domain_cln
-                                .get_name()
-                                .unwrap_or_else(|_| gettext("(Unable to load server name)"));
-                            let is_active = domain_cln.is_active().ok();
-                            let is_paused = domain_cln
-                                .get_state()
-                                .map(|(s, _)| s == VIR_DOMAIN_PAUSED)
-                                .unwrap_or_default();
-                            Ok((domain_id, name, is_active.map(|ia| ia && !is_paused)))
-                        })
-                        .await?
-                        .map_err(virt_err)?;
-                        let bx: Box<dyn ServerConnection> = Box::new(LibvirtServer::new(
-                            &hostname_cln,
-                            domain,
-                            self.id.clone(),
-                            name,
-                            is_active,
-                        ));
-                        Ok((Cow::Owned(domain_id.to_string()), bx))
-                    }
+                .map(|domain| async move {
+                    let (domain_id, server) = self.load_server(domain, false).await?;
+                    let bx: Box<dyn ServerConnection> = Box::new(server);
+                    Ok((Cow::Owned(domain_id), bx))
                 })
+                .buffer_unordered(CONCURRENT_DOMAIN_LOADS)
                 .try_collect()
                 .await?;
 
@@ -179,65 +250,39 @@ impl Connection for LibvirtConnection {
             Ok(servers)
         })
     }
-}
-
-#[derive(Debug, Clone)]
-struct LibvirtGraphicsCreds {
-    host: String,
-    port: NonZeroU32,
-    password: Option<SecureString>,
-}
 
-#[derive(Debug, Default, Clone)]
-struct LibvirtGraphics {
-    spice: Option<LibvirtGraphicsCreds>,
-    vnc: Option<LibvirtGraphicsCreds>,
-    rdp: Option<LibvirtGraphicsCreds>,
-}
-
-#[derive(Debug)]
-enum LibvirtXmlGraphics {
-    Vnc {
-        port: Option<i64>,
-        passwd: Option<String>,
-    },
-    Rdp {
-        port: Option<i64>,
-        passwd: Option<String>,
-    },
-    Spice {
-        port: Option<i64>,
-        passwd: Option<String>,
-    },
-    Other,
-}
-
-impl_deserialize_for_internally_tagged_enum! {
-    LibvirtXmlGraphics, "@type",
-    ("vnc"    => Vnc {
-        #[serde(rename = "@port", default)]
-        port: Option<i64>,
-        #[serde(rename = "@passwd", default)]
-        passwd: Option<String>,
-    }),
-    ("rdp" => Rdp {
-        #[serde(rename = "@port", default)]
-        port: Option<i64>,
-        #[serde(rename = "@passwd", default)]
-        passwd: Option<String>,
-    }),
-    ("spice" => Spice {
-        #[serde(rename = "@port", default)]
-        port: Option<i64>,
-        #[serde(rename = "@passwd", default)]
-        passwd: Option<String>,
-    }),
-    (_ => Other),
+    fn server<'a>(
+        &'a self,
+        path: &'a [String],
+    ) -> LocalBoxFuture<'a, ConnectionResult<Option<Box<dyn ServerConnection>>>> {
+        Box::pin(async move {
+            // Only the domain at the path is loaded, not all domains of the connection.
+            let [uuid] = path else {
+                return Ok(None);
+            };
+            let connection = self.connection.clone();
+            let uuid = uuid.clone();
+            let Some(domain) =
+                run_in_thread(move || connection.lookup_domain(&uuid).map_err(virt_err)).await??
+            else {
+                return Ok(None);
+            };
+            let (_, server) = self.load_server(domain, true).await?;
+            Ok(Some(Box::new(server) as Box<dyn ServerConnection>))
+        })
+    }
 }
 
 #[derive(Debug, Deserialize)]
 struct LibvirtXmlDevices {
+    #[serde(default)]
     graphics: Vec<LibvirtXmlGraphics>,
+    #[serde(default)]
+    serial: Vec<LibvirtXmlCharDevice>,
+    #[serde(default)]
+    console: Vec<LibvirtXmlCharDevice>,
+    #[serde(default)]
+    channel: Vec<LibvirtXmlCharDevice>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -245,42 +290,121 @@ struct LibvirtXmlDomain {
     devices: LibvirtXmlDevices,
 }
 
+/// Run state of a domain, as far as it matters for the actions and adapters.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum DomainState {
+    Running,
+    Paused,
+    /// Shut off with a managed save image, starting it resumes from the image.
+    Saved,
+    Off,
+}
+
+impl DomainState {
+    /// Loads the state of the domain. Blocks. `None` if it could not be determined.
+    fn of(domain: &Domain) -> Option<Self> {
+        if domain.is_active().ok()? {
+            let (state, _) = domain.get_state().ok()?;
+            Some(if state == VIR_DOMAIN_PAUSED {
+                Self::Paused
+            } else {
+                Self::Running
+            })
+        } else if domain.has_managed_save(0).unwrap_or_default() {
+            Some(Self::Saved)
+        } else {
+            Some(Self::Off)
+        }
+    }
+
+    fn is_active(self) -> bool {
+        matches!(self, Self::Running | Self::Paused)
+    }
+}
+
 pub struct LibvirtServer {
-    domain: VirtArc<Domain>,
-    is_active: Option<bool>,
-    connection_name: String,
-    name: String,
+    pub(crate) domain: VirtArc<Domain>,
+    /// UUID of the domain, which is also the ID of the server.
+    pub(crate) uuid: String,
+    state: Option<DomainState>,
+    pub(crate) connection_name: String,
+    pub(crate) name: String,
     graphics: LibvirtGraphics,
+    consoles: Vec<ConsoleDevice>,
+    /// Reachable addresses of the domain, see `guest_addresses`.
+    pub(crate) addresses: Vec<IpAddr>,
+    /// Whether the guest agent was asked for the `addresses`, see `LibvirtConnection::servers`.
+    pub(crate) agent_asked: bool,
+    pub(crate) guest_agent: Option<GuestAgent>,
+    is_local: bool,
+    adapter_options: AdapterOptions,
+}
+
+/// What is known about the devices of a running domain.
+#[derive(Default)]
+struct DomainDevices {
+    graphics: LibvirtGraphics,
+    consoles: Vec<ConsoleDevice>,
+    addresses: Vec<IpAddr>,
+    guest_agent: Option<GuestAgent>,
 }
 
 impl LibvirtServer {
     fn new(
         hostname: &str,
         domain: VirtArc<Domain>,
+        uuid: String,
         connection_name: String,
         name: String,
-        is_active: Option<bool>,
+        state: Option<DomainState>,
+        adapter_options: AdapterOptions,
+        ask_agent: bool,
     ) -> Self {
+        let DomainDevices {
+            graphics,
+            consoles,
+            addresses,
+            guest_agent,
+        } = if state.map_or(true, DomainState::is_active) {
+            Self::devices_for(
+                hostname,
+                adapter_options.passes_fds,
+                &name,
+                &domain,
+                ask_agent,
+            )
+        } else {
+            Default::default()
+        };
         Self {
-            graphics: if is_active.unwrap_or(true) {
-                Self::graphics_for(hostname, &name, &domain)
-            } else {
-                LibvirtGraphics::default()
-            },
+            graphics,
+            consoles,
+            addresses,
+            agent_asked: ask_agent,
+            guest_agent,
             domain,
+            uuid,
             connection_name,
             name,
-            is_active,
+            state,
+            is_local: hostname == "localhost",
+            adapter_options,
         }
     }
 
-    fn graphics_for(hostname: &str, name: &str, domain: &Domain) -> LibvirtGraphics {
-        debug!("loading graphics options for {name}");
+    fn devices_for(
+        hostname: &str,
+        passes_fds: bool,
+        name: &str,
+        domain: &Domain,
+        ask_agent: bool,
+    ) -> DomainDevices {
+        debug!("loading devices of {name}");
         let xml_str = match domain.get_xml_desc(VIR_DOMAIN_XML_SECURE) {
             Ok(xml) => xml,
             Err(err) => {
                 error!("Failed to load XML description for {name}: {err}");
-                return LibvirtGraphics::default();
+                return Default::default();
             }
         };
         let xml: LibvirtXmlDomain = match from_str(&xml_str) {
@@ -290,65 +414,83 @@ impl LibvirtServer {
             }
             Err(err) => {
                 error!("Failed to deserialize XML description for {name}: {err}");
-                return LibvirtGraphics::default();
+                return Default::default();
             }
         };
-        let mut graphics = LibvirtGraphics::default();
-
-        fn map_graphics(
-            host: &str,
-            inp: &LibvirtXmlGraphics,
-            out: &mut LibvirtGraphics,
-        ) -> Result<(), String> {
-            let built = LibvirtGraphicsCreds {
-                host: host.to_string(),
-                port: get_port(inp)?,
-                password: get_passwd(inp)?,
-            };
-            match inp {
-                LibvirtXmlGraphics::Vnc { .. } => {
-                    out.vnc = Some(built);
-                }
-                LibvirtXmlGraphics::Rdp { .. } => {
-                    out.rdp = Some(built);
-                }
-                LibvirtXmlGraphics::Spice { .. } => {
-                    out.spice = Some(built);
-                }
-                LibvirtXmlGraphics::Other => {}
-            }
-            Ok(())
-        }
-
-        // TODO: I guess we COULD try and figure out if the given address on the connection would even
-        //       allow us to connect, but it's pretty complicated with plenty failure / edge cases
-        //       to properly detect, and in most cases would probably just be confusing for the end
-        //       user, if they just see no option to connect.
-        let host = hostname;
-
-        for inp in &xml.devices.graphics {
-            if let Err(err) = map_graphics(host, inp, &mut graphics) {
-                warn!("failed to process graphics entry for {name}: {err}");
-            }
+        let graphics = LibvirtGraphics::resolve(hostname, passes_fds, &xml.devices.graphics);
+        debug!("Libvirt server {name} graphics connection info: {graphics:?}");
+        let consoles = consoles(
+            &xml.devices.serial,
+            &xml.devices.console,
+            &xml.devices.channel,
+        );
+        debug!("Libvirt server {name} consoles: {consoles:?}");
+        let guest_agent = guest_agent(&xml.devices.channel);
+        debug!("Libvirt server {name} guest agent: {guest_agent:?}");
+        let addresses = guest_addresses(
+            name,
+            domain,
+            ask_agent && guest_agent == Some(GuestAgent::Connected),
+        );
+        debug!("Libvirt server {name} addresses: {addresses:?}");
+        DomainDevices {
+            graphics,
+            consoles,
+            addresses,
+            guest_agent,
         }
+    }
 
-        debug!("Libvirt server {name} graphics connection info: {graphics:?}");
-        graphics
+    /// The primary address and the state of the guest agent, as far as known.
+    fn subtitle(&self) -> Option<String> {
+        let agent = match self.guest_agent {
+            Some(GuestAgent::Connected) => Some(gettext("Guest agent")),
+            Some(GuestAgent::Disconnected) => Some(gettext("Guest agent not running")),
+            None => None,
+        };
+        let parts: Vec<String> = self
+            .primary_address()
+            .map(|address| address.to_string())
+            .into_iter()
+            .chain(agent)
+            .collect();
+        (!parts.is_empty()).then(|| parts.join(" · "))
     }
 }
 
 impl Actionable for LibvirtServer {
     fn actions(&self) -> Vec<(Cow<'static, str>, Cow<'static, str>)> {
-        if self.is_active.unwrap_or_default() {
-            vec![
+        let mut actions = match self.state {
+            Some(DomainState::Running) => vec![
                 ("pmreboot".into(), gettext("Reboot").into()),
                 ("pmshutdown".into(), gettext("Shutdown").into()),
+                ("suspend".into(), gettext("Suspend").into()),
+                ("managedsave".into(), gettext("Save and Stop").into()),
                 ("reset".into(), gettext("Force Reset").into()),
                 ("poweroff".into(), gettext("Force Poweroff").into()),
-            ]
-        } else {
-            vec![("start".into(), gettext("Start / Resume").into())]
+            ],
+            Some(DomainState::Paused) => vec![
+                ("resume".into(), gettext("Resume").into()),
+                ("managedsave".into(), gettext("Save and Stop").into()),
+                ("poweroff".into(), gettext("Force Poweroff").into()),
+            ],
+            Some(DomainState::Saved) => vec![
+                ("start".into(), gettext("Start / Resume").into()),
+                (
+                    "managedsaveremove".into(),
+                    gettext("Discard Saved State").into(),
+                ),
+            ],
+            Some(DomainState::Off) | None => {
+                vec![("start".into(), gettext("Start / Resume").into())]
+            }
+        };
+        if self.state == Some(DomainState::Running) {
+            actions.extend(self.address_actions());
+            actions.extend(self.agent_actions());
         }
+        actions.extend(self.snapshot_actions());
+        actions
     }
 
     fn action<'a>(&self, action_id: &str) -> Option<ServerAction<'a>> {
@@ -358,6 +500,21 @@ impl Actionable for LibvirtServer {
             "reset" => Some(self.act_reset()),
             "poweroff" => Some(self.act_poweroff()),
             "start" => Some(self.act_start()),
+            "suspend" => Some(self.act_suspend()),
+            "resume" => Some(self.act_resume()),
+            "managedsave" => Some(self.act_managed_save()),
+            "managedsaveremove" => Some(self.act_managed_save_remove()),
+            "copyip" => Some(self.act_copy_ip()),
+            "agentping" => Some(self.act_agent_ping()),
+            "agentosinfo" => Some(self.act_agent_os_info()),
+            "agentfsfreeze" => Some(self.act_agent_fsfreeze()),
+            "agentfsthaw" => Some(self.act_agent_fsthaw()),
+            "agentsetuserpassword" => Some(self.act_agent_set_user_password()),
+            "agentexec" => Some(self.act_agent_exec()),
+            "snapshotlist" => Some(self.act_snapshot_list()),
+            "snapshotcreate" => Some(self.act_snapshot_create()),
+            "snapshotrevert" => Some(self.act_snapshot_revert()),
+            "snapshotdelete" => Some(self.act_snapshot_delete()),
             _ => None,
         }
     }
@@ -508,6 +665,101 @@ impl LibvirtServer {
         )
     }
 
+    fn act_suspend<'a>(&self) -> ServerAction<'a> {
+        ServerAction::new(
+            Box::new(self.domain.clone()),
+            Box::new(|params, _window, toov| {
+                Box::pin(async move {
+                    let domain = params.downcast::<VirtArc<Domain>>().unwrap();
+                    let (success, force_reload) = Self::exec_cmd(
+                        true,
+                        &domain,
+                        |domain| domain.suspend(),
+                        || gettext("Domain successfully suspended."),
+                        |err| {
+                            gettext_f("Failed to suspend domain: {err}", &[("err", err.message())])
+                        },
+                        toov.as_ref(),
+                    )
+                    .await;
+                    success || force_reload
+                })
+            }),
+        )
+    }
+    fn act_resume<'a>(&self) -> ServerAction<'a> {
+        ServerAction::new(
+            Box::new(self.domain.clone()),
+            Box::new(|params, _window, toov| {
+                Box::pin(async move {
+                    let domain = params.downcast::<VirtArc<Domain>>().unwrap();
+                    let (success, force_reload) = Self::exec_cmd(
+                        true,
+                        &domain,
+                        |domain| domain.resume(),
+                        || gettext("Domain successfully resumed."),
+                        |err| {
+                            gettext_f("Failed to resume domain: {err}", &[("err", err.message())])
+                        },
+                        toov.as_ref(),
+                    )
+                    .await;
+                    success || force_reload
+                })
+            }),
+        )
+    }
+    fn act_managed_save<'a>(&self) -> ServerAction<'a> {
+        ServerAction::new(
+            Box::new(self.domain.clone()),
+            Box::new(|params, _window, toov| {
+                Box::pin(async move {
+                    let domain = params.downcast::<VirtArc<Domain>>().unwrap();
+                    let (success, force_reload) = Self::exec_cmd(
+                        true,
+                        &domain,
+                        |domain| domain.managed_save(0),
+                        || gettext("Domain state successfully saved."),
+                        |err| {
+                            gettext_f(
+                                "Failed to save domain state: {err}",
+                                &[("err", err.message())],
+                            )
+                        },
+                        toov.as_ref(),
+                    )
+                    .await;
+                    success || force_reload
+                })
+            }),
+        )
+    }
+    fn act_managed_save_remove<'a>(&self) -> ServerAction<'a> {
+        ServerAction::new(
+            Box::new(self.domain.clone()),
+            Box::new(|params, _window, toov| {
+                Box::pin(async move {
+                    let domain = params.downcast::<VirtArc<Domain>>().unwrap();
+                    let (success, force_reload) = Self::exec_cmd(
+                        false,
+                        &domain,
+                        |domain| domain.managed_save_remove(0),
+                        || gettext("Saved domain state successfully discarded."),
+                        |err| {
+                            gettext_f(
+                                "Failed to discard saved domain state: {err}",
+                                &[("err", err.message())],
+                            )
+                        },
+                        toov.as_ref(),
+                    )
+                    .await;
+                    success || force_reload
+                })
+            }),
+        )
+    }
+
     async fn exec_cmd<F, S>(
         should_be_running: bool,
         domain: &VirtArc<Domain>,
@@ -551,9 +803,7 @@ impl LibvirtServer {
             )
         });
 
-        if let Some(toov) = toov {
-            toov.add_toast(adw::Toast::builder().title(&text).timeout(5).build());
-        }
+        show_toast(toov, &text);
         (success, should_reload)
     }
 }
@@ -562,13 +812,19 @@ impl ServerConnection for LibvirtServer {
     fn metadata(&self) -> ServerMetadata {
         ServerMetadataBuilder::default()
             .title(self.name.clone())
-            .is_online(self.is_active)
+            .subtitle(self.subtitle())
+            .is_online(self.state.map(DomainState::is_active))
+            .state(match self.state {
+                Some(DomainState::Paused) => Some(ServerState::Paused),
+                Some(DomainState::Saved) => Some(ServerState::Saved),
+                _ => None,
+            })
             .build()
             .unwrap()
     }
 
     fn supported_adapters(&self) -> Vec<(Cow<str>, Cow<str>)> {
-        if !self.is_active.unwrap_or(true) {
+        if !self.state.map_or(true, DomainState::is_active) {
             return vec![];
         }
         let mut adapters = Vec::with_capacity(4);
@@ -584,23 +840,34 @@ impl ServerConnection for LibvirtServer {
         if self.graphics.vnc.is_some() {
             adapters.push((VncAdapter::TAG.into(), gettext("VNC (Graphical)").into()));
         }
-        adapters.push((VtePtyAdapter::TAG.into(), gettext("Serial Console").into()));
+        if self.consoles.is_empty() {
+            // The default console of the domain, if there is one.
+            adapters.push((VtePtyAdapter::TAG.into(), gettext("Serial Console").into()));
+        }
+        for console in &self.consoles {
+            adapters.push((console.adapter_tag().into(), console.title.clone().into()));
+        }
+        if self.state == Some(DomainState::Running)
+            && (self.primary_address().is_some() || self.may_ask_agent())
+        {
+            adapters.push((SSH_ADAPTER_TAG.into(), gettext("SSH").into()));
+        }
         adapters
     }
 
     fn create_adapter(&self, tag: &str) -> LocalBoxFuture<ConnectionResult<Box<dyn Adapter>>> {
+        if tag == SSH_ADAPTER_TAG {
+            return self.create_ssh_adapter();
+        }
         let tag = tag.to_string();
         let graphics = self.graphics.clone();
+        let tls_ca = self.adapter_options.tls_ca.clone();
+        let is_local = self.is_local;
         Box::pin(async move {
             let bx: Box<dyn Adapter> = match &*tag {
                 SpiceAdapter::TAG => {
                     if let Some(creds) = graphics.spice {
-                        Box::new(SpiceAdapter::new(
-                            creds.host,
-                            creds.port.into(),
-                            String::new(),
-                            creds.password.unwrap_or_else(|| "".into()),
-                        ))
+                        Box::new(creds.spice_adapter(self.domain.clone(), tls_ca, is_local))
                     } else {
                         Err(ConnectionError::General(
                             None,
@@ -609,13 +876,8 @@ impl ServerConnection for LibvirtServer {
                     }
                 }
                 RdpAdapter::TAG => {
-                    if let Some(creds) = graphics.rdp {
-                        Box::new(RdpAdapter::new(
-                            creds.host,
-                            creds.port.into(),
-                            String::new(),
-                            creds.password.unwrap_or_else(|| "".into()),
-                        ))
+                    if let Some(adapter) = graphics.rdp.and_then(|creds| creds.rdp_adapter()) {
+                        Box::new(adapter)
                     } else {
                         Err(ConnectionError::General(
                             None,
@@ -625,12 +887,7 @@ impl ServerConnection for LibvirtServer {
                 }
                 VncAdapter::TAG => {
                     if let Some(creds) = graphics.vnc {
-                        Box::new(VncAdapter::new(
-                            creds.host,
-                            creds.port.into(),
-                            String::new(),
-                            creds.password.unwrap_or_else(|| "".into()),
-                        ))
+                        Box::new(creds.vnc_adapter(self.domain.clone(), tls_ca, is_local))
                     } else {
                         Err(ConnectionError::General(
                             None,
@@ -638,22 +895,37 @@ impl ServerConnection for LibvirtServer {
                         ))?
                     }
                 }
-                VtePtyAdapter::TAG => {
+                tag if tag == VtePtyAdapter::TAG || console_alias(tag).is_some() => {
+                    let (kind, alias) = match console_alias(tag) {
+                        None => (ConsoleKind::Console, ""),
+                        Some(alias) => {
+                            let Some(console) = self.consoles.iter().find(|c| c.alias == alias)
+                            else {
+                                return Err(ConnectionError::General(
+                                    None,
+                                    anyhow!("console {alias} not found on this domain"),
+                                ));
+                            };
+                            (console.kind, alias)
+                        }
+                    };
                     let uri = self
                         .domain
                         .get_connect()
                         .and_then(|c| c.get_uri())
                         .map_err(|e| ConnectionError::General(None, e.into()))?;
-                    let domid = self
-                        .domain
-                        .get_uuid_string()
-                        .map_err(|e| ConnectionError::General(None, e.into()))?;
+                    let domid = self.uuid.clone();
+                    let force = if self.adapter_options.console_force {
+                        "1"
+                    } else {
+                        "0"
+                    };
                     Box::new(VtePtyAdapter::new(
                         self.connection_name.clone(),
                         self.name.clone(),
-                        VtePtyAdapter::TAG.to_string(),
+                        tag.to_string(),
                         which_global(PTY_DRIVER_BIN).expect("failed to find libvirt vte driver in path. Is Field Monitor correctly installed?"),
-                        vec![uri, domid],
+                        vec![uri, domid, kind.as_str().to_string(), alias.to_string(), force.to_string()],
                     ))
                 }
                 tag => Err(ConnectionError::General(
@@ -666,11 +938,11 @@ impl ServerConnection for LibvirtServer {
     }
 }
 
-fn virt_err(error: virt::error::Error) -> ConnectionError {
+pub(crate) fn virt_err(error: virt::error::Error) -> ConnectionError {
     ConnectionError::General(Some(error.message().to_string()), error.into())
 }
 
-async fn run_in_thread<F, T>(task: F) -> ConnectionResult<T>
+pub(crate) async fn run_in_thread<F, T>(task: F) -> ConnectionResult<T>
 where
     F: (FnOnce() -> T) + Send + 'static,
     T: Send + 'static,
@@ -683,29 +955,3 @@ where
         .await
         .map_err(|e| ConnectionError::General(None, e.into()))
 }
-
-fn get_port(graphics: &LibvirtXmlGraphics) -> Result<NonZeroU32, String> {
-    let port_opt = match graphics {
-        LibvirtXmlGraphics::Vnc { port, .. } => port,
-        LibvirtXmlGraphics::Rdp { port, .. } => port,
-        LibvirtXmlGraphics::Spice { port, .. } => port,
-        LibvirtXmlGraphics::Other => return Err("unsupported graphics type".to_string()),
-    };
-    let Some(port_i64) = *port_opt else {
-        return Err("port missing".to_string());
-    };
-    let Ok(port) = u32::try_from(port_i64).and_then(NonZeroU32::try_from) else {
-        return Err("invalid port".to_string());
-    };
-    Ok(port)
-}
-
-fn get_passwd(graphics: &LibvirtXmlGraphics) -> Result<Option<SecureString>, String> {
-    let passwd_opt = match graphics {
-        LibvirtXmlGraphics::Vnc { passwd, .. } => passwd,
-        LibvirtXmlGraphics::Rdp { passwd, .. } => passwd,
-        LibvirtXmlGraphics::Spice { passwd, .. } => passwd,
-        LibvirtXmlGraphics::Other => return Err("unsupported graphics type".to_string()),
-    };
-    Ok(passwd_opt.as_ref().map(SecureString::from))
-}
@@ -0,0 +1,175 @@
+/* Copyright 2024 Marco Köpcke
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ *
+ * SPDX-License-Identifier: GPL-3.0-or-later
+ */
+//! Text consoles of domains.
+//!
+//! Serial ports and consoles with a pseudo terminal are opened with `virDomainOpenConsole`,
+//! channels with a UNIX socket with `virDomainOpenChannel`. The libvirt VTE driver does the
+//! actual work, identified by the alias of the device.
+
+use serde::Deserialize;
+
+use libfieldmonitor::adapter::vte_pty::VtePtyAdapter;
+use libfieldmonitor::i18n::gettext_f;
+
+use crate::agent::GuestAgent;
+
+/// Channel libvirt itself talks to the guest agent over.
+const GUEST_AGENT_CHANNEL: &str = "org.qemu.guest_agent.0";
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct LibvirtXmlCharDevice {
+    #[serde(rename = "@type")]
+    kind: String,
+    #[serde(default)]
+    target: Option<LibvirtXmlCharTarget>,
+    #[serde(default)]
+    alias: Option<LibvirtXmlAlias>,
+}
+
+#[derive(Debug, Deserialize)]
+struct LibvirtXmlCharTarget {
+    #[serde(rename = "@type", default)]
+    kind: Option<String>,
+    #[serde(rename = "@port", default)]
+    port: Option<u32>,
+    #[serde(rename = "@name", default)]
+    name: Option<String>,
+    /// `connected` if the guest side of a virtio channel is open.
+    #[serde(rename = "@state", default)]
+    state: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct LibvirtXmlAlias {
+    #[serde(rename = "@name")]
+    name: String,
+}
+
+/// How the VTE driver opens a console device.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum ConsoleKind {
+    Console,
+    Channel,
+}
+
+impl ConsoleKind {
+    pub(crate) fn as_str(self) -> &'static str {
+        match self {
+            Self::Console => "console",
+            Self::Channel => "channel",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct ConsoleDevice {
+    pub(crate) kind: ConsoleKind,
+    pub(crate) alias: String,
+    pub(crate) title: String,
+}
+
+impl ConsoleDevice {
+    pub(crate) fn adapter_tag(&self) -> String {
+        format!("{}-{}", VtePtyAdapter::TAG, self.alias)
+    }
+}
+
+/// The alias of the device an adapter tag created by `ConsoleDevice::adapter_tag` refers to.
+pub(crate) fn console_alias(adapter_tag: &str) -> Option<&str> {
+    adapter_tag
+        .strip_prefix(VtePtyAdapter::TAG)?
+        .strip_prefix('-')
+        .filter(|alias| !alias.is_empty())
+}
+
+/// The consoles that can be opened. Only running domains have aliases for their devices.
+pub(crate) fn consoles(
+    serials: &[LibvirtXmlCharDevice],
+    consoles: &[LibvirtXmlCharDevice],
+    channels: &[LibvirtXmlCharDevice],
+) -> Vec<ConsoleDevice> {
+    let mut devices: Vec<ConsoleDevice> = Vec::new();
+
+    // The first console is usually the first serial port again, with the same alias.
+    for (device, is_serial) in serials
+        .iter()
+        .map(|d| (d, true))
+        .chain(consoles.iter().map(|d| (d, false)))
+    {
+        let Some(alias) = &device.alias else {
+            continue;
+        };
+        if device.kind != "pty" || devices.iter().any(|d| d.alias == alias.name) {
+            continue;
+        }
+        let port = device
+            .target
+            .as_ref()
+            .and_then(|t| t.port)
+            .unwrap_or_default()
+            .to_string();
+        let target_kind = device.target.as_ref().and_then(|t| t.kind.as_deref());
+        let title = if is_serial || target_kind == Some("serial") {
+            gettext_f("Serial Console ({port})", &[("port", &port)])
+        } else {
+            gettext_f(
+                "Console ({port}, {type})",
+                &[("port", &port), ("type", target_kind.unwrap_or_default())],
+            )
+        };
+        devices.push(ConsoleDevice {
+            kind: ConsoleKind::Console,
+            alias: alias.name.clone(),
+            title,
+        });
+    }
+
+    for device in channels {
+        let (Some(alias), Some(name)) = (
+            &device.alias,
+            device.target.as_ref().and_then(|t| t.name.as_deref()),
+        ) else {
+            continue;
+        };
+        if device.kind != "unix" || name == GUEST_AGENT_CHANNEL {
+            continue;
+        }
+        devices.push(ConsoleDevice {
+            kind: ConsoleKind::Channel,
+            alias: alias.name.clone(),
+            title: gettext_f("Channel ({name})", &[("name", name)]),
+        });
+    }
+
+    devices
+}
+
+/// State of the channel of the QEMU guest agent, `None` if the domain has none.
+pub(crate) fn guest_agent(channels: &[LibvirtXmlCharDevice]) -> Option<GuestAgent> {
+    channels
+        .iter()
+        .filter_map(|device| device.target.as_ref())
+        .find(|target| target.name.as_deref() == Some(GUEST_AGENT_CHANNEL))
+        .map(|target| {
+            if target.state.as_deref() == Some("connected") {
+                GuestAgent::Connected
+            } else {
+                GuestAgent::Disconnected
+            }
+        })
+}
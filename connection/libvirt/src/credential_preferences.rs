@@ -0,0 +1,184 @@
+/* Copyright 2024 Marco Köpcke
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ *
+ * SPDX-License-Identifier: GPL-3.0-or-later
+ */
+//! Credentials libvirt may ask for while connecting: passphrases of SSH keys and SASL
+//! credentials.
+
+use std::cell::Cell;
+use std::cell::RefCell;
+
+use adw::subclass::prelude::*;
+use glib::clone;
+use gtk::glib;
+use gtk::prelude::*;
+use secure_string::SecureString;
+
+use libfieldmonitor::connection::ConnectionConfiguration;
+use libfieldmonitor::gtk::FieldMonitorSaveCredentialsButton;
+
+use crate::preferences::{LibvirtConfiguration, RemoteTransport};
+
+mod imp {
+    use super::*;
+
+    #[derive(Debug, Default, gtk::CompositeTemplate, glib::Properties)]
+    #[properties(wrapper_type = super::LibvirtCredentialPreferences)]
+    #[template(resource = "/de/capypara/FieldMonitor/connection/libvirt/credential_preferences.ui")]
+    pub struct LibvirtCredentialPreferences {
+        #[template_child]
+        pub ssh_passphrase_entry: TemplateChild<adw::PasswordEntryRow>,
+        #[template_child]
+        pub ssh_passphrase_entry_save_button: TemplateChild<FieldMonitorSaveCredentialsButton>,
+        #[template_child]
+        pub sasl_username_entry: TemplateChild<adw::EntryRow>,
+        #[template_child]
+        pub sasl_password_entry: TemplateChild<adw::PasswordEntryRow>,
+        #[template_child]
+        pub sasl_password_entry_save_button: TemplateChild<FieldMonitorSaveCredentialsButton>,
+        #[property(get, set)]
+        ssh_passphrase: RefCell<String>,
+        #[property(get, set)]
+        sasl_username: RefCell<String>,
+        #[property(get, set)]
+        sasl_password: RefCell<String>,
+        #[property(get, construct_only, default = true)]
+        /// If true: If the credentials are set to "ask", then still allow the user
+        /// to input a value, if false, do not allow the user to input a value.
+        pub use_temporary_credentials: Cell<bool>,
+    }
+
+    #[glib::object_subclass]
+    impl ObjectSubclass for LibvirtCredentialPreferences {
+        const NAME: &'static str = "LibvirtCredentialPreferences";
+        type Type = super::LibvirtCredentialPreferences;
+        type ParentType = adw::PreferencesGroup;
+
+        fn class_init(klass: &mut Self::Class) {
+            Self::bind_template(klass);
+        }
+
+        fn instance_init(obj: &glib::subclass::InitializingObject<Self>) {
+            obj.init_template();
+        }
+    }
+
+    #[glib::derived_properties]
+    impl ObjectImpl for LibvirtCredentialPreferences {
+        fn constructed(&self) {
+            self.parent_constructed();
+            if !self.use_temporary_credentials.get() {
+                for (button, entry) in [
+                    (
+                        &*self.ssh_passphrase_entry_save_button,
+                        &*self.ssh_passphrase_entry,
+                    ),
+                    (
+                        &*self.sasl_password_entry_save_button,
+                        &*self.sasl_password_entry,
+                    ),
+                ] {
+                    button
+                        .bind_property("save_password", entry, "editable")
+                        .sync_create()
+                        .build();
+                    // Clears the widget if it becomes non-editable
+                    entry.connect_notify(Some("editable"), move |w, _| {
+                        if !w.is_editable() {
+                            w.set_text("")
+                        }
+                    });
+                }
+            }
+        }
+    }
+    impl WidgetImpl for LibvirtCredentialPreferences {}
+    impl PreferencesGroupImpl for LibvirtCredentialPreferences {}
+}
+
+glib::wrapper! {
+    pub struct LibvirtCredentialPreferences(ObjectSubclass<imp::LibvirtCredentialPreferences>)
+        @extends gtk::Widget, adw::PreferencesGroup;
+}
+
+impl LibvirtCredentialPreferences {
+    pub fn new(
+        existing_configuration: Option<&ConnectionConfiguration>,
+        use_temporary_credentials: bool,
+    ) -> Self {
+        let slf: Self = glib::Object::builder()
+            .property("use-temporary-credentials", use_temporary_credentials)
+            .build();
+
+        if let Some(existing_configuration) = existing_configuration.cloned() {
+            glib::spawn_future_local(clone!(
+                #[weak]
+                slf,
+                async move {
+                    slf.propagate_settings(&existing_configuration).await;
+                }
+            ));
+        }
+
+        slf
+    }
+
+    /// Shows the credentials used with the transport, or all if `None`.
+    pub fn set_transport(&self, transport: Option<RemoteTransport>) {
+        let imp = self.imp();
+        let ssh = transport.map_or(true, |t| t == RemoteTransport::Ssh);
+        let sasl = transport.map_or(true, |t| t != RemoteTransport::Ssh);
+        imp.ssh_passphrase_entry.set_visible(ssh);
+        imp.sasl_username_entry.set_visible(sasl);
+        imp.sasl_password_entry.set_visible(sasl);
+    }
+
+    pub async fn propagate_settings(&self, existing_configuration: &ConnectionConfiguration) {
+        self.set_sasl_username(existing_configuration.sasl_username());
+        if let Ok(Some(v)) = existing_configuration.ssh_passphrase().await {
+            self.set_ssh_passphrase(v.unsecure());
+        }
+        if let Ok(Some(v)) = existing_configuration.sasl_password().await {
+            self.set_sasl_password(v.unsecure());
+        }
+    }
+
+    pub fn apply_persistent_config(
+        &self,
+        config: &mut ConnectionConfiguration,
+    ) -> Result<(), anyhow::Error> {
+        config.set_ssh_passphrase(non_empty(self.ssh_passphrase()));
+        config.set_sasl_username(self.sasl_username().trim());
+        config.set_sasl_password(non_empty(self.sasl_password()));
+        Ok(())
+    }
+
+    pub fn apply_session_config(
+        &self,
+        config: &mut ConnectionConfiguration,
+    ) -> Result<(), anyhow::Error> {
+        config.set_ssh_passphrase_session(non_empty(self.ssh_passphrase()));
+        config.set_sasl_username(self.sasl_username().trim());
+        config.set_sasl_password_session(non_empty(self.sasl_password()));
+        Ok(())
+    }
+}
+
+fn non_empty(value: String) -> Option<SecureString> {
+    Some(value)
+        .filter(|v| !v.is_empty())
+        .map(SecureString::from)
+}
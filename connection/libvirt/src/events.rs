@@ -0,0 +1,163 @@
+/* Copyright 2024 Marco Köpcke
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ *
+ * SPDX-License-Identifier: GPL-3.0-or-later
+ */
+//! Lifecycle events of domains.
+//!
+//! libvirt dispatches events from its own event loop. It runs in a separate thread next to the
+//! GLib main loop, the callbacks forward the changes to the connection over a channel.
+
+use std::ffi::{c_char, c_int, c_void, CStr};
+use std::mem;
+use std::ptr;
+use std::sync::Once;
+use std::thread;
+
+use futures::channel::mpsc::{unbounded, UnboundedReceiver, UnboundedSender};
+use log::{debug, error, warn};
+use virt::connect::Connect;
+use virt::sys;
+
+use libfieldmonitor::connection::ServerChange;
+
+static EVENT_LOOP: Once = Once::new();
+
+/// Registers the default event loop implementation of libvirt and runs it in a background
+/// thread. Connections only report events if they are opened after this was called.
+pub(crate) fn start_event_loop() {
+    EVENT_LOOP.call_once(|| {
+        // SAFETY: Only called once, before any connection that should report events is opened.
+        if unsafe { sys::virEventRegisterDefaultImpl() } < 0 {
+            error!("Failed to register libvirt event loop. Domain changes will not be shown.");
+            return;
+        }
+        let spawned = thread::Builder::new()
+            .name("libvirt-events".to_string())
+            .spawn(|| loop {
+                // SAFETY: The default implementation was registered above.
+                if unsafe { sys::virEventRunDefaultImpl() } < 0 {
+                    warn!("libvirt event loop iteration failed");
+                }
+            });
+        if let Err(err) = spawned {
+            error!("Failed to start libvirt event loop thread: {err}");
+        }
+    });
+}
+
+/// Registration for lifecycle events of all domains of a connection. The changes are sent to
+/// the receiver returned by `register` until this is dropped.
+pub(crate) struct DomainEvents {
+    connect: sys::virConnectPtr,
+    callback_id: c_int,
+}
+
+// SAFETY: libvirt connections may be used from any thread.
+unsafe impl Send for DomainEvents {}
+
+impl DomainEvents {
+    pub(crate) fn register(
+        connect: &Connect,
+    ) -> Result<(Self, UnboundedReceiver<ServerChange>), virt::error::Error> {
+        let (sender, receiver) = unbounded::<ServerChange>();
+        let opaque = Box::into_raw(Box::new(sender)) as *mut c_void;
+
+        // SAFETY: Like the VIR_DOMAIN_EVENT_CALLBACK macro of libvirt, the lifecycle callback is
+        // passed as generic callback. libvirt calls it with the arguments of lifecycle events.
+        // The sender is freed by libvirt via `free_sender` once the callback is deregistered.
+        unsafe {
+            let callback = mem::transmute::<
+                unsafe extern "C" fn(
+                    sys::virConnectPtr,
+                    sys::virDomainPtr,
+                    c_int,
+                    c_int,
+                    *mut c_void,
+                ) -> c_int,
+                unsafe extern "C" fn(sys::virConnectPtr, sys::virDomainPtr, *mut c_void),
+            >(lifecycle_callback);
+            let callback_id = sys::virConnectDomainEventRegisterAny(
+                connect.as_ptr(),
+                ptr::null_mut(),
+                sys::VIR_DOMAIN_EVENT_ID_LIFECYCLE as c_int,
+                Some(callback),
+                opaque,
+                Some(free_sender),
+            );
+            if callback_id < 0 {
+                free_sender(opaque);
+                return Err(virt::error::Error::last_error());
+            }
+            // Keep the connection alive until the callback is deregistered.
+            sys::virConnectRef(connect.as_ptr());
+            Ok((
+                Self {
+                    connect: connect.as_ptr(),
+                    callback_id,
+                },
+                receiver,
+            ))
+        }
+    }
+}
+
+impl Drop for DomainEvents {
+    fn drop(&mut self) {
+        // SAFETY: The connection was referenced when registering.
+        unsafe {
+            if sys::virConnectDomainEventDeregisterAny(self.connect, self.callback_id) < 0 {
+                warn!("Failed to deregister libvirt domain events");
+            }
+            sys::virConnectClose(self.connect);
+        }
+    }
+}
+
+/// The change to report for a lifecycle event of a domain.
+fn change_for_event(event: c_int, uuid: String) -> ServerChange {
+    if event == sys::VIR_DOMAIN_EVENT_DEFINED as c_int
+        || event == sys::VIR_DOMAIN_EVENT_UNDEFINED as c_int
+    {
+        ServerChange::ServersChanged
+    } else {
+        ServerChange::ServerChanged(vec![uuid])
+    }
+}
+
+unsafe extern "C" fn lifecycle_callback(
+    _connect: sys::virConnectPtr,
+    domain: sys::virDomainPtr,
+    event: c_int,
+    detail: c_int,
+    opaque: *mut c_void,
+) -> c_int {
+    let sender = &*(opaque as *const UnboundedSender<ServerChange>);
+    let mut uuid: [c_char; sys::VIR_UUID_STRING_BUFLEN as usize] =
+        [0; sys::VIR_UUID_STRING_BUFLEN as usize];
+    if sys::virDomainGetUUIDString(domain, uuid.as_mut_ptr()) < 0 {
+        warn!("Failed to get UUID of domain for lifecycle event {event}");
+        return 0;
+    }
+    let uuid = CStr::from_ptr(uuid.as_ptr()).to_string_lossy().into_owned();
+    debug!("Domain {uuid} lifecycle event {event} (detail {detail})");
+    // Fails if the connection is no longer interested, the callback is removed shortly.
+    sender.unbounded_send(change_for_event(event, uuid)).ok();
+    0
+}
+
+unsafe extern "C" fn free_sender(opaque: *mut c_void) {
+    drop(Box::from_raw(opaque as *mut UnboundedSender<ServerChange>));
+}
@@ -0,0 +1,338 @@
+/* Copyright 2024 Marco Köpcke
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ *
+ * SPDX-License-Identifier: GPL-3.0-or-later
+ */
+//! Graphics devices of domains and how to reach them.
+//!
+//! Graphics either listen on a network address or on a UNIX socket of the host. The latter
+//! (and `none` listeners) are connected to through a socket libvirt opens for us. libvirt can
+//! only pass that socket on if the daemon is reached through a local UNIX socket, over SSH,
+//! TLS or TCP these graphics can not be connected to.
+
+use std::net::IpAddr;
+use std::num::NonZeroU32;
+use std::os::fd::{FromRawFd, OwnedFd};
+use std::os::raw::c_uint;
+
+use anyhow::anyhow;
+use futures::FutureExt;
+use log::info;
+use quick_xml::impl_deserialize_for_internally_tagged_enum;
+use secure_string::SecureString;
+use serde::Deserialize;
+use virt::domain::Domain;
+use virt::sys::{virDomainOpenGraphicsFD, VIR_DOMAIN_OPEN_GRAPHICS_SKIPAUTH};
+
+use libfieldmonitor::adapter::rdp::RdpAdapter;
+use libfieldmonitor::adapter::spice::{SpiceAdapter, SpiceSessionConfigBuilder};
+use libfieldmonitor::adapter::types::FdSource;
+use libfieldmonitor::adapter::vnc::VncAdapter;
+
+use crate::connection::{run_in_thread, VirtArc};
+
+/// CA certificates libvirt configures for the graphics of local domains by default.
+const LOCAL_SPICE_CA: &str = "/etc/pki/libvirt-spice/ca-cert.pem";
+const LOCAL_VNC_CA: &str = "/etc/pki/libvirt-vnc/ca-cert.pem";
+
+#[derive(Debug)]
+pub(crate) enum LibvirtXmlGraphics {
+    Vnc(LibvirtXmlGraphicsSettings),
+    Rdp(LibvirtXmlGraphicsSettings),
+    Spice(LibvirtXmlGraphicsSettings),
+    Other,
+}
+
+impl_deserialize_for_internally_tagged_enum! {
+    LibvirtXmlGraphics, "@type",
+    ("vnc"   => Vnc(LibvirtXmlGraphicsSettings)),
+    ("rdp"   => Rdp(LibvirtXmlGraphicsSettings)),
+    ("spice" => Spice(LibvirtXmlGraphicsSettings)),
+    (_ => Other),
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub(crate) struct LibvirtXmlGraphicsSettings {
+    #[serde(rename = "@port", default)]
+    port: Option<i64>,
+    #[serde(rename = "@tlsPort", default)]
+    tls_port: Option<i64>,
+    #[serde(rename = "@listen", default)]
+    listen: Option<String>,
+    #[serde(rename = "@socket", default)]
+    socket: Option<String>,
+    #[serde(rename = "@passwd", default)]
+    passwd: Option<String>,
+    #[serde(rename = "listen", default)]
+    listens: Vec<LibvirtXmlGraphicsListen>,
+}
+
+#[derive(Debug, Deserialize)]
+struct LibvirtXmlGraphicsListen {
+    #[serde(rename = "@type", default)]
+    kind: Option<String>,
+    #[serde(rename = "@address", default)]
+    address: Option<String>,
+}
+
+/// Where to connect to for a graphics device.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum GraphicsEndpoint {
+    Tcp {
+        host: String,
+        port: Option<NonZeroU32>,
+        tls_port: Option<NonZeroU32>,
+    },
+    /// A socket opened via libvirt for the graphics device with this index.
+    Fd(u32),
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct LibvirtGraphicsCreds {
+    pub(crate) endpoint: GraphicsEndpoint,
+    pub(crate) password: Option<SecureString>,
+}
+
+#[derive(Debug, Default, Clone)]
+pub(crate) struct LibvirtGraphics {
+    pub(crate) spice: Option<LibvirtGraphicsCreds>,
+    pub(crate) vnc: Option<LibvirtGraphicsCreds>,
+    pub(crate) rdp: Option<LibvirtGraphicsCreds>,
+}
+
+impl LibvirtGraphics {
+    /// Resolves the graphics devices of a domain. `hostname` is the host of the libvirt
+    /// connection, `localhost` for local connections. `passes_fds` is whether libvirt can
+    /// open sockets to graphics for us, see `Hypervisor::passes_fds`.
+    pub(crate) fn resolve(
+        hostname: &str,
+        passes_fds: bool,
+        devices: &[LibvirtXmlGraphics],
+    ) -> Self {
+        let mut graphics = Self::default();
+        // virDomainOpenGraphicsFD counts all graphics devices, including unsupported ones.
+        for (index, device) in devices.iter().enumerate() {
+            let (out, settings, can_open_fd) = match device {
+                LibvirtXmlGraphics::Vnc(settings) => (&mut graphics.vnc, settings, passes_fds),
+                LibvirtXmlGraphics::Spice(settings) => (&mut graphics.spice, settings, passes_fds),
+                LibvirtXmlGraphics::Rdp(settings) => (&mut graphics.rdp, settings, false),
+                LibvirtXmlGraphics::Other => continue,
+            };
+            match settings.endpoint(hostname, index as u32, can_open_fd) {
+                Ok(endpoint) => {
+                    *out = Some(LibvirtGraphicsCreds {
+                        endpoint,
+                        password: settings.passwd.as_ref().map(SecureString::from),
+                    })
+                }
+                Err(err) => info!("skipping graphics device {index}: {err}"),
+            }
+        }
+        graphics
+    }
+}
+
+impl LibvirtXmlGraphicsSettings {
+    fn endpoint(
+        &self,
+        hostname: &str,
+        index: u32,
+        can_open_fd: bool,
+    ) -> Result<GraphicsEndpoint, String> {
+        let is_local = hostname == "localhost";
+        let fd = || {
+            if can_open_fd {
+                Ok(GraphicsEndpoint::Fd(index))
+            } else {
+                Err(
+                    "it has no network listener and libvirt can not pass a socket to it over \
+                     this connection, only over local UNIX socket connections"
+                        .to_string(),
+                )
+            }
+        };
+
+        // The listen elements supersede the attributes, libvirt only supports one.
+        let address = match self.listens.first() {
+            Some(listen) => match listen.kind.as_deref() {
+                Some("socket") | Some("none") => return fd(),
+                _ => listen.address.as_deref(),
+            },
+            None if self.socket.is_some() => return fd(),
+            None => self.listen.as_deref(),
+        };
+
+        let port = positive_port(self.port);
+        let tls_port = positive_port(self.tls_port);
+        if port.is_none() && tls_port.is_none() {
+            return Err("port missing".to_string());
+        }
+        let tcp = |host| GraphicsEndpoint::Tcp {
+            host,
+            port,
+            tls_port,
+        };
+
+        let host = match address.map(str::trim).filter(|a| !a.is_empty()) {
+            None => hostname.to_string(),
+            Some(address) => match address.parse::<IpAddr>() {
+                Ok(ip) if ip.is_unspecified() => hostname.to_string(),
+                // Loopback listeners of remote hosts are only reachable through libvirt. If
+                // it can't pass sockets, try the host, it may forward the port.
+                Ok(ip) if ip.is_loopback() && !is_local => {
+                    return fd().or_else(|_| Ok(tcp(hostname.to_string())));
+                }
+                _ if address == "localhost" && !is_local => {
+                    return fd().or_else(|_| Ok(tcp(hostname.to_string())));
+                }
+                _ => address.to_string(),
+            },
+        };
+        Ok(tcp(host))
+    }
+}
+
+fn positive_port(port: Option<i64>) -> Option<NonZeroU32> {
+    port.and_then(|port| u32::try_from(port).ok())
+        .and_then(|port| NonZeroU32::try_from(port).ok())
+}
+
+/// The host part of a URI, with IPv6 addresses in brackets.
+fn uri_host(host: &str) -> String {
+    if host.contains(':') {
+        format!("[{host}]")
+    } else {
+        host.to_string()
+    }
+}
+
+/// Opens sockets to the graphics device with the given index of the domain.
+///
+/// libvirt passes the socket over its own connection. This only works if the daemon is
+/// reached through a local UNIX socket, `LibvirtGraphics::resolve` only uses this then.
+fn graphics_fd_source(domain: VirtArc<Domain>, index: u32) -> FdSource {
+    FdSource::new(move || {
+        let domain = domain.clone();
+        async move {
+            run_in_thread(move || {
+                // SAFETY: The domain is kept alive by the closure. On success libvirt
+                // transfers ownership of the returned file descriptor.
+                let fd = unsafe {
+                    virDomainOpenGraphicsFD(
+                        domain.as_ptr(),
+                        index as c_uint,
+                        VIR_DOMAIN_OPEN_GRAPHICS_SKIPAUTH as c_uint,
+                    )
+                };
+                if fd < 0 {
+                    let err = virt::error::Error::last_error();
+                    return Err(anyhow!("failed to open graphics socket: {err}"));
+                }
+                // SAFETY: See above.
+                Ok(unsafe { OwnedFd::from_raw_fd(fd) })
+            })
+            .await
+            .map_err(|err| anyhow!("{err}"))?
+        }
+        .boxed_local()
+    })
+}
+
+impl LibvirtGraphicsCreds {
+    pub(crate) fn spice_adapter(
+        self,
+        domain: VirtArc<Domain>,
+        ca: Option<String>,
+        is_local: bool,
+    ) -> SpiceAdapter {
+        let mut config = SpiceSessionConfigBuilder::default();
+        config.password(Some(self.password.unwrap_or_else(|| "".into())));
+        match self.endpoint {
+            GraphicsEndpoint::Fd(index) => {
+                config.fd_source(Some(graphics_fd_source(domain, index)));
+            }
+            GraphicsEndpoint::Tcp {
+                host,
+                port,
+                tls_port,
+            } => {
+                let mut uri = format!("spice://{}", uri_host(&host));
+                let mut params = Vec::with_capacity(2);
+                if let Some(port) = port {
+                    params.push(format!("port={port}"));
+                }
+                if let Some(tls_port) = tls_port {
+                    params.push(format!("tls-port={tls_port}"));
+                    config.ca(ca_for(ca, is_local, LOCAL_SPICE_CA).map(String::into_bytes));
+                }
+                uri.push('?');
+                uri.push_str(&params.join("&"));
+                config.uri(Some(uri));
+            }
+        }
+        SpiceAdapter::new_with_custom_config(config.build().unwrap())
+    }
+
+    pub(crate) fn vnc_adapter(
+        self,
+        domain: VirtArc<Domain>,
+        ca: Option<String>,
+        is_local: bool,
+    ) -> VncAdapter {
+        let password = self.password.unwrap_or_else(|| "".into());
+        let ca = ca_for(ca, is_local, LOCAL_VNC_CA);
+        match self.endpoint {
+            GraphicsEndpoint::Fd(index) => VncAdapter::new_with_fd_source(
+                graphics_fd_source(domain, index),
+                String::new(),
+                password,
+                ca,
+            ),
+            // VNC negotiates TLS on its single port.
+            GraphicsEndpoint::Tcp { host, port, .. } => {
+                let port = port.map(u32::from).unwrap_or_default();
+                match ca {
+                    Some(ca) => VncAdapter::new_with_ca(host, port, String::new(), password, ca),
+                    None => VncAdapter::new(host, port, String::new(), password),
+                }
+            }
+        }
+    }
+
+    pub(crate) fn rdp_adapter(self) -> Option<RdpAdapter> {
+        match self.endpoint {
+            GraphicsEndpoint::Tcp {
+                host,
+                port: Some(port),
+                ..
+            } => Some(RdpAdapter::new(
+                host,
+                port.into(),
+                String::new(),
+                self.password.unwrap_or_else(|| "".into()),
+            )),
+            _ => None,
+        }
+    }
+}
+
+/// The configured CA, or the default CA of libvirt for local connections.
+fn ca_for(ca: Option<String>, is_local: bool, local_ca: &str) -> Option<String> {
+    ca.or_else(|| {
+        is_local
+            .then(|| std::fs::read_to_string(local_ca).ok())
+            .flatten()
+    })
+}
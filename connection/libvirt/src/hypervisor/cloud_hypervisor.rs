@@ -0,0 +1,52 @@
+/* Copyright 2024 Marco Köpcke
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ *
+ * SPDX-License-Identifier: GPL-3.0-or-later
+ */
+//! Cloud Hypervisor virtual machines.
+
+use gettextrs::gettext;
+use libfieldmonitor::connection::*;
+
+use crate::hypervisor::{Hypervisor, LibvirtConnectionProvider};
+
+pub struct LibvirtCloudHypervisorConnectionProviderConstructor;
+
+impl ConnectionProviderConstructor for LibvirtCloudHypervisorConnectionProviderConstructor {
+    fn new(&self) -> Box<dyn ConnectionProvider> {
+        Box::new(LibvirtConnectionProvider::<CloudHypervisor>::new())
+    }
+}
+
+pub(crate) struct CloudHypervisor;
+
+impl Hypervisor for CloudHypervisor {
+    const TAG: &'static str = "libvirt-ch";
+    const ICON: &'static str = "network-server-symbolic";
+    const SCHEME: &'static str = "ch";
+    const HAS_USER_SESSION: bool = true;
+
+    fn title() -> String {
+        gettext("Cloud Hypervisor")
+    }
+
+    fn add_title() -> String {
+        gettext("Add Cloud Hypervisor Connection")
+    }
+
+    fn description() -> String {
+        gettext("Cloud Hypervisor connection via libvirt")
+    }
+}
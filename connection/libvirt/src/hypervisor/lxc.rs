@@ -0,0 +1,51 @@
+/* Copyright 2024 Marco Köpcke
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ *
+ * SPDX-License-Identifier: GPL-3.0-or-later
+ */
+//! Linux containers managed by the LXC driver of libvirt.
+
+use gettextrs::gettext;
+use libfieldmonitor::connection::*;
+
+use crate::hypervisor::{Hypervisor, LibvirtConnectionProvider};
+
+pub struct LibvirtLxcConnectionProviderConstructor;
+
+impl ConnectionProviderConstructor for LibvirtLxcConnectionProviderConstructor {
+    fn new(&self) -> Box<dyn ConnectionProvider> {
+        Box::new(LibvirtConnectionProvider::<Lxc>::new())
+    }
+}
+
+pub(crate) struct Lxc;
+
+impl Hypervisor for Lxc {
+    const TAG: &'static str = "libvirt-lxc";
+    const ICON: &'static str = "container-symbolic";
+    const SCHEME: &'static str = "lxc";
+
+    fn title() -> String {
+        gettext("LXC")
+    }
+
+    fn add_title() -> String {
+        gettext("Add LXC Connection")
+    }
+
+    fn description() -> String {
+        gettext("LXC container connection via libvirt")
+    }
+}
@@ -15,7 +15,350 @@
  *
  * SPDX-License-Identifier: GPL-3.0-or-later
  */
+//! Connection providers for the hypervisor drivers of libvirt.
+//!
+//! The providers only differ in how the libvirt URI is built and which settings they
+//! offer. Which adapters are available for a server is decided by the domain XML,
+//! independent of the hypervisor.
 
+use std::borrow::Cow;
+use std::marker::PhantomData;
+
+use adw::prelude::*;
+use futures::future::LocalBoxFuture;
+use libfieldmonitor::connection::*;
+use libfieldmonitor::i18n::gettext_f;
+
+pub use cloud_hypervisor::*;
+pub use lxc::*;
 pub use qemu::*;
+pub use test_driver::*;
+pub use uri::*;
+pub use xen::*;
+
+use crate::auth::Credentials;
+use crate::connection::{AdapterOptions, LibvirtConnection};
+use crate::credential_preferences::LibvirtCredentialPreferences;
+use crate::preferences::{LibvirtConfiguration, LibvirtPreferences, RemoteTransport};
+use crate::tls;
 
+mod cloud_hypervisor;
+mod lxc;
 mod qemu;
+mod test_driver;
+mod uri;
+mod xen;
+
+/// The transport and host part of remote URIs, up to the path.
+fn remote_uri_authority(configuration: &ConnectionConfiguration) -> String {
+    let (transport, user) = match configuration.remote_transport() {
+        RemoteTransport::Ssh => (
+            if configuration.ssh_use_libssh() {
+                "+libssh"
+            } else {
+                "+ssh"
+            },
+            configuration.ssh_username(),
+        ),
+        RemoteTransport::Tls => ("+tls", ""),
+        RemoteTransport::Tcp => ("+tcp", ""),
+    };
+    let user = if user.is_empty() {
+        String::new()
+    } else {
+        format!("{user}@")
+    };
+    let port = configuration
+        .ssh_port()
+        .map(|port| format!(":{port}"))
+        .unwrap_or_default();
+    format!(
+        "{transport}://{user}{}{port}/",
+        configuration.ssh_hostname()
+    )
+}
+
+/// The query of remote URIs, including the leading `?`.
+fn remote_uri_params(configuration: &ConnectionConfiguration) -> String {
+    let mut params = Vec::new();
+    match configuration.remote_transport() {
+        RemoteTransport::Ssh => {
+            if configuration.ssh_use_libssh() {
+                if !configuration.ssh_known_hosts().is_empty() {
+                    params.push(("known_hosts", configuration.ssh_known_hosts().into()));
+                }
+                if !configuration.ssh_auth_methods().is_empty() {
+                    params.push(("sshauth", configuration.ssh_auth_methods().into()));
+                }
+            } else {
+                params.push(("no_tty", "1".into()));
+            }
+            if !configuration.ssh_keyfile().is_empty() {
+                params.push(("keyfile", configuration.ssh_keyfile().into()));
+            }
+            if !configuration.ssh_verify_host_key() {
+                params.push(("no_verify", "1".into()));
+            }
+        }
+        RemoteTransport::Tls => {
+            if has_pki_files(configuration) {
+                let pki_dir = tls::pki_dir(configuration.id());
+                params.push(("pkipath", pki_dir.to_string_lossy().into_owned().into()));
+            }
+            if !configuration.tls_verify_certificate() {
+                params.push(("no_verify", "1".into()));
+            }
+        }
+        RemoteTransport::Tcp => {}
+    }
+    uri_query(&params)
+}
+
+/// Whether certificates for TLS are configured. Otherwise the default locations of libvirt
+/// are used.
+fn has_pki_files(configuration: &ConnectionConfiguration) -> bool {
+    !configuration.tls_ca_cert().is_empty() || !configuration.tls_client_cert().is_empty()
+}
+
+/// A URI query with the values escaped, or an empty string if there are no parameters.
+fn uri_query(params: &[(&str, Cow<str>)]) -> String {
+    if params.is_empty() {
+        return String::new();
+    }
+    let params = params
+        .iter()
+        .map(|(key, value)| format!("{key}={}", uri_escape(value)))
+        .collect::<Vec<_>>();
+    format!("?{}", params.join("&"))
+}
+
+fn uri_escape(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for byte in value.bytes() {
+        if byte.is_ascii_alphanumeric() || b"-._~/,".contains(&byte) {
+            escaped.push(byte as char);
+        } else {
+            escaped.push_str(&format!("%{byte:02X}"));
+        }
+    }
+    escaped
+}
+
+/// A hypervisor driver of libvirt.
+pub(crate) trait Hypervisor: 'static {
+    const TAG: &'static str;
+    const ICON: &'static str;
+    /// Scheme of the URIs of the driver.
+    const SCHEME: &'static str;
+    /// Whether the driver has per-user session instances next to the system instance.
+    const HAS_USER_SESSION: bool = false;
+    /// Whether the driver can be used on remote hosts.
+    const HAS_REMOTE: bool = true;
+    /// Whether the URI is entered by the user.
+    const HAS_CUSTOM_URI: bool = false;
+
+    fn title() -> String;
+    fn add_title() -> String;
+    fn description() -> String;
+
+    fn build_uri(configuration: &ConnectionConfiguration) -> String {
+        let instance = if Self::HAS_USER_SESSION && configuration.user_session() {
+            "session"
+        } else {
+            "system"
+        };
+
+        if Self::HAS_REMOTE && configuration.use_ssh() {
+            format!(
+                "{}{}{instance}{}",
+                Self::SCHEME,
+                remote_uri_authority(configuration),
+                remote_uri_params(configuration)
+            )
+        } else {
+            format!("{}:///{instance}", Self::SCHEME)
+        }
+    }
+
+    /// The host adapters connect to.
+    fn hostname(configuration: &ConnectionConfiguration) -> String {
+        if Self::HAS_REMOTE && configuration.use_ssh() {
+            configuration.ssh_hostname().to_string()
+        } else {
+            "localhost".to_string()
+        }
+    }
+
+    /// Whether libvirt can pass sockets to us over the connection. This is only the case
+    /// if the daemon is reached through a local UNIX socket.
+    fn passes_fds(configuration: &ConnectionConfiguration) -> bool {
+        !(Self::HAS_REMOTE && configuration.use_ssh())
+    }
+}
+
+pub(crate) struct LibvirtConnectionProvider<H>(PhantomData<H>);
+
+impl<H: Hypervisor> LibvirtConnectionProvider<H> {
+    pub(crate) fn new() -> Self {
+        Self(PhantomData)
+    }
+
+    fn make_preferences(configuration: Option<&ConnectionConfiguration>) -> LibvirtPreferences {
+        LibvirtPreferences::new(
+            configuration,
+            H::HAS_USER_SESSION,
+            H::HAS_REMOTE,
+            H::HAS_CUSTOM_URI,
+        )
+    }
+}
+
+impl<H: Hypervisor> ConnectionProvider for LibvirtConnectionProvider<H> {
+    fn tag(&self) -> &'static str {
+        H::TAG
+    }
+
+    fn title(&self) -> Cow<'static, str> {
+        H::title().into()
+    }
+
+    fn title_plural(&self) -> Cow<str> {
+        H::title().into()
+    }
+
+    fn add_title(&self) -> Cow<str> {
+        H::add_title().into()
+    }
+
+    fn title_for<'a>(&self, config: &'a ConnectionConfiguration) -> Option<&'a str> {
+        config.title()
+    }
+
+    fn description(&self) -> Cow<str> {
+        H::description().into()
+    }
+
+    fn icon(&self) -> IconSpec<()> {
+        IconSpec::Named(H::ICON.into())
+    }
+
+    fn preferences(&self, configuration: Option<&ConnectionConfiguration>) -> gtk::Widget {
+        Self::make_preferences(configuration).upcast()
+    }
+
+    fn update_connection(
+        &self,
+        preferences: gtk::Widget,
+        mut configuration: DualScopedConnectionConfiguration,
+    ) -> LocalBoxFuture<anyhow::Result<DualScopedConnectionConfiguration>> {
+        Box::pin(async move {
+            let preferences = preferences
+                .downcast::<LibvirtPreferences>()
+                .expect("update_connection got invalid widget type");
+
+            // Update general config
+            configuration = configuration
+                .transform_update_unified(|config| preferences.apply_general_config(config))?;
+
+            // Update credentials
+            let credentials = preferences.credentials();
+            self.store_credentials(&[], credentials.clone().upcast(), configuration)
+                .await
+        })
+    }
+
+    fn configure_credentials(
+        &self,
+        _server_path: &[String],
+        configuration: &ConnectionConfiguration,
+    ) -> PreferencesGroupOrPage {
+        let credentials = LibvirtCredentialPreferences::new(Some(configuration), true);
+        credentials.set_transport((!H::HAS_CUSTOM_URI).then_some(configuration.remote_transport()));
+        PreferencesGroupOrPage::Group(credentials.upcast())
+    }
+
+    fn store_credentials(
+        &self,
+        _server_path: &[String],
+        preferences: gtk::Widget,
+        configuration: DualScopedConnectionConfiguration,
+    ) -> LocalBoxFuture<anyhow::Result<DualScopedConnectionConfiguration>> {
+        Box::pin(async move {
+            let preferences = preferences
+                .downcast::<LibvirtCredentialPreferences>()
+                .expect("store_credentials got invalid widget type");
+
+            configuration.transform_update_separate(
+                |c_session| preferences.apply_persistent_config(c_session),
+                |c_persistent| preferences.apply_session_config(c_persistent),
+            )
+        })
+    }
+
+    fn load_connection(
+        &self,
+        configuration: ConnectionConfiguration,
+    ) -> LocalBoxFuture<ConnectionResult<Box<dyn Connection>>> {
+        Box::pin(async move {
+            let remote = H::HAS_REMOTE && configuration.use_ssh();
+            let transport = configuration.remote_transport();
+            let credentials = if remote && transport == RemoteTransport::Ssh {
+                Credentials {
+                    username: None,
+                    password: configuration.ssh_passphrase().await.map_err(secret_err)?,
+                }
+            } else {
+                Credentials {
+                    username: Some(configuration.sasl_username())
+                        .filter(|v| !v.is_empty())
+                        .map(ToString::to_string),
+                    password: configuration.sasl_password().await.map_err(secret_err)?,
+                }
+            };
+            let tls_ca =
+                if remote && transport == RemoteTransport::Tls && has_pki_files(&configuration) {
+                    let client_key = configuration.tls_client_key().await.map_err(secret_err)?;
+                    tls::write_pki_dir(
+                        configuration.id(),
+                        configuration.tls_ca_cert(),
+                        configuration.tls_client_cert(),
+                        client_key.as_ref(),
+                    )
+                    // Graphics with TLS are usually signed by the same CA.
+                    .and_then(|()| tls::read_pem(configuration.tls_ca_cert()))
+                    .map_err(|err| {
+                        ConnectionError::General(
+                            Some(gettext_f(
+                                "Failed to load the TLS certificates: {err}",
+                                &[("err", &err.to_string())],
+                            )),
+                            err.into(),
+                        )
+                    })?
+                } else {
+                    None
+                };
+            let conn: Box<dyn Connection> = Box::new(
+                LibvirtConnection::new(
+                    configuration.id(),
+                    &H::hostname(&configuration),
+                    &H::build_uri(&configuration),
+                    configuration.title().unwrap_or_default(),
+                    H::ICON.into(),
+                    credentials,
+                    AdapterOptions {
+                        tls_ca,
+                        console_force: configuration.console_force(),
+                        passes_fds: H::passes_fds(&configuration),
+                    },
+                )
+                .await?,
+            );
+            Ok(conn)
+        })
+    }
+}
+
+fn secret_err(err: anyhow::Error) -> ConnectionError {
+    ConnectionError::General(None, err)
+}
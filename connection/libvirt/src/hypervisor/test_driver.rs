@@ -0,0 +1,60 @@
+/* Copyright 2024 Marco Köpcke
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ *
+ * SPDX-License-Identifier: GPL-3.0-or-later
+ */
+//! The test driver of libvirt.
+//!
+//! It simulates a hypervisor with a single running domain, without touching the system.
+//! Useful for demos and CI. Changes are not persisted and every connection starts from the
+//! same state.
+
+use gettextrs::gettext;
+use libfieldmonitor::connection::*;
+
+use crate::hypervisor::{Hypervisor, LibvirtConnectionProvider};
+
+pub struct LibvirtTestConnectionProviderConstructor;
+
+impl ConnectionProviderConstructor for LibvirtTestConnectionProviderConstructor {
+    fn new(&self) -> Box<dyn ConnectionProvider> {
+        Box::new(LibvirtConnectionProvider::<TestDriver>::new())
+    }
+}
+
+pub(crate) struct TestDriver;
+
+impl Hypervisor for TestDriver {
+    const TAG: &'static str = "libvirt-test";
+    const ICON: &'static str = "bug-symbolic";
+    const SCHEME: &'static str = "test";
+    const HAS_REMOTE: bool = false;
+
+    fn title() -> String {
+        gettext("libvirt Test Driver")
+    }
+
+    fn add_title() -> String {
+        gettext("Add libvirt Test Driver Connection")
+    }
+
+    fn description() -> String {
+        gettext("Simulated hypervisor for demonstrations and testing")
+    }
+
+    fn build_uri(_configuration: &ConnectionConfiguration) -> String {
+        format!("{}:///default", Self::SCHEME)
+    }
+}
@@ -0,0 +1,103 @@
+/* Copyright 2024 Marco Köpcke
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ *
+ * SPDX-License-Identifier: GPL-3.0-or-later
+ */
+//! Connections to any libvirt URI.
+//!
+//! For drivers and transports without a dedicated provider, eg. TLS or TCP connections.
+
+use gettextrs::gettext;
+use libfieldmonitor::connection::*;
+
+use crate::hypervisor::{Hypervisor, LibvirtConnectionProvider};
+use crate::preferences::LibvirtConfiguration;
+
+pub struct LibvirtUriConnectionProviderConstructor;
+
+impl ConnectionProviderConstructor for LibvirtUriConnectionProviderConstructor {
+    fn new(&self) -> Box<dyn ConnectionProvider> {
+        Box::new(LibvirtConnectionProvider::<CustomUri>::new())
+    }
+}
+
+pub(crate) struct CustomUri;
+
+impl Hypervisor for CustomUri {
+    const TAG: &'static str = "libvirt-uri";
+    const ICON: &'static str = "network-server-symbolic";
+    const SCHEME: &'static str = "";
+    const HAS_REMOTE: bool = false;
+    const HAS_CUSTOM_URI: bool = true;
+
+    fn title() -> String {
+        gettext("libvirt URI")
+    }
+
+    fn add_title() -> String {
+        gettext("Add libvirt Connection")
+    }
+
+    fn description() -> String {
+        gettext("Connection to any libvirt URI")
+    }
+
+    fn build_uri(configuration: &ConnectionConfiguration) -> String {
+        configuration.uri().trim().to_string()
+    }
+
+    fn hostname(configuration: &ConnectionConfiguration) -> String {
+        uri_hostname(configuration.uri())
+    }
+
+    fn passes_fds(configuration: &ConnectionConfiguration) -> bool {
+        uri_is_local_socket(configuration.uri())
+    }
+}
+
+/// Whether a libvirt URI reaches the daemon through a local UNIX socket.
+fn uri_is_local_socket(uri: &str) -> bool {
+    let Some((scheme, rest)) = uri.trim().split_once("://") else {
+        return true;
+    };
+    match scheme.split_once('+') {
+        Some((_, transport)) => transport == "unix",
+        // Without a transport libvirt uses TLS for URIs with a host.
+        None => rest.split(['/', '?']).next().unwrap_or_default().is_empty(),
+    }
+}
+
+/// The host of a libvirt URI, or `localhost` for local URIs.
+fn uri_hostname(uri: &str) -> String {
+    let Some((_, rest)) = uri.trim().split_once("://") else {
+        return "localhost".to_string();
+    };
+    let authority = rest.split(['/', '?']).next().unwrap_or_default();
+    let host = authority
+        .rsplit_once('@')
+        .map_or(authority, |(_, host)| host);
+    let host = if let Some(bracketed) = host.strip_prefix('[') {
+        bracketed
+            .split_once(']')
+            .map_or(bracketed, |(host, _)| host)
+    } else {
+        host.split_once(':').map_or(host, |(host, _)| host)
+    };
+    if host.is_empty() {
+        "localhost".to_string()
+    } else {
+        host.to_string()
+    }
+}
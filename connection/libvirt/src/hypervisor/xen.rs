@@ -0,0 +1,51 @@
+/* Copyright 2024 Marco Köpcke
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ *
+ * SPDX-License-Identifier: GPL-3.0-or-later
+ */
+//! Xen virtual machines, managed by the libxl driver of libvirt.
+
+use gettextrs::gettext;
+use libfieldmonitor::connection::*;
+
+use crate::hypervisor::{Hypervisor, LibvirtConnectionProvider};
+
+pub struct LibvirtXenConnectionProviderConstructor;
+
+impl ConnectionProviderConstructor for LibvirtXenConnectionProviderConstructor {
+    fn new(&self) -> Box<dyn ConnectionProvider> {
+        Box::new(LibvirtConnectionProvider::<Xen>::new())
+    }
+}
+
+pub(crate) struct Xen;
+
+impl Hypervisor for Xen {
+    const TAG: &'static str = "libvirt-xen";
+    const ICON: &'static str = "network-server-symbolic";
+    const SCHEME: &'static str = "xen";
+
+    fn title() -> String {
+        gettext("Xen")
+    }
+
+    fn add_title() -> String {
+        gettext("Add Xen Connection")
+    }
+
+    fn description() -> String {
+        gettext("Xen hypervisor connection via libvirt")
+    }
+}
@@ -17,6 +17,17 @@
  */
 pub use hypervisor::*;
 
+mod addresses;
+mod agent;
+mod auth;
 mod connection;
+mod console;
+mod credential_preferences;
+mod events;
+mod graphics;
 mod hypervisor;
-mod qemu_preferences;
+mod preferences;
+mod snapshot;
+#[cfg(test)]
+mod tests;
+mod tls;
@@ -0,0 +1,628 @@
+/* Copyright 2024 Marco Köpcke
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ *
+ * SPDX-License-Identifier: GPL-3.0-or-later
+ */
+
+use std::cell::Cell;
+use std::cell::RefCell;
+
+use adw::subclass::prelude::*;
+use anyhow::anyhow;
+use futures::future::BoxFuture;
+use glib::clone;
+use gtk::gio::{PropertyAction, SimpleActionGroup};
+use gtk::glib;
+use gtk::prelude::*;
+use num_enum::TryFromPrimitive;
+use secure_string::SecureString;
+
+use libfieldmonitor::connection::{ConfigAccess, ConfigAccessMut, ConnectionConfiguration};
+use libfieldmonitor::impl_simple_macro_param_spec;
+
+use crate::credential_preferences::LibvirtCredentialPreferences;
+
+const SSH_CLIENT_OPENSSH: u32 = 0;
+const SSH_CLIENT_LIBSSH: u32 = 1;
+
+/// How libvirt connects to remote hosts.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum RemoteTransport {
+    #[default]
+    Ssh,
+    Tls,
+    /// Unencrypted, authenticated via SASL.
+    Tcp,
+}
+
+impl RemoteTransport {
+    fn as_str(&self) -> &'static str {
+        match self {
+            RemoteTransport::Ssh => "ssh",
+            RemoteTransport::Tls => "tls",
+            RemoteTransport::Tcp => "tcp",
+        }
+    }
+
+    fn from_str(value: &str) -> Option<Self> {
+        match value {
+            "ssh" => Some(RemoteTransport::Ssh),
+            "tls" => Some(RemoteTransport::Tls),
+            "tcp" => Some(RemoteTransport::Tcp),
+            _ => None,
+        }
+    }
+
+    /// Position in the combo row of the preferences.
+    pub(crate) fn index(&self) -> u32 {
+        match self {
+            RemoteTransport::Ssh => 0,
+            RemoteTransport::Tls => 1,
+            RemoteTransport::Tcp => 2,
+        }
+    }
+
+    pub(crate) fn from_index(index: u32) -> Self {
+        match index {
+            1 => RemoteTransport::Tls,
+            2 => RemoteTransport::Tcp,
+            _ => RemoteTransport::Ssh,
+        }
+    }
+}
+
+pub(super) trait LibvirtConfiguration {
+    fn title(&self) -> Option<&str>;
+    fn set_title(&mut self, value: &str);
+    fn user_session(&self) -> bool;
+    fn set_user_session(&mut self, value: bool);
+    fn use_ssh(&self) -> bool;
+    fn set_use_ssh(&mut self, value: bool);
+    fn ssh_username(&self) -> &str;
+    fn set_ssh_username(&mut self, value: &str);
+    fn ssh_hostname(&self) -> &str;
+    fn set_ssh_hostname(&mut self, value: &str);
+    fn ssh_port(&self) -> Option<u16>;
+    fn set_ssh_port(&mut self, value: u16);
+    fn ssh_keyfile(&self) -> &str;
+    fn set_ssh_keyfile(&mut self, value: &str);
+    fn ssh_known_hosts(&self) -> &str;
+    fn set_ssh_known_hosts(&mut self, value: &str);
+    fn ssh_verify_host_key(&self) -> bool;
+    fn set_ssh_verify_host_key(&mut self, value: bool);
+    /// Use the libssh client built into libvirt instead of the OpenSSH binary.
+    fn ssh_use_libssh(&self) -> bool;
+    fn set_ssh_use_libssh(&mut self, value: bool);
+    /// Comma-separated authentication methods for libssh.
+    fn ssh_auth_methods(&self) -> &str;
+    fn set_ssh_auth_methods(&mut self, value: &str);
+    fn ssh_passphrase(&self) -> BoxFuture<anyhow::Result<Option<SecureString>>>;
+    fn set_ssh_passphrase(&mut self, value: Option<SecureString>);
+    fn set_ssh_passphrase_session(&mut self, value: Option<SecureString>);
+    fn remote_transport(&self) -> RemoteTransport;
+    fn set_remote_transport(&mut self, value: RemoteTransport);
+    /// Path or PEM of the CA certificate for TLS.
+    fn tls_ca_cert(&self) -> &str;
+    fn set_tls_ca_cert(&mut self, value: &str);
+    /// Path or PEM of the client certificate for TLS.
+    fn tls_client_cert(&self) -> &str;
+    fn set_tls_client_cert(&mut self, value: &str);
+    /// Path or PEM of the client key for TLS.
+    fn tls_client_key(&self) -> BoxFuture<anyhow::Result<Option<SecureString>>>;
+    fn set_tls_client_key(&mut self, value: Option<SecureString>);
+    fn tls_verify_certificate(&self) -> bool;
+    fn set_tls_verify_certificate(&mut self, value: bool);
+    fn sasl_username(&self) -> &str;
+    fn set_sasl_username(&mut self, value: &str);
+    fn sasl_password(&self) -> BoxFuture<anyhow::Result<Option<SecureString>>>;
+    fn set_sasl_password(&mut self, value: Option<SecureString>);
+    fn set_sasl_password_session(&mut self, value: Option<SecureString>);
+    fn uri(&self) -> &str;
+    fn set_uri(&mut self, value: &str);
+    /// Take over consoles that other clients have open, instead of failing to open them.
+    fn console_force(&self) -> bool;
+    fn set_console_force(&mut self, value: bool);
+}
+
+impl LibvirtConfiguration for ConnectionConfiguration {
+    fn title(&self) -> Option<&str> {
+        self.get_try_as_str("title")
+    }
+
+    fn set_title(&mut self, value: &str) {
+        self.set_value("title", value);
+    }
+
+    fn user_session(&self) -> bool {
+        self.get_try_as_bool("user-session").unwrap_or_default()
+    }
+
+    fn set_user_session(&mut self, value: bool) {
+        self.set_value("user-session", value)
+    }
+
+    fn use_ssh(&self) -> bool {
+        self.get_try_as_bool("use-ssh").unwrap_or_default()
+    }
+
+    fn set_use_ssh(&mut self, value: bool) {
+        self.set_value("use-ssh", value)
+    }
+
+    fn ssh_username(&self) -> &str {
+        self.get_try_as_str("ssh-username").unwrap_or_default()
+    }
+
+    fn set_ssh_username(&mut self, value: &str) {
+        self.set_value("ssh-username", value);
+    }
+
+    fn ssh_hostname(&self) -> &str {
+        self.get_try_as_str("ssh-hostname").unwrap_or_default()
+    }
+
+    fn set_ssh_hostname(&mut self, value: &str) {
+        self.set_value("ssh-hostname", value);
+    }
+
+    fn ssh_port(&self) -> Option<u16> {
+        self.get_try_as_u64("ssh-port")
+            .and_then(|v| u16::try_from(v).ok())
+            .filter(|v| *v != 0)
+    }
+
+    fn set_ssh_port(&mut self, value: u16) {
+        self.set_value("ssh-port", value);
+    }
+
+    fn ssh_keyfile(&self) -> &str {
+        self.get_try_as_str("ssh-keyfile").unwrap_or_default()
+    }
+
+    fn set_ssh_keyfile(&mut self, value: &str) {
+        self.set_value("ssh-keyfile", value);
+    }
+
+    fn ssh_known_hosts(&self) -> &str {
+        self.get_try_as_str("ssh-known-hosts").unwrap_or_default()
+    }
+
+    fn set_ssh_known_hosts(&mut self, value: &str) {
+        self.set_value("ssh-known-hosts", value);
+    }
+
+    fn ssh_verify_host_key(&self) -> bool {
+        self.get_try_as_bool("ssh-verify-host-key").unwrap_or(true)
+    }
+
+    fn set_ssh_verify_host_key(&mut self, value: bool) {
+        self.set_value("ssh-verify-host-key", value)
+    }
+
+    fn ssh_use_libssh(&self) -> bool {
+        self.get_try_as_bool("ssh-use-libssh").unwrap_or_default()
+    }
+
+    fn set_ssh_use_libssh(&mut self, value: bool) {
+        self.set_value("ssh-use-libssh", value)
+    }
+
+    fn ssh_auth_methods(&self) -> &str {
+        self.get_try_as_str("ssh-auth-methods").unwrap_or_default()
+    }
+
+    fn set_ssh_auth_methods(&mut self, value: &str) {
+        self.set_value("ssh-auth-methods", value);
+    }
+
+    fn ssh_passphrase(&self) -> BoxFuture<anyhow::Result<Option<SecureString>>> {
+        Box::pin(async move {
+            if let Some(pw) = self.get_try_as_sec_string("__session__ssh-passphrase") {
+                return Ok(Some(pw));
+            }
+            self.get_secret("ssh-passphrase").await
+        })
+    }
+
+    fn set_ssh_passphrase(&mut self, value: Option<SecureString>) {
+        match value {
+            None => self.clear_secret("ssh-passphrase"),
+            Some(value) => self.set_secret("ssh-passphrase", value),
+        }
+    }
+
+    fn set_ssh_passphrase_session(&mut self, value: Option<SecureString>) {
+        match value {
+            None => {
+                self.clear("__session__ssh-passphrase");
+            }
+            Some(value) => {
+                self.set_secure_string("__session__ssh-passphrase", value.clone());
+            }
+        }
+    }
+
+    fn remote_transport(&self) -> RemoteTransport {
+        self.get_try_as_str("remote-transport")
+            .and_then(RemoteTransport::from_str)
+            .unwrap_or_default()
+    }
+
+    fn set_remote_transport(&mut self, value: RemoteTransport) {
+        self.set_value("remote-transport", value.as_str());
+    }
+
+    fn tls_ca_cert(&self) -> &str {
+        self.get_try_as_str("tls-ca-cert").unwrap_or_default()
+    }
+
+    fn set_tls_ca_cert(&mut self, value: &str) {
+        self.set_value("tls-ca-cert", value);
+    }
+
+    fn tls_client_cert(&self) -> &str {
+        self.get_try_as_str("tls-client-cert").unwrap_or_default()
+    }
+
+    fn set_tls_client_cert(&mut self, value: &str) {
+        self.set_value("tls-client-cert", value);
+    }
+
+    fn tls_client_key(&self) -> BoxFuture<anyhow::Result<Option<SecureString>>> {
+        self.get_secret("tls-client-key")
+    }
+
+    fn set_tls_client_key(&mut self, value: Option<SecureString>) {
+        match value {
+            None => self.clear_secret("tls-client-key"),
+            Some(value) => self.set_secret("tls-client-key", value),
+        }
+    }
+
+    fn tls_verify_certificate(&self) -> bool {
+        self.get_try_as_bool("tls-verify-certificate")
+            .unwrap_or(true)
+    }
+
+    fn set_tls_verify_certificate(&mut self, value: bool) {
+        self.set_value("tls-verify-certificate", value)
+    }
+
+    fn sasl_username(&self) -> &str {
+        self.get_try_as_str("sasl-username").unwrap_or_default()
+    }
+
+    fn set_sasl_username(&mut self, value: &str) {
+        self.set_value("sasl-username", value);
+    }
+
+    fn sasl_password(&self) -> BoxFuture<anyhow::Result<Option<SecureString>>> {
+        Box::pin(async move {
+            if let Some(pw) = self.get_try_as_sec_string("__session__sasl-password") {
+                return Ok(Some(pw));
+            }
+            self.get_secret("sasl-password").await
+        })
+    }
+
+    fn set_sasl_password(&mut self, value: Option<SecureString>) {
+        match value {
+            None => self.clear_secret("sasl-password"),
+            Some(value) => self.set_secret("sasl-password", value),
+        }
+    }
+
+    fn set_sasl_password_session(&mut self, value: Option<SecureString>) {
+        match value {
+            None => {
+                self.clear("__session__sasl-password");
+            }
+            Some(value) => {
+                self.set_secure_string("__session__sasl-password", value.clone());
+            }
+        }
+    }
+
+    fn uri(&self) -> &str {
+        self.get_try_as_str("uri").unwrap_or_default()
+    }
+
+    fn set_uri(&mut self, value: &str) {
+        self.set_value("uri", value);
+    }
+
+    fn console_force(&self) -> bool {
+        self.get_try_as_bool("console-force").unwrap_or(true)
+    }
+
+    fn set_console_force(&mut self, value: bool) {
+        self.set_value("console-force", value)
+    }
+}
+
+#[derive(Copy, Clone, Debug, Default, TryFromPrimitive, Eq, PartialEq)]
+#[repr(u32)]
+pub enum SessionType {
+    #[default]
+    System = 0,
+    User = 1,
+}
+
+impl_simple_macro_param_spec!(SessionType, u32);
+
+mod imp {
+    use super::*;
+
+    #[derive(Debug, Default, gtk::CompositeTemplate, glib::Properties)]
+    #[properties(wrapper_type = super::LibvirtPreferences)]
+    #[template(resource = "/de/capypara/FieldMonitor/connection/libvirt/preferences.ui")]
+    pub struct LibvirtPreferences {
+        #[template_child]
+        pub(super) radio_session_system: TemplateChild<gtk::CheckButton>,
+        #[template_child]
+        pub(super) radio_session_user: TemplateChild<gtk::CheckButton>,
+        #[template_child]
+        pub(super) ssh_port_entry: TemplateChild<adw::EntryRow>,
+        #[template_child]
+        pub(super) ssh_username_entry: TemplateChild<adw::EntryRow>,
+        #[template_child]
+        pub(super) ssh_keyfile_entry: TemplateChild<adw::EntryRow>,
+        #[template_child]
+        pub(super) ssh_verify_host_key_switch: TemplateChild<adw::SwitchRow>,
+        #[template_child]
+        pub(super) ssh_client_combo: TemplateChild<adw::ComboRow>,
+        #[template_child]
+        pub(super) ssh_known_hosts_entry: TemplateChild<adw::EntryRow>,
+        #[template_child]
+        pub(super) ssh_auth_methods_entry: TemplateChild<adw::EntryRow>,
+        #[template_child]
+        pub(super) tls_ca_cert_entry: TemplateChild<adw::EntryRow>,
+        #[template_child]
+        pub(super) tls_client_cert_entry: TemplateChild<adw::EntryRow>,
+        #[template_child]
+        pub(super) tls_client_key_entry: TemplateChild<adw::PasswordEntryRow>,
+        #[template_child]
+        pub(super) tls_verify_certificate_switch: TemplateChild<adw::SwitchRow>,
+        #[template_child]
+        pub(super) credentials: TemplateChild<LibvirtCredentialPreferences>,
+
+        #[property(get, set)]
+        pub title: RefCell<String>,
+        #[property(get, set)]
+        pub session_type: Cell<SessionType>,
+        #[property(get, set)]
+        pub use_ssh: Cell<bool>,
+        #[property(get, set)]
+        pub ssh_hostname: RefCell<String>,
+        #[property(get, set)]
+        pub ssh_username: RefCell<String>,
+        #[property(get, set)]
+        pub ssh_port: RefCell<String>,
+        #[property(get, set)]
+        pub ssh_keyfile: RefCell<String>,
+        #[property(get, set)]
+        pub ssh_known_hosts: RefCell<String>,
+        #[property(get, set, default = true)]
+        pub ssh_verify_host_key: Cell<bool>,
+        #[property(get, set, default = true)]
+        pub console_force: Cell<bool>,
+        #[property(get, set)]
+        pub ssh_client: Cell<u32>,
+        #[property(get, set)]
+        pub ssh_auth_methods: RefCell<String>,
+        #[property(get, set)]
+        pub remote_transport: Cell<u32>,
+        #[property(get, set)]
+        pub tls_ca_cert: RefCell<String>,
+        #[property(get, set)]
+        pub tls_client_cert: RefCell<String>,
+        #[property(get, set)]
+        pub tls_client_key: RefCell<String>,
+        #[property(get, set, default = true)]
+        pub tls_verify_certificate: Cell<bool>,
+        #[property(get, set)]
+        pub uri: RefCell<String>,
+        #[property(get, set, construct_only)]
+        pub show_session_type: Cell<bool>,
+        #[property(get, set, construct_only)]
+        pub show_remote: Cell<bool>,
+        #[property(get, set, construct_only)]
+        pub show_uri: Cell<bool>,
+    }
+
+    #[glib::object_subclass]
+    impl ObjectSubclass for LibvirtPreferences {
+        const NAME: &'static str = "LibvirtPreferences";
+        type Type = super::LibvirtPreferences;
+        type ParentType = adw::PreferencesPage;
+
+        fn class_init(klass: &mut Self::Class) {
+            Self::bind_template(klass);
+            Self::Type::bind_template_callbacks(klass);
+        }
+
+        fn instance_init(obj: &glib::subclass::InitializingObject<Self>) {
+            obj.init_template();
+        }
+    }
+
+    #[glib::derived_properties]
+    impl ObjectImpl for LibvirtPreferences {}
+    impl WidgetImpl for LibvirtPreferences {}
+    impl PreferencesPageImpl for LibvirtPreferences {}
+}
+
+glib::wrapper! {
+    pub struct LibvirtPreferences(ObjectSubclass<imp::LibvirtPreferences>)
+        @extends gtk::Widget, adw::PreferencesPage;
+}
+
+impl LibvirtPreferences {
+    /// Preferences for a connection. Only the groups for the settings the hypervisor uses
+    /// are shown.
+    pub fn new(
+        config: Option<&ConnectionConfiguration>,
+        show_session_type: bool,
+        show_remote: bool,
+        show_uri: bool,
+    ) -> Self {
+        let slf: Self = glib::Object::builder()
+            .property("show-session-type", show_session_type)
+            .property("show-remote", show_remote)
+            .property("show-uri", show_uri)
+            .build();
+
+        let imp = slf.imp();
+        let action = PropertyAction::new("session-type", &slf, "session-type");
+        let act_grp = SimpleActionGroup::new();
+        act_grp.add_action(&action);
+        slf.insert_action_group("libvirt-preferences", Some(&act_grp));
+
+        imp.radio_session_system
+            .set_action_name(Some("libvirt-preferences.session-type"));
+        imp.radio_session_system
+            .set_action_target(Some(&0_u32.to_variant()));
+        imp.radio_session_user
+            .set_action_name(Some("libvirt-preferences.session-type"));
+        imp.radio_session_user
+            .set_action_target(Some(&1_u32.to_variant()));
+
+        imp.credentials.set_visible(show_remote || show_uri);
+        if show_uri {
+            imp.credentials.set_transport(None);
+        }
+
+        if let Some(config) = config {
+            if let Some(title) = config.title() {
+                slf.set_title(title);
+            }
+            slf.set_session_type(if config.user_session() {
+                SessionType::User
+            } else {
+                SessionType::System
+            });
+            slf.set_use_ssh(config.use_ssh());
+            slf.set_ssh_hostname(config.ssh_hostname());
+            slf.set_ssh_username(config.ssh_username());
+            slf.set_ssh_port(
+                config
+                    .ssh_port()
+                    .as_ref()
+                    .map(ToString::to_string)
+                    .unwrap_or_default(),
+            );
+            slf.set_ssh_keyfile(config.ssh_keyfile());
+            slf.set_ssh_known_hosts(config.ssh_known_hosts());
+            slf.set_ssh_verify_host_key(config.ssh_verify_host_key());
+            slf.set_ssh_client(if config.ssh_use_libssh() {
+                SSH_CLIENT_LIBSSH
+            } else {
+                SSH_CLIENT_OPENSSH
+            });
+            slf.set_ssh_auth_methods(config.ssh_auth_methods());
+            slf.set_remote_transport(config.remote_transport().index());
+            slf.set_tls_ca_cert(config.tls_ca_cert());
+            slf.set_tls_client_cert(config.tls_client_cert());
+            slf.set_tls_verify_certificate(config.tls_verify_certificate());
+            slf.set_uri(config.uri());
+            slf.set_console_force(config.console_force());
+
+            let config = config.clone();
+            glib::spawn_future_local(clone!(
+                #[weak]
+                slf,
+                async move {
+                    if let Ok(Some(v)) = config.tls_client_key().await {
+                        slf.set_tls_client_key(v.unsecure());
+                    }
+                    slf.imp().credentials.propagate_settings(&config).await;
+                }
+            ));
+        }
+
+        slf
+    }
+
+    pub fn apply_general_config(
+        &self,
+        config: &mut ConnectionConfiguration,
+    ) -> Result<(), anyhow::Error> {
+        config.set_title(&self.title());
+        config.set_user_session(self.session_type() == SessionType::User);
+        config.set_use_ssh(self.use_ssh());
+        config.set_ssh_hostname(&self.ssh_hostname());
+        config.set_ssh_username(&self.ssh_username());
+        let ssh_port = self.ssh_port();
+        let ssh_port = ssh_port.trim();
+        let Some(ssh_port) = (if ssh_port.is_empty() {
+            Some(0)
+        } else {
+            ssh_port.parse::<u16>().ok().filter(|v| *v != 0)
+        }) else {
+            self.imp().ssh_port_entry.add_css_class("error");
+            return Err(anyhow!("invalid ssh port"));
+        };
+        self.imp().ssh_port_entry.remove_css_class("error");
+        config.set_ssh_port(ssh_port);
+        config.set_ssh_keyfile(self.ssh_keyfile().trim());
+        config.set_ssh_known_hosts(self.ssh_known_hosts().trim());
+        config.set_ssh_verify_host_key(self.ssh_verify_host_key());
+        config.set_ssh_use_libssh(self.ssh_client() == SSH_CLIENT_LIBSSH);
+        config.set_ssh_auth_methods(self.ssh_auth_methods().trim());
+        config.set_remote_transport(RemoteTransport::from_index(self.remote_transport()));
+        config.set_tls_ca_cert(self.tls_ca_cert().trim());
+        config.set_tls_client_cert(self.tls_client_cert().trim());
+        let tls_client_key = self.tls_client_key();
+        let tls_client_key = tls_client_key.trim();
+        config.set_tls_client_key(
+            Some(tls_client_key)
+                .filter(|v| !v.is_empty())
+                .map(SecureString::from),
+        );
+        config.set_tls_verify_certificate(self.tls_verify_certificate());
+        config.set_uri(&self.uri());
+        config.set_console_force(self.console_force());
+        Ok(())
+    }
+
+    pub fn credentials(&self) -> &LibvirtCredentialPreferences {
+        &self.imp().credentials
+    }
+}
+
+#[gtk::template_callbacks]
+impl LibvirtPreferences {
+    #[template_callback]
+    fn on_remote_settings_changed(&self) {
+        let imp = self.imp();
+        let transport = RemoteTransport::from_index(self.remote_transport());
+        let ssh = transport == RemoteTransport::Ssh;
+        let libssh = ssh && self.ssh_client() == SSH_CLIENT_LIBSSH;
+        let tls = transport == RemoteTransport::Tls;
+
+        imp.ssh_username_entry.set_visible(ssh);
+        imp.ssh_keyfile_entry.set_visible(ssh);
+        imp.ssh_verify_host_key_switch.set_visible(ssh);
+        imp.ssh_client_combo.set_visible(ssh);
+        imp.ssh_known_hosts_entry.set_visible(libssh);
+        imp.ssh_auth_methods_entry.set_visible(libssh);
+        imp.tls_ca_cert_entry.set_visible(tls);
+        imp.tls_client_cert_entry.set_visible(tls);
+        imp.tls_client_key_entry.set_visible(tls);
+        imp.tls_verify_certificate_switch.set_visible(tls);
+        imp.credentials
+            .set_transport((!self.show_uri()).then_some(transport));
+    }
+}
@@ -0,0 +1,506 @@
+/* Copyright 2024 Marco Köpcke
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ *
+ * SPDX-License-Identifier: GPL-3.0-or-later
+ */
+//! Snapshot management actions for domains.
+//!
+//! Snapshots form a tree: every snapshot is based on the snapshot that was current when it
+//! was created.
+
+use std::borrow::Cow;
+
+use adw::prelude::*;
+use gettextrs::gettext;
+use gtk::glib;
+use libfieldmonitor::connection::{ServerAction, RECONNECT_SERVER_ACTION};
+use libfieldmonitor::gtk::{show_toast, FieldMonitorActionParametersDialog, SnapshotPrompt};
+use libfieldmonitor::i18n::gettext_f;
+use log::{error, warn};
+use quick_xml::de::from_str;
+use quick_xml::escape::escape;
+use serde::Deserialize;
+use virt::domain::Domain;
+use virt::domain_snapshot::DomainSnapshot;
+use virt::sys::{
+    VIR_DOMAIN_SNAPSHOT_CREATE_ATOMIC, VIR_DOMAIN_SNAPSHOT_CREATE_DISK_ONLY,
+    VIR_DOMAIN_SNAPSHOT_DELETE_CHILDREN, VIR_DOMAIN_SNAPSHOT_REVERT_RUNNING,
+};
+
+use crate::connection::{run_in_thread, LibvirtServer, VirtArc};
+
+#[derive(Debug, Deserialize)]
+struct LibvirtXmlSnapshot {
+    name: String,
+    #[serde(default)]
+    description: Option<String>,
+    #[serde(default)]
+    state: Option<String>,
+    #[serde(rename = "creationTime", default)]
+    creation_time: Option<i64>,
+    #[serde(default)]
+    parent: Option<LibvirtXmlSnapshotParent>,
+    #[serde(default)]
+    memory: Option<LibvirtXmlSnapshotLocation>,
+    #[serde(default)]
+    disks: Option<LibvirtXmlSnapshotDisks>,
+}
+
+#[derive(Debug, Deserialize)]
+struct LibvirtXmlSnapshotParent {
+    name: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct LibvirtXmlSnapshotDisks {
+    #[serde(default)]
+    disk: Vec<LibvirtXmlSnapshotLocation>,
+}
+
+#[derive(Debug, Deserialize)]
+struct LibvirtXmlSnapshotLocation {
+    #[serde(rename = "@snapshot", default)]
+    snapshot: Option<String>,
+}
+
+impl LibvirtXmlSnapshotLocation {
+    fn is_external(&self) -> bool {
+        self.snapshot.as_deref() == Some("external")
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct SnapshotInfo {
+    pub(crate) name: String,
+    pub(crate) description: Option<String>,
+    pub(crate) parent: Option<String>,
+    pub(crate) creation_time: Option<i64>,
+    /// State of the domain when the snapshot was taken.
+    pub(crate) state: Option<String>,
+    /// Whether the snapshot is stored in separate files instead of inside the disk images.
+    pub(crate) external: bool,
+    pub(crate) is_current: bool,
+    /// Number of ancestors of the snapshot.
+    pub(crate) depth: usize,
+}
+
+impl SnapshotInfo {
+    pub(crate) fn from_xml(xml: &str, is_current: bool) -> Result<Self, quick_xml::de::DeError> {
+        let xml: LibvirtXmlSnapshot = from_str(xml)?;
+        let external = xml.memory.as_ref().is_some_and(|m| m.is_external())
+            || xml
+                .disks
+                .as_ref()
+                .is_some_and(|d| d.disk.iter().any(|disk| disk.is_external()));
+        Ok(Self {
+            name: xml.name,
+            description: xml
+                .description
+                .map(|d| d.trim().to_string())
+                .filter(|d| !d.is_empty()),
+            parent: xml.parent.map(|p| p.name),
+            creation_time: xml.creation_time,
+            state: xml.state,
+            external,
+            is_current,
+            depth: 0,
+        })
+    }
+
+    fn subtitle(&self) -> String {
+        let mut parts = Vec::with_capacity(4);
+        if self.is_current {
+            parts.push(gettext("Current"));
+        }
+        if let Some(creation_time) = self
+            .creation_time
+            .and_then(|t| glib::DateTime::from_unix_local(t).ok())
+            .and_then(|t| t.format("%c").ok())
+        {
+            parts.push(creation_time.to_string());
+        }
+        parts.push(if self.external {
+            gettext("External")
+        } else {
+            gettext("Internal")
+        });
+        if let Some(description) = &self.description {
+            parts.push(description.clone());
+        }
+        parts.join(" · ")
+    }
+
+    /// The name, indented by the depth of the snapshot in the tree.
+    fn tree_title(&self) -> String {
+        if self.depth == 0 {
+            self.name.clone()
+        } else {
+            format!("{}↳ {}", "\u{2003}".repeat(self.depth - 1), self.name)
+        }
+    }
+}
+
+/// Orders the snapshots depth-first, oldest first, and sets their depth. Snapshots whose
+/// parent is missing are treated as roots.
+pub(crate) fn snapshot_tree(mut snapshots: Vec<SnapshotInfo>) -> Vec<SnapshotInfo> {
+    snapshots.sort_by(|a, b| {
+        a.creation_time
+            .cmp(&b.creation_time)
+            .then_with(|| a.name.cmp(&b.name))
+    });
+    let names: Vec<String> = snapshots.iter().map(|s| s.name.clone()).collect();
+    let is_root = |s: &SnapshotInfo| !matches!(&s.parent, Some(parent) if names.contains(parent));
+
+    let mut ordered = Vec::with_capacity(snapshots.len());
+    let mut stack: Vec<(usize, SnapshotInfo)> = Vec::new();
+    let (roots, mut rest): (Vec<_>, Vec<_>) = snapshots.into_iter().partition(is_root);
+    stack.extend(roots.into_iter().rev().map(|s| (0, s)));
+    while let Some((depth, mut snapshot)) = stack.pop() {
+        let (children, others): (Vec<_>, Vec<_>) = rest
+            .into_iter()
+            .partition(|s| s.parent.as_deref() == Some(snapshot.name.as_str()));
+        rest = others;
+        stack.extend(children.into_iter().rev().map(|s| (depth + 1, s)));
+        snapshot.depth = depth;
+        ordered.push(snapshot);
+    }
+    ordered
+}
+
+/// Loads the snapshots of a domain as a tree, see `snapshot_tree`. Blocks.
+pub(crate) fn list_snapshots(domain: &Domain) -> Result<Vec<SnapshotInfo>, virt::error::Error> {
+    let mut snapshots = Vec::new();
+    for snapshot in domain.list_all_snapshots(0)? {
+        let xml = snapshot.get_xml_desc(0)?;
+        let is_current = snapshot.is_current(0).unwrap_or_default();
+        match SnapshotInfo::from_xml(&xml, is_current) {
+            Ok(info) => snapshots.push(info),
+            Err(err) => warn!("failed to parse snapshot XML: {err}"),
+        }
+    }
+    Ok(snapshot_tree(snapshots))
+}
+
+/// The XML to create a snapshot with.
+pub(crate) fn snapshot_xml(name: &str, description: &str) -> String {
+    let description = if description.is_empty() {
+        String::new()
+    } else {
+        format!("<description>{}</description>", escape(description))
+    };
+    format!(
+        "<domainsnapshot><name>{}</name>{description}</domainsnapshot>",
+        escape(name)
+    )
+}
+
+impl LibvirtServer {
+    pub(crate) fn snapshot_actions(&self) -> Vec<(Cow<'static, str>, Cow<'static, str>)> {
+        vec![
+            ("snapshotlist".into(), gettext("Snapshots").into()),
+            ("snapshotcreate".into(), gettext("Create Snapshot…").into()),
+            (
+                "snapshotrevert".into(),
+                gettext("Revert to Snapshot…").into(),
+            ),
+            ("snapshotdelete".into(), gettext("Delete Snapshot…").into()),
+        ]
+    }
+
+    pub(crate) fn act_snapshot_list<'a>(&self) -> ServerAction<'a> {
+        let name = self.name.clone();
+        ServerAction::new(
+            Box::new(self.domain.clone()),
+            Box::new(move |params, window, toov| {
+                let name = name.clone();
+                Box::pin(async move {
+                    let domain = params.downcast::<VirtArc<Domain>>().unwrap();
+                    let Some(snapshots) = load_snapshots(&domain, toov.as_ref()).await else {
+                        return false;
+                    };
+
+                    let dialog = FieldMonitorActionParametersDialog::new_informational(
+                        &gettext_f("Snapshots of {vm}", &[("vm", &name)]),
+                        snapshots
+                            .is_empty()
+                            .then(|| gettext("This domain has no snapshots."))
+                            .as_deref(),
+                    );
+                    for snapshot in &snapshots {
+                        dialog.add_info_row(&snapshot.tree_title(), &snapshot.subtitle());
+                    }
+                    dialog.run(window.as_ref()).await;
+                    false
+                })
+            }),
+        )
+    }
+
+    pub(crate) fn act_snapshot_create<'a>(&self) -> ServerAction<'a> {
+        let name = self.name.clone();
+        ServerAction::new(
+            Box::new(self.domain.clone()),
+            Box::new(move |params, window, toov| {
+                let name = name.clone();
+                Box::pin(async move {
+                    let domain = params.downcast::<VirtArc<Domain>>().unwrap();
+
+                    let dialog = FieldMonitorActionParametersDialog::new(
+                        &gettext_f("Create Snapshot of {vm}", &[("vm", &name)]),
+                        None,
+                        &gettext("Create"),
+                    );
+                    let default_name = glib::DateTime::now_local()
+                        .and_then(|now| now.format("snapshot_%Y%m%d_%H%M%S"))
+                        .map(|name| name.to_string())
+                        .unwrap_or_default();
+                    let name_row = dialog.add_entry_row(&gettext("Name"), &default_name);
+                    let description_row = dialog.add_entry_row(&gettext("Description"), "");
+                    let internal = gettext("Internal");
+                    let external = gettext("External (Disks Only)");
+                    let kind_row =
+                        dialog.add_combo_row(&gettext("Type"), &[&internal, &external], 0);
+
+                    if !dialog.run(window.as_ref()).await {
+                        return false;
+                    }
+
+                    let snapname = name_row.text().trim().to_string();
+                    if snapname.is_empty() {
+                        show_toast(toov.as_ref(), &gettext("A snapshot name is required."));
+                        return false;
+                    }
+                    let xml = snapshot_xml(&snapname, description_row.text().trim());
+                    let flags = if kind_row.selected() == 1 {
+                        VIR_DOMAIN_SNAPSHOT_CREATE_DISK_ONLY | VIR_DOMAIN_SNAPSHOT_CREATE_ATOMIC
+                    } else {
+                        0
+                    };
+
+                    snapshot_cmd(
+                        &domain,
+                        move |domain| DomainSnapshot::create_xml(domain, &xml, flags).map(|_| ()),
+                        || gettext("Snapshot successfully created."),
+                        |err| {
+                            gettext_f(
+                                "Failed to create snapshot: {err}",
+                                &[("err", err.message())],
+                            )
+                        },
+                        toov.as_ref(),
+                    )
+                    .await
+                })
+            }),
+        )
+    }
+
+    pub(crate) fn act_snapshot_revert<'a>(&self) -> ServerAction<'a> {
+        let name = self.name.clone();
+        let server_path = format!("{}/{}", self.connection_name, self.uuid);
+        ServerAction::new(
+            Box::new(self.domain.clone()),
+            Box::new(move |params, window, toov| {
+                let name = name.clone();
+                let server_path = server_path.clone();
+                Box::pin(async move {
+                    let domain = params.downcast::<VirtArc<Domain>>().unwrap();
+                    let prompt = SnapshotPrompt {
+                        heading: &gettext_f("Revert {vm}?", &[("vm", &name)]),
+                        body: &gettext(
+                            "The current state of the domain will be lost. This can not be undone.",
+                        ),
+                        confirm_label: &gettext("Revert"),
+                    };
+                    let Some((snapname, start_row)) = choose_snapshot(
+                        &domain,
+                        window.as_ref(),
+                        toov.as_ref(),
+                        &prompt,
+                        |dialog| dialog.add_switch_row(&gettext("Start After Revert"), None, false),
+                    )
+                    .await
+                    else {
+                        return false;
+                    };
+                    let flags = if start_row.is_active() {
+                        VIR_DOMAIN_SNAPSHOT_REVERT_RUNNING
+                    } else {
+                        0
+                    };
+
+                    let success = snapshot_cmd(
+                        &domain,
+                        move |domain| {
+                            DomainSnapshot::lookup_by_name(domain, &snapname, 0)?.revert(flags)
+                        },
+                        || gettext("Domain successfully reverted."),
+                        |err| {
+                            gettext_f(
+                                "Failed to revert to snapshot: {err}",
+                                &[("err", err.message())],
+                            )
+                        },
+                        toov.as_ref(),
+                    )
+                    .await;
+
+                    // Open displays show the state from before the revert.
+                    if success {
+                        if let Some(window) = window {
+                            if let Err(err) = window.activate_action(
+                                RECONNECT_SERVER_ACTION,
+                                Some(&server_path.to_variant()),
+                            ) {
+                                warn!("failed to reconnect to reverted domain: {err}");
+                            }
+                        }
+                    }
+                    success
+                })
+            }),
+        )
+    }
+
+    pub(crate) fn act_snapshot_delete<'a>(&self) -> ServerAction<'a> {
+        let name = self.name.clone();
+        ServerAction::new(
+            Box::new(self.domain.clone()),
+            Box::new(move |params, window, toov| {
+                let name = name.clone();
+                Box::pin(async move {
+                    let domain = params.downcast::<VirtArc<Domain>>().unwrap();
+                    let prompt = SnapshotPrompt {
+                        heading: &gettext_f("Delete Snapshot of {vm}?", &[("vm", &name)]),
+                        body: &gettext("The snapshot will be deleted permanently."),
+                        confirm_label: &gettext("Delete"),
+                    };
+                    let Some((snapname, children_row)) = choose_snapshot(
+                        &domain,
+                        window.as_ref(),
+                        toov.as_ref(),
+                        &prompt,
+                        |dialog| {
+                            dialog.add_switch_row(&gettext("Delete Child Snapshots"), None, false)
+                        },
+                    )
+                    .await
+                    else {
+                        return false;
+                    };
+                    let flags = if children_row.is_active() {
+                        VIR_DOMAIN_SNAPSHOT_DELETE_CHILDREN
+                    } else {
+                        0
+                    };
+
+                    snapshot_cmd(
+                        &domain,
+                        move |domain| {
+                            DomainSnapshot::lookup_by_name(domain, &snapname, 0)?.delete(flags)
+                        },
+                        || gettext("Snapshot successfully deleted."),
+                        |err| {
+                            gettext_f(
+                                "Failed to delete snapshot: {err}",
+                                &[("err", err.message())],
+                            )
+                        },
+                        toov.as_ref(),
+                    )
+                    .await
+                })
+            }),
+        )
+    }
+}
+
+/// Loads the snapshots of a domain. Shows a toast and returns `None` on error.
+async fn load_snapshots(
+    domain: &VirtArc<Domain>,
+    toov: Option<&adw::ToastOverlay>,
+) -> Option<Vec<SnapshotInfo>> {
+    let domain = domain.clone();
+    match run_in_thread(move || list_snapshots(&domain)).await {
+        Ok(Ok(snapshots)) => Some(snapshots),
+        Ok(Err(err)) => {
+            warn!("failed to load snapshots: {err}");
+            show_toast(
+                toov,
+                &gettext_f("Failed to load snapshots: {err}", &[("err", err.message())]),
+            );
+            None
+        }
+        Err(err) => {
+            error!("Internal error loading snapshots: {err}");
+            show_toast(toov, &gettext("Failed to load snapshots."));
+            None
+        }
+    }
+}
+
+/// Asks the user to pick one of the snapshots of a domain, the current one by default, see
+/// `libfieldmonitor::gtk::choose_snapshot`. Returns `None` if there are no snapshots or the user
+/// cancelled.
+async fn choose_snapshot<T>(
+    domain: &VirtArc<Domain>,
+    window: Option<&gtk::Window>,
+    toov: Option<&adw::ToastOverlay>,
+    prompt: &SnapshotPrompt<'_>,
+    add_rows: impl FnOnce(&FieldMonitorActionParametersDialog) -> T,
+) -> Option<(String, T)> {
+    let snapshots = load_snapshots(domain, toov).await?;
+    if snapshots.is_empty() {
+        show_toast(toov, &gettext("This domain has no snapshots."));
+        return None;
+    }
+
+    let titles: Vec<String> = snapshots.iter().map(SnapshotInfo::tree_title).collect();
+    let titles: Vec<&str> = titles.iter().map(String::as_str).collect();
+    let current = snapshots.iter().position(|s| s.is_current).unwrap_or(0);
+    let (index, extra) =
+        libfieldmonitor::gtk::choose_snapshot(window, prompt, &titles, current as u32, add_rows)
+            .await?;
+    Some((snapshots[index].name.clone(), extra))
+}
+
+/// Runs a snapshot command in a thread and shows the outcome as a toast. Returns true on
+/// success.
+async fn snapshot_cmd<F>(
+    domain: &VirtArc<Domain>,
+    cmd: F,
+    success_msg: impl (FnOnce() -> String) + Send + 'static,
+    err_msg: impl (FnOnce(virt::error::Error) -> String) + Send + 'static,
+    toov: Option<&adw::ToastOverlay>,
+) -> bool
+where
+    F: (FnOnce(&Domain) -> Result<(), virt::error::Error>) + Send + 'static,
+{
+    let domain = domain.clone();
+    let (success, text) = run_in_thread(move || match cmd(&domain) {
+        Ok(()) => (true, success_msg()),
+        Err(err) => (false, err_msg(err)),
+    })
+    .await
+    .unwrap_or_else(|e| {
+        error!("Internal error running action: {e}");
+        (
+            false,
+            gettext("Internal error while trying to execute command."),
+        )
+    });
+    show_toast(toov, &text);
+    success
+}
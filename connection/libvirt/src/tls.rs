@@ -0,0 +1,135 @@
+/* Copyright 2024 Marco Köpcke
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ *
+ * SPDX-License-Identifier: GPL-3.0-or-later
+ */
+//! Certificates for TLS connections.
+//!
+//! libvirt loads them from a directory with fixed file names. The configured certificates
+//! and keys, either paths or PEM, are copied into a private directory per connection.
+
+use std::fs::{self, DirBuilder, OpenOptions};
+use std::io::{self, Write};
+use std::os::unix::fs::{DirBuilderExt, OpenOptionsExt};
+use std::path::{Path, PathBuf};
+
+use gtk::glib;
+use secure_string::SecureString;
+
+const PEM_BEGIN: &str = "-----BEGIN ";
+const PEM_END: &str = "-----END ";
+const CA_CERT_FILE: &str = "cacert.pem";
+const CLIENT_CERT_FILE: &str = "clientcert.pem";
+const CLIENT_KEY_FILE: &str = "clientkey.pem";
+
+/// The directory to load the certificates of the connection from.
+pub(crate) fn pki_dir(connection_id: &str) -> PathBuf {
+    let connection_id: String = connection_id
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '-' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    glib::user_runtime_dir()
+        .join("field-monitor")
+        .join("libvirt-pki")
+        .join(connection_id)
+}
+
+/// Writes the certificates and key to the directory of the connection. Each is either a path
+/// or PEM. Files that are not configured are removed.
+pub(crate) fn write_pki_dir(
+    connection_id: &str,
+    ca_cert: &str,
+    client_cert: &str,
+    client_key: Option<&SecureString>,
+) -> io::Result<()> {
+    let dir = pki_dir(connection_id);
+    DirBuilder::new().recursive(true).mode(0o700).create(&dir)?;
+    write_pki_file(&dir.join(CA_CERT_FILE), ca_cert)?;
+    write_pki_file(&dir.join(CLIENT_CERT_FILE), client_cert)?;
+    write_pki_file(
+        &dir.join(CLIENT_KEY_FILE),
+        client_key.map(SecureString::unsecure).unwrap_or_default(),
+    )
+}
+
+/// The PEM of a configured certificate, which is either a path or PEM. `None` if nothing is
+/// configured.
+pub(crate) fn read_pem(path_or_pem: &str) -> io::Result<Option<String>> {
+    let path_or_pem = path_or_pem.trim();
+    if path_or_pem.is_empty() {
+        Ok(None)
+    } else if path_or_pem.starts_with(PEM_BEGIN) {
+        Ok(Some(normalize_pem(path_or_pem)))
+    } else {
+        fs::read_to_string(path_or_pem).map(Some)
+    }
+}
+
+fn write_pki_file(path: &Path, path_or_pem: &str) -> io::Result<()> {
+    let Some(pem) = read_pem(path_or_pem)? else {
+        return match fs::remove_file(path) {
+            Err(err) if err.kind() != io::ErrorKind::NotFound => Err(err),
+            _ => Ok(()),
+        };
+    };
+    OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .mode(0o600)
+        .open(path)?
+        .write_all(pem.as_bytes())
+}
+
+/// Restores the line breaks of PEM that was pasted into a single line entry.
+pub(crate) fn normalize_pem(pem: &str) -> String {
+    if pem.contains('\n') {
+        return format!("{}\n", pem.trim_end());
+    }
+    let mut out = String::with_capacity(pem.len() + pem.len() / 64 + 8);
+    let mut rest = pem;
+    while let Some(begin) = rest.find(PEM_BEGIN) {
+        let after_begin = &rest[begin + PEM_BEGIN.len()..];
+        let Some(label_end) = after_begin.find("-----") else {
+            break;
+        };
+        let label = &after_begin[..label_end];
+        let body_and_rest = &after_begin[label_end + 5..];
+        let end_marker = format!("{PEM_END}{label}-----");
+        let Some(body_end) = body_and_rest.find(&end_marker) else {
+            break;
+        };
+        let body: String = body_and_rest[..body_end]
+            .chars()
+            .filter(|c| !c.is_whitespace())
+            .collect();
+
+        out.push_str(&format!("{PEM_BEGIN}{label}-----\n"));
+        for line in body.as_bytes().chunks(64) {
+            out.push_str(&String::from_utf8_lossy(line));
+            out.push('\n');
+        }
+        out.push_str(&end_marker);
+        out.push('\n');
+        rest = &body_and_rest[body_end + end_marker.len()..];
+    }
+    out
+}
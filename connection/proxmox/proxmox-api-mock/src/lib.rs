@@ -0,0 +1,467 @@
+/* Copyright 2024 Marco Köpcke
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ *
+ * SPDX-License-Identifier: GPL-3.0-or-later
+ */
+//! Local stand-in for the Proxmox VE API, for tests.
+//!
+//! Serves responses recorded from a real cluster (see `fixtures/`) over HTTP or HTTPS, so the
+//! API client and the connection provider can be tested without a cluster. Requests are
+//! matched by method and path relative to `/api2/json/`; the query string is ignored.
+//! Requests without a matching route are answered with 501. All requests are recorded and
+//! can be inspected afterwards.
+//!
+//! The HTTPS variant uses a self-signed certificate for `localhost` and `127.0.0.1`, so clients
+//! must ignore certificate errors.
+
+use std::io;
+use std::net::SocketAddr;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+use log::{debug, warn};
+use serde_json::{json, Value};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::net::TcpListener;
+use tokio::task::JoinHandle;
+use tokio_native_tls::native_tls;
+use tokio_native_tls::TlsAcceptor;
+
+/// Ticket returned by the `ticket` fixture.
+pub const TICKET: &str = "PVE:root@pam:6718A3C2::bW9jay10aWNrZXQtMQ==";
+/// CSRF prevention token returned by the `ticket` fixture.
+pub const CSRF_TOKEN: &str = "6718A3C2:Y3NyZi10b2tlbi0x";
+/// Ticket returned by the `ticket_renewed` fixture.
+pub const TICKET_RENEWED: &str = "PVE:root@pam:6718B1D4::bW9jay10aWNrZXQtMg==";
+/// CSRF prevention token returned by the `ticket_renewed` fixture.
+pub const CSRF_TOKEN_RENEWED: &str = "6718B1D4:Y3NyZi10b2tlbi0y";
+/// Name of the online node in the recorded cluster.
+pub const NODE: &str = "pve1";
+
+const API_PREFIX: &str = "/api2/json/";
+const CERT_PEM: &[u8] = include_bytes!("../fixtures/cert.pem");
+const KEY_PEM: &[u8] = include_bytes!("../fixtures/key.pem");
+
+/// Response served for a route.
+#[derive(Clone, Debug)]
+pub struct MockResponse {
+    status: u16,
+    body: String,
+}
+
+impl MockResponse {
+    /// Responds with the recorded response `fixtures/{name}.json` and status 200.
+    pub fn fixture(name: &str) -> Self {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("fixtures")
+            .join(format!("{name}.json"));
+        let body = std::fs::read_to_string(&path)
+            .unwrap_or_else(|err| panic!("failed to read fixture {}: {err}", path.display()));
+        Self { status: 200, body }
+    }
+
+    /// Responds with `{"data": data}` and status 200.
+    pub fn data(data: Value) -> Self {
+        Self::json(200, json!({ "data": data }))
+    }
+
+    /// Responds with the JSON document and status.
+    pub fn json(status: u16, body: Value) -> Self {
+        Self {
+            status,
+            body: body.to_string(),
+        }
+    }
+
+    /// Responds like Proxmox does for missing or expired credentials.
+    pub fn unauthorized() -> Self {
+        Self::fixture("unauthorized").with_status(401)
+    }
+
+    pub fn with_status(mut self, status: u16) -> Self {
+        self.status = status;
+        self
+    }
+}
+
+/// A request received by the server.
+#[derive(Clone, Debug)]
+pub struct RecordedRequest {
+    pub method: String,
+    /// Path relative to `/api2/json/`.
+    pub path: String,
+    /// Query string, without the leading `?`.
+    pub query: String,
+    /// Headers in the order received, names in lowercase.
+    pub headers: Vec<(String, String)>,
+    pub body: String,
+}
+
+impl RecordedRequest {
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(k, _)| k.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str())
+    }
+
+    /// Value of a field of the URL encoded form in the body.
+    pub fn form_value(&self, key: &str) -> Option<String> {
+        decode_form(&self.body)
+            .into_iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v)
+    }
+
+    /// Value of a parameter in the query string.
+    pub fn query_value(&self, key: &str) -> Option<String> {
+        decode_form(&self.query)
+            .into_iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v)
+    }
+}
+
+struct Route {
+    method: String,
+    path: String,
+    response: MockResponse,
+    once: bool,
+}
+
+#[derive(Default)]
+struct State {
+    routes: Vec<Route>,
+    requests: Vec<RecordedRequest>,
+}
+
+impl State {
+    /// Routes only used once are preferred over the others, so that they can temporarily
+    /// override them. Otherwise the first added route wins.
+    fn respond(&mut self, method: &str, path: &str) -> Option<MockResponse> {
+        let matches = |route: &Route| route.method == method && route.path == path;
+        if let Some(pos) = self
+            .routes
+            .iter()
+            .position(|route| route.once && matches(route))
+        {
+            return Some(self.routes.remove(pos).response);
+        }
+        self.routes
+            .iter()
+            .find(|route| matches(route))
+            .map(|route| route.response.clone())
+    }
+}
+
+/// The running server. It is stopped when dropped.
+///
+/// Must be started from within a Tokio runtime, which then runs the server.
+pub struct MockProxmox {
+    addr: SocketAddr,
+    tls: bool,
+    state: Arc<Mutex<State>>,
+    task: JoinHandle<()>,
+}
+
+impl MockProxmox {
+    /// Starts a server speaking plain HTTP.
+    pub async fn start() -> Self {
+        Self::start_with(None).await
+    }
+
+    /// Starts a server speaking HTTPS with a self-signed certificate.
+    pub async fn start_tls() -> Self {
+        let identity = native_tls::Identity::from_pkcs8(CERT_PEM, KEY_PEM)
+            .expect("the certificate fixture must be valid");
+        let acceptor =
+            native_tls::TlsAcceptor::new(identity).expect("failed to set up the TLS acceptor");
+        Self::start_with(Some(acceptor.into())).await
+    }
+
+    async fn start_with(acceptor: Option<TlsAcceptor>) -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("failed to bind the mock server");
+        let addr = listener.local_addr().unwrap();
+        let state = Arc::new(Mutex::new(State::default()));
+        let tls = acceptor.is_some();
+        let task = tokio::spawn(accept_loop(listener, acceptor, state.clone()));
+        debug!("mock proxmox listening on {addr} (tls: {tls})");
+        Self {
+            addr,
+            tls,
+            state,
+            task,
+        }
+    }
+
+    pub fn port(&self) -> u16 {
+        self.addr.port()
+    }
+
+    /// The API root of the server, e.g. `https://127.0.0.1:1234/api2/json`.
+    pub fn root(&self) -> String {
+        let scheme = if self.tls { "https" } else { "http" };
+        format!("{scheme}://{}/api2/json", self.addr)
+    }
+
+    /// Serves the response for all requests to the route.
+    pub fn mock(&self, method: &str, path: &str, response: MockResponse) {
+        self.add_route(method, path, response, false);
+    }
+
+    /// Serves the response for the next request to the route only. Takes precedence over
+    /// routes added with [`MockProxmox::mock`].
+    pub fn mock_once(&self, method: &str, path: &str, response: MockResponse) {
+        self.add_route(method, path, response, true);
+    }
+
+    /// Issues [`TICKET`] for any username and password.
+    pub fn mock_ticket_auth(&self) {
+        self.mock("POST", "access/ticket", MockResponse::fixture("ticket"));
+    }
+
+    /// Serves the recorded listings of a cluster with an online node ([`NODE`]) and an
+    /// offline node, their VMs and the permissions of the user. The guest agent of the
+    /// running VM is not available.
+    pub fn mock_cluster(&self) {
+        self.mock("GET", "nodes", MockResponse::fixture("nodes"));
+        self.mock(
+            "GET",
+            &format!("nodes/{NODE}/qemu"),
+            MockResponse::fixture("qemu"),
+        );
+        self.mock(
+            "GET",
+            &format!("nodes/{NODE}/lxc"),
+            MockResponse::fixture("lxc"),
+        );
+        self.mock(
+            "GET",
+            "cluster/status",
+            MockResponse::fixture("cluster_status"),
+        );
+        self.mock(
+            "GET",
+            "cluster/resources",
+            MockResponse::fixture("cluster_resources"),
+        );
+        self.mock(
+            "GET",
+            "access/permissions",
+            MockResponse::fixture("permissions"),
+        );
+        self.mock(
+            "GET",
+            &format!("nodes/{NODE}/qemu/100/agent/network-get-interfaces"),
+            MockResponse::fixture("agent_not_running").with_status(500),
+        );
+    }
+
+    /// All requests received so far.
+    pub fn requests(&self) -> Vec<RecordedRequest> {
+        self.state.lock().unwrap().requests.clone()
+    }
+
+    /// All requests received so far for the route.
+    pub fn requests_to(&self, method: &str, path: &str) -> Vec<RecordedRequest> {
+        self.requests()
+            .into_iter()
+            .filter(|req| req.method == method && req.path == path)
+            .collect()
+    }
+
+    fn add_route(&self, method: &str, path: &str, response: MockResponse, once: bool) {
+        self.state.lock().unwrap().routes.push(Route {
+            method: method.to_string(),
+            path: path.trim_start_matches('/').to_string(),
+            response,
+            once,
+        });
+    }
+}
+
+impl Drop for MockProxmox {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+async fn accept_loop(
+    listener: TcpListener,
+    acceptor: Option<TlsAcceptor>,
+    state: Arc<Mutex<State>>,
+) {
+    loop {
+        let stream = match listener.accept().await {
+            Ok((stream, _)) => stream,
+            Err(err) => {
+                warn!("mock proxmox failed to accept: {err}");
+                continue;
+            }
+        };
+        let acceptor = acceptor.clone();
+        let state = state.clone();
+        tokio::spawn(async move {
+            let result = match acceptor {
+                None => serve(stream, &state).await,
+                Some(acceptor) => match acceptor.accept(stream).await {
+                    Ok(stream) => serve(stream, &state).await,
+                    // Expected if the client rejects the certificate.
+                    Err(err) => Err(io::Error::other(err)),
+                },
+            };
+            if let Err(err) = result {
+                debug!("mock proxmox connection failed: {err}");
+            }
+        });
+    }
+}
+
+/// Serves a single HTTP/1.1 request, then closes the connection.
+async fn serve<S>(mut stream: S, state: &Mutex<State>) -> io::Result<()>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let mut buf = Vec::with_capacity(4096);
+    let head_len = loop {
+        if let Some(pos) = buf.windows(4).position(|w| w == b"\r\n\r\n") {
+            break pos + 4;
+        }
+        if read_more(&mut stream, &mut buf).await? == 0 {
+            return Ok(());
+        }
+    };
+
+    let head = String::from_utf8_lossy(&buf[..head_len]).into_owned();
+    let mut lines = head.split("\r\n");
+    let mut request_line = lines.next().unwrap_or_default().split(' ');
+    let method = request_line.next().unwrap_or_default().to_string();
+    let target = request_line.next().unwrap_or_default();
+    let (path, query) = target.split_once('?').unwrap_or((target, ""));
+    let path = path
+        .strip_prefix(API_PREFIX)
+        .unwrap_or(path.trim_start_matches('/'))
+        .to_string();
+    let headers: Vec<(String, String)> = lines
+        .filter_map(|line| line.split_once(':'))
+        .map(|(k, v)| (k.trim().to_ascii_lowercase(), v.trim().to_string()))
+        .collect();
+
+    let content_length = headers
+        .iter()
+        .find(|(k, _)| k == "content-length")
+        .and_then(|(_, v)| v.parse::<usize>().ok())
+        .unwrap_or_default();
+    while buf.len() < head_len + content_length {
+        if read_more(&mut stream, &mut buf).await? == 0 {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+    }
+    let body = String::from_utf8_lossy(&buf[head_len..head_len + content_length]).into_owned();
+
+    let response = {
+        let mut state = state.lock().unwrap();
+        let response = state.respond(&method, &path);
+        state.requests.push(RecordedRequest {
+            method: method.clone(),
+            path: path.clone(),
+            query: query.to_string(),
+            headers,
+            body,
+        });
+        response
+    };
+    let response = response.unwrap_or_else(|| {
+        warn!("mock proxmox has no route for {method} {path}");
+        MockResponse::json(
+            501,
+            json!({ "data": null, "reason": format!("no mock for {method} {path}") }),
+        )
+    });
+
+    let raw = format!(
+        "HTTP/1.1 {} {}\r\nContent-Type: application/json;charset=UTF-8\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        response.status,
+        reason_phrase(response.status),
+        response.body.len(),
+        response.body
+    );
+    stream.write_all(raw.as_bytes()).await?;
+    stream.shutdown().await
+}
+
+async fn read_more<S>(stream: &mut S, buf: &mut Vec<u8>) -> io::Result<usize>
+where
+    S: AsyncRead + Unpin,
+{
+    let mut chunk = [0; 4096];
+    let n = stream.read(&mut chunk).await?;
+    buf.extend_from_slice(&chunk[..n]);
+    Ok(n)
+}
+
+fn reason_phrase(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        400 => "Bad Request",
+        401 => "No ticket",
+        403 => "Permission check failed",
+        404 => "Not Found",
+        500 => "Internal Server Error",
+        501 => "Not Implemented",
+        595 => "Errors during connection establishment",
+        596 => "Broken pipe",
+        _ => "Unknown",
+    }
+}
+
+/// Decodes `application/x-www-form-urlencoded` data.
+fn decode_form(data: &str) -> Vec<(String, String)> {
+    data.split('&')
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| {
+            let (k, v) = pair.split_once('=').unwrap_or((pair, ""));
+            (decode_component(k), decode_component(v))
+        })
+        .collect()
+}
+
+fn decode_component(component: &str) -> String {
+    let bytes = component.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'+' => out.push(b' '),
+            b'%' if i + 2 < bytes.len() => {
+                match std::str::from_utf8(&bytes[i + 1..i + 3])
+                    .ok()
+                    .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+                {
+                    Some(byte) => {
+                        out.push(byte);
+                        i += 2;
+                    }
+                    None => out.push(b'%'),
+                }
+            }
+            byte => out.push(byte),
+        }
+        i += 1;
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
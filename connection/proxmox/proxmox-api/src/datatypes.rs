@@ -15,7 +15,9 @@
  *
  * SPDX-License-Identifier: GPL-3.0-or-later
  */
+use std::collections::HashMap;
 use std::fmt::{Display, Formatter};
+use std::net::IpAddr;
 use std::num::NonZeroU32;
 
 use serde::de::{Error, Unexpected};
@@ -52,6 +54,12 @@ pub struct Node {
     /// Used memory in bytes.
     #[serde(default)]
     pub mem: Option<i64>,
+    /// Size of the root file system in bytes.
+    #[serde(default)]
+    pub maxdisk: Option<i64>,
+    /// Used space of the root file system in bytes.
+    #[serde(default)]
+    pub disk: Option<i64>,
     /// The SSL fingerprint for the node certificate.
     #[serde(default)]
     pub ssl_fingerprint: Option<String>,
@@ -83,6 +91,21 @@ pub struct LxcVm {
     /// Maximum usable CPUs.
     #[serde(default)]
     pub cpus: Option<f64>,
+    /// Current CPU usage.
+    #[serde(default)]
+    pub cpu: Option<f64>,
+    /// Current memory usage in bytes.
+    #[serde(default)]
+    pub mem: Option<i64>,
+    /// Current root disk usage in bytes.
+    #[serde(default)]
+    pub disk: Option<i64>,
+    /// The amount of traffic in bytes that was sent to the guest over the network since it was started.
+    #[serde(default)]
+    pub netin: Option<i64>,
+    /// The amount of traffic in bytes that was sent from the guest over the network since it was started.
+    #[serde(default)]
+    pub netout: Option<i64>,
     /// The current config lock, if any.
     #[serde(default)]
     pub lock: Option<String>,
@@ -118,6 +141,21 @@ pub struct QemuVm {
     /// Maximum usable CPUs.
     #[serde(default)]
     pub cpus: Option<f64>,
+    /// Current CPU usage.
+    #[serde(default)]
+    pub cpu: Option<f64>,
+    /// Current memory usage in bytes.
+    #[serde(default)]
+    pub mem: Option<i64>,
+    /// Current root disk usage in bytes.
+    #[serde(default)]
+    pub disk: Option<i64>,
+    /// The amount of traffic in bytes that was sent to the guest over the network since it was started.
+    #[serde(default)]
+    pub netin: Option<i64>,
+    /// The amount of traffic in bytes that was sent from the guest over the network since it was started.
+    #[serde(default)]
+    pub netout: Option<i64>,
     /// The current config lock, if any.
     #[serde(default)]
     pub lock: Option<String>,
@@ -145,6 +183,9 @@ pub struct QemuVm {
     /// The current configured tags, if any.
     #[serde(default)]
     pub tags: Option<String>,
+    /// Determines if the VM is a template.
+    #[serde(default, deserialize_with = "deserialize_opt_int_bool")]
+    pub template: Option<bool>,
     /// Uptime.
     #[serde(default)]
     pub uptime: Option<i64>,
@@ -170,6 +211,21 @@ pub struct QemuVmStatus {
     /// Maximum usable CPUs.
     #[serde(default)]
     pub cpus: Option<f64>,
+    /// Current CPU usage.
+    #[serde(default)]
+    pub cpu: Option<f64>,
+    /// Current memory usage in bytes.
+    #[serde(default)]
+    pub mem: Option<i64>,
+    /// Current root disk usage in bytes.
+    #[serde(default)]
+    pub disk: Option<i64>,
+    /// The amount of traffic in bytes that was sent to the guest over the network since it was started.
+    #[serde(default)]
+    pub netin: Option<i64>,
+    /// The amount of traffic in bytes that was sent from the guest over the network since it was started.
+    #[serde(default)]
+    pub netout: Option<i64>,
     /// The current config lock, if any.
     #[serde(default)]
     pub lock: Option<String>,
@@ -205,6 +261,342 @@ pub struct QemuVmStatus {
     pub uptime: Option<i64>,
 }
 
+/// Response of GET /node/{node}/lxc/{vmid}/status/current
+///
+/// https://pve.proxmox.com/pve-docs/api-viewer/index.html#/nodes/{node}/lxc/{vmid}/status/current
+#[derive(PartialEq, Deserialize, Debug, Clone)]
+pub struct LxcVmStatus {
+    /// LXC Container status.
+    pub status: VmStatus,
+    /// The (unique) ID of the VM.
+    pub vmid: VmId,
+    /// Maximum usable CPUs.
+    #[serde(default)]
+    pub cpus: Option<f64>,
+    /// Current CPU usage.
+    #[serde(default)]
+    pub cpu: Option<f64>,
+    /// Current memory usage in bytes.
+    #[serde(default)]
+    pub mem: Option<i64>,
+    /// Current root disk usage in bytes.
+    #[serde(default)]
+    pub disk: Option<i64>,
+    /// The amount of traffic in bytes that was sent to the guest over the network since it was started.
+    #[serde(default)]
+    pub netin: Option<i64>,
+    /// The amount of traffic in bytes that was sent from the guest over the network since it was started.
+    #[serde(default)]
+    pub netout: Option<i64>,
+    /// The current config lock, if any.
+    #[serde(default)]
+    pub lock: Option<String>,
+    /// Root disk size in bytes.
+    #[serde(default)]
+    pub maxdisk: Option<i64>,
+    /// Maximum memory in bytes.
+    #[serde(default)]
+    pub maxmem: Option<i64>,
+    /// Maximum SWAP memory in bytes.
+    #[serde(default)]
+    pub maxswap: Option<i64>,
+    /// Container name.
+    #[serde(default)]
+    pub name: Option<String>,
+    /// The current configured tags, if any.
+    #[serde(default)]
+    pub tags: Option<String>,
+    /// Uptime.
+    #[serde(default)]
+    pub uptime: Option<i64>,
+}
+
+/// Response of GET /nodes/{node}/status
+///
+/// https://pve.proxmox.com/pve-docs/api-viewer/index.html#/nodes/{node}/status
+#[derive(PartialEq, Deserialize, Debug, Clone)]
+pub struct NodeStatusReport {
+    /// CPU utilization.
+    #[serde(default)]
+    pub cpu: Option<f64>,
+    /// CPU information.
+    #[serde(default)]
+    pub cpuinfo: Option<NodeCpuInfo>,
+    /// An array of load avg for 1, 5 and 15 minutes respectively.
+    #[serde(default)]
+    pub loadavg: Option<Vec<String>>,
+    /// Memory usage.
+    #[serde(default)]
+    pub memory: Option<NodeStorageUsage>,
+    /// Root filesystem usage.
+    #[serde(default)]
+    pub rootfs: Option<NodeStorageUsage>,
+    /// SWAP usage.
+    #[serde(default)]
+    pub swap: Option<NodeStorageUsage>,
+    /// Node uptime in seconds.
+    #[serde(default)]
+    pub uptime: Option<i64>,
+    /// The PVE version string.
+    #[serde(default)]
+    pub pveversion: Option<String>,
+}
+
+/// Part of `NodeStatusReport`
+#[derive(PartialEq, Deserialize, Debug, Clone)]
+pub struct NodeCpuInfo {
+    /// Number of CPU threads.
+    #[serde(default)]
+    pub cpus: Option<i64>,
+    /// CPU model.
+    #[serde(default)]
+    pub model: Option<String>,
+    /// Number of CPU sockets.
+    #[serde(default)]
+    pub sockets: Option<i64>,
+}
+
+/// Part of `NodeStatusReport`
+#[derive(PartialEq, Deserialize, Debug, Clone)]
+pub struct NodeStorageUsage {
+    /// Free space in bytes.
+    #[serde(default)]
+    pub free: Option<i64>,
+    /// Total space in bytes.
+    #[serde(default)]
+    pub total: Option<i64>,
+    /// Used space in bytes.
+    #[serde(default)]
+    pub used: Option<i64>,
+}
+
+/// Single element of response of the rrddata API endpoints. Only fields that are relevant for the
+/// type of resource the data was requested for are set.
+///
+/// - https://pve.proxmox.com/pve-docs/api-viewer/index.html#/nodes/{node}/rrddata
+/// - https://pve.proxmox.com/pve-docs/api-viewer/index.html#/nodes/{node}/lxc/{vmid}/rrddata
+/// - https://pve.proxmox.com/pve-docs/api-viewer/index.html#/nodes/{node}/qemu/{vmid}/rrddata
+#[derive(PartialEq, Deserialize, Debug, Clone)]
+pub struct RrdDataPoint {
+    /// Unix timestamp of the data point.
+    pub time: i64,
+    /// CPU utilization.
+    #[serde(default)]
+    pub cpu: Option<f64>,
+    /// Number of available CPUs.
+    #[serde(default)]
+    pub maxcpu: Option<f64>,
+    /// VM only: Used memory in bytes.
+    #[serde(default)]
+    pub mem: Option<f64>,
+    /// VM only: Available memory in bytes.
+    #[serde(default)]
+    pub maxmem: Option<f64>,
+    /// Node only: Used memory in bytes.
+    #[serde(default)]
+    pub memused: Option<f64>,
+    /// Node only: Available memory in bytes.
+    #[serde(default)]
+    pub memtotal: Option<f64>,
+    /// Incoming network traffic in bytes per second.
+    #[serde(default)]
+    pub netin: Option<f64>,
+    /// Outgoing network traffic in bytes per second.
+    #[serde(default)]
+    pub netout: Option<f64>,
+    /// VM only: Disk reads in bytes per second.
+    #[serde(default)]
+    pub diskread: Option<f64>,
+    /// VM only: Disk writes in bytes per second.
+    #[serde(default)]
+    pub diskwrite: Option<f64>,
+    /// Node only: Load average.
+    #[serde(default)]
+    pub loadavg: Option<f64>,
+    /// Node only: IO wait.
+    #[serde(default)]
+    pub iowait: Option<f64>,
+}
+
+/// Single element of response of GET /cluster/resources
+///
+/// https://pve.proxmox.com/pve-docs/api-viewer/index.html#/cluster/resources
+#[derive(PartialEq, Deserialize, Debug, Clone)]
+pub struct ClusterResource {
+    /// Resource id.
+    pub id: String,
+    /// Resource type.
+    pub r#type: ClusterResourceType,
+    /// The cluster node name (when type in node,storage,qemu,lxc).
+    #[serde(default)]
+    pub node: Option<NodeId>,
+    /// The numerical vmid (when type in qemu,lxc).
+    #[serde(default)]
+    pub vmid: Option<VmId>,
+    /// Name of the resource.
+    #[serde(default)]
+    pub name: Option<String>,
+    /// Resource type dependent status.
+    #[serde(default)]
+    pub status: Option<String>,
+    /// The pool name (when type in pool,qemu,lxc).
+    #[serde(default)]
+    pub pool: Option<String>,
+    /// The guest's tags (when type in qemu,lxc).
+    #[serde(default)]
+    pub tags: Option<String>,
+    /// The guest's current config lock (when type in qemu,lxc)
+    #[serde(default)]
+    pub lock: Option<String>,
+    /// Determines if the guest is a template. (when type in qemu,lxc)
+    #[serde(default, deserialize_with = "deserialize_opt_int_bool")]
+    pub template: Option<bool>,
+    /// CPU utilization (when type in node,qemu,lxc).
+    #[serde(default)]
+    pub cpu: Option<f64>,
+    /// Number of available CPUs (when type in node,qemu,lxc).
+    #[serde(default)]
+    pub maxcpu: Option<f64>,
+    /// Used memory in bytes (when type in node,qemu,lxc).
+    #[serde(default)]
+    pub mem: Option<i64>,
+    /// Number of available memory in bytes (when type in node,qemu,lxc).
+    #[serde(default)]
+    pub maxmem: Option<i64>,
+    /// Used disk space in bytes (when type in storage), used root image space for VMs (type in
+    /// qemu,lxc).
+    #[serde(default)]
+    pub disk: Option<i64>,
+    /// Storage size in bytes (when type in storage), root image size for VMs (type in qemu,lxc).
+    #[serde(default)]
+    pub maxdisk: Option<i64>,
+    /// Uptime of node or virtual guest in seconds (when type in node,qemu,lxc).
+    #[serde(default)]
+    pub uptime: Option<i64>,
+}
+
+impl ClusterResource {
+    /// The type of VM, if this resource is a VM.
+    pub fn vm_type(&self) -> Option<VmType> {
+        match self.r#type {
+            ClusterResourceType::Lxc => Some(VmType::Lxc),
+            ClusterResourceType::Qemu => Some(VmType::Qemu),
+            _ => None,
+        }
+    }
+
+    /// Status of the resource, if this resource is a VM.
+    pub fn vm_status(&self) -> VmStatus {
+        match self.status.as_deref() {
+            Some("running") => VmStatus::Running,
+            Some("stopped") => VmStatus::Stopped,
+            _ => VmStatus::Unknown,
+        }
+    }
+
+    /// The tags of the guest, split into a list.
+    pub fn tag_list(&self) -> Vec<&str> {
+        split_tags(self.tags.as_deref())
+    }
+}
+
+/// Splits the `tags` property of a guest into the individual tags.
+pub fn split_tags(tags: Option<&str>) -> Vec<&str> {
+    tags.unwrap_or_default()
+        .split([';', ',', ' '])
+        .filter(|tag| !tag.is_empty())
+        .collect()
+}
+
+/// Response of GET /access/permissions
+///
+/// Maps ACL paths to the privileges the user has on them. A privilege with value `1` is
+/// propagated to all paths below.
+///
+/// https://pve.proxmox.com/pve-docs/api-viewer/index.html#/access/permissions
+#[derive(PartialEq, Eq, Deserialize, Debug, Clone, Default)]
+#[serde(transparent)]
+pub struct Permissions(pub HashMap<String, HashMap<String, u8>>);
+
+impl Permissions {
+    /// Whether the privilege is granted on the ACL path (e.g. `/vms/100`), either directly or
+    /// propagated from a parent path.
+    pub fn has_privilege(&self, path: &str, privilege: &str) -> bool {
+        if self
+            .0
+            .get(path)
+            .is_some_and(|privileges| privileges.contains_key(privilege))
+        {
+            return true;
+        }
+        let mut parent = path;
+        while let Some((rest, _)) = parent.rsplit_once('/') {
+            parent = if rest.is_empty() { "/" } else { rest };
+            if self
+                .0
+                .get(parent)
+                .and_then(|privileges| privileges.get(privilege))
+                .is_some_and(|propagate| *propagate != 0)
+            {
+                return true;
+            }
+            if parent == "/" {
+                break;
+            }
+        }
+        false
+    }
+}
+
+/// Single element of response of GET /cluster/status
+///
+/// https://pve.proxmox.com/pve-docs/api-viewer/index.html#/cluster/status
+#[derive(PartialEq, Deserialize, Debug, Clone)]
+pub struct ClusterStatusEntry {
+    pub id: String,
+    /// Name of the node or cluster.
+    pub name: String,
+    pub r#type: ClusterStatusType,
+    /// [node] IP of the resolved nodename.
+    #[serde(default)]
+    pub ip: Option<String>,
+    /// [node] Indicates if this is the responding node.
+    #[serde(default, deserialize_with = "deserialize_opt_int_bool")]
+    pub local: Option<bool>,
+    /// [node] Indicates if the node is online or offline.
+    #[serde(default, deserialize_with = "deserialize_opt_int_bool")]
+    pub online: Option<bool>,
+}
+
+#[derive(Eq, PartialEq, Deserialize, Debug, Clone, Copy)]
+pub enum ClusterStatusType {
+    #[serde(rename = "cluster")]
+    Cluster,
+    #[serde(rename = "node")]
+    Node,
+    #[serde(other)]
+    Unknown,
+}
+
+/// Type of a resource in the cluster.
+#[derive(Eq, PartialEq, Deserialize, Debug, Clone, Copy)]
+pub enum ClusterResourceType {
+    #[serde(rename = "node")]
+    Node,
+    #[serde(rename = "storage")]
+    Storage,
+    #[serde(rename = "pool")]
+    Pool,
+    #[serde(rename = "qemu")]
+    Qemu,
+    #[serde(rename = "lxc")]
+    Lxc,
+    #[serde(rename = "sdn")]
+    Sdn,
+    #[serde(other)]
+    Unknown,
+}
+
 /// Status of a VM
 #[derive(Eq, PartialEq, Deserialize, Debug, Clone, Copy)]
 pub enum VmStatus {
@@ -267,6 +659,352 @@ pub struct Vncproxy {
     pub user: String,
 }
 
+/// Single element of response of GET /nodes/{node}/{lxc,qemu}/{vmid}/snapshot
+///
+/// - https://pve.proxmox.com/pve-docs/api-viewer/index.html#/nodes/{node}/lxc/{vmid}/snapshot
+/// - https://pve.proxmox.com/pve-docs/api-viewer/index.html#/nodes/{node}/qemu/{vmid}/snapshot
+#[derive(PartialEq, Deserialize, Debug, Clone)]
+pub struct VmSnapshot {
+    /// Snapshot identifier. Value 'current' identifies the current VM.
+    pub name: String,
+    /// Snapshot description.
+    #[serde(default)]
+    pub description: Option<String>,
+    /// Parent snapshot identifier.
+    #[serde(default)]
+    pub parent: Option<String>,
+    /// Snapshot creation time
+    #[serde(default)]
+    pub snaptime: Option<i64>,
+    /// QEMU only: Snapshot includes RAM.
+    #[serde(default, deserialize_with = "deserialize_opt_int_bool")]
+    pub vmstate: Option<bool>,
+}
+
+impl VmSnapshot {
+    /// Name of the pseudo-snapshot the API returns for the current state of the VM.
+    pub const CURRENT: &'static str = "current";
+
+    /// Whether this is not an actual snapshot but the current state of the VM.
+    pub fn is_current(&self) -> bool {
+        self.name == Self::CURRENT
+    }
+}
+
+/// Response of GET /nodes/{node}/qemu/{vmid}/config
+///
+/// Only contains the properties used by clients of this crate.
+///
+/// https://pve.proxmox.com/pve-docs/api-viewer/index.html#/nodes/{node}/qemu/{vmid}/config
+#[derive(PartialEq, Deserialize, Debug, Clone, Default)]
+pub struct QemuVmConfig {
+    /// Set a name for the VM. Only used on the configuration web interface.
+    #[serde(default)]
+    pub name: Option<String>,
+    /// Description for the VM.
+    #[serde(default)]
+    pub description: Option<String>,
+    /// Tags of the VM. This is only meta information.
+    #[serde(default)]
+    pub tags: Option<String>,
+    /// Create a serial device inside the VM (n is 0 to 3).
+    #[serde(default)]
+    pub serial0: Option<String>,
+    /// Create a serial device inside the VM (n is 0 to 3).
+    #[serde(default)]
+    pub serial1: Option<String>,
+    /// Create a serial device inside the VM (n is 0 to 3).
+    #[serde(default)]
+    pub serial2: Option<String>,
+    /// Create a serial device inside the VM (n is 0 to 3).
+    #[serde(default)]
+    pub serial3: Option<String>,
+    /// Boot order, eg. `order=scsi0;ide2;net0`.
+    #[serde(default)]
+    pub boot: Option<String>,
+    /// All other properties, including the drives (eg. `ide2`, `scsi0`).
+    #[serde(flatten)]
+    pub other: HashMap<String, Value>,
+}
+
+impl QemuVmConfig {
+    /// The serial ports configured for the VM.
+    pub fn serial_ports(&self) -> Vec<VmTermproxySerial> {
+        [
+            (VmTermproxySerial::Serial0, &self.serial0),
+            (VmTermproxySerial::Serial1, &self.serial1),
+            (VmTermproxySerial::Serial2, &self.serial2),
+            (VmTermproxySerial::Serial3, &self.serial3),
+        ]
+        .into_iter()
+        .filter(|(_, device)| device.is_some())
+        .map(|(serial, _)| serial)
+        .collect()
+    }
+
+    /// The CD/DVD drives of the VM, sorted by their key.
+    pub fn cdrom_drives(&self) -> Vec<QemuCdromDrive> {
+        let mut drives: Vec<_> = self
+            .other
+            .iter()
+            .filter(|(key, _)| is_cdrom_capable_drive_key(key))
+            .filter_map(|(key, value)| QemuCdromDrive::parse(key, value.as_str()?))
+            .collect();
+        drives.sort_by(|a, b| a.key.cmp(&b.key));
+        drives
+    }
+
+    /// The devices in the boot order. Empty if the VM uses the legacy boot order format or
+    /// the default boot order.
+    pub fn boot_order(&self) -> Vec<&str> {
+        self.boot
+            .as_deref()
+            .into_iter()
+            .flat_map(|boot| boot.split(','))
+            .filter_map(|part| part.strip_prefix("order="))
+            .flat_map(|order| order.split(';'))
+            .filter(|device| !device.is_empty())
+            .collect()
+    }
+}
+
+/// Response of GET /nodes/{node}/lxc/{vmid}/config
+///
+/// Only contains the properties used by clients of this crate.
+///
+/// https://pve.proxmox.com/pve-docs/api-viewer/index.html#/nodes/{node}/lxc/{vmid}/config
+#[derive(PartialEq, Deserialize, Debug, Clone, Default)]
+pub struct LxcVmConfig {
+    /// Host name for the container.
+    #[serde(default)]
+    pub hostname: Option<String>,
+    /// Description for the container.
+    #[serde(default)]
+    pub description: Option<String>,
+    /// Tags of the container. This is only meta information.
+    #[serde(default)]
+    pub tags: Option<String>,
+    /// All other properties.
+    #[serde(flatten)]
+    pub other: HashMap<String, Value>,
+}
+
+/// Response of GET /nodes/{node}/config
+///
+/// Only contains the properties used by clients of this crate.
+///
+/// https://pve.proxmox.com/pve-docs/api-viewer/index.html#/nodes/{node}/config
+#[derive(PartialEq, Deserialize, Debug, Clone, Default)]
+pub struct NodeConfig {
+    /// Description for the node.
+    #[serde(default)]
+    pub description: Option<String>,
+    /// All other properties.
+    #[serde(flatten)]
+    pub other: HashMap<String, Value>,
+}
+
+/// Whether the config key is a drive that can be a CD/DVD drive.
+fn is_cdrom_capable_drive_key(key: &str) -> bool {
+    ["ide", "sata", "scsi"].iter().any(|bus| {
+        key.strip_prefix(bus)
+            .is_some_and(|n| !n.is_empty() && n.chars().all(|c| c.is_ascii_digit()))
+    })
+}
+
+/// A CD/DVD drive of a QEMU VM.
+#[derive(Eq, PartialEq, Debug, Clone)]
+pub struct QemuCdromDrive {
+    /// Key of the drive in the VM config, eg. `ide2`.
+    pub key: String,
+    /// Volume of the inserted medium, eg. `local:iso/debian.iso`, or `cdrom` if a physical
+    /// drive of the host is passed through. `None` if the drive is empty.
+    pub volume: Option<String>,
+}
+
+impl QemuCdromDrive {
+    const MEDIA_CDROM: &'static str = "media=cdrom";
+    const NO_MEDIUM: &'static str = "none";
+
+    fn parse(key: &str, value: &str) -> Option<Self> {
+        let mut parts = value.split(',');
+        let volume = parts.next()?;
+        if !parts.any(|part| part == Self::MEDIA_CDROM) {
+            return None;
+        }
+        Some(Self {
+            key: key.to_string(),
+            volume: (volume != Self::NO_MEDIUM).then(|| volume.to_string()),
+        })
+    }
+
+    /// Drive specification to insert the volume into a drive, or eject the medium if `None`.
+    pub fn config_value(volume: Option<&str>) -> String {
+        format!(
+            "{},{}",
+            volume.unwrap_or(Self::NO_MEDIUM),
+            Self::MEDIA_CDROM
+        )
+    }
+}
+
+/// Result of a command of the QEMU guest agent.
+#[derive(PartialEq, Deserialize, Debug, Clone)]
+pub(crate) struct QemuAgentResult<T> {
+    pub result: T,
+}
+
+/// Entry of the response of GET /nodes/{node}/qemu/{vmid}/agent/network-get-interfaces
+///
+/// https://pve.proxmox.com/pve-docs/api-viewer/index.html#/nodes/{node}/qemu/{vmid}/agent/network-get-interfaces
+#[derive(PartialEq, Deserialize, Debug, Clone)]
+pub struct QemuAgentNetworkInterface {
+    /// Name of the interface in the guest, eg. `eth0`.
+    pub name: String,
+    /// MAC address.
+    #[serde(rename = "hardware-address", default)]
+    pub hardware_address: Option<String>,
+    #[serde(rename = "ip-addresses", default)]
+    pub ip_addresses: Vec<QemuAgentIpAddress>,
+}
+
+impl QemuAgentNetworkInterface {
+    /// The addresses of the interface that can be reached from outside of the guest, ie.
+    /// without loopback and link-local addresses.
+    pub fn reachable_addresses(&self) -> Vec<IpAddr> {
+        self.ip_addresses
+            .iter()
+            .filter_map(|address| address.ip_address.parse::<IpAddr>().ok())
+            .filter(|address| {
+                !address.is_loopback()
+                    && !address.is_unspecified()
+                    && match address {
+                        IpAddr::V4(address) => !address.is_link_local(),
+                        IpAddr::V6(address) => address.segments()[0] & 0xffc0 != 0xfe80,
+                    }
+            })
+            .collect()
+    }
+}
+
+#[derive(PartialEq, Deserialize, Debug, Clone)]
+pub struct QemuAgentIpAddress {
+    #[serde(rename = "ip-address")]
+    pub ip_address: String,
+    /// `ipv4` or `ipv6`.
+    #[serde(rename = "ip-address-type")]
+    pub ip_address_type: String,
+    #[serde(default)]
+    pub prefix: Option<u8>,
+}
+
+/// Response of GET /nodes/{node}/storage
+///
+/// https://pve.proxmox.com/pve-docs/api-viewer/index.html#/nodes/{node}/storage
+#[derive(Eq, PartialEq, Deserialize, Debug, Clone)]
+pub struct NodeStorage {
+    /// The storage identifier.
+    pub storage: String,
+    /// Storage type.
+    pub r#type: String,
+    /// Allowed storage content types, comma-separated.
+    pub content: String,
+    /// Set when storage is accessible.
+    #[serde(default, deserialize_with = "deserialize_opt_int_bool")]
+    pub active: Option<bool>,
+    /// Set when storage is enabled (not disabled).
+    #[serde(default, deserialize_with = "deserialize_opt_int_bool")]
+    pub enabled: Option<bool>,
+}
+
+/// Response of GET /nodes/{node}/storage/{storage}/content
+///
+/// https://pve.proxmox.com/pve-docs/api-viewer/index.html#/nodes/{node}/storage/{storage}/content
+#[derive(Eq, PartialEq, Deserialize, Debug, Clone)]
+pub struct StorageContent {
+    /// Volume identifier, eg. `local:iso/debian.iso`.
+    pub volid: String,
+    /// Content type.
+    pub content: String,
+    /// Format identifier ('raw', 'qcow2', 'subvol', 'iso', 'tgz' ...)
+    pub format: String,
+    /// Volume size in bytes.
+    #[serde(default)]
+    pub size: Option<u64>,
+    /// Creation time (seconds since the UNIX Epoch).
+    #[serde(default)]
+    pub ctime: Option<i64>,
+    /// Associated Owner VMID.
+    #[serde(default)]
+    pub vmid: Option<VmId>,
+    /// Optional notes.
+    #[serde(default)]
+    pub notes: Option<String>,
+}
+
+impl StorageContent {
+    /// The name of the volume without the storage, eg. `debian.iso` for
+    /// `local:iso/debian.iso`.
+    pub fn name(&self) -> &str {
+        let volume = self
+            .volid
+            .split_once(':')
+            .map_or(self.volid.as_str(), |(_, volume)| volume);
+        volume.rsplit('/').next().unwrap_or(volume)
+    }
+}
+
+/// Response of GET /nodes/{node}/tasks/{upid}/status
+///
+/// https://pve.proxmox.com/pve-docs/api-viewer/index.html#/nodes/{node}/tasks/{upid}/status
+#[derive(PartialEq, Deserialize, Debug, Clone)]
+pub struct TaskStatus {
+    /// Unique task ID.
+    pub upid: String,
+    /// The cluster node name.
+    pub node: NodeId,
+    /// Whether the task is still running.
+    pub status: TaskRunningStatus,
+    /// Exit status of the task, once it has stopped. 'OK' if it was successful.
+    #[serde(default)]
+    pub exitstatus: Option<String>,
+    /// Task type, eg. 'qmigrate'.
+    pub r#type: String,
+    /// ID of the object the task works on, eg. the VM ID.
+    #[serde(default)]
+    pub id: Option<String>,
+    /// User that started the task.
+    #[serde(default)]
+    pub user: Option<String>,
+    /// Start time of the task.
+    #[serde(default)]
+    pub starttime: Option<i64>,
+}
+
+impl TaskStatus {
+    /// Exit status of successful tasks.
+    pub const EXIT_OK: &'static str = "OK";
+
+    pub fn is_running(&self) -> bool {
+        self.status == TaskRunningStatus::Running
+    }
+
+    /// Whether the task has stopped and was successful.
+    pub fn is_ok(&self) -> bool {
+        self.status == TaskRunningStatus::Stopped
+            && self.exitstatus.as_deref() == Some(Self::EXIT_OK)
+    }
+}
+
+/// Whether a task is still running.
+#[derive(Eq, PartialEq, Deserialize, Debug, Clone, Copy)]
+pub enum TaskRunningStatus {
+    #[serde(rename = "running")]
+    Running,
+    #[serde(rename = "stopped")]
+    Stopped,
+}
+
 #[derive(Eq, PartialEq, Deserialize, Debug, Clone)]
 pub(crate) struct Ticket {
     pub ticket: String,
@@ -16,7 +16,13 @@
  * SPDX-License-Identifier: GPL-3.0-or-later
  */
 use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fmt::{Display, Formatter};
 use std::num::NonZeroU32;
+use std::str::FromStr;
+
+use crate::NodeId;
 
 pub(crate) trait VmStatusInput {
     type LxcInput: Serialize;
@@ -333,7 +339,7 @@ pub enum NodeTermproxyCmd {
 
 #[derive(Debug, Clone, Default, PartialEq, Eq)]
 pub struct VmTermproxyInput {
-    /// LXC only: opens a serial terminal (defaults to display)
+    /// QEMU only: opens a serial terminal (defaults to display)
     pub serial: Option<VmTermproxySerial>,
 }
 
@@ -349,28 +355,58 @@ pub enum VmTermproxySerial {
     Serial3,
 }
 
+impl VmTermproxySerial {
+    pub const ALL: [Self; 4] = [Self::Serial0, Self::Serial1, Self::Serial2, Self::Serial3];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            VmTermproxySerial::Serial0 => "serial0",
+            VmTermproxySerial::Serial1 => "serial1",
+            VmTermproxySerial::Serial2 => "serial2",
+            VmTermproxySerial::Serial3 => "serial3",
+        }
+    }
+}
+
+impl Display for VmTermproxySerial {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        self.as_str().fmt(f)
+    }
+}
+
+impl FromStr for VmTermproxySerial {
+    type Err = crate::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .into_iter()
+            .find(|serial| serial.as_str() == s)
+            .ok_or(crate::Error::InvalidIdValue)
+    }
+}
+
 #[derive(Serialize, Debug, Clone, PartialEq, Eq)]
-pub(crate) struct VmTermproxyInputLxc {
+pub(crate) struct VmTermproxyInputLxc {}
+
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub(crate) struct VmTermproxyInputQemu {
     /// opens a serial terminal (defaults to display)
     #[serde(skip_serializing_if = "Option::is_none")]
     pub serial: Option<VmTermproxySerial>,
 }
 
-#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
-pub(crate) struct VmTermproxyInputQemu {}
-
 impl VmStatusInput for VmTermproxyInput {
     type LxcInput = VmTermproxyInputLxc;
     type QemuInput = VmTermproxyInputQemu;
 
     fn into_lxc(self) -> Self::LxcInput {
-        VmTermproxyInputLxc {
-            serial: self.serial,
-        }
+        VmTermproxyInputLxc {}
     }
 
     fn into_qemu(self) -> Self::QemuInput {
-        VmTermproxyInputQemu {}
+        VmTermproxyInputQemu {
+            serial: self.serial,
+        }
     }
 }
 
@@ -477,3 +513,314 @@ impl VmStatusInput for VmVncproxyInput {
         }
     }
 }
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct VmSnapshotCreateInput {
+    /// The name of the snapshot.
+    pub snapname: String,
+    /// A textual description or comment.
+    pub description: Option<String>,
+    /// QEMU only: Save the vmstate
+    pub vmstate: Option<bool>,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub(crate) struct VmSnapshotCreateInputLxc {
+    /// The name of the snapshot.
+    pub snapname: String,
+    /// A textual description or comment.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub(crate) struct VmSnapshotCreateInputQemu {
+    /// The name of the snapshot.
+    pub snapname: String,
+    /// A textual description or comment.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    /// Save the vmstate
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub vmstate: Option<u8>,
+}
+
+impl VmStatusInput for VmSnapshotCreateInput {
+    type LxcInput = VmSnapshotCreateInputLxc;
+    type QemuInput = VmSnapshotCreateInputQemu;
+
+    fn into_lxc(self) -> Self::LxcInput {
+        VmSnapshotCreateInputLxc {
+            snapname: self.snapname,
+            description: self.description,
+        }
+    }
+
+    fn into_qemu(self) -> Self::QemuInput {
+        VmSnapshotCreateInputQemu {
+            snapname: self.snapname,
+            description: self.description,
+            vmstate: self.vmstate.map(|v| if v { 1 } else { 0 }),
+        }
+    }
+}
+
+#[derive(Serialize, Debug, Default, Clone, PartialEq, Eq)]
+pub struct VmSnapshotRollbackInput {
+    /// Whether the VM should get started after rolling back successfully.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub start: Option<u8>,
+}
+
+#[derive(Serialize, Debug, Default, Clone, PartialEq, Eq)]
+pub struct VmSnapshotDeleteInput {
+    /// For removal from config file, even if removing disk snapshots fails.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub force: Option<u8>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VmMigrateInput {
+    /// Target node.
+    pub target: NodeId,
+    /// QEMU: Use online/live migration if VM is running. LXC: Use online migration.
+    pub online: Option<bool>,
+    /// LXC only: Use restart migration.
+    pub restart: Option<bool>,
+    /// QEMU only: Enable live storage migration for local disk.
+    pub with_local_disks: Option<bool>,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub(crate) struct VmMigrateInputLxc {
+    /// Target node.
+    pub target: String,
+    /// Use online migration.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub online: Option<u8>,
+    /// Use restart migration.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub restart: Option<u8>,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub(crate) struct VmMigrateInputQemu {
+    /// Target node.
+    pub target: String,
+    /// Use online/live migration if VM is running. Ignored if VM is stopped.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub online: Option<u8>,
+    /// Enable live storage migration for local disk.
+    #[serde(rename = "with-local-disks", skip_serializing_if = "Option::is_none")]
+    pub with_local_disks: Option<u8>,
+}
+
+impl VmStatusInput for VmMigrateInput {
+    type LxcInput = VmMigrateInputLxc;
+    type QemuInput = VmMigrateInputQemu;
+
+    fn into_lxc(self) -> Self::LxcInput {
+        VmMigrateInputLxc {
+            target: self.target.to_string(),
+            online: self.online.map(|v| if v { 1 } else { 0 }),
+            restart: self.restart.map(|v| if v { 1 } else { 0 }),
+        }
+    }
+
+    fn into_qemu(self) -> Self::QemuInput {
+        VmMigrateInputQemu {
+            target: self.target.to_string(),
+            online: self.online.map(|v| if v { 1 } else { 0 }),
+            with_local_disks: self.with_local_disks.map(|v| if v { 1 } else { 0 }),
+        }
+    }
+}
+
+#[derive(Serialize, Debug, Default, Clone, PartialEq, Eq)]
+pub struct RrdDataInput {
+    /// Specify the time frame you are interested in.
+    pub timeframe: RrdTimeframe,
+    /// The RRD consolidation function
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cf: Option<RrdConsolidation>,
+}
+
+#[derive(Eq, PartialEq, Serialize, Debug, Default, Clone, Copy)]
+pub enum RrdTimeframe {
+    #[default]
+    #[serde(rename = "hour")]
+    Hour,
+    #[serde(rename = "day")]
+    Day,
+    #[serde(rename = "week")]
+    Week,
+    #[serde(rename = "month")]
+    Month,
+    #[serde(rename = "year")]
+    Year,
+}
+
+#[derive(Eq, PartialEq, Serialize, Debug, Clone, Copy)]
+pub enum RrdConsolidation {
+    #[serde(rename = "AVERAGE")]
+    Average,
+    #[serde(rename = "MAX")]
+    Max,
+}
+
+#[derive(Serialize, Debug, Default, Clone, PartialEq, Eq)]
+pub struct ClusterResourcesInput {
+    /// Resource type.
+    #[serde(rename = "type", skip_serializing_if = "Option::is_none")]
+    pub r#type: Option<ClusterResourcesFilter>,
+}
+
+#[derive(Eq, PartialEq, Serialize, Debug, Clone, Copy)]
+pub enum ClusterResourcesFilter {
+    #[serde(rename = "vm")]
+    Vm,
+    #[serde(rename = "storage")]
+    Storage,
+    #[serde(rename = "node")]
+    Node,
+    #[serde(rename = "sdn")]
+    Sdn,
+}
+
+#[derive(Serialize, Debug, Default, Clone, PartialEq, Eq)]
+pub struct NodeStorageInput {
+    /// Only list stores which support this content type.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub content: Option<StorageContentType>,
+    /// Only list stores which are enabled (not disabled in config).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub enabled: Option<u8>,
+}
+
+#[derive(Serialize, Debug, Default, Clone, PartialEq, Eq)]
+pub struct StorageContentInput {
+    /// Only list content of this type.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub content: Option<StorageContentType>,
+    /// Only list images for this VM.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub vmid: Option<u64>,
+}
+
+#[derive(Eq, PartialEq, Serialize, Debug, Clone, Copy)]
+pub enum StorageContentType {
+    #[serde(rename = "iso")]
+    Iso,
+    #[serde(rename = "backup")]
+    Backup,
+    #[serde(rename = "images")]
+    Images,
+    #[serde(rename = "rootdir")]
+    Rootdir,
+    #[serde(rename = "vztmpl")]
+    Vztmpl,
+    #[serde(rename = "snippets")]
+    Snippets,
+}
+
+#[derive(Serialize, Debug, Default, Clone, PartialEq, Eq)]
+pub struct QemuVmConfigUpdateInput {
+    /// Boot order, eg. `order=ide2;scsi0`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub boot: Option<String>,
+    /// A list of settings to delete, comma-separated.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub delete: Option<String>,
+    /// Description for the VM.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    /// Drives to change, by their key (eg. `ide2`), with their new drive specification.
+    #[serde(flatten)]
+    pub drives: BTreeMap<String, String>,
+}
+
+#[derive(Serialize, Debug, Default, Clone, PartialEq, Eq)]
+pub struct LxcVmConfigUpdateInput {
+    /// A list of settings to delete, comma-separated.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub delete: Option<String>,
+    /// Description for the container.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+}
+
+#[derive(Serialize, Debug, Default, Clone, PartialEq, Eq)]
+pub struct NodeConfigUpdateInput {
+    /// A list of settings to delete, comma-separated.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub delete: Option<String>,
+    /// Description for the node.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct QemuAgentSetUserPasswordInput {
+    /// The user to set the password for.
+    pub username: String,
+    /// The new password.
+    pub password: String,
+    /// Set to 1 if the password has already been passed through crypt().
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub crypted: Option<u8>,
+}
+
+#[derive(Serialize, Debug, Default, Clone, PartialEq, Eq)]
+pub struct QemuVmCloneInput {
+    /// VMID for the clone.
+    pub newid: u64,
+    /// Set a name for the new VM.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    /// Target node. Only allowed if the original VM is on shared storage.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub target: Option<String>,
+    /// Create a full copy of all disks. Linked clones are only possible from templates.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub full: Option<u8>,
+    /// Target storage for full clone.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub storage: Option<String>,
+}
+
+#[derive(Serialize, Debug, Default, Clone, PartialEq, Eq)]
+pub struct VzdumpInput {
+    /// The ID of the guest system you want to backup.
+    pub vmid: u64,
+    /// Store resulting file to this storage.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub storage: Option<String>,
+    /// Backup mode.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mode: Option<VzdumpMode>,
+    /// Compress dump file.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub compress: Option<VzdumpCompression>,
+}
+
+#[derive(Eq, PartialEq, Serialize, Debug, Clone, Copy)]
+pub enum VzdumpMode {
+    #[serde(rename = "snapshot")]
+    Snapshot,
+    #[serde(rename = "suspend")]
+    Suspend,
+    #[serde(rename = "stop")]
+    Stop,
+}
+
+#[derive(Eq, PartialEq, Serialize, Debug, Clone, Copy)]
+pub enum VzdumpCompression {
+    #[serde(rename = "0")]
+    None,
+    #[serde(rename = "lzo")]
+    Lzo,
+    #[serde(rename = "gzip")]
+    Gzip,
+    #[serde(rename = "zstd")]
+    Zstd,
+}
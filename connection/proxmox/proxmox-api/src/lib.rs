@@ -16,10 +16,12 @@
  * SPDX-License-Identifier: GPL-3.0-or-later
  */
 use std::borrow::Cow;
-use std::sync::atomic::{AtomicBool, Ordering};
-use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
 
 pub use crate::datatypes::*;
+pub use crate::proxy::*;
 use futures::future::BoxFuture;
 use futures::lock::Mutex;
 use http::{header, HeaderMap, HeaderValue, Method, StatusCode, Uri};
@@ -28,9 +30,11 @@ use reqwest::{ClientBuilder, RequestBuilder, Response};
 use secure_string::SecureString;
 use serde::de::DeserializeOwned;
 use serde::Serialize;
+use serde_json::Value;
 use thiserror::Error;
 
 mod datatypes;
+mod proxy;
 
 #[derive(Debug, Error)]
 pub enum Error {
@@ -42,6 +46,10 @@ pub enum Error {
     InvalidHeaderValue(#[from] http::header::InvalidHeaderValue),
     #[error("the value provided for an identifier is invalid")]
     InvalidIdValue,
+    #[error("no API endpoint with a host was given")]
+    NoEndpoint,
+    #[error("the proxy configuration is invalid")]
+    InvalidProxy,
     #[error("authentication failed")]
     AuthFailed,
     #[error("API returned no data")]
@@ -54,33 +62,104 @@ pub enum Error {
 
 pub type Result<T> = std::result::Result<T, Error>;
 
+/// How long to wait for an endpoint to accept a connection before trying the next one.
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+
 #[derive(Clone, Debug)]
-struct Client {
-    client: reqwest::Client,
-    ignore_ssl_errors: bool,
+struct Endpoint {
     hostname: String,
     root: String, // ends with /
 }
 
-impl Client {
-    fn new(root: &Uri, ignore_ssl_errors: bool) -> Result<Self> {
+impl Endpoint {
+    fn new(root: &Uri) -> Result<Self> {
         let mut root_str = root.to_string();
         if !root_str.ends_with('/') {
             root_str = format!("{root}/");
         }
         Ok(Self {
-            client: ClientBuilder::new()
-                .danger_accept_invalid_certs(ignore_ssl_errors)
-                .build()?,
-            ignore_ssl_errors,
-            hostname: root.host().unwrap().to_string(),
+            hostname: root.host().ok_or(Error::NoEndpoint)?.to_string(),
             root: root_str,
         })
     }
+}
+
+#[derive(Clone, Debug)]
+struct Client {
+    client: reqwest::Client,
+    ignore_ssl_errors: bool,
+    proxy: Option<ProxyConfig>,
+    /// All endpoints of the cluster, shared between all clones of the client. Endpoints are
+    /// only ever added, so indices stay valid.
+    endpoints: Arc<RwLock<Vec<Endpoint>>>,
+    /// Index of the endpoint currently in use.
+    current_endpoint: Arc<AtomicUsize>,
+}
+
+impl Client {
+    fn new(roots: &[Uri], ignore_ssl_errors: bool, proxy: Option<ProxyConfig>) -> Result<Self> {
+        let endpoints = roots
+            .iter()
+            .map(Endpoint::new)
+            .collect::<Result<Vec<_>>>()?;
+        if endpoints.is_empty() {
+            return Err(Error::NoEndpoint);
+        }
+        let mut builder = ClientBuilder::new()
+            .danger_accept_invalid_certs(ignore_ssl_errors)
+            .connect_timeout(CONNECT_TIMEOUT);
+        if let Some(proxy) = &proxy {
+            builder = builder.proxy(proxy.reqwest_proxy()?);
+        }
+        Ok(Self {
+            client: builder.build()?,
+            ignore_ssl_errors,
+            proxy,
+            endpoints: Arc::new(RwLock::new(endpoints)),
+            current_endpoint: Default::default(),
+        })
+    }
+
+    fn endpoint(&self) -> Endpoint {
+        self.endpoints.read().unwrap()[self.current_endpoint.load(Ordering::Acquire)].clone()
+    }
+
+    fn endpoint_count(&self) -> usize {
+        self.endpoints.read().unwrap().len()
+    }
+
+    fn add_endpoints(&self, roots: &[Uri]) -> Result<()> {
+        let mut endpoints = self.endpoints.write().unwrap();
+        for root in roots {
+            let endpoint = Endpoint::new(root)?;
+            if !endpoints.iter().any(|known| known.root == endpoint.root) {
+                debug!("adding endpoint {}", endpoint.root);
+                endpoints.push(endpoint);
+            }
+        }
+        Ok(())
+    }
+
+    /// Switches to the next endpoint, after `failed` could not be reached. Does nothing if
+    /// another request already switched away from it.
+    fn next_endpoint(&self, failed: usize) {
+        let endpoints = self.endpoints.read().unwrap();
+        let next = (failed + 1) % endpoints.len();
+        if self
+            .current_endpoint
+            .compare_exchange(failed, next, Ordering::AcqRel, Ordering::Acquire)
+            .is_ok()
+        {
+            warn!(
+                "{} is unreachable, switching to {}",
+                endpoints[failed].hostname, endpoints[next].hostname
+            );
+        }
+    }
 
     fn request(&self, method: Method, path: &str) -> RequestBuilder {
         self.client
-            .request(method, format!("{}{}", self.root, path))
+            .request(method, format!("{}{}", self.endpoint().root, path))
     }
 }
 
@@ -101,20 +180,25 @@ impl TicketProvider {
     async fn reauth(&self) -> Result<()> {
         debug!("re-issuing ticket");
         self.just_reauth.store(true, Ordering::Release);
-        self.current_ticket.lock().await.replace(
-            self.client
-                .request(Method::POST, "access/ticket")
-                .form(&[
-                    ("username", &*self.user),
-                    ("password", self.password.unsecure()),
-                ])
-                .send()
-                .await?
-                .json::<Wrapper<Ticket>>()
-                .await?
-                .data
-                .ok_or(Error::MissingData)?,
-        );
+        let response = self
+            .client
+            .request(Method::POST, "access/ticket")
+            .form(&[
+                ("username", &*self.user),
+                ("password", self.password.unsecure()),
+            ])
+            .send()
+            .await?;
+        // Proxmox answers wrong credentials with 401 and no data.
+        if response.status() == StatusCode::UNAUTHORIZED {
+            return Err(Error::AuthFailed);
+        }
+        let ticket = response
+            .json::<Wrapper<Ticket>>()
+            .await?
+            .data
+            .ok_or(Error::MissingData)?;
+        self.current_ticket.lock().await.replace(ticket);
         Ok(())
     }
 }
@@ -228,15 +312,20 @@ pub struct ProxmoxApiClient {
 }
 
 /// Public API
+///
+/// Clients are created with a list of API roots of the same cluster. The first one is used
+/// until it becomes unreachable, after which the next one is used. Tickets and API keys are
+/// valid on all nodes of a cluster, so authentication carries over.
 impl ProxmoxApiClient {
     pub async fn connect_with_apikey(
-        root: &Uri,
+        roots: &[Uri],
         tokenid: &str,
         apikey: SecureString,
         ignore_ssl_errors: bool,
+        proxy: Option<ProxyConfig>,
     ) -> Result<Self> {
         debug!("creating proxmox client with api key");
-        let client = Client::new(root, ignore_ssl_errors)?;
+        let client = Client::new(roots, ignore_ssl_errors, proxy)?;
         Ok(Self {
             client: client.clone(),
             api_access_provider: Box::new(ApikeyProvider {
@@ -247,13 +336,14 @@ impl ProxmoxApiClient {
     }
 
     pub async fn connect_with_ticket(
-        root: &Uri,
+        roots: &[Uri],
         user: &str,
         password: SecureString,
         ignore_ssl_errors: bool,
+        proxy: Option<ProxyConfig>,
     ) -> Result<Self> {
         debug!("creating proxmox client with username and password");
-        let client = Client::new(root, ignore_ssl_errors)?;
+        let client = Client::new(roots, ignore_ssl_errors, proxy)?;
         Ok(Self {
             client: client.clone(),
             api_access_provider: Box::new(TicketProvider {
@@ -270,8 +360,9 @@ impl ProxmoxApiClient {
         self.api_access_provider.tag()
     }
 
-    pub fn clientconfig_root(&self) -> &str {
-        &self.client.root
+    /// The API root currently in use.
+    pub fn clientconfig_root(&self) -> String {
+        self.client.endpoint().root
     }
 
     pub fn clientconfig_user_or_tokenid(&self) -> &str {
@@ -286,14 +377,45 @@ impl ProxmoxApiClient {
         self.client.ignore_ssl_errors
     }
 
-    pub fn clientconfig_hostname(&self) -> &str {
-        &self.client.hostname
+    /// The hostname of the API root currently in use.
+    pub fn clientconfig_hostname(&self) -> String {
+        self.client.endpoint().hostname
+    }
+
+    /// Adds further API roots of the same cluster to fail over to, for example nodes that were
+    /// discovered after connecting. Roots the client already knows are skipped.
+    pub fn add_endpoints(&self, roots: &[Uri]) -> Result<()> {
+        self.client.add_endpoints(roots)
+    }
+
+    pub fn clientconfig_proxy(&self) -> Option<&ProxyConfig> {
+        self.client.proxy.as_ref()
     }
 
     pub async fn nodes(&self) -> Result<Vec<Node>> {
         self.get_without_params_json("nodes").await
     }
 
+    pub async fn access_permissions(&self) -> Result<Permissions> {
+        self.get_without_params_json("access/permissions").await
+    }
+
+    pub async fn cluster_status(&self) -> Result<Vec<ClusterStatusEntry>> {
+        self.get_without_params_json("cluster/status").await
+    }
+
+    /// The next free VMID.
+    pub async fn cluster_nextid(&self) -> Result<VmId> {
+        self.get_without_params_json("cluster/nextid").await
+    }
+
+    pub async fn cluster_resources(
+        &self,
+        input: &ClusterResourcesInput,
+    ) -> Result<Vec<ClusterResource>> {
+        self.get_json("cluster/resources", input).await
+    }
+
     pub async fn node_lxc(&self, node: &NodeId) -> Result<Vec<LxcVm>> {
         let mut vms: Vec<LxcVm> = self
             .get_without_params_json(&format!("nodes/{}/lxc", node))
@@ -349,6 +471,156 @@ impl ProxmoxApiClient {
             .await
     }
 
+    pub async fn vm_lxc_status_current(&self, node: &NodeId, vm: &VmId) -> Result<LxcVmStatus> {
+        self.get_without_params_json(&format!("nodes/{}/lxc/{}/status/current", node, vm))
+            .await
+    }
+
+    pub async fn node_status(&self, node: &NodeId) -> Result<NodeStatusReport> {
+        self.get_without_params_json(&format!("nodes/{}/status", node))
+            .await
+    }
+
+    pub async fn vm_qemu_config(&self, node: &NodeId, vm: &VmId) -> Result<QemuVmConfig> {
+        self.get_without_params_json(&format!("nodes/{node}/qemu/{vm}/config"))
+            .await
+    }
+
+    pub async fn vm_qemu_config_update(
+        &self,
+        node: &NodeId,
+        vm: &VmId,
+        input: &QemuVmConfigUpdateInput,
+    ) -> Result<()> {
+        self.put_form_empty(&format!("nodes/{node}/qemu/{vm}/config"), input)
+            .await
+    }
+
+    pub async fn vm_lxc_config(&self, node: &NodeId, vm: &VmId) -> Result<LxcVmConfig> {
+        self.get_without_params_json(&format!("nodes/{node}/lxc/{vm}/config"))
+            .await
+    }
+
+    pub async fn vm_lxc_config_update(
+        &self,
+        node: &NodeId,
+        vm: &VmId,
+        input: &LxcVmConfigUpdateInput,
+    ) -> Result<()> {
+        self.put_form_empty(&format!("nodes/{node}/lxc/{vm}/config"), input)
+            .await
+    }
+
+    pub async fn node_config(&self, node: &NodeId) -> Result<NodeConfig> {
+        self.get_without_params_json(&format!("nodes/{node}/config"))
+            .await
+    }
+
+    pub async fn node_config_update(
+        &self,
+        node: &NodeId,
+        input: &NodeConfigUpdateInput,
+    ) -> Result<()> {
+        self.put_form_empty(&format!("nodes/{node}/config"), input)
+            .await
+    }
+
+    /// Clones the VM (or template). Returns the UPID of the clone task.
+    pub async fn vm_qemu_clone(
+        &self,
+        node: &NodeId,
+        vm: &VmId,
+        input: &QemuVmCloneInput,
+    ) -> Result<String> {
+        self.post_form_json(&format!("nodes/{node}/qemu/{vm}/clone"), input)
+            .await
+    }
+
+    pub async fn vm_qemu_agent_network_interfaces(
+        &self,
+        node: &NodeId,
+        vm: &VmId,
+    ) -> Result<Vec<QemuAgentNetworkInterface>> {
+        self.get_without_params_json::<QemuAgentResult<_>>(&format!(
+            "nodes/{node}/qemu/{vm}/agent/network-get-interfaces"
+        ))
+        .await
+        .map(|v| v.result)
+    }
+
+    /// Freezes the filesystems of the guest. Returns the number of frozen filesystems.
+    pub async fn vm_qemu_agent_fsfreeze_freeze(&self, node: &NodeId, vm: &VmId) -> Result<u64> {
+        self.post_form_json::<_, QemuAgentResult<_>>(
+            &format!("nodes/{node}/qemu/{vm}/agent/fsfreeze-freeze"),
+            &(),
+        )
+        .await
+        .map(|v| v.result)
+    }
+
+    /// Thaws the filesystems of the guest. Returns the number of thawed filesystems.
+    pub async fn vm_qemu_agent_fsfreeze_thaw(&self, node: &NodeId, vm: &VmId) -> Result<u64> {
+        self.post_form_json::<_, QemuAgentResult<_>>(
+            &format!("nodes/{node}/qemu/{vm}/agent/fsfreeze-thaw"),
+            &(),
+        )
+        .await
+        .map(|v| v.result)
+    }
+
+    pub async fn vm_qemu_agent_set_user_password(
+        &self,
+        node: &NodeId,
+        vm: &VmId,
+        input: &QemuAgentSetUserPasswordInput,
+    ) -> Result<()> {
+        self.post_form_json::<_, Value>(
+            &format!("nodes/{node}/qemu/{vm}/agent/set-user-password"),
+            input,
+        )
+        .await
+        .map(|_| ())
+    }
+
+    pub async fn node_storages(
+        &self,
+        node: &NodeId,
+        input: &NodeStorageInput,
+    ) -> Result<Vec<NodeStorage>> {
+        self.get_json(&format!("nodes/{node}/storage"), input).await
+    }
+
+    pub async fn node_storage_content(
+        &self,
+        node: &NodeId,
+        storage: &str,
+        input: &StorageContentInput,
+    ) -> Result<Vec<StorageContent>> {
+        self.get_json(&format!("nodes/{node}/storage/{storage}/content"), input)
+            .await
+    }
+
+    pub async fn node_rrddata(
+        &self,
+        node: &NodeId,
+        input: &RrdDataInput,
+    ) -> Result<Vec<RrdDataPoint>> {
+        self.get_json(&format!("nodes/{}/rrddata", node), input)
+            .await
+    }
+
+    pub async fn vm_rrddata(
+        &self,
+        node: &NodeId,
+        vm: &VmId,
+        vm_type: Option<VmType>,
+        input: &RrdDataInput,
+    ) -> Result<Vec<RrdDataPoint>> {
+        let vm_type = self.vm_type(node, vm, vm_type).await?;
+        self.get_json(&format!("nodes/{node}/{vm_type}/{vm}/rrddata"), input)
+            .await
+    }
+
     pub async fn vm_available_console_proxies(
         &self,
         node: &NodeId,
@@ -442,6 +714,118 @@ impl ProxmoxApiClient {
             .await
     }
 
+    pub async fn vm_snapshots(
+        &self,
+        node: &NodeId,
+        vm: &VmId,
+        vm_type: Option<VmType>,
+    ) -> Result<Vec<VmSnapshot>> {
+        let vm_type = self.vm_type(node, vm, vm_type).await?;
+        self.get_without_params_json(&format!("nodes/{node}/{vm_type}/{vm}/snapshot"))
+            .await
+    }
+
+    pub async fn vm_snapshot_create(
+        &self,
+        node: &NodeId,
+        vm: &VmId,
+        vm_type: Option<VmType>,
+        input: VmSnapshotCreateInput,
+    ) -> Result<String> {
+        let vm_type = self.vm_type(node, vm, vm_type).await?;
+        match vm_type {
+            VmType::Lxc => {
+                self.post_form_json(
+                    &format!("nodes/{node}/lxc/{vm}/snapshot"),
+                    &input.into_lxc(),
+                )
+                .await
+            }
+            VmType::Qemu => {
+                self.post_form_json(
+                    &format!("nodes/{node}/qemu/{vm}/snapshot"),
+                    &input.into_qemu(),
+                )
+                .await
+            }
+        }
+    }
+
+    pub async fn vm_snapshot_rollback(
+        &self,
+        node: &NodeId,
+        vm: &VmId,
+        vm_type: Option<VmType>,
+        snapname: &str,
+        input: VmSnapshotRollbackInput,
+    ) -> Result<String> {
+        let vm_type = self.vm_type(node, vm, vm_type).await?;
+        self.post_form_json(
+            &format!(
+                "nodes/{node}/{vm_type}/{vm}/snapshot/{}/rollback",
+                urlencoding::encode(snapname)
+            ),
+            &input,
+        )
+        .await
+    }
+
+    pub async fn vm_snapshot_delete(
+        &self,
+        node: &NodeId,
+        vm: &VmId,
+        vm_type: Option<VmType>,
+        snapname: &str,
+        input: VmSnapshotDeleteInput,
+    ) -> Result<String> {
+        let vm_type = self.vm_type(node, vm, vm_type).await?;
+        self.delete_json(
+            &format!(
+                "nodes/{node}/{vm_type}/{vm}/snapshot/{}",
+                urlencoding::encode(snapname)
+            ),
+            &input,
+        )
+        .await
+    }
+
+    pub async fn vm_migrate(
+        &self,
+        node: &NodeId,
+        vm: &VmId,
+        vm_type: Option<VmType>,
+        input: VmMigrateInput,
+    ) -> Result<String> {
+        let vm_type = self.vm_type(node, vm, vm_type).await?;
+        match vm_type {
+            VmType::Lxc => {
+                self.post_form_json(&format!("nodes/{node}/lxc/{vm}/migrate"), &input.into_lxc())
+                    .await
+            }
+            VmType::Qemu => {
+                self.post_form_json(
+                    &format!("nodes/{node}/qemu/{vm}/migrate"),
+                    &input.into_qemu(),
+                )
+                .await
+            }
+        }
+    }
+
+    /// Starts a backup. Returns the UPID of the backup task.
+    pub async fn node_vzdump(&self, node: &NodeId, input: &VzdumpInput) -> Result<String> {
+        self.post_form_json(&format!("nodes/{node}/vzdump"), input)
+            .await
+    }
+
+    pub async fn node_task_status(&self, node: &NodeId, upid: &str) -> Result<TaskStatus> {
+        self.get_without_params_json(&format!(
+            "nodes/{node}/tasks/{}/status",
+            urlencoding::encode(upid)
+        ))
+        .await
+    }
+
     pub async fn node_termproxy(
         &self,
         node: &NodeId,
@@ -568,14 +952,14 @@ impl ProxmoxApiClient {
         let url = match vm {
             None => format!(
                 "{}nodes/{}/vncwebsocket?port={}&vncticket={}",
-                self.client.root.replacen("http", "ws", 1),
+                self.client.endpoint().root.replacen("http", "ws", 1),
                 node,
                 input.port,
                 urlencoding::encode(&input.vncticket)
             ),
             Some((vm_id, VmType::Qemu)) => format!(
                 "{}nodes/{}/qemu/{}/vncwebsocket?port={}&vncticket={}",
-                self.client.root.replacen("http", "ws", 1),
+                self.client.endpoint().root.replacen("http", "ws", 1),
                 node,
                 vm_id,
                 input.port,
@@ -583,7 +967,7 @@ impl ProxmoxApiClient {
             ),
             Some((vm_id, VmType::Lxc)) => format!(
                 "{}nodes/{}/lxc/{}/vncwebsocket?port={}&vncticket={}",
-                self.client.root.replacen("http", "ws", 1),
+                self.client.endpoint().root.replacen("http", "ws", 1),
                 node,
                 vm_id,
                 input.port,
@@ -594,7 +978,7 @@ impl ProxmoxApiClient {
         let mut builder = http::Request::builder()
             .method(Method::GET)
             .uri(&url)
-            .header("Host", &self.client.hostname)
+            .header("Host", &self.client.endpoint().hostname)
             .header("Connection", "Upgrade")
             .header("Upgrade", "websocket")
             .header("Sec-WebSocket-Protocol", "binary")
@@ -701,6 +1085,41 @@ impl ProxmoxApiClient {
             .and_then(|v| self.handle_wrapper(status, v))
     }
 
+    async fn delete_json<P, T>(&self, route: &str, params: &P) -> Result<T>
+    where
+        P: Serialize + ?Sized,
+        T: DeserializeOwned,
+    {
+        let resp = self.delete(route, params).await?;
+        let status = resp.status();
+        resp.json::<Wrapper<T>>()
+            .await
+            .map_err(Into::into)
+            .and_then(|v| self.handle_wrapper(status, v))
+    }
+
+    /// PUTs the form to a route that returns no data.
+    async fn put_form_empty<B>(&self, route: &str, body: &B) -> Result<()>
+    where
+        B: Serialize + ?Sized,
+    {
+        let resp = self.put_form(route, body).await?;
+        let status = resp.status();
+        if status.is_client_error() || status.is_server_error() {
+            match resp
+                .json::<Wrapper<Value>>()
+                .await
+                .ok()
+                .and_then(|v| v.reason)
+            {
+                Some(reason) => Err(Error::Api(status, reason)),
+                None => Err(Error::ApiUnknown(status)),
+            }
+        } else {
+            Ok(())
+        }
+    }
+
     async fn get_without_params(&self, route: &str) -> Result<Response> {
         debug!("GET @ {route}");
         self.do_request(Method::GET, route, |req| req).await
@@ -724,6 +1143,24 @@ impl ProxmoxApiClient {
             .await
     }
 
+    async fn put_form<B>(&self, route: &str, body: &B) -> Result<Response>
+    where
+        B: Serialize + ?Sized,
+    {
+        debug!("PUT @ {route}");
+        self.do_request(Method::PUT, route, |req| req.form(body))
+            .await
+    }
+
+    async fn delete<P>(&self, route: &str, params: &P) -> Result<Response>
+    where
+        P: Serialize + ?Sized,
+    {
+        debug!("DELETE @ {route}");
+        self.do_request(Method::DELETE, route, |req| req.query(params))
+            .await
+    }
+
     async fn base_request(&self, method: Method, route: &str) -> Result<RequestBuilder> {
         let auth_header = self.api_access_provider.provide_auth_headers().await?;
         Ok(self
@@ -733,6 +1170,39 @@ impl ProxmoxApiClient {
             .header(header::AUTHORIZATION, auth_header.auth_header.as_ref()))
     }
 
+    /// Sends the request. If the current endpoint can not be reached, the request is retried
+    /// on the other endpoints.
+    async fn send<F>(
+        &self,
+        method: &Method,
+        route: &str,
+        modify_request: &F,
+    ) -> Result<reqwest::Result<Response>>
+    where
+        F: Fn(RequestBuilder) -> RequestBuilder,
+    {
+        let mut attempts_left = self.client.endpoint_count();
+        loop {
+            attempts_left -= 1;
+            let endpoint = self.client.current_endpoint.load(Ordering::Acquire);
+            // Getting the auth headers may already need to reach the endpoint to get a ticket.
+            let response = match self.base_request(method.clone(), route).await {
+                Ok(request) => modify_request(request).send().await,
+                Err(Error::Request(err)) if attempts_left > 0 && is_unreachable(&err) => {
+                    self.client.next_endpoint(endpoint);
+                    continue;
+                }
+                Err(err) => return Err(err),
+            };
+            match &response {
+                Err(err) if attempts_left > 0 && is_unreachable(err) => {
+                    self.client.next_endpoint(endpoint);
+                }
+                _ => return Ok(response),
+            }
+        }
+    }
+
     async fn do_request<F>(
         &self,
         method: Method,
@@ -742,9 +1212,7 @@ impl ProxmoxApiClient {
     where
         F: Fn(RequestBuilder) -> RequestBuilder,
     {
-        let mut response = modify_request(self.base_request(method.clone(), route).await?)
-            .send()
-            .await;
+        let mut response = self.send(&method, route, &modify_request).await?;
 
         let status = response
             .as_ref()
@@ -757,11 +1225,13 @@ impl ProxmoxApiClient {
             match self.api_access_provider.failed_auth().await {
                 DoAfterAuthRetry::Retry => {
                     debug!("access backend indicated retry possible: retrying");
-                    response = modify_request(self.base_request(method, route).await?)
-                        .send()
-                        .await;
+                    response = self.send(&method, route, &modify_request).await?;
 
-                    if response.is_ok() {
+                    let still_unauthorized = matches!(
+                        &response,
+                        Ok(resp) if resp.status() == StatusCode::UNAUTHORIZED
+                    );
+                    if response.is_ok() && !still_unauthorized {
                         debug!("retry success");
                     } else {
                         debug!("retry failed");
@@ -796,3 +1266,8 @@ impl ProxmoxApiClient {
         }
     }
 }
+
+/// Whether the error indicates the endpoint could not be reached at all.
+fn is_unreachable(err: &reqwest::Error) -> bool {
+    err.is_connect() || err.is_timeout()
+}
@@ -0,0 +1,252 @@
+/* Copyright 2024 Marco Köpcke
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ *
+ * SPDX-License-Identifier: GPL-3.0-or-later
+ */
+//! Proxy servers to connect to Proxmox through.
+//!
+//! The API client uses the proxy via `reqwest`. Other connections (websockets, VNC, SPICE)
+//! can be tunnelled through it using [`connect_tcp`].
+
+use std::fmt::{Display, Formatter};
+use std::io;
+use std::str::FromStr;
+
+use base64::prelude::*;
+use http::Uri;
+use secure_string::SecureString;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::TcpStream;
+use tokio_socks::tcp::Socks5Stream;
+
+use crate::{Error, Result};
+
+/// Maximum size of the response header to a HTTP `CONNECT` request.
+const MAX_CONNECT_RESPONSE_SIZE: usize = 16 * 1024;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ProxyKind {
+    /// HTTP proxy, using `CONNECT` for all connections.
+    Http,
+    /// SOCKS5 proxy. Host names are resolved by the proxy.
+    Socks5,
+}
+
+impl ProxyKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ProxyKind::Http => "http",
+            ProxyKind::Socks5 => "socks5",
+        }
+    }
+}
+
+impl Display for ProxyKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for ProxyKind {
+    type Err = Error;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "http" => Ok(ProxyKind::Http),
+            // `socks5h` is what most tools call SOCKS5 with remote name resolution.
+            "socks5" | "socks5h" => Ok(ProxyKind::Socks5),
+            _ => Err(Error::InvalidProxy),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ProxyConfig {
+    pub kind: ProxyKind,
+    pub host: String,
+    pub port: u16,
+    pub username: Option<String>,
+    pub password: Option<SecureString>,
+}
+
+impl ProxyConfig {
+    /// URL of the proxy, including the credentials.
+    pub fn to_url(&self) -> String {
+        let scheme = match self.kind {
+            ProxyKind::Http => "http",
+            ProxyKind::Socks5 => "socks5h",
+        };
+        let credentials = match &self.username {
+            Some(username) => format!(
+                "{}:{}@",
+                urlencoding::encode(username),
+                urlencoding::encode(
+                    self.password
+                        .as_ref()
+                        .map(SecureString::unsecure)
+                        .unwrap_or_default()
+                )
+            ),
+            None => String::new(),
+        };
+        format!(
+            "{scheme}://{credentials}{}:{}",
+            bracket_ipv6(&self.host),
+            self.port
+        )
+    }
+
+    /// Parses a URL as created by [`Self::to_url`].
+    pub fn from_url(url: &str) -> Result<Self> {
+        let uri = Uri::from_str(url).map_err(|_| Error::InvalidProxy)?;
+        let kind = ProxyKind::from_str(uri.scheme_str().ok_or(Error::InvalidProxy)?)?;
+        let authority = uri.authority().ok_or(Error::InvalidProxy)?;
+        let host = authority.host();
+        let host = host
+            .strip_prefix('[')
+            .and_then(|host| host.strip_suffix(']'))
+            .unwrap_or(host)
+            .to_string();
+        let port = authority.port_u16().ok_or(Error::InvalidProxy)?;
+
+        let (username, password) = match authority.as_str().rsplit_once('@') {
+            Some((userinfo, _)) => {
+                let (username, password) = userinfo.split_once(':').unwrap_or((userinfo, ""));
+                let decode = |s| {
+                    urlencoding::decode(s)
+                        .map(|s| s.into_owned())
+                        .map_err(|_| Error::InvalidProxy)
+                };
+                (
+                    Some(decode(username)?),
+                    Some(SecureString::from(decode(password)?)),
+                )
+            }
+            None => (None, None),
+        };
+
+        Ok(Self {
+            kind,
+            host,
+            port,
+            username,
+            password,
+        })
+    }
+
+    pub(crate) fn reqwest_proxy(&self) -> Result<reqwest::Proxy> {
+        Ok(reqwest::Proxy::all(self.to_url())?)
+    }
+
+    /// Opens a TCP connection to the target through the proxy.
+    pub async fn connect(&self, host: &str, port: u16) -> io::Result<TcpStream> {
+        match self.kind {
+            ProxyKind::Http => self.connect_http(host, port).await,
+            ProxyKind::Socks5 => self.connect_socks5(host, port).await,
+        }
+    }
+
+    async fn connect_http(&self, host: &str, port: u16) -> io::Result<TcpStream> {
+        let mut stream = TcpStream::connect((self.host.as_str(), self.port)).await?;
+
+        let target = format!("{}:{}", bracket_ipv6(host), port);
+        let mut request = format!("CONNECT {target} HTTP/1.1\r\nHost: {target}\r\n");
+        if let Some(username) = &self.username {
+            let credentials = format!(
+                "{}:{}",
+                username,
+                self.password
+                    .as_ref()
+                    .map(SecureString::unsecure)
+                    .unwrap_or_default()
+            );
+            request.push_str(&format!(
+                "Proxy-Authorization: Basic {}\r\n",
+                BASE64_STANDARD.encode(credentials)
+            ));
+        }
+        request.push_str("\r\n");
+        stream.write_all(request.as_bytes()).await?;
+
+        // Read the response header byte-wise, so nothing after it is consumed.
+        let mut reader = BufReader::with_capacity(1, &mut stream);
+        let mut status_line = String::new();
+        reader.read_line(&mut status_line).await?;
+        let mut header_size = status_line.len();
+        loop {
+            let mut line = String::new();
+            let len = reader.read_line(&mut line).await?;
+            header_size += len;
+            if len == 0 || header_size > MAX_CONNECT_RESPONSE_SIZE {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "invalid response from proxy",
+                ));
+            }
+            if line == "\r\n" || line == "\n" {
+                break;
+            }
+        }
+
+        match status_line.split_whitespace().nth(1) {
+            Some("200") => Ok(stream),
+            _ => Err(io::Error::new(
+                io::ErrorKind::ConnectionRefused,
+                format!("proxy refused connection: {}", status_line.trim()),
+            )),
+        }
+    }
+
+    async fn connect_socks5(&self, host: &str, port: u16) -> io::Result<TcpStream> {
+        let proxy = (self.host.as_str(), self.port);
+        let stream = match &self.username {
+            Some(username) => {
+                Socks5Stream::connect_with_password(
+                    proxy,
+                    (host, port),
+                    username,
+                    self.password
+                        .as_ref()
+                        .map(SecureString::unsecure)
+                        .unwrap_or_default(),
+                )
+                .await
+            }
+            None => Socks5Stream::connect(proxy, (host, port)).await,
+        }
+        .map_err(io::Error::other)?;
+        Ok(stream.into_inner())
+    }
+}
+
+/// Opens a TCP connection to the target, through the proxy if one is given.
+pub async fn connect_tcp(
+    proxy: Option<&ProxyConfig>,
+    host: &str,
+    port: u16,
+) -> io::Result<TcpStream> {
+    match proxy {
+        Some(proxy) => proxy.connect(host, port).await,
+        None => TcpStream::connect((host, port)).await,
+    }
+}
+
+fn bracket_ipv6(host: &str) -> String {
+    if host.contains(':') && !host.starts_with('[') {
+        format!("[{host}]")
+    } else {
+        host.to_string()
+    }
+}
@@ -0,0 +1,549 @@
+/* Copyright 2024 Marco Köpcke
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ *
+ * SPDX-License-Identifier: GPL-3.0-or-later
+ */
+//! Contract tests of the API client against recorded Proxmox responses.
+
+use std::net::TcpListener;
+use std::str::FromStr;
+
+use http::{StatusCode, Uri};
+use proxmox_api::{
+    ClusterResourceType, ClusterStatusType, Error, NodeConfigUpdateInput, NodeId, NodeStatus,
+    ProxmoxApiClient, QemuVmConfigUpdateInput, VmId, VmSpiceproxyInput, VmStartInput, VmStatus,
+    VmTermproxyInput, VmType, VmVncproxyInput,
+};
+use proxmox_api_mock::{MockProxmox, MockResponse, CSRF_TOKEN, NODE, TICKET, TICKET_RENEWED};
+use secure_string::SecureString;
+use serde_json::json;
+
+const USER: &str = "root@pam";
+const PASSWORD: &str = "hunter2";
+const TOKENID: &str = "root@pam!field-monitor";
+const APIKEY: &str = "8a0c7d36-3f2e-4b8e-9c41-5d2f1e6a7b90";
+
+fn root(mock: &MockProxmox) -> Uri {
+    mock.root().parse().unwrap()
+}
+
+async fn ticket_client(mock: &MockProxmox) -> ProxmoxApiClient {
+    ProxmoxApiClient::connect_with_ticket(
+        &[root(mock)],
+        USER,
+        SecureString::from_str(PASSWORD).unwrap(),
+        true,
+        None,
+    )
+    .await
+    .unwrap()
+}
+
+async fn apikey_client(mock: &MockProxmox) -> ProxmoxApiClient {
+    ProxmoxApiClient::connect_with_apikey(
+        &[root(mock)],
+        TOKENID,
+        SecureString::from_str(APIKEY).unwrap(),
+        true,
+        None,
+    )
+    .await
+    .unwrap()
+}
+
+fn node() -> NodeId {
+    NodeId::from_str(NODE).unwrap()
+}
+
+#[tokio::test]
+async fn ticket_is_requested_once_and_sent_with_csrf_token() {
+    let mock = MockProxmox::start().await;
+    mock.mock_ticket_auth();
+    mock.mock_cluster();
+    let client = ticket_client(&mock).await;
+
+    client.nodes().await.unwrap();
+    client.cluster_status().await.unwrap();
+
+    let tickets = mock.requests_to("POST", "access/ticket");
+    assert_eq!(tickets.len(), 1);
+    assert_eq!(tickets[0].form_value("username").as_deref(), Some(USER));
+    assert_eq!(tickets[0].form_value("password").as_deref(), Some(PASSWORD));
+
+    for path in ["nodes", "cluster/status"] {
+        let request = &mock.requests_to("GET", path)[0];
+        assert_eq!(
+            request.header("authorization"),
+            Some(format!("PVEAuthCookie={TICKET}").as_str())
+        );
+        assert_eq!(request.header("csrfpreventiontoken"), Some(CSRF_TOKEN));
+    }
+}
+
+#[tokio::test]
+async fn expired_ticket_is_reissued_and_request_retried() {
+    let mock = MockProxmox::start().await;
+    mock.mock_ticket_auth();
+    mock.mock_cluster();
+    let client = ticket_client(&mock).await;
+    client.nodes().await.unwrap();
+
+    mock.mock_once("GET", "nodes", MockResponse::unauthorized());
+    mock.mock_once(
+        "POST",
+        "access/ticket",
+        MockResponse::fixture("ticket_renewed"),
+    );
+    let nodes = client.nodes().await.unwrap();
+    assert_eq!(nodes.len(), 2);
+
+    assert_eq!(mock.requests_to("POST", "access/ticket").len(), 2);
+    let requests = mock.requests_to("GET", "nodes");
+    assert_eq!(requests.len(), 3);
+    assert_eq!(
+        requests[2].header("authorization"),
+        Some(format!("PVEAuthCookie={TICKET_RENEWED}").as_str())
+    );
+
+    // The new ticket is kept for further requests.
+    client.cluster_status().await.unwrap();
+    assert_eq!(mock.requests_to("POST", "access/ticket").len(), 2);
+}
+
+#[tokio::test]
+async fn reissued_ticket_rejected_again_fails_auth() {
+    let mock = MockProxmox::start().await;
+    mock.mock_ticket_auth();
+    mock.mock_cluster();
+    let client = ticket_client(&mock).await;
+    client.nodes().await.unwrap();
+
+    mock.mock_once("GET", "nodes", MockResponse::unauthorized());
+    mock.mock_once("GET", "nodes", MockResponse::unauthorized());
+    assert!(matches!(client.nodes().await, Err(Error::AuthFailed)));
+    // Reissued exactly once, no retry loop.
+    assert_eq!(mock.requests_to("POST", "access/ticket").len(), 2);
+    assert_eq!(mock.requests_to("GET", "nodes").len(), 3);
+
+    // Once the API accepts the ticket again, requests succeed.
+    client.nodes().await.unwrap();
+}
+
+#[tokio::test]
+async fn wrong_password_fails_auth() {
+    let mock = MockProxmox::start().await;
+    mock.mock("POST", "access/ticket", MockResponse::unauthorized());
+    mock.mock_cluster();
+    let client = ticket_client(&mock).await;
+
+    assert!(matches!(client.nodes().await, Err(Error::AuthFailed)));
+    assert!(mock.requests_to("GET", "nodes").is_empty());
+}
+
+#[tokio::test]
+async fn apikey_is_sent_as_token_header() {
+    let mock = MockProxmox::start().await;
+    mock.mock_cluster();
+    let client = apikey_client(&mock).await;
+
+    client.nodes().await.unwrap();
+
+    let request = &mock.requests_to("GET", "nodes")[0];
+    assert_eq!(
+        request.header("authorization"),
+        Some(format!("PVEAPIToken={TOKENID}={APIKEY}").as_str())
+    );
+    assert_eq!(request.header("csrfpreventiontoken"), None);
+    assert!(mock.requests_to("POST", "access/ticket").is_empty());
+}
+
+#[tokio::test]
+async fn rejected_apikey_fails_auth_without_retry() {
+    let mock = MockProxmox::start().await;
+    mock.mock("GET", "nodes", MockResponse::unauthorized());
+    let client = apikey_client(&mock).await;
+
+    assert!(matches!(client.nodes().await, Err(Error::AuthFailed)));
+    assert_eq!(mock.requests_to("GET", "nodes").len(), 1);
+}
+
+#[tokio::test]
+async fn nodes_are_parsed() {
+    let mock = MockProxmox::start().await;
+    mock.mock_cluster();
+    let client = apikey_client(&mock).await;
+
+    let nodes = client.nodes().await.unwrap();
+    assert_eq!(nodes.len(), 2);
+    assert_eq!(nodes[0].node.as_ref(), "pve2");
+    assert_eq!(nodes[0].status, NodeStatus::Offline);
+    assert_eq!(nodes[0].maxmem, None);
+    assert_eq!(nodes[1].node, node());
+    assert_eq!(nodes[1].status, NodeStatus::Online);
+    assert_eq!(nodes[1].maxcpu, Some(8));
+    assert_eq!(nodes[1].mem, Some(9126113280));
+}
+
+#[tokio::test]
+async fn guests_are_parsed_and_sorted() {
+    let mock = MockProxmox::start().await;
+    mock.mock_cluster();
+    let client = apikey_client(&mock).await;
+
+    let qemu = client.node_qemu(&node(), false).await.unwrap();
+    let ids: Vec<u64> = qemu.iter().map(|vm| vm.vmid.clone().into()).collect();
+    assert_eq!(ids, [100, 101, 9000]);
+    assert_eq!(qemu[0].status, VmStatus::Running);
+    assert_eq!(qemu[0].running_qemu.as_deref(), Some("9.0.2"));
+    assert_eq!(qemu[0].tags.as_deref(), Some("prod;web"));
+    assert_eq!(qemu[0].template, None);
+    assert_eq!(qemu[2].template, Some(true));
+    assert_eq!(
+        mock.requests_to("GET", &format!("nodes/{NODE}/qemu"))[0]
+            .query_value("full")
+            .as_deref(),
+        Some("0")
+    );
+
+    // LXC listings return the VMIDs as strings.
+    let lxc = client.node_lxc(&node()).await.unwrap();
+    assert_eq!(lxc[0].vmid, VmId::from(200));
+    assert_eq!(lxc[1].vmid, VmId::from(201));
+    assert_eq!(lxc[1].status, VmStatus::Running);
+    assert_eq!(lxc[1].name.as_deref(), Some("dns"));
+}
+
+#[tokio::test]
+async fn cluster_status_resources_and_permissions_are_parsed() {
+    let mock = MockProxmox::start().await;
+    mock.mock_cluster();
+    let client = apikey_client(&mock).await;
+
+    let status = client.cluster_status().await.unwrap();
+    assert_eq!(status[0].r#type, ClusterStatusType::Cluster);
+    assert_eq!(status[1].r#type, ClusterStatusType::Node);
+    assert_eq!(status[1].ip.as_deref(), Some("127.0.0.1"));
+    assert_eq!(status[1].local, Some(true));
+    assert_eq!(status[2].online, Some(false));
+
+    let resources = client.cluster_resources(&Default::default()).await.unwrap();
+    let template = resources.iter().find(|res| res.id == "qemu/9000").unwrap();
+    assert!(matches!(template.vm_type(), Some(VmType::Qemu)));
+    assert_eq!(template.template, Some(true));
+    let webserver = resources.iter().find(|res| res.id == "qemu/100").unwrap();
+    assert_eq!(webserver.pool.as_deref(), Some("production"));
+    assert_eq!(webserver.tag_list(), ["prod", "web"]);
+    assert_eq!(webserver.vm_status(), VmStatus::Running);
+    assert!(resources
+        .iter()
+        .any(|res| res.r#type == ClusterResourceType::Storage));
+
+    let permissions = client.access_permissions().await.unwrap();
+    assert!(permissions.has_privilege("/vms/101", "VM.Console"));
+    assert!(permissions.has_privilege("/vms/100", "VM.PowerMgmt"));
+    assert!(!permissions.has_privilege("/vms/101", "VM.PowerMgmt"));
+    assert!(permissions.has_privilege("/nodes/pve1", "Sys.PowerMgmt"));
+}
+
+#[tokio::test]
+async fn console_proxies_are_parsed() {
+    let mock = MockProxmox::start().await;
+    mock.mock(
+        "POST",
+        &format!("nodes/{NODE}/qemu/100/spiceproxy"),
+        MockResponse::fixture("spiceproxy"),
+    );
+    mock.mock(
+        "POST",
+        &format!("nodes/{NODE}/qemu/100/vncproxy"),
+        MockResponse::fixture("vncproxy"),
+    );
+    mock.mock(
+        "POST",
+        &format!("nodes/{NODE}/lxc/201/termproxy"),
+        MockResponse::fixture("termproxy"),
+    );
+    let client = apikey_client(&mock).await;
+
+    // Ports are sometimes returned as strings.
+    let spice = client
+        .vm_spiceproxy(
+            &node(),
+            &VmId::from(100),
+            Some(VmType::Qemu),
+            VmSpiceproxyInput::default(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(spice.tls_port.get(), 61000);
+    assert_eq!(spice.proxy, "http://127.0.0.1:3128");
+    assert!(spice.host_subject.is_some());
+
+    let vnc = client
+        .vm_vncproxy(
+            &node(),
+            &VmId::from(100),
+            Some(VmType::Qemu),
+            VmVncproxyInput {
+                websocket: Some(1),
+                ..Default::default()
+            },
+        )
+        .await
+        .unwrap();
+    assert_eq!(vnc.port.get(), 5900);
+    assert_eq!(vnc.user, USER);
+    assert_eq!(
+        mock.requests_to("POST", &format!("nodes/{NODE}/qemu/100/vncproxy"))[0]
+            .form_value("websocket")
+            .as_deref(),
+        Some("1")
+    );
+
+    let (vm_type, term) = client
+        .vm_termproxy(
+            &node(),
+            &VmId::from(201),
+            Some(VmType::Lxc),
+            VmTermproxyInput::default(),
+        )
+        .await
+        .unwrap();
+    assert!(matches!(vm_type, VmType::Lxc));
+    assert_eq!(term.port.get(), 5901);
+}
+
+#[tokio::test]
+async fn notes_are_read_and_updated() {
+    let mock = MockProxmox::start().await;
+    let notes = "# Runbook\n\n**Do not reboot** during business hours.";
+    mock.mock(
+        "GET",
+        &format!("nodes/{NODE}/qemu/100/config"),
+        MockResponse::data(json!({ "name": "web", "description": notes, "tags": "prod;web" })),
+    );
+    mock.mock(
+        "GET",
+        &format!("nodes/{NODE}/lxc/200/config"),
+        MockResponse::data(json!({ "hostname": "proxy" })),
+    );
+    mock.mock(
+        "GET",
+        &format!("nodes/{NODE}/config"),
+        MockResponse::data(json!({ "description": "Rack 2" })),
+    );
+    mock.mock(
+        "PUT",
+        &format!("nodes/{NODE}/qemu/100/config"),
+        MockResponse::data(json!(null)),
+    );
+    mock.mock(
+        "PUT",
+        &format!("nodes/{NODE}/config"),
+        MockResponse::fixture("permission_denied").with_status(403),
+    );
+    let client = apikey_client(&mock).await;
+
+    let config = client
+        .vm_qemu_config(&node(), &VmId::from(100))
+        .await
+        .unwrap();
+    assert_eq!(config.description.as_deref(), Some(notes));
+    assert_eq!(config.tags.as_deref(), Some("prod;web"));
+    let config = client
+        .vm_lxc_config(&node(), &VmId::from(200))
+        .await
+        .unwrap();
+    assert_eq!(config.hostname.as_deref(), Some("proxy"));
+    assert_eq!(config.description, None);
+    let config = client.node_config(&node()).await.unwrap();
+    assert_eq!(config.description.as_deref(), Some("Rack 2"));
+
+    client
+        .vm_qemu_config_update(
+            &node(),
+            &VmId::from(100),
+            &QemuVmConfigUpdateInput {
+                description: Some("Reboot at will.".into()),
+                ..Default::default()
+            },
+        )
+        .await
+        .unwrap();
+    let update = &mock.requests_to("PUT", &format!("nodes/{NODE}/qemu/100/config"))[0];
+    assert_eq!(
+        update.form_value("description").as_deref(),
+        Some("Reboot at will.")
+    );
+    assert_eq!(update.form_value("delete"), None);
+
+    assert!(matches!(
+        client
+            .node_config_update(
+                &node(),
+                &NodeConfigUpdateInput {
+                    delete: Some("description".into()),
+                    ..Default::default()
+                },
+            )
+            .await,
+        Err(Error::Api(StatusCode::FORBIDDEN, _))
+    ));
+    let update = &mock.requests_to("PUT", &format!("nodes/{NODE}/config"))[0];
+    assert_eq!(update.form_value("delete").as_deref(), Some("description"));
+}
+
+#[tokio::test]
+async fn error_statuses_are_reported() {
+    let mock = MockProxmox::start().await;
+    mock.mock(
+        "POST",
+        &format!("nodes/{NODE}/qemu/100/status/start"),
+        MockResponse::fixture("permission_denied").with_status(403),
+    );
+    mock.mock(
+        "GET",
+        &format!("nodes/{NODE}/qemu/999/status/current"),
+        MockResponse::fixture("no_such_vm").with_status(500),
+    );
+    mock.mock("GET", "cluster/nextid", MockResponse::data(json!(null)));
+    let client = apikey_client(&mock).await;
+
+    match client
+        .vm_start(
+            &node(),
+            &VmId::from(100),
+            Some(VmType::Qemu),
+            VmStartInput::default(),
+        )
+        .await
+    {
+        Err(Error::Api(StatusCode::FORBIDDEN, reason)) => {
+            assert_eq!(reason, "Permission check failed (/vms/100, VM.PowerMgmt)")
+        }
+        other => panic!("unexpected result: {other:?}"),
+    }
+    assert!(matches!(
+        client
+            .vm_qemu_status_current(&node(), &VmId::from(999))
+            .await,
+        Err(Error::ApiUnknown(StatusCode::INTERNAL_SERVER_ERROR))
+    ));
+    assert!(matches!(
+        client.cluster_nextid().await,
+        Err(Error::MissingData)
+    ));
+}
+
+#[tokio::test]
+async fn self_signed_certificate_requires_ignoring_ssl_errors() {
+    let mock = MockProxmox::start_tls().await;
+    mock.mock_cluster();
+
+    let client = apikey_client(&mock).await;
+    assert_eq!(client.nodes().await.unwrap().len(), 2);
+
+    let strict_client = ProxmoxApiClient::connect_with_apikey(
+        &[root(&mock)],
+        TOKENID,
+        SecureString::from_str(APIKEY).unwrap(),
+        false,
+        None,
+    )
+    .await
+    .unwrap();
+    assert!(matches!(
+        strict_client.nodes().await,
+        Err(Error::Request(_))
+    ));
+}
+
+#[tokio::test]
+async fn unreachable_endpoint_fails_over_to_next() {
+    // Nothing listens on the port once the listener is dropped.
+    let unreachable_port = TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port();
+    let unreachable: Uri = format!("http://127.0.0.1:{unreachable_port}/api2/json")
+        .parse()
+        .unwrap();
+
+    let mock = MockProxmox::start().await;
+    mock.mock_ticket_auth();
+    mock.mock_cluster();
+    let client = ProxmoxApiClient::connect_with_ticket(
+        &[unreachable, root(&mock)],
+        USER,
+        SecureString::from_str(PASSWORD).unwrap(),
+        false,
+        None,
+    )
+    .await
+    .unwrap();
+
+    assert_eq!(client.nodes().await.unwrap().len(), 2);
+    assert_eq!(client.clientconfig_root(), format!("{}/", mock.root()));
+    assert_eq!(mock.requests_to("POST", "access/ticket").len(), 1);
+}
+
+#[tokio::test]
+async fn clients_need_an_endpoint_with_a_host() {
+    let connect = |roots: Vec<Uri>| {
+        ProxmoxApiClient::connect_with_apikey(
+            &roots,
+            TOKENID,
+            SecureString::from_str(APIKEY).unwrap(),
+            false,
+            None,
+        )
+    };
+
+    assert!(matches!(connect(vec![]).await, Err(Error::NoEndpoint)));
+    assert!(matches!(
+        connect(vec![Uri::from_static("/api2/json")]).await,
+        Err(Error::NoEndpoint)
+    ));
+}
+
+#[tokio::test]
+async fn added_endpoints_are_failed_over_to() {
+    let unreachable_port = TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port();
+    let unreachable: Uri = format!("http://127.0.0.1:{unreachable_port}/api2/json")
+        .parse()
+        .unwrap();
+
+    let mock = MockProxmox::start().await;
+    mock.mock_cluster();
+    let client = ProxmoxApiClient::connect_with_apikey(
+        &[unreachable.clone()],
+        TOKENID,
+        SecureString::from_str(APIKEY).unwrap(),
+        false,
+        None,
+    )
+    .await
+    .unwrap();
+    assert!(client.nodes().await.is_err());
+
+    client.add_endpoints(&[unreachable, root(&mock)]).unwrap();
+    assert_eq!(client.nodes().await.unwrap().len(), 2);
+    assert_eq!(client.clientconfig_root(), format!("{}/", mock.root()));
+}
@@ -0,0 +1,350 @@
+/* Copyright 2024 Marco Köpcke
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ *
+ * SPDX-License-Identifier: GPL-3.0-or-later
+ */
+//! Integration of the QEMU guest agent: addresses of VMs and actions run inside the guest.
+
+use std::borrow::Cow;
+use std::net::IpAddr;
+use std::time::Duration;
+
+use adw::prelude::*;
+use anyhow::anyhow;
+use futures::future::LocalBoxFuture;
+use gettextrs::gettext;
+use gtk::gdk;
+use libfieldmonitor::adapter::types::Adapter;
+use libfieldmonitor::adapter::vte_pty::{VtePtyAdapter, SSH_ADAPTER_TAG, SSH_DRIVER_BIN};
+use libfieldmonitor::connection::{ConnectionError, ConnectionResult, ServerAction};
+use libfieldmonitor::gtk::FieldMonitorActionParametersDialog;
+use libfieldmonitor::i18n::gettext_f;
+use log::{debug, warn};
+use proxmox_api::{NodeId, ProxmoxApiClient, QemuAgentSetUserPasswordInput, VmId, VmType};
+use tokio::time::timeout;
+use which::which_global;
+
+use crate::permissions::{
+    VM_GUEST_AGENT_FILE_SYSTEM_MGMT, VM_GUEST_AGENT_UNRESTRICTED, VM_MONITOR,
+};
+use crate::tokiort::run_on_tokio;
+use crate::{exec_cmd, show_toast, ExecParams, ProxmoxVm};
+
+/// The guest agent may be configured, but not running in the guest. Don't wait for it too long.
+const AGENT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Addresses reported by the guest agent, IPv4 addresses first. `None` if the guest agent is
+/// not enabled in the configuration of the VM or not available.
+///
+/// Must be run inside the Tokio runtime.
+async fn guest_addresses(
+    client: &ProxmoxApiClient,
+    node_id: &NodeId,
+    vm_id: &VmId,
+) -> Option<Vec<IpAddr>> {
+    // Don't wait for agents that are not even configured.
+    match client.vm_qemu_status_current(node_id, vm_id).await {
+        Ok(status) if status.agent != Some(true) => return None,
+        Ok(_) => {}
+        Err(err) => debug!("failed to load status of VM {vm_id}: {err}"),
+    }
+
+    match timeout(
+        AGENT_TIMEOUT,
+        client.vm_qemu_agent_network_interfaces(node_id, vm_id),
+    )
+    .await
+    {
+        Ok(Ok(interfaces)) => {
+            let (mut addresses, ipv6): (Vec<_>, Vec<_>) = interfaces
+                .iter()
+                .flat_map(|interface| interface.reachable_addresses())
+                .partition(IpAddr::is_ipv4);
+            addresses.extend(ipv6);
+            Some(addresses)
+        }
+        // Most likely the guest agent is just not installed.
+        Ok(Err(err)) => {
+            debug!("guest agent of VM {vm_id} is not available: {err}");
+            None
+        }
+        Err(_) => {
+            debug!("guest agent of VM {vm_id} did not respond in time");
+            None
+        }
+    }
+}
+
+impl ProxmoxVm {
+    /// Loads the addresses of the VM from its guest agent, if it is a running QEMU VM.
+    ///
+    /// This is only done for single VMs, not for listings: every VM costs further requests and
+    /// an agent that does not respond takes up to `AGENT_TIMEOUT`.
+    pub(crate) async fn with_guest_addresses(mut self) -> ConnectionResult<Self> {
+        if !matches!(self.vm_type, VmType::Qemu) || !self.is_running() {
+            return Ok(self);
+        }
+        run_on_tokio(async move {
+            self.guest_addresses = guest_addresses(&self.client, &self.node_id, &self.vm_id).await;
+            Ok(self)
+        })
+        .await
+    }
+
+    /// The address of the VM to show and connect to, if known.
+    pub(crate) fn primary_address(&self) -> Option<IpAddr> {
+        self.guest_addresses.as_ref()?.first().copied()
+    }
+
+    /// Whether the user may run the guest agent command. Proxmox VE 9 split `VM.Monitor`
+    /// into several `VM.GuestAgent.*` privileges.
+    fn may_use_agent(&self, privilege: &str) -> bool {
+        self.may(VM_MONITOR) || self.may(privilege)
+    }
+
+    pub(crate) fn agent_actions(&self) -> Vec<(Cow<'static, str>, Cow<'static, str>)> {
+        if !self.is_running() || self.guest_addresses.is_none() {
+            return vec![];
+        }
+        let mut actions = Vec::with_capacity(4);
+        if self.primary_address().is_some() {
+            actions.push(("vmcopyip".into(), gettext("Copy IP Address").into()));
+        }
+        if self.may_use_agent(VM_GUEST_AGENT_FILE_SYSTEM_MGMT) {
+            actions.push(("vmfsfreeze".into(), gettext("Freeze Filesystems").into()));
+            actions.push(("vmfsthaw".into(), gettext("Thaw Filesystems").into()));
+        }
+        if self.may_use_agent(VM_GUEST_AGENT_UNRESTRICTED) {
+            actions.push((
+                "vmsetuserpassword".into(),
+                gettext("Set User Password…").into(),
+            ));
+        }
+        actions
+    }
+
+    pub(crate) fn act_copy_ip<'a>(&self) -> ServerAction<'a> {
+        let title = self.title();
+        let addresses: Vec<String> = self
+            .guest_addresses
+            .iter()
+            .flatten()
+            .map(ToString::to_string)
+            .collect();
+        ServerAction::new(
+            Box::new(()),
+            Box::new(move |_params, window, toov| {
+                let title = title.clone();
+                let addresses = addresses.clone();
+                Box::pin(async move {
+                    let address = match addresses.as_slice() {
+                        [] => return false,
+                        [address] => address.clone(),
+                        _ => {
+                            let dialog = FieldMonitorActionParametersDialog::new(
+                                &gettext_f("Copy IP Address of {vm}", &[("vm", &title)]),
+                                None,
+                                &gettext("Copy"),
+                            );
+                            let choices: Vec<&str> = addresses.iter().map(String::as_str).collect();
+                            let address_row =
+                                dialog.add_combo_row(&gettext("Address"), &choices, 0);
+                            if !dialog.run(window.as_ref()).await {
+                                return false;
+                            }
+                            match addresses.get(address_row.selected() as usize) {
+                                Some(address) => address.clone(),
+                                None => return false,
+                            }
+                        }
+                    };
+
+                    let Some(display) = gdk::Display::default() else {
+                        return false;
+                    };
+                    display.clipboard().set_text(&address);
+                    show_toast(
+                        toov.as_ref(),
+                        &gettext_f(
+                            "Copied {address} to the clipboard.",
+                            &[("address", &address)],
+                        ),
+                    );
+                    false
+                })
+            }),
+        )
+    }
+
+    pub(crate) fn act_fsfreeze<'a>(&self) -> ServerAction<'a> {
+        ServerAction::new(
+            Box::new(self.params()),
+            Box::new(|params, _window, toov| {
+                Box::pin(async move {
+                    let params = params.downcast::<ExecParams>().unwrap();
+
+                    let (success, force_reload) = exec_cmd(
+                        params,
+                        |params| async move {
+                            params
+                                .client
+                                .vm_qemu_agent_fsfreeze_freeze(
+                                    &params.node_id.unwrap(),
+                                    &params.vm_id.unwrap(),
+                                )
+                                .await
+                        },
+                        || gettext("The filesystems of the VM are now frozen."),
+                        |err| {
+                            warn!("failed fsfreeze: {err:?}");
+                            gettext("Failed to freeze the filesystems.")
+                        },
+                        toov.as_ref(),
+                    )
+                    .await;
+                    success || force_reload
+                })
+            }),
+        )
+    }
+
+    pub(crate) fn act_fsthaw<'a>(&self) -> ServerAction<'a> {
+        ServerAction::new(
+            Box::new(self.params()),
+            Box::new(|params, _window, toov| {
+                Box::pin(async move {
+                    let params = params.downcast::<ExecParams>().unwrap();
+
+                    let (success, force_reload) = exec_cmd(
+                        params,
+                        |params| async move {
+                            params
+                                .client
+                                .vm_qemu_agent_fsfreeze_thaw(
+                                    &params.node_id.unwrap(),
+                                    &params.vm_id.unwrap(),
+                                )
+                                .await
+                        },
+                        || gettext("The filesystems of the VM were thawed."),
+                        |err| {
+                            warn!("failed fsthaw: {err:?}");
+                            gettext("Failed to thaw the filesystems.")
+                        },
+                        toov.as_ref(),
+                    )
+                    .await;
+                    success || force_reload
+                })
+            }),
+        )
+    }
+
+    pub(crate) fn act_set_user_password<'a>(&self) -> ServerAction<'a> {
+        let title = self.title();
+        ServerAction::new(
+            Box::new(self.params()),
+            Box::new(move |params, window, toov| {
+                let title = title.clone();
+                Box::pin(async move {
+                    let dialog = FieldMonitorActionParametersDialog::new(
+                        &gettext_f("Set User Password in {vm}", &[("vm", &title)]),
+                        Some(&gettext(
+                            "The password is set by the guest agent running inside of the VM.",
+                        )),
+                        &gettext("Set Password"),
+                    );
+                    let username_row = dialog.add_entry_row(&gettext("Username"), "root");
+                    let password_row = adw::PasswordEntryRow::builder()
+                        .title(gettext("Password"))
+                        .build();
+                    dialog.add_row(&password_row);
+
+                    if !dialog.run(window.as_ref()).await {
+                        return false;
+                    }
+
+                    let input = QemuAgentSetUserPasswordInput {
+                        username: username_row.text().to_string(),
+                        password: password_row.text().to_string(),
+                        crypted: None,
+                    };
+                    if input.username.is_empty() || input.password.is_empty() {
+                        show_toast(
+                            toov.as_ref(),
+                            &gettext("Username and password must not be empty."),
+                        );
+                        return false;
+                    }
+
+                    let params = params.downcast::<ExecParams>().unwrap();
+                    let (success, force_reload) = exec_cmd(
+                        params,
+                        move |params| {
+                            let input = input.clone();
+                            async move {
+                                params
+                                    .client
+                                    .vm_qemu_agent_set_user_password(
+                                        &params.node_id.unwrap(),
+                                        &params.vm_id.unwrap(),
+                                        &input,
+                                    )
+                                    .await
+                            }
+                        },
+                        || gettext("The password was set."),
+                        |err| {
+                            warn!("failed to set user password: {err:?}");
+                            gettext("Failed to set the password.")
+                        },
+                        toov.as_ref(),
+                    )
+                    .await;
+                    success || force_reload
+                })
+            }),
+        )
+    }
+
+    /// Adapter opening an SSH session to the primary address of the VM.
+    pub(crate) fn create_ssh_adapter(&self) -> LocalBoxFuture<ConnectionResult<Box<dyn Adapter>>> {
+        let address = self.primary_address();
+        let connection_id = self.connection_id.clone();
+        let server_id = format!("{}/{}", self.node_id, self.vm_id);
+        Box::pin(async move {
+            let address = address.ok_or_else(|| {
+                ConnectionError::General(
+                    Some(gettext("The address of the VM is not known.")),
+                    anyhow!("no guest agent address"),
+                )
+            })?;
+            let driver = which_global(SSH_DRIVER_BIN).map_err(|err| {
+                ConnectionError::General(
+                    Some(gettext("Field Monitor is not correctly installed.")),
+                    anyhow!(err),
+                )
+            })?;
+            let adapter: Box<dyn Adapter> = Box::new(VtePtyAdapter::new(
+                connection_id,
+                server_id,
+                SSH_ADAPTER_TAG.to_string(),
+                driver,
+                vec![address.to_string(), String::new(), String::new()],
+            ));
+            Ok(adapter)
+        })
+    }
+}
@@ -0,0 +1,413 @@
+/* Copyright 2024 Marco Köpcke
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ *
+ * SPDX-License-Identifier: GPL-3.0-or-later
+ */
+//! Backups of VMs and containers (vzdump).
+
+use std::borrow::Cow;
+use std::sync::Arc;
+
+use adw::prelude::*;
+use gettextrs::gettext;
+use gtk::glib;
+use libfieldmonitor::connection::ServerAction;
+use libfieldmonitor::gtk::FieldMonitorActionParametersDialog;
+use libfieldmonitor::i18n::gettext_f;
+use log::warn;
+use proxmox_api::{
+    NodeId, NodeStorageInput, ProxmoxApiClient, StorageContent, StorageContentInput,
+    StorageContentType, VmId, VzdumpCompression, VzdumpInput, VzdumpMode,
+};
+
+use crate::permissions::{VM_AUDIT, VM_BACKUP};
+use crate::task::wait_for_task;
+use crate::tokiort::run_on_tokio;
+use crate::{map_proxmox_error, show_toast, ExecParams, ProxmoxVm};
+
+const MODES: [VzdumpMode; 3] = [VzdumpMode::Snapshot, VzdumpMode::Suspend, VzdumpMode::Stop];
+const COMPRESSIONS: [VzdumpCompression; 4] = [
+    VzdumpCompression::Zstd,
+    VzdumpCompression::Gzip,
+    VzdumpCompression::Lzo,
+    VzdumpCompression::None,
+];
+
+/// A backup of the VM and the storage it is on.
+struct Backup {
+    storage: String,
+    content: StorageContent,
+}
+
+impl ProxmoxVm {
+    pub(crate) fn backup_actions(&self) -> Vec<(Cow<'static, str>, Cow<'static, str>)> {
+        let mut actions = Vec::with_capacity(2);
+        if self.may(VM_AUDIT) {
+            actions.push(("vmbackuplist".into(), gettext("Backups").into()));
+        }
+        if self.may(VM_BACKUP) {
+            actions.push(("vmbackup".into(), gettext("Back Up Now…").into()));
+        }
+        actions
+    }
+
+    pub(crate) fn act_backup_list<'a>(&self) -> ServerAction<'a> {
+        let title = self.title();
+        ServerAction::new(
+            Box::new(self.params()),
+            Box::new(move |params, window, toov| {
+                let title = title.clone();
+                Box::pin(async move {
+                    let params = params.downcast::<ExecParams>().unwrap();
+                    let Some(backups) = load_backups(&params, toov.as_ref()).await else {
+                        return false;
+                    };
+
+                    let dialog = FieldMonitorActionParametersDialog::new_informational(
+                        &gettext_f("Backups of {vm}", &[("vm", &title)]),
+                        backups
+                            .is_empty()
+                            .then(|| gettext("This VM has no backups."))
+                            .as_deref(),
+                    );
+                    for backup in &backups {
+                        dialog.add_info_row(&backup_title(backup), &backup_subtitle(backup));
+                    }
+                    dialog.run(window.as_ref()).await;
+                    false
+                })
+            }),
+        )
+    }
+
+    pub(crate) fn act_backup<'a>(&self) -> ServerAction<'a> {
+        let title = self.title();
+        ServerAction::new(
+            Box::new(self.params()),
+            Box::new(move |params, window, toov| {
+                let title = title.clone();
+                Box::pin(async move {
+                    let params = params.downcast::<ExecParams>().unwrap();
+                    let Some(storages) = load_backup_storages(&params, toov.as_ref()).await else {
+                        return false;
+                    };
+                    if storages.is_empty() {
+                        show_toast(
+                            toov.as_ref(),
+                            &gettext("There is no storage for backups on this node."),
+                        );
+                        return false;
+                    }
+
+                    let dialog = FieldMonitorActionParametersDialog::new(
+                        &gettext_f("Back Up {vm}", &[("vm", &title)]),
+                        None,
+                        &gettext("Back Up"),
+                    );
+                    let storage_names: Vec<&str> = storages.iter().map(String::as_str).collect();
+                    let storage_row = dialog.add_combo_row(&gettext("Storage"), &storage_names, 0);
+                    let mode_names: Vec<String> = MODES.iter().copied().map(mode_name).collect();
+                    let mode_names: Vec<&str> = mode_names.iter().map(String::as_str).collect();
+                    let mode_row = dialog.add_combo_row(&gettext("Mode"), &mode_names, 0);
+                    let compression_names: Vec<String> =
+                        COMPRESSIONS.iter().copied().map(compression_name).collect();
+                    let compression_names: Vec<&str> =
+                        compression_names.iter().map(String::as_str).collect();
+                    let compression_row =
+                        dialog.add_combo_row(&gettext("Compression"), &compression_names, 0);
+
+                    if !dialog.run(window.as_ref()).await {
+                        return false;
+                    }
+
+                    let Some(storage) = storages.get(storage_row.selected() as usize).cloned()
+                    else {
+                        return false;
+                    };
+                    let vm_id = params.vm_id.clone().unwrap();
+                    let input = VzdumpInput {
+                        vmid: vm_id.clone().into(),
+                        storage: Some(storage.clone()),
+                        mode: MODES.get(mode_row.selected() as usize).copied(),
+                        compress: COMPRESSIONS
+                            .get(compression_row.selected() as usize)
+                            .copied(),
+                    };
+
+                    let client = params.client.clone();
+                    let node_id = params.node_id.clone().unwrap();
+                    let upid = {
+                        let client = client.clone();
+                        let node_id = node_id.clone();
+                        run_on_tokio(async move {
+                            client
+                                .node_vzdump(&node_id, &input)
+                                .await
+                                .map_err(map_proxmox_error)
+                        })
+                        .await
+                    };
+                    let upid = match upid {
+                        Ok(upid) => upid,
+                        Err(err) => {
+                            warn!("failed to start backup: {err:?}");
+                            show_toast(toov.as_ref(), &gettext("Failed to start the backup."));
+                            return false;
+                        }
+                    };
+
+                    show_toast(
+                        toov.as_ref(),
+                        &gettext_f("Backing up {vm}…", &[("vm", &title)]),
+                    );
+
+                    let status = match wait_for_task(client.clone(), node_id.clone(), upid).await {
+                        Ok(status) if status.is_ok() => status,
+                        Ok(status) => {
+                            warn!("backup failed: {:?}", status.exitstatus);
+                            show_toast(
+                                toov.as_ref(),
+                                &gettext_f(
+                                    "Backup of {vm} failed: {error}",
+                                    &[
+                                        ("vm", &title),
+                                        ("error", status.exitstatus.as_deref().unwrap_or("?")),
+                                    ],
+                                ),
+                            );
+                            return false;
+                        }
+                        Err(err) => {
+                            warn!("failed to track backup: {err:?}");
+                            show_toast(
+                                toov.as_ref(),
+                                &gettext("Failed to retrieve the status of the backup."),
+                            );
+                            return false;
+                        }
+                    };
+
+                    // The task status has no end time, but it was polled until it stopped.
+                    let duration = status.starttime.and_then(|starttime| {
+                        let now = glib::DateTime::now_utc().ok()?.to_unix();
+                        Some(format_duration(now - starttime))
+                    });
+                    let size = newest_backup(client, node_id, vm_id, storage)
+                        .await
+                        .and_then(|backup| backup.size)
+                        .map(glib::format_size);
+
+                    let text = match (duration, size) {
+                        (Some(duration), Some(size)) => gettext_f(
+                            "Backup of {vm} finished after {duration}, its size is {size}.",
+                            &[("vm", &title), ("duration", &duration), ("size", &size)],
+                        ),
+                        (Some(duration), None) => gettext_f(
+                            "Backup of {vm} finished after {duration}.",
+                            &[("vm", &title), ("duration", &duration)],
+                        ),
+                        (None, _) => gettext_f("Backup of {vm} finished.", &[("vm", &title)]),
+                    };
+                    show_toast(toov.as_ref(), &text);
+                    false
+                })
+            }),
+        )
+    }
+}
+
+/// Loads the storages of the node of the VM that can hold backups. Shows a toast and returns
+/// `None` on error.
+async fn load_backup_storages(
+    params: &ExecParams,
+    toov: Option<&adw::ToastOverlay>,
+) -> Option<Vec<String>> {
+    let client = params.client.clone();
+    let node_id = params.node_id.clone().unwrap();
+    let result = run_on_tokio(async move {
+        backup_storages(&client, &node_id)
+            .await
+            .map_err(map_proxmox_error)
+    })
+    .await;
+
+    match result {
+        Ok(storages) => Some(storages),
+        Err(err) => {
+            warn!("failed to load backup storages: {err:?}");
+            show_toast(toov, &gettext("Failed to load the storages."));
+            None
+        }
+    }
+}
+
+/// Loads the backups of the VM on all storages of its node, newest first. Shows a toast and
+/// returns `None` on error.
+async fn load_backups(
+    params: &ExecParams,
+    toov: Option<&adw::ToastOverlay>,
+) -> Option<Vec<Backup>> {
+    let client = params.client.clone();
+    let node_id = params.node_id.clone().unwrap();
+    let vm_id = params.vm_id.clone().unwrap();
+    let result = run_on_tokio(async move {
+        let mut backups = Vec::new();
+        for storage in backup_storages(&client, &node_id)
+            .await
+            .map_err(map_proxmox_error)?
+        {
+            match backups_on_storage(&client, &node_id, &vm_id, &storage).await {
+                Ok(content) => backups.extend(content.into_iter().map(|content| Backup {
+                    storage: storage.clone(),
+                    content,
+                })),
+                Err(err) => warn!("failed to list backups on {storage}: {err}"),
+            }
+        }
+        backups.sort_by(|a, b| b.content.ctime.cmp(&a.content.ctime));
+        Ok(backups)
+    })
+    .await;
+
+    match result {
+        Ok(backups) => Some(backups),
+        Err(err) => {
+            warn!("failed to load backups: {err:?}");
+            show_toast(toov, &gettext("Failed to load the backups."));
+            None
+        }
+    }
+}
+
+/// The most recent backup of the VM on the storage, if it can be loaded.
+async fn newest_backup(
+    client: Arc<ProxmoxApiClient>,
+    node_id: NodeId,
+    vm_id: VmId,
+    storage: String,
+) -> Option<StorageContent> {
+    let result = run_on_tokio(async move {
+        backups_on_storage(&client, &node_id, &vm_id, &storage)
+            .await
+            .map_err(map_proxmox_error)
+    })
+    .await;
+
+    match result {
+        Ok(backups) => backups.into_iter().max_by_key(|backup| backup.ctime),
+        Err(err) => {
+            warn!("failed to load the new backup: {err:?}");
+            None
+        }
+    }
+}
+
+async fn backup_storages(
+    client: &ProxmoxApiClient,
+    node_id: &NodeId,
+) -> proxmox_api::Result<Vec<String>> {
+    let mut storages: Vec<_> = client
+        .node_storages(
+            node_id,
+            &NodeStorageInput {
+                content: Some(StorageContentType::Backup),
+                enabled: Some(1),
+            },
+        )
+        .await?
+        .into_iter()
+        .map(|storage| storage.storage)
+        .collect();
+    storages.sort();
+    Ok(storages)
+}
+
+async fn backups_on_storage(
+    client: &ProxmoxApiClient,
+    node_id: &NodeId,
+    vm_id: &VmId,
+    storage: &str,
+) -> proxmox_api::Result<Vec<StorageContent>> {
+    client
+        .node_storage_content(
+            node_id,
+            storage,
+            &StorageContentInput {
+                content: Some(StorageContentType::Backup),
+                vmid: Some(vm_id.clone().into()),
+            },
+        )
+        .await
+}
+
+fn mode_name(mode: VzdumpMode) -> String {
+    match mode {
+        VzdumpMode::Snapshot => gettext("Snapshot"),
+        VzdumpMode::Suspend => gettext("Suspend"),
+        VzdumpMode::Stop => gettext("Stop"),
+    }
+}
+
+fn compression_name(compression: VzdumpCompression) -> String {
+    match compression {
+        VzdumpCompression::Zstd => gettext("ZSTD (fast and good)"),
+        VzdumpCompression::Gzip => gettext("GZIP (good)"),
+        VzdumpCompression::Lzo => gettext("LZO (fast)"),
+        VzdumpCompression::None => gettext("None"),
+    }
+}
+
+fn format_duration(seconds: i64) -> String {
+    let seconds = seconds.max(0);
+    if seconds < 60 {
+        gettext_f("{seconds} s", &[("seconds", &seconds.to_string())])
+    } else {
+        gettext_f(
+            "{minutes} min {seconds} s",
+            &[
+                ("minutes", &(seconds / 60).to_string()),
+                ("seconds", &(seconds % 60).to_string()),
+            ],
+        )
+    }
+}
+
+fn backup_title(backup: &Backup) -> String {
+    backup
+        .content
+        .ctime
+        .and_then(|ctime| glib::DateTime::from_unix_local(ctime).ok())
+        .and_then(|ctime| ctime.format("%c").ok())
+        .map(|ctime| ctime.to_string())
+        .unwrap_or_else(|| backup.content.name().to_string())
+}
+
+fn backup_subtitle(backup: &Backup) -> String {
+    let mut parts = Vec::with_capacity(3);
+    parts.push(backup.storage.clone());
+    if let Some(size) = backup.content.size {
+        parts.push(glib::format_size(size).to_string());
+    }
+    if let Some(notes) = backup
+        .content
+        .notes
+        .as_deref()
+        .map(str::trim)
+        .filter(|notes| !notes.is_empty())
+    {
+        parts.push(notes.to_string());
+    }
+    parts.join(" · ")
+}
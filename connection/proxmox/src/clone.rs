@@ -0,0 +1,309 @@
+/* Copyright 2024 Marco Köpcke
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ *
+ * SPDX-License-Identifier: GPL-3.0-or-later
+ */
+//! Creating new VMs from templates.
+
+use adw::prelude::*;
+use gettextrs::gettext;
+use libfieldmonitor::adapter::vnc::VncAdapter;
+use libfieldmonitor::connection::{ServerAction, SHOW_SERVER_ACTION};
+use libfieldmonitor::gtk::FieldMonitorActionParametersDialog;
+use libfieldmonitor::i18n::gettext_f;
+use log::warn;
+use proxmox_api::{
+    NodeId, NodeStatus, NodeStorageInput, ProxmoxApiClient, QemuVmCloneInput, StorageContentType,
+    VmId,
+};
+
+use crate::permissions::VM_POWER_MGMT;
+use crate::task::{start_vm, wait_for_task};
+use crate::tokiort::run_on_tokio;
+use crate::{map_proxmox_error, show_toast, ExecParams, ProxmoxVm};
+
+/// Choices for the clone dialog.
+struct CloneOptions {
+    next_id: VmId,
+    /// Online nodes, the node of the template first.
+    nodes: Vec<NodeId>,
+    /// Storages on the node of the template that can hold disk images.
+    storages: Vec<String>,
+}
+
+impl ProxmoxVm {
+    pub(crate) fn act_clone<'a>(&self) -> ServerAction<'a> {
+        let title = self.title();
+        let connection_id = self.connection_id.clone();
+        let permissions = self.permissions.clone();
+        ServerAction::new(
+            Box::new(self.params()),
+            Box::new(move |params, window, toov| {
+                let title = title.clone();
+                let connection_id = connection_id.clone();
+                let permissions = permissions.clone();
+                Box::pin(async move {
+                    let params = params.downcast::<ExecParams>().unwrap();
+                    let Some(options) = load_clone_options(&params, toov.as_ref()).await else {
+                        return false;
+                    };
+
+                    let dialog = FieldMonitorActionParametersDialog::new(
+                        &gettext_f("Clone {vm}", &[("vm", &title)]),
+                        None,
+                        &gettext("Clone"),
+                    );
+                    let vm_id_row =
+                        dialog.add_entry_row(&gettext("VM ID"), &options.next_id.to_string());
+                    let name_row = dialog.add_entry_row(&gettext("Name"), "");
+                    let node_names: Vec<&str> = options.nodes.iter().map(AsRef::as_ref).collect();
+                    let node_row = dialog.add_combo_row(&gettext("Target Node"), &node_names, 0);
+                    let full_row = dialog.add_switch_row(
+                        &gettext("Full Clone"),
+                        Some(&gettext(
+                            "Copy all disks instead of sharing them with the template",
+                        )),
+                        false,
+                    );
+                    let same_storage = gettext("Same as Template");
+                    let storage_names: Vec<&str> = [same_storage.as_str()]
+                        .into_iter()
+                        .chain(options.storages.iter().map(String::as_str))
+                        .collect();
+                    let storage_row =
+                        dialog.add_combo_row(&gettext("Target Storage"), &storage_names, 0);
+                    full_row
+                        .bind_property("active", &storage_row, "sensitive")
+                        .sync_create()
+                        .build();
+
+                    // The new VM is not in a pool, so only privileges on all VMs apply to it.
+                    let may_start = permissions.vm(&options.next_id, None, VM_POWER_MGMT);
+                    let start_row = may_start.then(|| {
+                        dialog.add_switch_row(
+                            &gettext("Start VM"),
+                            Some(&gettext("Start the new VM after cloning")),
+                            false,
+                        )
+                    });
+                    let console_row = match &start_row {
+                        Some(start_row) if window.is_some() => {
+                            let console_row = dialog.add_switch_row(
+                                &gettext("Open Console"),
+                                Some(&gettext("Connect to the new VM once it is started")),
+                                true,
+                            );
+                            start_row
+                                .bind_property("active", &console_row, "sensitive")
+                                .sync_create()
+                                .build();
+                            Some(console_row)
+                        }
+                        _ => None,
+                    };
+
+                    if !dialog.run(window.as_ref()).await {
+                        return false;
+                    }
+
+                    let Ok(new_id) = vm_id_row.text().trim().parse::<u64>() else {
+                        show_toast(toov.as_ref(), &gettext("The VM ID must be a number."));
+                        return false;
+                    };
+                    let Some(target) = options.nodes.get(node_row.selected() as usize).cloned()
+                    else {
+                        return false;
+                    };
+                    let node_id = params.node_id.clone().unwrap();
+                    let full = full_row.is_active();
+                    let name = name_row.text().trim().to_string();
+                    let input = QemuVmCloneInput {
+                        newid: new_id,
+                        name: (!name.is_empty()).then_some(name),
+                        target: (target != node_id).then(|| target.to_string()),
+                        full: Some(if full { 1 } else { 0 }),
+                        storage: (storage_row.selected() as usize)
+                            .checked_sub(1)
+                            .and_then(|i| options.storages.get(i))
+                            .filter(|_| full)
+                            .cloned(),
+                    };
+                    let start = start_row.is_some_and(|row| row.is_active());
+                    let open_console = start && console_row.is_some_and(|row| row.is_active());
+
+                    let client = params.client.clone();
+                    let upid = {
+                        let client = client.clone();
+                        let node_id = node_id.clone();
+                        let vm_id = params.vm_id.clone().unwrap();
+                        run_on_tokio(async move {
+                            client
+                                .vm_qemu_clone(&node_id, &vm_id, &input)
+                                .await
+                                .map_err(map_proxmox_error)
+                        })
+                        .await
+                    };
+                    let upid = match upid {
+                        Ok(upid) => upid,
+                        Err(err) => {
+                            warn!("failed to start clone: {err:?}");
+                            show_toast(toov.as_ref(), &gettext("Failed to start cloning."));
+                            return false;
+                        }
+                    };
+
+                    let new_id = VmId::from(new_id);
+                    let new_id_str = new_id.to_string();
+                    let clone_vars = [("vm", title.as_str()), ("new", new_id_str.as_str())];
+                    show_toast(
+                        toov.as_ref(),
+                        &gettext_f("Cloning {vm} to {new}…", &clone_vars),
+                    );
+
+                    // The clone task runs on the node of the template, even if the target
+                    // node is a different one.
+                    match wait_for_task(client.clone(), node_id, upid).await {
+                        Ok(status) if status.is_ok() => {}
+                        Ok(status) => {
+                            warn!("clone failed: {:?}", status.exitstatus);
+                            show_toast(
+                                toov.as_ref(),
+                                &gettext_f(
+                                    "Cloning {vm} failed: {error}",
+                                    &[
+                                        ("vm", &title),
+                                        ("error", status.exitstatus.as_deref().unwrap_or("?")),
+                                    ],
+                                ),
+                            );
+                            return true;
+                        }
+                        Err(err) => {
+                            warn!("failed to track clone: {err:?}");
+                            show_toast(
+                                toov.as_ref(),
+                                &gettext("Failed to retrieve the status of the clone."),
+                            );
+                            return true;
+                        }
+                    }
+
+                    let started = start && start_vm(client, target.clone(), new_id).await;
+                    if !start {
+                        show_toast(
+                            toov.as_ref(),
+                            &gettext_f("{vm} was cloned to {new}.", &clone_vars),
+                        );
+                    } else if started {
+                        show_toast(
+                            toov.as_ref(),
+                            &gettext_f(
+                                "{vm} was cloned to {new}, which is now starting.",
+                                &clone_vars,
+                            ),
+                        );
+                    } else {
+                        show_toast(
+                            toov.as_ref(),
+                            &gettext_f(
+                                "{vm} was cloned to {new}, but it could not be started.",
+                                &clone_vars,
+                            ),
+                        );
+                    }
+
+                    if let Some(window) = &window {
+                        let path = format!("{connection_id}/{target}/{new_id_str}");
+                        let adapter_id = if open_console && started {
+                            VncAdapter::TAG.to_string()
+                        } else {
+                            String::new()
+                        };
+                        if let Err(err) = window.activate_action(
+                            SHOW_SERVER_ACTION,
+                            Some(&(path, adapter_id).to_variant()),
+                        ) {
+                            warn!("failed to show the new VM: {err}");
+                        }
+                    }
+                    true
+                })
+            }),
+        )
+    }
+}
+
+/// Loads the suggested VM ID, the target nodes and storages. Shows a toast and returns `None`
+/// on error.
+async fn load_clone_options(
+    params: &ExecParams,
+    toov: Option<&adw::ToastOverlay>,
+) -> Option<CloneOptions> {
+    let client = params.client.clone();
+    let node_id = params.node_id.clone().unwrap();
+    let result = run_on_tokio(async move {
+        fetch_clone_options(&client, &node_id)
+            .await
+            .map_err(map_proxmox_error)
+    })
+    .await;
+
+    match result {
+        Ok(options) => Some(options),
+        Err(err) => {
+            warn!("failed to load clone options: {err:?}");
+            show_toast(toov, &gettext("Failed to load the nodes and storages."));
+            None
+        }
+    }
+}
+
+async fn fetch_clone_options(
+    client: &ProxmoxApiClient,
+    node_id: &NodeId,
+) -> proxmox_api::Result<CloneOptions> {
+    let next_id = client.cluster_nextid().await?;
+
+    let mut nodes: Vec<_> = client
+        .nodes()
+        .await?
+        .into_iter()
+        .filter(|node| node.status == NodeStatus::Online && &node.node != node_id)
+        .map(|node| node.node)
+        .collect();
+    nodes.sort_by(|a, b| a.as_ref().cmp(b.as_ref()));
+    nodes.insert(0, node_id.clone());
+
+    let mut storages: Vec<_> = client
+        .node_storages(
+            node_id,
+            &NodeStorageInput {
+                content: Some(StorageContentType::Images),
+                enabled: Some(1),
+            },
+        )
+        .await?
+        .into_iter()
+        .map(|storage| storage.storage)
+        .collect();
+    storages.sort();
+
+    Ok(CloneOptions {
+        next_id,
+        nodes,
+        storages,
+    })
+}
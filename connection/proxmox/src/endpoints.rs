@@ -0,0 +1,137 @@
+/* Copyright 2024 Marco Köpcke
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ *
+ * SPDX-License-Identifier: GPL-3.0-or-later
+ */
+//! API endpoints of a connection.
+//!
+//! The API client fails over between all endpoints of a connection: the configured hostname,
+//! the configured fallback hostnames and the nodes of the cluster. The nodes are discovered
+//! after connecting and stored in the configuration, so they are also known if the configured
+//! hostnames can not be reached the next time.
+//!
+//! Discovered nodes are addressed by their IP address, which their TLS certificate is verified
+//! against. Unless certificate errors are ignored, failing over to them only works if the
+//! certificates of the nodes are valid for their IP addresses.
+
+use std::num::NonZeroU32;
+use std::str::FromStr;
+
+use anyhow::anyhow;
+use gettextrs::gettext;
+use http::uri::Authority;
+use http::Uri;
+use libfieldmonitor::connection::{ConnectionConfiguration, ConnectionError, ConnectionResult};
+use log::{debug, warn};
+use proxmox_api::{ClusterStatusType, ProxmoxApiClient};
+
+use crate::map_proxmox_error;
+use crate::preferences::ProxmoxConfiguration;
+
+const DEFAULT_PORT: u32 = 8006;
+
+/// All API roots of the connection, in the order they should be tried.
+pub(crate) fn api_roots(config: &ConnectionConfiguration) -> ConnectionResult<Vec<Uri>> {
+    let port = config.port().map(NonZeroU32::get).unwrap_or(DEFAULT_PORT);
+
+    let mut authorities = vec![format!(
+        "{}:{}",
+        config.hostname().unwrap_or_default(),
+        port
+    )];
+    for endpoint in config.additional_endpoints() {
+        match Authority::from_str(&endpoint) {
+            Ok(authority) if authority.port().is_some() => authorities.push(endpoint),
+            Ok(_) => authorities.push(format!("{endpoint}:{port}")),
+            Err(err) => warn!("ignoring invalid fallback endpoint {endpoint}: {err}"),
+        }
+    }
+    if config.discover_endpoints() {
+        authorities.extend(config.discovered_endpoints());
+    }
+
+    let mut roots: Vec<Uri> = Vec::with_capacity(authorities.len());
+    for authority in authorities {
+        let root = api_root(authority)?;
+        if !roots.contains(&root) {
+            roots.push(root);
+        }
+    }
+    Ok(roots)
+}
+
+fn api_root(authority: String) -> ConnectionResult<Uri> {
+    Uri::builder()
+        .scheme("https")
+        .authority(authority)
+        .path_and_query("/api2/json")
+        .build()
+        .map_err(|err| {
+            ConnectionError::General(
+                Some(gettext(
+                    "Was unable to build a valid URL to connect to. Check your settings.",
+                )),
+                anyhow!(err),
+            )
+        })
+}
+
+/// Looks up the nodes of the cluster and adds them to the endpoints of the client. Returns
+/// them as `host:port` authorities, to be stored in the configuration.
+/// Errors are only logged, since discovery is optional.
+pub(crate) async fn discover_endpoints(
+    client: &ProxmoxApiClient,
+    config: &ConnectionConfiguration,
+) -> Option<Vec<String>> {
+    if !config.discover_endpoints() {
+        return None;
+    }
+    let port = config.port().map(NonZeroU32::get).unwrap_or(DEFAULT_PORT);
+
+    match client.cluster_status().await {
+        Ok(status) => {
+            let nodes: Vec<String> = status
+                .into_iter()
+                .filter(|entry| entry.r#type == ClusterStatusType::Node)
+                .filter_map(|entry| entry.ip)
+                .map(|ip| {
+                    if ip.contains(':') {
+                        format!("[{ip}]:{port}")
+                    } else {
+                        format!("{ip}:{port}")
+                    }
+                })
+                .collect();
+            debug!("discovered cluster nodes: {nodes:?}");
+            let added = nodes
+                .iter()
+                .cloned()
+                .map(api_root)
+                .collect::<ConnectionResult<Vec<_>>>()
+                .and_then(|roots| client.add_endpoints(&roots).map_err(map_proxmox_error));
+            match added {
+                Ok(()) => Some(nodes),
+                Err(err) => {
+                    warn!("failed to use discovered cluster nodes: {err}");
+                    None
+                }
+            }
+        }
+        Err(err) => {
+            warn!("failed to discover cluster nodes: {err}");
+            None
+        }
+    }
+}
@@ -0,0 +1,231 @@
+/* Copyright 2024 Marco Köpcke
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ *
+ * SPDX-License-Identifier: GPL-3.0-or-later
+ */
+//! Grouping of VMs by resource pool or tag in the server tree.
+//!
+//! When grouped, a VM can be part of several groups (tags) or move between groups. Its path
+//! is then `<group>/<vmid>`, but `ProxmoxConnection::server` resolves any such path by the
+//! VM ID alone, so paths stay valid no matter which group they were opened from.
+
+use std::borrow::Cow;
+use std::sync::Arc;
+
+use anyhow::anyhow;
+use futures::future::LocalBoxFuture;
+use gettextrs::gettext;
+use indexmap::IndexMap;
+use libfieldmonitor::adapter::types::Adapter;
+use libfieldmonitor::connection::*;
+use proxmox_api::{
+    ClusterResource, ClusterResourcesFilter, ClusterResourcesInput, ProxmoxApiClient,
+};
+
+use crate::metrics::ResourceUsage;
+use crate::permissions::UserPermissions;
+use crate::preferences::ProxmoxGroupBy;
+use crate::tokiort::run_on_tokio;
+use crate::vncwebsocket::VncOptions;
+use crate::{map_proxmox_error, ProxmoxConnection, ProxmoxNode, ProxmoxVm};
+
+/// Server ID of the group containing the nodes, when grouping by pool or tag.
+pub(crate) const NODES_GROUP: &str = "nodes";
+/// Server ID of the group containing VMs without a pool or tag.
+const UNGROUPED_GROUP: &str = "ungrouped";
+
+#[derive(Clone)]
+pub(crate) struct ProxmoxGroup {
+    title: String,
+    icon: &'static str,
+    nodes: Vec<ProxmoxNode>,
+    vms: Vec<ProxmoxVm>,
+}
+
+impl Actionable for ProxmoxGroup {}
+
+impl ServerConnection for ProxmoxGroup {
+    fn metadata(&self) -> ServerMetadata {
+        ServerMetadataBuilder::default()
+            .title(self.title.clone())
+            .icon(IconSpec::Named(self.icon.into()))
+            .build()
+            .unwrap()
+    }
+
+    fn supported_adapters(&self) -> Vec<(Cow<str>, Cow<str>)> {
+        vec![]
+    }
+
+    fn create_adapter(&self, _tag: &str) -> LocalBoxFuture<ConnectionResult<Box<dyn Adapter>>> {
+        Box::pin(async move {
+            Err(ConnectionError::General(
+                None,
+                anyhow!("groups do not support adapters"),
+            ))
+        })
+    }
+
+    fn servers(&self) -> LocalBoxFuture<ConnectionResult<ServerMap>> {
+        let mut server_map: ServerMap = IndexMap::new();
+        for node in &self.nodes {
+            server_map.insert(node.id.to_string().into(), Box::new(node.clone()));
+        }
+        for vm in &self.vms {
+            server_map.insert(vm.vm_id.to_string().into(), Box::new(vm.clone()));
+        }
+        Box::pin(async move { Ok(server_map) })
+    }
+}
+
+impl ProxmoxVm {
+    /// Creates a VM from an entry of the cluster resources. Returns `None` if the resource
+    /// is not a VM.
+    pub(crate) fn from_resource(
+        client: Arc<ProxmoxApiClient>,
+        connection_id: &str,
+        resource: &ClusterResource,
+        vnc: VncOptions,
+        permissions: UserPermissions,
+    ) -> Option<Self> {
+        Some(Self {
+            client,
+            connection_id: connection_id.to_string(),
+            node_id: resource.node.clone()?,
+            vm_id: resource.vmid.clone()?,
+            vm_type: resource.vm_type()?,
+            name: resource.name.clone(),
+            status: resource.vm_status(),
+            pool: resource.pool.clone(),
+            tags: resource
+                .tag_list()
+                .into_iter()
+                .map(ToString::to_string)
+                .collect(),
+            template: resource.template.unwrap_or_default(),
+            usage: ResourceUsage {
+                cpu: resource.cpu,
+                mem: resource.mem,
+                maxmem: resource.maxmem,
+                disk: resource.disk,
+                maxdisk: resource.maxdisk,
+            },
+            vnc,
+            permissions,
+            guest_addresses: None,
+        })
+    }
+}
+
+impl ProxmoxConnection {
+    /// Loads all VMs of the cluster.
+    pub(crate) async fn cluster_vms(&self) -> ConnectionResult<Vec<ProxmoxVm>> {
+        let client = self.client.clone();
+        let resources = run_on_tokio(async move {
+            client
+                .cluster_resources(&ClusterResourcesInput {
+                    r#type: Some(ClusterResourcesFilter::Vm),
+                })
+                .await
+                .map_err(map_proxmox_error)
+        })
+        .await?;
+
+        let mut vms: Vec<_> = resources
+            .iter()
+            .filter_map(|resource| {
+                ProxmoxVm::from_resource(
+                    self.client.clone(),
+                    &self.connection_id,
+                    resource,
+                    self.vnc.clone(),
+                    self.permissions.clone(),
+                )
+            })
+            .collect();
+        vms.sort_by(|a, b| a.vm_id.cmp(&b.vm_id));
+        Ok(vms)
+    }
+
+    /// Returns the servers of the connection, grouped by pool or tag. The nodes are in a group
+    /// of their own.
+    pub(crate) async fn grouped_servers(
+        &self,
+        group_by: ProxmoxGroupBy,
+    ) -> ConnectionResult<ServerMap> {
+        let nodes = self.nodes().await?;
+        let vms = self.cluster_vms().await?;
+
+        let mut groups: IndexMap<String, ProxmoxGroup> = IndexMap::new();
+        let mut ungrouped = Vec::new();
+        for vm in vms {
+            let group_names: Vec<String> = match group_by {
+                ProxmoxGroupBy::Pool => vm.pool.iter().cloned().collect(),
+                ProxmoxGroupBy::Tag => vm.tags.clone(),
+                ProxmoxGroupBy::Node => unreachable!("node grouping is the default tree"),
+            };
+            if group_names.is_empty() {
+                ungrouped.push(vm);
+                continue;
+            }
+            for name in group_names {
+                let key = match group_by {
+                    ProxmoxGroupBy::Pool => format!("pool:{name}"),
+                    _ => format!("tag:{name}"),
+                };
+                groups
+                    .entry(key)
+                    .or_insert_with(|| ProxmoxGroup {
+                        title: name.clone(),
+                        icon: "folder-symbolic",
+                        nodes: vec![],
+                        vms: vec![],
+                    })
+                    .vms
+                    .push(vm.clone());
+            }
+        }
+        groups.sort_keys();
+
+        let mut server_map: ServerMap = IndexMap::new();
+        server_map.insert(
+            NODES_GROUP.into(),
+            Box::new(ProxmoxGroup {
+                title: gettext("Nodes"),
+                icon: "building-symbolic",
+                nodes,
+                vms: vec![],
+            }),
+        );
+        for (key, group) in groups {
+            server_map.insert(key.into(), Box::new(group));
+        }
+        if !ungrouped.is_empty() {
+            server_map.insert(
+                UNGROUPED_GROUP.into(),
+                Box::new(ProxmoxGroup {
+                    title: match group_by {
+                        ProxmoxGroupBy::Pool => gettext("No Pool"),
+                        _ => gettext("Untagged"),
+                    },
+                    icon: "folder-symbolic",
+                    nodes: vec![],
+                    vms: ungrouped,
+                }),
+            );
+        }
+        Ok(server_map)
+    }
+}
@@ -16,39 +16,70 @@
  * SPDX-License-Identifier: GPL-3.0-or-later
  */
 use std::borrow::Cow;
+use std::collections::HashMap;
 use std::future::Future;
-use std::mem;
-use std::num::NonZeroU32;
+use std::net::IpAddr;
 use std::str::FromStr;
 use std::sync::Arc;
 use std::time::Duration;
 
 use crate::credential_preferences::ProxmoxCredentialPreferences;
-use crate::preferences::{ProxmoxConfiguration, ProxmoxPreferences};
+use crate::endpoints::{api_roots, discover_endpoints};
+use crate::grouping::NODES_GROUP;
+use crate::metrics::{metrics_from_rrddata, rrd_input, ResourceUsage};
+use crate::notes::load_notes;
+use crate::permissions::{
+    UserPermissions, SYS_CONSOLE, SYS_POWER_MGMT, VM_CLONE, VM_CONFIG_CDROM, VM_CONSOLE,
+    VM_MIGRATE, VM_POWER_MGMT,
+};
+use crate::preferences::{
+    ProxmoxConfiguration, ProxmoxGroupBy, ProxmoxPreferences, DISCOVERED_ENDPOINTS,
+};
+use crate::spicetunnel::start_spice_tunnel;
 use crate::tokiort::{run_on_tokio, tkruntime};
+use crate::vncwebsocket::{vnc_adapter_creds, VncOptions};
 use adw::prelude::Cast;
 use anyhow::anyhow;
 use async_std::task::sleep;
 use futures::future::LocalBoxFuture;
 use gettextrs::gettext;
 use gtk::Widget;
-use http::Uri;
+use indexmap::IndexMap;
 use libfieldmonitor::adapter::spice::{SpiceAdapter, SpiceSessionConfigBuilder};
 use libfieldmonitor::adapter::types::Adapter;
 use libfieldmonitor::adapter::vnc::VncAdapter;
-use libfieldmonitor::adapter::vte_pty::VtePtyAdapter;
+use libfieldmonitor::adapter::vte_pty::{VtePtyAdapter, SSH_ADAPTER_TAG};
 use libfieldmonitor::connection::*;
-use log::{error, warn};
+use libfieldmonitor::gtk::show_toast;
+use libfieldmonitor::i18n::gettext_f;
+use log::{debug, error, warn};
 use proxmox_api::{
-    NodeId, NodeStatus, ProxmoxApiClient, Spiceproxy, Termproxy, VmConsoleProxyType, VmId,
-    VmStatus, VmType, Vncproxy,
+    split_tags, ClusterResourcesFilter, ClusterResourcesInput, NodeId, NodeStatus,
+    ProxmoxApiClient, ProxyConfig, Spiceproxy, Termproxy, VmConsoleProxyType, VmId, VmStatus,
+    VmTermproxyInput, VmTermproxySerial, VmType, Vncproxy,
 };
 use secure_string::SecureString;
 use which::which_global;
 
+mod agent;
+mod backup;
+mod clone;
 mod credential_preferences;
+mod endpoints;
+mod grouping;
+mod media;
+mod metrics;
+mod migrate;
+mod notes;
+mod permissions;
 mod preferences;
+mod snapshot;
+mod spicetunnel;
+mod task;
+#[cfg(test)]
+mod tests;
 mod tokiort;
+mod vncwebsocket;
 
 pub const PTY_DRIVER_BIN: &str = "de.capypara.FieldMonitor.PtyDrv.Proxmox";
 
@@ -161,29 +192,16 @@ struct ProxmoxConnection {
     connection_id: String,
     title: String,
     client: Arc<ProxmoxApiClient>,
+    group_by: ProxmoxGroupBy,
+    vnc: VncOptions,
+    permissions: UserPermissions,
+    /// Nodes of the cluster discovered while connecting, to be stored in the configuration.
+    discovered_endpoints: Option<Vec<String>>,
 }
 
 impl ProxmoxConnection {
     async fn connect(config: ConnectionConfiguration) -> ConnectionResult<Self> {
-        let authority = format!(
-            "{}:{}",
-            config.hostname().unwrap_or_default(),
-            config.port().map(NonZeroU32::get).unwrap_or(8006)
-        );
-
-        let api_root = Uri::builder()
-            .scheme("https")
-            .authority(authority)
-            .path_and_query("/api2/json")
-            .build()
-            .map_err(|err| {
-                ConnectionError::General(
-                    Some(gettext(
-                        "Was unable to build a valid URL to connect to. Check your settings.",
-                    )),
-                    anyhow!(err),
-                )
-            })?;
+        let api_roots = api_roots(&config)?;
 
         let pass = config
             .password_or_apikey()
@@ -198,32 +216,82 @@ impl ProxmoxConnection {
             })?
             .unwrap_or_else(|| SecureString::from_str("").unwrap());
 
+        let proxy = config.proxy().await.map_err(|err| {
+            ConnectionError::General(
+                Some(gettext(
+                    "Failed to retrieve the proxy password from secrets service.",
+                )),
+                anyhow!(err),
+            )
+        })?;
+
         let client = if config.use_apikey() {
             ProxmoxApiClient::connect_with_apikey(
-                &api_root,
+                &api_roots,
                 config.tokenid().unwrap_or_default(),
                 pass,
                 config.ignore_ssl_cert_error(),
+                proxy,
             )
             .await
             .map_err(map_proxmox_error)
         } else {
             ProxmoxApiClient::connect_with_ticket(
-                &api_root,
+                &api_roots,
                 config.username().unwrap_or_default(),
                 pass,
                 config.ignore_ssl_cert_error(),
+                proxy,
             )
             .await
             .map_err(map_proxmox_error)
         }?;
 
+        let discovered_endpoints = discover_endpoints(&client, &config).await;
+        let permissions = UserPermissions::load(&client).await;
+
         Ok(Self {
             connection_id: config.id().to_string(),
             title: config.title().unwrap_or_default().to_string(),
             client: Arc::new(client),
+            group_by: config.group_by(),
+            vnc: VncOptions::new(config.force_vnc_websocket()),
+            permissions,
+            discovered_endpoints,
         })
     }
+
+    /// Loads all nodes of the cluster.
+    async fn nodes(&self) -> ConnectionResult<Vec<ProxmoxNode>> {
+        let connection_id = self.connection_id.clone();
+        let client = self.client.clone();
+        let vnc = self.vnc.clone();
+        let permissions = self.permissions.clone();
+        run_on_tokio(async move {
+            Ok(client
+                .nodes()
+                .await
+                .map_err(map_proxmox_error)?
+                .into_iter()
+                .map(|node| ProxmoxNode {
+                    client: client.clone(),
+                    connection_id: connection_id.clone(),
+                    id: node.node,
+                    status: node.status,
+                    usage: ResourceUsage {
+                        cpu: node.cpu,
+                        mem: node.mem,
+                        maxmem: node.maxmem,
+                        disk: node.disk,
+                        maxdisk: node.maxdisk,
+                    },
+                    vnc: vnc.clone(),
+                    permissions: permissions.clone(),
+                })
+                .collect())
+        })
+        .await
+    }
 }
 
 impl Actionable for ProxmoxConnection {}
@@ -239,64 +307,225 @@ impl Connection for ProxmoxConnection {
 
     fn servers(&self) -> LocalBoxFuture<ConnectionResult<ServerMap>> {
         Box::pin(async move {
-            let connection_id = self.connection_id.clone();
-            let client = self.client.clone();
-            let map = run_on_tokio(async move {
-                let mut server_map = ServerMapSend::default();
-
-                for node in client.nodes().await.map_err(map_proxmox_error)? {
-                    server_map.insert(
-                        node.node.to_string().into(),
-                        Box::new(ProxmoxNode {
-                            client: client.clone(),
-                            connection_id: connection_id.clone(),
-                            id: node.node,
-                            status: NodeStatus::Online,
-                        }),
-                    );
-                }
+            if self.group_by != ProxmoxGroupBy::Node {
+                return self.grouped_servers(self.group_by).await;
+            }
 
-                Ok(server_map)
-            })
-            .await?;
+            let mut server_map: ServerMap = IndexMap::new();
+            for node in self.nodes().await? {
+                server_map.insert(node.id.to_string().into(), Box::new(node));
+            }
+            Ok(server_map)
+        })
+    }
 
-            // TODO: Is this actually safe?
-            let map_cast: ServerMap = unsafe { mem::transmute(map) };
+    fn configuration_updates(&self) -> Vec<(String, serde_yaml::Value)> {
+        self.discovered_endpoints
+            .iter()
+            .map(|endpoints| (DISCOVERED_ENDPOINTS.to_string(), endpoints.clone().into()))
+            .collect()
+    }
 
-            Ok(map_cast)
+    fn server<'a>(
+        &'a self,
+        path: &'a [String],
+    ) -> LocalBoxFuture<'a, ConnectionResult<Option<Box<dyn ServerConnection>>>> {
+        Box::pin(async move {
+            let id = match path {
+                [id] => id,
+                // When grouping by pool or tag, the nodes are in a group of their own.
+                [group, node_id]
+                    if group == NODES_GROUP && self.group_by != ProxmoxGroupBy::Node =>
+                {
+                    node_id
+                }
+                // In the default tree, the VM is looked up on the node of the path first. If it is
+                // not there, it was migrated or the path is from another grouping, so it is looked
+                // up by its ID in the whole cluster.
+                [parent, vm_id] => {
+                    let Ok(vm_id) = vm_id.parse::<u64>().map(VmId::from) else {
+                        return Ok(None);
+                    };
+                    let mut vm = None;
+                    if self.group_by == ProxmoxGroupBy::Node {
+                        if let Some(node) = self
+                            .nodes()
+                            .await?
+                            .into_iter()
+                            .find(|node| node.id.as_ref() == parent)
+                        {
+                            vm = match node.vms().await {
+                                Ok(vms) => vms.into_iter().find(|vm| vm.vm_id == vm_id),
+                                Err(err) => {
+                                    debug!("failed to load VMs of node {parent}: {err:?}");
+                                    None
+                                }
+                            };
+                        }
+                    }
+                    if vm.is_none() {
+                        vm = self
+                            .cluster_vms()
+                            .await?
+                            .into_iter()
+                            .find(|vm| vm.vm_id == vm_id);
+                    }
+                    let Some(vm) = vm else {
+                        return Ok(None);
+                    };
+                    return Ok(Some(Box::new(vm.with_guest_addresses().await?)));
+                }
+                _ => return Ok(None),
+            };
+            if let Some(node) = self
+                .nodes()
+                .await?
+                .into_iter()
+                .find(|node| node.id.as_ref() == id)
+            {
+                return Ok(Some(Box::new(node)));
+            }
+            // Not a node, may still be a group.
+            Ok(self.servers().await?.swap_remove(id.as_str()))
         })
     }
 }
 
+#[derive(Clone)]
 struct ProxmoxNode {
     client: Arc<ProxmoxApiClient>,
     connection_id: String,
     id: NodeId,
     status: NodeStatus,
+    usage: ResourceUsage,
+    vnc: VncOptions,
+    permissions: UserPermissions,
 }
 
 impl Actionable for ProxmoxNode {
     fn actions(&self) -> Vec<(Cow<'static, str>, Cow<'static, str>)> {
-        if self.status != NodeStatus::Offline {
+        let mut actions = if self.status != NodeStatus::Offline
+            && self.permissions.node(&self.id, SYS_POWER_MGMT)
+        {
             vec![
                 ("nodereboot".into(), gettext("Reboot").into()),
                 ("nodeshutdown".into(), gettext("Shutdown").into()),
             ]
         } else {
             vec![]
+        };
+        if self.status != NodeStatus::Offline {
+            actions.extend(self.notes_actions());
         }
+        actions
     }
 
     fn action<'a>(&self, action_id: &str) -> Option<ServerAction<'a>> {
         match action_id {
             "nodereboot" => Some(self.act_reboot()),
             "nodeshutdown" => Some(self.act_shutdown()),
+            "nodeeditnotes" => Some(self.act_edit_notes()),
             _ => None,
         }
     }
 }
 
 impl ProxmoxNode {
+    /// Loads the containers and VMs of the node.
+    async fn vms(&self) -> ConnectionResult<Vec<ProxmoxVm>> {
+        let client = self.client.clone();
+        let connection_id = self.connection_id.clone();
+        let node_id = self.id.clone();
+        let vnc = self.vnc.clone();
+        let permissions = self.permissions.clone();
+
+        run_on_tokio(async move {
+            let mut vms = Vec::new();
+
+            // The node listings do not contain the pools, which can grant permissions.
+            let pools: HashMap<String, String> = client
+                .cluster_resources(&ClusterResourcesInput {
+                    r#type: Some(ClusterResourcesFilter::Vm),
+                })
+                .await
+                .map(|resources| {
+                    resources
+                        .into_iter()
+                        .filter_map(|resource| Some((resource.vmid?.to_string(), resource.pool?)))
+                        .collect()
+                })
+                .unwrap_or_else(|err| {
+                    warn!("failed to load pools of VMs: {err:?}");
+                    HashMap::new()
+                });
+
+            for vm in client.node_lxc(&node_id).await.map_err(map_proxmox_error)? {
+                let pool = pools.get(&vm.vmid.to_string()).cloned();
+                vms.push(ProxmoxVm {
+                    client: client.clone(),
+                    connection_id: connection_id.clone(),
+                    node_id: node_id.clone(),
+                    vm_id: vm.vmid,
+                    vm_type: VmType::Lxc,
+                    name: vm.name,
+                    status: vm.status,
+                    pool,
+                    tags: split_tags(vm.tags.as_deref())
+                        .into_iter()
+                        .map(ToString::to_string)
+                        .collect(),
+                    template: false,
+                    usage: ResourceUsage {
+                        cpu: vm.cpu,
+                        mem: vm.mem,
+                        maxmem: vm.maxmem,
+                        disk: vm.disk,
+                        maxdisk: vm.maxdisk,
+                    },
+                    vnc: vnc.clone(),
+                    permissions: permissions.clone(),
+                    guest_addresses: None,
+                });
+            }
+
+            for vm in client
+                .node_qemu(&node_id, false)
+                .await
+                .map_err(map_proxmox_error)?
+            {
+                let pool = pools.get(&vm.vmid.to_string()).cloned();
+                vms.push(ProxmoxVm {
+                    client: client.clone(),
+                    connection_id: connection_id.clone(),
+                    node_id: node_id.clone(),
+                    vm_id: vm.vmid,
+                    vm_type: VmType::Qemu,
+                    name: vm.name,
+                    status: vm.status,
+                    pool,
+                    tags: split_tags(vm.tags.as_deref())
+                        .into_iter()
+                        .map(ToString::to_string)
+                        .collect(),
+                    template: vm.template.unwrap_or_default(),
+                    usage: ResourceUsage {
+                        cpu: vm.cpu,
+                        mem: vm.mem,
+                        maxmem: vm.maxmem,
+                        disk: vm.disk,
+                        maxdisk: vm.maxdisk,
+                    },
+                    vnc: vnc.clone(),
+                    permissions: permissions.clone(),
+                    guest_addresses: None,
+                });
+            }
+
+            Ok(vms)
+        })
+        .await
+    }
+
     fn params(&self) -> ExecParams {
         ExecParams {
             client: self.client.clone(),
@@ -366,8 +595,14 @@ impl ServerConnection for ProxmoxNode {
             NodeStatus::Offline => Some(false),
             NodeStatus::Unknown => None,
         };
+        let subtitle = if self.status == NodeStatus::Online {
+            self.usage.subtitle()
+        } else {
+            None
+        };
         ServerMetadataBuilder::default()
             .title(self.id.to_string())
+            .subtitle(subtitle)
             .icon(IconSpec::Named("building-symbolic".into()))
             .is_online(is_online)
             .build()
@@ -375,7 +610,7 @@ impl ServerConnection for ProxmoxNode {
     }
 
     fn supported_adapters(&self) -> Vec<(Cow<str>, Cow<str>)> {
-        if self.status == NodeStatus::Offline {
+        if self.status == NodeStatus::Offline || !self.permissions.node(&self.id, SYS_CONSOLE) {
             vec![]
         } else {
             vec![
@@ -393,64 +628,51 @@ impl ServerConnection for ProxmoxNode {
             self.id.as_ref(),
             self.client.clone(),
             ProxmoxEntity::Node(self.id.clone()),
+            self.vnc.clone(),
         )
     }
 
     fn servers(&self) -> LocalBoxFuture<ConnectionResult<ServerMap>> {
         Box::pin(async move {
-            let client = self.client.clone();
-            let connection_id = self.connection_id.clone();
-            let node_id = self.id.clone();
-
-            let map = run_on_tokio(async move {
-                let mut server_map = ServerMapSend::default();
-
-                for vm in client.node_lxc(&node_id).await.map_err(map_proxmox_error)? {
-                    server_map.insert(
-                        vm.vmid.to_string().into(),
-                        Box::new(ProxmoxVm {
-                            client: client.clone(),
-                            connection_id: connection_id.clone(),
-                            node_id: node_id.clone(),
-                            vm_id: vm.vmid,
-                            vm_type: VmType::Lxc,
-                            name: vm.name,
-                            status: vm.status,
-                        }),
-                    );
-                }
+            Ok(self
+                .vms()
+                .await?
+                .into_iter()
+                .map(|vm| {
+                    let id = vm.vm_id.to_string().into();
+                    (id, Box::new(vm) as Box<dyn ServerConnection>)
+                })
+                .collect())
+        })
+    }
 
-                for vm in client
-                    .node_qemu(&node_id, false)
+    fn metrics(&self) -> LocalBoxFuture<ConnectionResult<Vec<ServerMetric>>> {
+        if self.status == NodeStatus::Offline {
+            return Box::pin(async move { Ok(Vec::new()) });
+        }
+        let client = self.client.clone();
+        let node_id = self.id.clone();
+        Box::pin(async move {
+            let points = run_on_tokio(async move {
+                client
+                    .node_rrddata(&node_id, &rrd_input())
                     .await
-                    .map_err(map_proxmox_error)?
-                {
-                    server_map.insert(
-                        vm.vmid.to_string().into(),
-                        Box::new(ProxmoxVm {
-                            client: client.clone(),
-                            connection_id: connection_id.clone(),
-                            node_id: node_id.clone(),
-                            vm_id: vm.vmid,
-                            vm_type: VmType::Qemu,
-                            name: vm.name,
-                            status: vm.status,
-                        }),
-                    );
-                }
-
-                Ok(server_map)
+                    .map_err(map_proxmox_error)
             })
             .await?;
-
-            // TODO: Is this actually safe?
-            let map_cast: ServerMap = unsafe { mem::transmute(map) };
-
-            Ok(map_cast)
+            Ok(metrics_from_rrddata(&points))
         })
     }
+
+    fn notes(&self) -> LocalBoxFuture<ConnectionResult<Option<String>>> {
+        if self.status == NodeStatus::Offline {
+            return Box::pin(async move { Ok(None) });
+        }
+        Box::pin(load_notes(self.params()))
+    }
 }
 
+#[derive(Clone)]
 struct ProxmoxVm {
     client: Arc<ProxmoxApiClient>,
     connection_id: String,
@@ -459,11 +681,23 @@ struct ProxmoxVm {
     vm_type: VmType,
     name: Option<String>,
     status: VmStatus,
+    pool: Option<String>,
+    tags: Vec<String>,
+    /// Templates can not be started, only cloned.
+    template: bool,
+    usage: ResourceUsage,
+    vnc: VncOptions,
+    permissions: UserPermissions,
+    /// Addresses reported by the guest agent, the primary address first. `None` if the guest
+    /// agent is not available or the addresses were not loaded, see `with_guest_addresses`.
+    guest_addresses: Option<Vec<IpAddr>>,
 }
 
 impl Actionable for ProxmoxVm {
     fn actions(&self) -> Vec<(Cow<'static, str>, Cow<'static, str>)> {
-        if self.status == VmStatus::Running {
+        let mut actions = if self.template || !self.may(VM_POWER_MGMT) {
+            vec![]
+        } else if self.is_running() {
             match self.vm_type {
                 VmType::Lxc => vec![
                     ("vmreboot".into(), gettext("Reboot").into()),
@@ -479,7 +713,21 @@ impl Actionable for ProxmoxVm {
             }
         } else {
             vec![("vmstart".into(), gettext("Start / Resume").into())]
+        };
+        if matches!(self.vm_type, VmType::Qemu) && self.may(VM_CONFIG_CDROM) {
+            actions.push(("vmchangemedia".into(), gettext("Change CD/DVD…").into()));
+        }
+        if self.may(VM_MIGRATE) {
+            actions.push(("vmmigrate".into(), gettext("Migrate…").into()));
         }
+        if self.template && matches!(self.vm_type, VmType::Qemu) && self.may(VM_CLONE) {
+            actions.push(("vmclone".into(), gettext("Clone…").into()));
+        }
+        actions.extend(self.snapshot_actions());
+        actions.extend(self.backup_actions());
+        actions.extend(self.notes_actions());
+        actions.extend(self.agent_actions());
+        actions
     }
 
     fn action<'a>(&self, action_id: &str) -> Option<ServerAction<'a>> {
@@ -489,12 +737,43 @@ impl Actionable for ProxmoxVm {
             "vmreset" => Some(self.act_reset()),
             "vmstop" => Some(self.act_stop()),
             "vmstart" => Some(self.act_start()),
+            "vmchangemedia" => Some(self.act_change_media()),
+            "vmmigrate" => Some(self.act_migrate()),
+            "vmclone" => Some(self.act_clone()),
+            "vmsnapshotlist" => Some(self.act_snapshot_list()),
+            "vmsnapshotcreate" => Some(self.act_snapshot_create()),
+            "vmsnapshotrollback" => Some(self.act_snapshot_rollback()),
+            "vmsnapshotdelete" => Some(self.act_snapshot_delete()),
+            "vmbackuplist" => Some(self.act_backup_list()),
+            "vmbackup" => Some(self.act_backup()),
+            "vmeditnotes" => Some(self.act_edit_notes()),
+            "vmcopyip" => Some(self.act_copy_ip()),
+            "vmfsfreeze" => Some(self.act_fsfreeze()),
+            "vmfsthaw" => Some(self.act_fsthaw()),
+            "vmsetuserpassword" => Some(self.act_set_user_password()),
             _ => None,
         }
     }
 }
 
 impl ProxmoxVm {
+    fn title(&self) -> String {
+        match &self.name {
+            None => self.vm_id.to_string(),
+            Some(name) => format!("{} ({})", self.vm_id, name),
+        }
+    }
+
+    fn is_running(&self) -> bool {
+        self.status == VmStatus::Running
+    }
+
+    /// Whether the user has the privilege on this VM.
+    fn may(&self, privilege: &str) -> bool {
+        self.permissions
+            .vm(&self.vm_id, self.pool.as_deref(), privilege)
+    }
+
     fn params(&self) -> ExecParams {
         ExecParams {
             client: self.client.clone(),
@@ -682,20 +961,29 @@ impl ServerConnection for ProxmoxVm {
             VmStatus::Unknown => None,
         };
 
-        let title = match &self.name {
-            None => self.vm_id.to_string(),
-            Some(name) => format!("{} ({})", self.vm_id, name),
-        };
+        let title = self.title();
 
         let icon = match self.vm_type {
             VmType::Lxc => IconSpec::Named("container-symbolic".into()),
             VmType::Qemu => IconSpec::Default,
         };
 
+        let subtitle = if self.is_running() {
+            match (self.primary_address(), self.usage.subtitle()) {
+                (Some(address), Some(usage)) => Some(format!("{address} · {usage}")),
+                (Some(address), None) => Some(address.to_string()),
+                (None, usage) => usage,
+            }
+        } else {
+            None
+        };
+
         ServerMetadataBuilder::default()
             .title(title)
+            .subtitle(subtitle)
             .icon(icon)
             .is_online(is_online)
+            .tags(self.tags.clone())
             .build()
             .unwrap()
     }
@@ -717,7 +1005,7 @@ impl ServerConnection for ProxmoxVm {
             };
         }
 
-        if self.status != VmStatus::Running {
+        let mut adapters = if !self.is_running() || !self.may(VM_CONSOLE) {
             vec![]
         } else {
             // TODO: Async?
@@ -738,6 +1026,26 @@ impl ServerConnection for ProxmoxVm {
                     adapters.push(TERM!());
                 }
 
+                if matches!(self.vm_type, VmType::Qemu) {
+                    match self.client.vm_qemu_config(&self.node_id, &self.vm_id).await {
+                        Ok(config) => {
+                            for serial in config.serial_ports() {
+                                adapters.push((
+                                    serial_adapter_tag(serial).into(),
+                                    gettext_f(
+                                        "Serial Console ({port})",
+                                        &[("port", serial.as_str())],
+                                    )
+                                    .into(),
+                                ));
+                            }
+                        }
+                        Err(err) => {
+                            warn!("Failed to load serial ports of VM: {err:?}");
+                        }
+                    }
+                }
+
                 Ok(adapters)
             });
 
@@ -745,18 +1053,54 @@ impl ServerConnection for ProxmoxVm {
                 error!("Failed to load available connectors for a VM: {err:?}. Assume all.");
                 vec![SPICE!(), VNC!(), TERM!()]
             })
+        };
+        if self.is_running() && self.primary_address().is_some() {
+            adapters.push((SSH_ADAPTER_TAG.into(), gettext("SSH").into()));
         }
+        adapters
     }
 
     fn create_adapter(&self, tag: &str) -> LocalBoxFuture<ConnectionResult<Box<dyn Adapter>>> {
+        if tag == SSH_ADAPTER_TAG {
+            return self.create_ssh_adapter();
+        }
         create_proxmox_adapter(
             tag,
             &self.connection_id,
             &format!("{}/{}", self.node_id, self.vm_id),
             self.client.clone(),
             ProxmoxEntity::Vm(self.vm_type, self.node_id.clone(), self.vm_id.clone()),
+            self.vnc.clone(),
         )
     }
+
+    fn metrics(&self) -> LocalBoxFuture<ConnectionResult<Vec<ServerMetric>>> {
+        if !self.is_running() {
+            return Box::pin(async move { Ok(Vec::new()) });
+        }
+        let client = self.client.clone();
+        let node_id = self.node_id.clone();
+        let vm_id = self.vm_id.clone();
+        let vm_type = self.vm_type;
+        Box::pin(async move {
+            let points = run_on_tokio(async move {
+                client
+                    .vm_rrddata(&node_id, &vm_id, Some(vm_type), &rrd_input())
+                    .await
+                    .map_err(map_proxmox_error)
+            })
+            .await?;
+            Ok(metrics_from_rrddata(&points))
+        })
+    }
+
+    fn notes(&self) -> LocalBoxFuture<ConnectionResult<Option<String>>> {
+        Box::pin(load_notes(self.params()))
+    }
+
+    fn is_migrating(&self) -> LocalBoxFuture<ConnectionResult<bool>> {
+        self.check_migrating()
+    }
 }
 
 fn map_proxmox_error(error: proxmox_api::Error) -> ConnectionError {
@@ -766,6 +1110,7 @@ fn map_proxmox_error(error: proxmox_api::Error) -> ConnectionError {
     }
 }
 
+#[derive(Clone)]
 struct ExecParams {
     client: Arc<ProxmoxApiClient>,
     node_id: Option<NodeId>,
@@ -809,9 +1154,7 @@ where
         sleep(Duration::from_millis(750)).await;
     }
 
-    if let Some(toov) = toov {
-        toov.add_toast(adw::Toast::builder().title(&text).timeout(5).build());
-    }
+    show_toast(toov, &text);
     (success, should_reload)
 }
 
@@ -822,6 +1165,8 @@ enum ProxmoxEntity {
 
 enum AdapterCreds {
     Vnc(Vncproxy),
+    /// VNC tunnelled through the API, relayed on the given local port.
+    VncWebsocket(Vncproxy, u16),
     Spice(Spiceproxy),
     Term(Termproxy),
 }
@@ -832,14 +1177,17 @@ fn create_proxmox_adapter<'a>(
     server_id: &str,
     client: Arc<ProxmoxApiClient>,
     entity: ProxmoxEntity,
+    vnc: VncOptions,
 ) -> LocalBoxFuture<'a, ConnectionResult<Box<dyn Adapter>>> {
     let connection_id = connection_id.to_string();
     let server_id = server_id.to_string();
     let adapter_tag = adapter_tag.to_string();
+    let serial = serial_adapter_port(&adapter_tag);
     let adapter_type = match &*adapter_tag {
         SpiceAdapter::TAG => VmConsoleProxyType::Spice,
         VncAdapter::TAG => VmConsoleProxyType::Vnc,
         VtePtyAdapter::TAG => VmConsoleProxyType::Term,
+        _ if serial.is_some() => VmConsoleProxyType::Term,
         _ => {
             return Box::pin(async move {
                 Err(ConnectionError::General(
@@ -853,12 +1201,7 @@ fn create_proxmox_adapter<'a>(
     Box::pin(run_on_tokio(async move {
         let adapter_creds = match &entity {
             ProxmoxEntity::Node(node_id) => match adapter_type {
-                VmConsoleProxyType::Vnc => AdapterCreds::Vnc(
-                    client
-                        .node_vncshell(node_id, Default::default())
-                        .await
-                        .map_err(map_proxmox_error)?,
-                ),
+                VmConsoleProxyType::Vnc => vnc_adapter_creds(&client, &entity, &vnc).await?,
                 VmConsoleProxyType::Spice => AdapterCreds::Spice(
                     client
                         .node_spiceshell(node_id, Default::default())
@@ -873,12 +1216,7 @@ fn create_proxmox_adapter<'a>(
                 ),
             },
             ProxmoxEntity::Vm(vm_type, node_id, vm_id) => match adapter_type {
-                VmConsoleProxyType::Vnc => AdapterCreds::Vnc(
-                    client
-                        .vm_vncproxy(node_id, vm_id, Some(*vm_type), Default::default())
-                        .await
-                        .map_err(map_proxmox_error)?,
-                ),
+                VmConsoleProxyType::Vnc => vnc_adapter_creds(&client, &entity, &vnc).await?,
                 VmConsoleProxyType::Spice => AdapterCreds::Spice(
                     client
                         .vm_spiceproxy(node_id, vm_id, Some(*vm_type), Default::default())
@@ -887,7 +1225,7 @@ fn create_proxmox_adapter<'a>(
                 ),
                 VmConsoleProxyType::Term => AdapterCreds::Term(
                     client
-                        .vm_termproxy(node_id, vm_id, Some(*vm_type), Default::default())
+                        .vm_termproxy(node_id, vm_id, Some(*vm_type), VmTermproxyInput { serial })
                         .await
                         .map_err(map_proxmox_error)?
                         .1,
@@ -897,33 +1235,52 @@ fn create_proxmox_adapter<'a>(
 
         let adapter: Box<dyn Adapter> = match adapter_creds {
             AdapterCreds::Vnc(vncproxy) => Box::new(VncAdapter::new_with_ca(
-                client.clientconfig_hostname().to_string(),
+                client.clientconfig_hostname(),
                 vncproxy.port.into(),
                 vncproxy.user,
                 vncproxy.ticket.into(),
                 vncproxy.cert,
             )),
-            AdapterCreds::Spice(spiceproxy) => Box::new(SpiceAdapter::new_with_custom_config(
-                SpiceSessionConfigBuilder::default()
-                    .host(Some(spiceproxy.host))
-                    .password(Some(spiceproxy.password.into()))
-                    .proxy(Some(spiceproxy.proxy))
-                    .tls_port(Some(spiceproxy.tls_port))
-                    .ca(spiceproxy
-                        .ca
-                        .map(|s| s.replace(r"\n", "\n"))
-                        .map(String::into_bytes))
-                    .cert_subject(spiceproxy.host_subject)
-                    .build()
-                    .unwrap(),
+            AdapterCreds::VncWebsocket(vncproxy, local_port) => Box::new(VncAdapter::new(
+                "127.0.0.1".to_string(),
+                local_port.into(),
+                vncproxy.user,
+                vncproxy.ticket.into(),
             )),
+            AdapterCreds::Spice(spiceproxy) => {
+                let proxy = match client.clientconfig_proxy() {
+                    Some(proxy) => start_spice_tunnel(proxy.clone(), &spiceproxy.proxy)
+                        .await
+                        .map_err(|err| {
+                            ConnectionError::General(
+                                Some(gettext("Failed to route SPICE through the proxy.")),
+                                err,
+                            )
+                        })?,
+                    None => spiceproxy.proxy,
+                };
+                Box::new(SpiceAdapter::new_with_custom_config(
+                    SpiceSessionConfigBuilder::default()
+                        .host(Some(spiceproxy.host))
+                        .password(Some(spiceproxy.password.into()))
+                        .proxy(Some(proxy))
+                        .tls_port(Some(spiceproxy.tls_port))
+                        .ca(spiceproxy
+                            .ca
+                            .map(|s| s.replace(r"\n", "\n"))
+                            .map(String::into_bytes))
+                        .cert_subject(spiceproxy.host_subject)
+                        .build()
+                        .unwrap(),
+                ))
+            }
             AdapterCreds::Term(termproxy) => {
                 let (node_id, vm_type, vm_id) = match entity {
                     ProxmoxEntity::Node(node_id) => {
                         (node_id.to_string(), String::new(), String::new())
                     }
                     ProxmoxEntity::Vm(vm_type, node_id, vm_id) => {
-                        (vm_type.to_string(), node_id.to_string(), vm_id.to_string())
+                        (node_id.to_string(), vm_type.to_string(), vm_id.to_string())
                     }
                 };
                 let ignore_ssl_errors = if client.clientconfig_ignore_ssl_errors() {
@@ -939,7 +1296,7 @@ fn create_proxmox_adapter<'a>(
                     which_global(PTY_DRIVER_BIN).expect("failed to find libvirt vte driver in path. Is Field Monitor correctly installed?"),
                     vec![
                         client.clientconfig_connection_type().to_string(),
-                        client.clientconfig_root().to_string(),
+                        client.clientconfig_root(),
                         client.clientconfig_user_or_tokenid().to_string(),
                         client.clientconfig_password_or_apikey().unsecure().to_string(),
                         ignore_ssl_errors.to_string(),
@@ -949,7 +1306,12 @@ fn create_proxmox_adapter<'a>(
                         serde_json::to_string(&termproxy)
                             .map_err(|e| ConnectionError::General(
                                 None, anyhow!("failed serialization: {e}").context(e)
-                            ))?
+                            ))?,
+                        serial.map(|serial| serial.to_string()).unwrap_or_default(),
+                        client
+                            .clientconfig_proxy()
+                            .map(ProxyConfig::to_url)
+                            .unwrap_or_default(),
                     ],
                 ))
             }
@@ -958,3 +1320,17 @@ fn create_proxmox_adapter<'a>(
         Ok(adapter)
     }))
 }
+
+/// Tag of the console adapter attaching to the given serial port of a VM.
+fn serial_adapter_tag(serial: VmTermproxySerial) -> String {
+    format!("{}-{}", VtePtyAdapter::TAG, serial)
+}
+
+/// The serial port an adapter tag created by `serial_adapter_tag` refers to.
+fn serial_adapter_port(adapter_tag: &str) -> Option<VmTermproxySerial> {
+    adapter_tag
+        .strip_prefix(VtePtyAdapter::TAG)?
+        .strip_prefix('-')?
+        .parse()
+        .ok()
+}
@@ -0,0 +1,318 @@
+/* Copyright 2024 Marco Köpcke
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ *
+ * SPDX-License-Identifier: GPL-3.0-or-later
+ */
+//! Changing the CD/DVD media of QEMU VMs.
+//!
+//! Proxmox has no notion of booting from a drive only once. To still support it, the boot
+//! order is changed, the VM is started and the previous boot order is restored right after.
+//! Since the VM is running at that point, Proxmox keeps the restored boot order as a pending
+//! change that applies the next time the VM is started.
+
+use std::sync::Arc;
+
+use adw::prelude::*;
+use gettextrs::gettext;
+use libfieldmonitor::connection::{ConnectionResult, ServerAction};
+use libfieldmonitor::gtk::FieldMonitorActionParametersDialog;
+use libfieldmonitor::i18n::gettext_f;
+use log::warn;
+use proxmox_api::{
+    NodeId, NodeStorageInput, ProxmoxApiClient, QemuCdromDrive, QemuVmConfigUpdateInput,
+    StorageContent, StorageContentInput, StorageContentType, VmId,
+};
+
+use crate::permissions::{VM_CONFIG_OPTIONS, VM_POWER_MGMT};
+use crate::task::start_vm;
+use crate::tokiort::run_on_tokio;
+use crate::{map_proxmox_error, show_toast, ExecParams, ProxmoxVm};
+
+/// Setting of the VM config that contains the boot order.
+const BOOT_SETTING: &str = "boot";
+
+/// CD/DVD drives of a VM and the media that can be inserted into them.
+struct Media {
+    drives: Vec<QemuCdromDrive>,
+    /// The boot setting as it is configured.
+    boot: Option<String>,
+    boot_order: Vec<String>,
+    isos: Vec<StorageContent>,
+}
+
+impl Media {
+    /// Index of the medium inserted into the drive in the medium combo row. 0 is no medium.
+    fn selected_medium(&self, drive: Option<&QemuCdromDrive>) -> u32 {
+        drive
+            .and_then(|drive| drive.volume.as_ref())
+            .and_then(|volume| self.isos.iter().position(|iso| &iso.volid == volume))
+            .map_or(0, |i| i as u32 + 1)
+    }
+
+    /// Boot order with the drive as the first device.
+    fn boot_first(&self, drive: &str) -> String {
+        let mut devices = vec![drive];
+        devices.extend(
+            self.boot_order
+                .iter()
+                .map(String::as_str)
+                .filter(|device| *device != drive),
+        );
+        format!("order={}", devices.join(";"))
+    }
+}
+
+impl ProxmoxVm {
+    pub(crate) fn act_change_media<'a>(&self) -> ServerAction<'a> {
+        let title = self.title();
+        let is_running = self.is_running();
+        let may_start = self.may(VM_POWER_MGMT);
+        let may_change_boot = self.may(VM_CONFIG_OPTIONS);
+        ServerAction::new(
+            Box::new(self.params()),
+            Box::new(move |params, window, toov| {
+                let title = title.clone();
+                Box::pin(async move {
+                    let params = params.downcast::<ExecParams>().unwrap();
+                    let Some(media) = load_media(&params, toov.as_ref()).await else {
+                        return false;
+                    };
+                    if media.drives.is_empty() {
+                        show_toast(
+                            toov.as_ref(),
+                            &gettext_f("{vm} has no CD/DVD drive.", &[("vm", &title)]),
+                        );
+                        return false;
+                    }
+                    let media = Arc::new(media);
+
+                    let dialog = FieldMonitorActionParametersDialog::new(
+                        &gettext_f("Change CD/DVD of {vm}", &[("vm", &title)]),
+                        None,
+                        &gettext("Apply"),
+                    );
+                    let drive_names: Vec<&str> = media
+                        .drives
+                        .iter()
+                        .map(|drive| drive.key.as_str())
+                        .collect();
+                    let drive_row = dialog.add_combo_row(&gettext("Drive"), &drive_names, 0);
+                    let no_medium = gettext("No Medium");
+                    let medium_names: Vec<&str> = [no_medium.as_str()]
+                        .into_iter()
+                        .chain(media.isos.iter().map(StorageContent::name))
+                        .collect();
+                    let medium_row = dialog.add_combo_row(
+                        &gettext("Medium"),
+                        &medium_names,
+                        media.selected_medium(media.drives.first()),
+                    );
+                    drive_row.connect_selected_notify({
+                        let media = media.clone();
+                        let medium_row = medium_row.clone();
+                        move |drive_row| {
+                            medium_row.set_selected(
+                                media.selected_medium(
+                                    media.drives.get(drive_row.selected() as usize),
+                                ),
+                            );
+                        }
+                    });
+
+                    let start_row = (!is_running && may_start).then(|| {
+                        dialog.add_switch_row(
+                            &gettext("Start VM"),
+                            Some(&gettext("Start the VM after changing the medium")),
+                            true,
+                        )
+                    });
+                    let boot_row = match &start_row {
+                        Some(start_row) if may_change_boot => {
+                            let boot_row = dialog.add_switch_row(
+                                &gettext("Boot From Drive Once"),
+                                Some(&gettext(
+                                    "Boot from the drive when starting, then restore the boot order",
+                                )),
+                                false,
+                            );
+                            start_row
+                                .bind_property("active", &boot_row, "sensitive")
+                                .sync_create()
+                                .build();
+                            Some(boot_row)
+                        }
+                        _ => None,
+                    };
+
+                    if !dialog.run(window.as_ref()).await {
+                        return false;
+                    }
+
+                    let Some(drive) = media.drives.get(drive_row.selected() as usize) else {
+                        return false;
+                    };
+                    let volume = (medium_row.selected() as usize)
+                        .checked_sub(1)
+                        .and_then(|i| media.isos.get(i))
+                        .map(|iso| iso.volid.clone());
+                    let start = start_row.is_some_and(|row| row.is_active());
+                    let boot_once = start && boot_row.is_some_and(|row| row.is_active());
+
+                    let client = params.client.clone();
+                    let node_id = params.node_id.clone().unwrap();
+                    let vm_id = params.vm_id.clone().unwrap();
+                    let input = QemuVmConfigUpdateInput {
+                        boot: boot_once.then(|| media.boot_first(&drive.key)),
+                        delete: None,
+                        description: None,
+                        drives: [(
+                            drive.key.clone(),
+                            QemuCdromDrive::config_value(volume.as_deref()),
+                        )]
+                        .into(),
+                    };
+                    if let Err(err) = update_config(&client, &node_id, &vm_id, input).await {
+                        warn!("failed to change media: {err:?}");
+                        show_toast(toov.as_ref(), &gettext("Failed to change the CD/DVD."));
+                        return false;
+                    }
+
+                    if !start {
+                        show_toast(
+                            toov.as_ref(),
+                            &gettext_f("The CD/DVD of {vm} was changed.", &[("vm", &title)]),
+                        );
+                        return true;
+                    }
+
+                    let started = start_vm(client.clone(), node_id.clone(), vm_id.clone()).await;
+                    if boot_once {
+                        let input = QemuVmConfigUpdateInput {
+                            boot: media.boot.clone(),
+                            delete: media.boot.is_none().then(|| BOOT_SETTING.to_string()),
+                            description: None,
+                            drives: Default::default(),
+                        };
+                        if let Err(err) = update_config(&client, &node_id, &vm_id, input).await {
+                            warn!("failed to restore boot order: {err:?}");
+                            show_toast(
+                                toov.as_ref(),
+                                &gettext_f(
+                                    "Failed to restore the boot order of {vm}.",
+                                    &[("vm", &title)],
+                                ),
+                            );
+                        }
+                    }
+
+                    if started {
+                        show_toast(toov.as_ref(), &gettext("VM is now starting."));
+                    } else {
+                        show_toast(toov.as_ref(), &gettext("Failed to send start command."));
+                    }
+                    true
+                })
+            }),
+        )
+    }
+}
+
+/// Loads the CD/DVD drives of the VM and the ISO images on the storages of its node.
+/// Shows a toast and returns `None` on error.
+async fn load_media(params: &ExecParams, toov: Option<&adw::ToastOverlay>) -> Option<Media> {
+    let client = params.client.clone();
+    let node_id = params.node_id.clone().unwrap();
+    let vm_id = params.vm_id.clone().unwrap();
+    let result = run_on_tokio(async move {
+        fetch_media(&client, &node_id, &vm_id)
+            .await
+            .map_err(map_proxmox_error)
+    })
+    .await;
+
+    match result {
+        Ok(media) => Some(media),
+        Err(err) => {
+            warn!("failed to load media: {err:?}");
+            show_toast(toov, &gettext("Failed to load the CD/DVD drives."));
+            None
+        }
+    }
+}
+
+async fn fetch_media(
+    client: &ProxmoxApiClient,
+    node_id: &NodeId,
+    vm_id: &VmId,
+) -> proxmox_api::Result<Media> {
+    let config = client.vm_qemu_config(node_id, vm_id).await?;
+    let storages = client
+        .node_storages(
+            node_id,
+            &NodeStorageInput {
+                content: Some(StorageContentType::Iso),
+                enabled: Some(1),
+            },
+        )
+        .await?;
+
+    let mut isos = Vec::new();
+    for storage in storages {
+        let content = client
+            .node_storage_content(
+                node_id,
+                &storage.storage,
+                &StorageContentInput {
+                    content: Some(StorageContentType::Iso),
+                    ..Default::default()
+                },
+            )
+            .await;
+        match content {
+            Ok(content) => isos.extend(content),
+            Err(err) => warn!("failed to list ISO images of {}: {err}", storage.storage),
+        }
+    }
+    isos.sort_by(|a, b| a.volid.cmp(&b.volid));
+
+    Ok(Media {
+        drives: config.cdrom_drives(),
+        boot_order: config
+            .boot_order()
+            .into_iter()
+            .map(ToString::to_string)
+            .collect(),
+        boot: config.boot,
+        isos,
+    })
+}
+
+async fn update_config(
+    client: &Arc<ProxmoxApiClient>,
+    node_id: &NodeId,
+    vm_id: &VmId,
+    input: QemuVmConfigUpdateInput,
+) -> ConnectionResult<()> {
+    let client = client.clone();
+    let node_id = node_id.clone();
+    let vm_id = vm_id.clone();
+    run_on_tokio(async move {
+        client
+            .vm_qemu_config_update(&node_id, &vm_id, &input)
+            .await
+            .map_err(map_proxmox_error)
+    })
+    .await
+}
@@ -0,0 +1,136 @@
+/* Copyright 2024 Marco Köpcke
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ *
+ * SPDX-License-Identifier: GPL-3.0-or-later
+ */
+//! Resource usage of nodes and VMs.
+
+use gettextrs::gettext;
+use gtk::glib;
+use libfieldmonitor::connection::{MetricUnit, ServerMetric, ServerMetricBuilder};
+use libfieldmonitor::i18n::gettext_f;
+use proxmox_api::{RrdConsolidation, RrdDataInput, RrdDataPoint, RrdTimeframe};
+
+/// Current resource usage as reported by the resource listing endpoints.
+#[derive(Debug, Clone, Copy, Default)]
+pub(crate) struct ResourceUsage {
+    pub cpu: Option<f64>,
+    pub mem: Option<i64>,
+    pub maxmem: Option<i64>,
+    pub disk: Option<i64>,
+    pub maxdisk: Option<i64>,
+}
+
+impl ResourceUsage {
+    /// Short human-readable summary, to be used as subtitle.
+    pub fn subtitle(&self) -> Option<String> {
+        let mut parts = Vec::with_capacity(3);
+        if let Some(cpu) = self.cpu {
+            parts.push(gettext_f(
+                "CPU {usage}",
+                &[("usage", &format!("{:.0}\u{202F}%", cpu * 100.0))],
+            ));
+        }
+        match (self.mem, self.maxmem) {
+            (Some(mem), Some(maxmem)) if maxmem > 0 => parts.push(gettext_f(
+                "Memory {used} of {total}",
+                &[
+                    ("used", &glib::format_size(mem.max(0) as u64)),
+                    ("total", &glib::format_size(maxmem as u64)),
+                ],
+            )),
+            (Some(mem), _) => parts.push(gettext_f(
+                "Memory {used}",
+                &[("used", &glib::format_size(mem.max(0) as u64))],
+            )),
+            _ => {}
+        }
+        // QEMU VMs always report 0 as used disk space, since the usage inside of the guest is not
+        // known to Proxmox.
+        match (self.disk, self.maxdisk) {
+            (Some(disk), Some(maxdisk)) if disk > 0 && maxdisk > 0 => parts.push(gettext_f(
+                "Disk {used} of {total}",
+                &[
+                    ("used", &glib::format_size(disk as u64)),
+                    ("total", &glib::format_size(maxdisk as u64)),
+                ],
+            )),
+            _ => {}
+        }
+        if parts.is_empty() {
+            None
+        } else {
+            Some(parts.join(" · "))
+        }
+    }
+}
+
+/// Parameters used to request the history shown in the UI.
+pub(crate) fn rrd_input() -> RrdDataInput {
+    RrdDataInput {
+        timeframe: RrdTimeframe::Hour,
+        cf: Some(RrdConsolidation::Average),
+    }
+}
+
+/// Converts RRD data of a node or VM into metrics for the UI.
+pub(crate) fn metrics_from_rrddata(points: &[RrdDataPoint]) -> Vec<ServerMetric> {
+    let series = |f: &dyn Fn(&RrdDataPoint) -> Option<f64>| -> Vec<(i64, f64)> {
+        points
+            .iter()
+            .filter_map(|point| f(point).map(|v| (point.time, v)))
+            .collect()
+    };
+
+    let max_mem = points
+        .iter()
+        .rev()
+        .find_map(|point| point.maxmem.or(point.memtotal));
+
+    vec![
+        ServerMetricBuilder::default()
+            .title(gettext("CPU"))
+            .unit(MetricUnit::Ratio)
+            .max(Some(1.0))
+            .points(series(&|point| point.cpu))
+            .build()
+            .unwrap(),
+        ServerMetricBuilder::default()
+            .title(gettext("Memory"))
+            .unit(MetricUnit::Bytes)
+            .max(max_mem)
+            .points(series(&|point| point.mem.or(point.memused)))
+            .build()
+            .unwrap(),
+        ServerMetricBuilder::default()
+            .title(gettext("Network"))
+            .unit(MetricUnit::BytesPerSecond)
+            .points(series(&|point| match (point.netin, point.netout) {
+                (None, None) => None,
+                (netin, netout) => Some(netin.unwrap_or_default() + netout.unwrap_or_default()),
+            }))
+            .build()
+            .unwrap(),
+        ServerMetricBuilder::default()
+            .title(gettext("Disk"))
+            .unit(MetricUnit::BytesPerSecond)
+            .points(series(&|point| match (point.diskread, point.diskwrite) {
+                (None, None) => None,
+                (read, write) => Some(read.unwrap_or_default() + write.unwrap_or_default()),
+            }))
+            .build()
+            .unwrap(),
+    ]
+}
@@ -0,0 +1,221 @@
+/* Copyright 2024 Marco Köpcke
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ *
+ * SPDX-License-Identifier: GPL-3.0-or-later
+ */
+//! Migration of VMs and containers between the nodes of a cluster.
+
+use adw::prelude::*;
+use futures::future::LocalBoxFuture;
+use gettextrs::gettext;
+use libfieldmonitor::connection::{ConnectionResult, ServerAction};
+use libfieldmonitor::gtk::FieldMonitorActionParametersDialog;
+use libfieldmonitor::i18n::gettext_f;
+use log::warn;
+use proxmox_api::{
+    ClusterResourcesFilter, ClusterResourcesInput, NodeId, NodeStatus, VmMigrateInput, VmType,
+};
+
+use crate::task::wait_for_task;
+use crate::tokiort::run_on_tokio;
+use crate::{map_proxmox_error, show_toast, ExecParams, ProxmoxVm};
+
+/// Config lock Proxmox sets on a guest while it is being migrated.
+const MIGRATE_LOCK: &str = "migrate";
+
+impl ProxmoxVm {
+    pub(crate) fn act_migrate<'a>(&self) -> ServerAction<'a> {
+        let title = self.title();
+        let is_running = self.is_running();
+        ServerAction::new(
+            Box::new(self.params()),
+            Box::new(move |params, window, toov| {
+                let title = title.clone();
+                Box::pin(async move {
+                    let params = params.downcast::<ExecParams>().unwrap();
+                    let Some(targets) = load_target_nodes(&params, toov.as_ref()).await else {
+                        return false;
+                    };
+                    if targets.is_empty() {
+                        show_toast(
+                            toov.as_ref(),
+                            &gettext("There are no other online nodes to migrate to."),
+                        );
+                        return false;
+                    }
+
+                    let dialog = FieldMonitorActionParametersDialog::new(
+                        &gettext_f("Migrate {vm}", &[("vm", &title)]),
+                        None,
+                        &gettext("Migrate"),
+                    );
+                    let names: Vec<&str> = targets.iter().map(AsRef::as_ref).collect();
+                    let target_row = dialog.add_combo_row(&gettext("Target Node"), &names, 0);
+                    let vm_type = params.vm_type.unwrap();
+                    let online_row = (is_running && matches!(vm_type, VmType::Qemu)).then(|| {
+                        dialog.add_switch_row(
+                            &gettext("Live Migration"),
+                            Some(&gettext("Keep the VM running while it is being migrated")),
+                            true,
+                        )
+                    });
+                    let restart_row = (is_running && matches!(vm_type, VmType::Lxc)).then(|| {
+                        dialog.add_switch_row(
+                            &gettext("Restart Migration"),
+                            Some(&gettext(
+                                "Shut the container down, migrate it and start it again on the target node",
+                            )),
+                            true,
+                        )
+                    });
+                    let local_disks_row = matches!(vm_type, VmType::Qemu).then(|| {
+                        dialog.add_switch_row(
+                            &gettext("Include Local Disks"),
+                            Some(&gettext(
+                                "Copy disks that are only stored on the current node",
+                            )),
+                            false,
+                        )
+                    });
+
+                    if !dialog.run(window.as_ref()).await {
+                        return false;
+                    }
+
+                    let Some(target) = targets.get(target_row.selected() as usize).cloned() else {
+                        return false;
+                    };
+                    let input = VmMigrateInput {
+                        target: target.clone(),
+                        online: online_row.map(|row| row.is_active()),
+                        restart: restart_row.map(|row| row.is_active()),
+                        with_local_disks: local_disks_row.map(|row| row.is_active()),
+                    };
+
+                    let client = params.client.clone();
+                    let node_id = params.node_id.clone().unwrap();
+                    let upid = {
+                        let node_id = node_id.clone();
+                        let vm_id = params.vm_id.clone().unwrap();
+                        run_on_tokio(async move {
+                            client
+                                .vm_migrate(&node_id, &vm_id, Some(vm_type), input)
+                                .await
+                                .map_err(map_proxmox_error)
+                        })
+                        .await
+                    };
+                    let upid = match upid {
+                        Ok(upid) => upid,
+                        Err(err) => {
+                            warn!("failed to start migration: {err:?}");
+                            show_toast(toov.as_ref(), &gettext("Failed to start migration."));
+                            return false;
+                        }
+                    };
+
+                    let target_vars = [("vm", title.as_str()), ("node", target.as_ref())];
+                    show_toast(
+                        toov.as_ref(),
+                        &gettext_f("Migrating {vm} to {node}…", &target_vars),
+                    );
+
+                    match wait_for_task(params.client.clone(), node_id, upid).await {
+                        Ok(status) if status.is_ok() => show_toast(
+                            toov.as_ref(),
+                            &gettext_f("{vm} was migrated to {node}.", &target_vars),
+                        ),
+                        Ok(status) => {
+                            warn!("migration failed: {:?}", status.exitstatus);
+                            show_toast(
+                                toov.as_ref(),
+                                &gettext_f(
+                                    "Migration of {vm} failed: {error}",
+                                    &[
+                                        ("vm", &title),
+                                        ("error", status.exitstatus.as_deref().unwrap_or("?")),
+                                    ],
+                                ),
+                            )
+                        }
+                        Err(err) => {
+                            warn!("failed to track migration: {err:?}");
+                            show_toast(
+                                toov.as_ref(),
+                                &gettext("Failed to retrieve the status of the migration."),
+                            )
+                        }
+                    }
+                    true
+                })
+            }),
+        )
+    }
+
+    /// Whether the VM is currently being migrated or is already running on a different node
+    /// than the one this instance was loaded for.
+    pub(crate) fn check_migrating(&self) -> LocalBoxFuture<ConnectionResult<bool>> {
+        let client = self.client.clone();
+        let node_id = self.node_id.clone();
+        let vm_id = self.vm_id.clone();
+        Box::pin(async move {
+            let resources = run_on_tokio(async move {
+                client
+                    .cluster_resources(&ClusterResourcesInput {
+                        r#type: Some(ClusterResourcesFilter::Vm),
+                    })
+                    .await
+                    .map_err(map_proxmox_error)
+            })
+            .await?;
+
+            Ok(resources
+                .into_iter()
+                .find(|resource| resource.vmid.as_ref() == Some(&vm_id))
+                .is_some_and(|resource| {
+                    resource.node.as_ref() != Some(&node_id)
+                        || resource.lock.as_deref() == Some(MIGRATE_LOCK)
+                }))
+        })
+    }
+}
+
+/// Loads the online nodes a VM can be migrated to. Shows a toast and returns `None` on error.
+async fn load_target_nodes(
+    params: &ExecParams,
+    toov: Option<&adw::ToastOverlay>,
+) -> Option<Vec<NodeId>> {
+    let client = params.client.clone();
+    let result = run_on_tokio(async move { client.nodes().await.map_err(map_proxmox_error) }).await;
+
+    match result {
+        Ok(nodes) => {
+            let mut targets: Vec<_> = nodes
+                .into_iter()
+                .filter(|node| {
+                    node.status == NodeStatus::Online && Some(&node.node) != params.node_id.as_ref()
+                })
+                .map(|node| node.node)
+                .collect();
+            targets.sort_by(|a, b| a.as_ref().cmp(b.as_ref()));
+            Some(targets)
+        }
+        Err(err) => {
+            warn!("failed to load nodes: {err:?}");
+            show_toast(toov, &gettext("Failed to load the nodes of the cluster."));
+            None
+        }
+    }
+}
@@ -0,0 +1,215 @@
+/* Copyright 2024 Marco Köpcke
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ *
+ * SPDX-License-Identifier: GPL-3.0-or-later
+ */
+//! Notes of nodes and VMs.
+//!
+//! Proxmox calls them the description. It is Markdown and part of the config of the node
+//! or VM.
+
+use std::borrow::Cow;
+
+use adw::prelude::*;
+use gettextrs::gettext;
+use libfieldmonitor::connection::{ConnectionResult, ServerAction};
+use libfieldmonitor::gtk::FieldMonitorActionParametersDialog;
+use libfieldmonitor::i18n::gettext_f;
+use log::warn;
+use proxmox_api::{LxcVmConfigUpdateInput, NodeConfigUpdateInput, QemuVmConfigUpdateInput, VmType};
+
+use crate::permissions::{SYS_MODIFY, VM_CONFIG_OPTIONS};
+use crate::tokiort::run_on_tokio;
+use crate::{map_proxmox_error, show_toast, ExecParams, ProxmoxNode, ProxmoxVm};
+
+/// Setting of the configs that contains the notes.
+const DESCRIPTION_SETTING: &str = "description";
+
+impl ProxmoxVm {
+    pub(crate) fn notes_actions(&self) -> Vec<(Cow<'static, str>, Cow<'static, str>)> {
+        if self.may(VM_CONFIG_OPTIONS) {
+            vec![("vmeditnotes".into(), gettext("Edit Notes…").into())]
+        } else {
+            vec![]
+        }
+    }
+
+    pub(crate) fn act_edit_notes<'a>(&self) -> ServerAction<'a> {
+        act_edit_notes(self.params(), self.title())
+    }
+}
+
+impl ProxmoxNode {
+    pub(crate) fn notes_actions(&self) -> Vec<(Cow<'static, str>, Cow<'static, str>)> {
+        if self.permissions.node(&self.id, SYS_MODIFY) {
+            vec![("nodeeditnotes".into(), gettext("Edit Notes…").into())]
+        } else {
+            vec![]
+        }
+    }
+
+    pub(crate) fn act_edit_notes<'a>(&self) -> ServerAction<'a> {
+        act_edit_notes(self.params(), self.id.to_string())
+    }
+}
+
+/// Loads the notes of the VM, or of the node if the parameters contain no VM.
+pub(crate) async fn load_notes(params: ExecParams) -> ConnectionResult<Option<String>> {
+    run_on_tokio(async move {
+        let node_id = params.node_id.unwrap();
+        match (params.vm_id, params.vm_type) {
+            (None, _) => params
+                .client
+                .node_config(&node_id)
+                .await
+                .map(|config| config.description),
+            (Some(vm_id), Some(VmType::Lxc)) => params
+                .client
+                .vm_lxc_config(&node_id, &vm_id)
+                .await
+                .map(|config| config.description),
+            (Some(vm_id), _) => params
+                .client
+                .vm_qemu_config(&node_id, &vm_id)
+                .await
+                .map(|config| config.description),
+        }
+        .map_err(map_proxmox_error)
+    })
+    .await
+}
+
+/// Saves the notes of the VM, or of the node if the parameters contain no VM. Empty notes
+/// are removed.
+async fn save_notes(params: ExecParams, notes: String) -> ConnectionResult<()> {
+    let (description, delete) = if notes.trim().is_empty() {
+        (None, Some(DESCRIPTION_SETTING.to_string()))
+    } else {
+        (Some(notes), None)
+    };
+    run_on_tokio(async move {
+        let node_id = params.node_id.unwrap();
+        match (params.vm_id, params.vm_type) {
+            (None, _) => {
+                params
+                    .client
+                    .node_config_update(
+                        &node_id,
+                        &NodeConfigUpdateInput {
+                            delete,
+                            description,
+                        },
+                    )
+                    .await
+            }
+            (Some(vm_id), Some(VmType::Lxc)) => {
+                params
+                    .client
+                    .vm_lxc_config_update(
+                        &node_id,
+                        &vm_id,
+                        &LxcVmConfigUpdateInput {
+                            delete,
+                            description,
+                        },
+                    )
+                    .await
+            }
+            (Some(vm_id), _) => {
+                params
+                    .client
+                    .vm_qemu_config_update(
+                        &node_id,
+                        &vm_id,
+                        &QemuVmConfigUpdateInput {
+                            delete,
+                            description,
+                            ..Default::default()
+                        },
+                    )
+                    .await
+            }
+        }
+        .map_err(map_proxmox_error)
+    })
+    .await
+}
+
+fn act_edit_notes<'a>(params: ExecParams, title: String) -> ServerAction<'a> {
+    ServerAction::new(
+        Box::new(params),
+        Box::new(move |params, window, toov| {
+            let title = title.clone();
+            Box::pin(async move {
+                let params = params.downcast::<ExecParams>().unwrap();
+                let notes = match load_notes((*params).clone()).await {
+                    Ok(notes) => notes.unwrap_or_default(),
+                    Err(err) => {
+                        warn!("failed to load notes: {err:?}");
+                        show_toast(toov.as_ref(), &gettext("Failed to load the notes."));
+                        return false;
+                    }
+                };
+
+                let dialog = FieldMonitorActionParametersDialog::new(
+                    &gettext_f("Notes of {server}", &[("server", &title)]),
+                    Some(&gettext("Notes are formatted with Markdown.")),
+                    &gettext("Save"),
+                );
+                let buffer = gtk::TextBuffer::builder().text(&notes).build();
+                let text_view = gtk::TextView::builder()
+                    .buffer(&buffer)
+                    .monospace(true)
+                    .wrap_mode(gtk::WrapMode::WordChar)
+                    .top_margin(12)
+                    .bottom_margin(12)
+                    .left_margin(12)
+                    .right_margin(12)
+                    .build();
+                dialog.add_row(
+                    &gtk::ListBoxRow::builder()
+                        .activatable(false)
+                        .selectable(false)
+                        .child(
+                            &gtk::ScrolledWindow::builder()
+                                .hscrollbar_policy(gtk::PolicyType::Never)
+                                .min_content_height(200)
+                                .child(&text_view)
+                                .build(),
+                        )
+                        .build(),
+                );
+
+                if !dialog.run(window.as_ref()).await {
+                    return false;
+                }
+
+                let new_notes = buffer
+                    .text(&buffer.start_iter(), &buffer.end_iter(), false)
+                    .to_string();
+                if new_notes == notes {
+                    return false;
+                }
+                if let Err(err) = save_notes(*params, new_notes).await {
+                    warn!("failed to save notes: {err:?}");
+                    show_toast(toov.as_ref(), &gettext("Failed to save the notes."));
+                    return false;
+                }
+                show_toast(toov.as_ref(), &gettext("Notes saved."));
+                true
+            })
+        }),
+    )
+}
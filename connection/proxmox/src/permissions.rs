@@ -0,0 +1,75 @@
+/* Copyright 2024 Marco Köpcke
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ *
+ * SPDX-License-Identifier: GPL-3.0-or-later
+ */
+//! Privileges of the user of a connection, used to only offer actions the user may run.
+
+use std::sync::Arc;
+
+use log::warn;
+use proxmox_api::{NodeId, Permissions, ProxmoxApiClient, VmId};
+
+pub(crate) const SYS_POWER_MGMT: &str = "Sys.PowerMgmt";
+pub(crate) const SYS_CONSOLE: &str = "Sys.Console";
+pub(crate) const SYS_MODIFY: &str = "Sys.Modify";
+pub(crate) const VM_POWER_MGMT: &str = "VM.PowerMgmt";
+pub(crate) const VM_CONSOLE: &str = "VM.Console";
+pub(crate) const VM_MIGRATE: &str = "VM.Migrate";
+pub(crate) const VM_CLONE: &str = "VM.Clone";
+pub(crate) const VM_AUDIT: &str = "VM.Audit";
+pub(crate) const VM_BACKUP: &str = "VM.Backup";
+pub(crate) const VM_CONFIG_CDROM: &str = "VM.Config.CDROM";
+pub(crate) const VM_CONFIG_OPTIONS: &str = "VM.Config.Options";
+pub(crate) const VM_MONITOR: &str = "VM.Monitor";
+pub(crate) const VM_GUEST_AGENT_FILE_SYSTEM_MGMT: &str = "VM.GuestAgent.FileSystemMgmt";
+pub(crate) const VM_GUEST_AGENT_UNRESTRICTED: &str = "VM.GuestAgent.Unrestricted";
+pub(crate) const VM_SNAPSHOT: &str = "VM.Snapshot";
+pub(crate) const VM_SNAPSHOT_ROLLBACK: &str = "VM.Snapshot.Rollback";
+
+/// Effective permissions of the user. If they could not be loaded, everything is assumed
+/// to be allowed and the API rejects what is not.
+#[derive(Clone, Default)]
+pub(crate) struct UserPermissions(Option<Arc<Permissions>>);
+
+impl UserPermissions {
+    /// Loads the permissions. Must be run inside the Tokio runtime.
+    pub(crate) async fn load(client: &ProxmoxApiClient) -> Self {
+        match client.access_permissions().await {
+            Ok(permissions) => Self(Some(Arc::new(permissions))),
+            Err(err) => {
+                warn!("failed to load permissions, assuming all are granted: {err}");
+                Self(None)
+            }
+        }
+    }
+
+    fn has(&self, path: &str, privilege: &str) -> bool {
+        match &self.0 {
+            None => true,
+            Some(permissions) => permissions.has_privilege(path, privilege),
+        }
+    }
+
+    pub(crate) fn node(&self, node: &NodeId, privilege: &str) -> bool {
+        self.has(&format!("/nodes/{node}"), privilege)
+    }
+
+    /// Privileges on a VM can also be granted via the resource pool it is in.
+    pub(crate) fn vm(&self, vm: &VmId, pool: Option<&str>, privilege: &str) -> bool {
+        self.has(&format!("/vms/{vm}"), privilege)
+            || pool.is_some_and(|pool| self.has(&format!("/pool/{pool}"), privilege))
+    }
+}
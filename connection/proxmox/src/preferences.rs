@@ -25,30 +25,137 @@ use futures::future::BoxFuture;
 use glib::clone;
 use gtk::glib;
 use gtk::prelude::*;
+use proxmox_api::{ProxyConfig, ProxyKind};
 use secure_string::SecureString;
 
 use libfieldmonitor::connection::{ConfigAccess, ConfigAccessMut, ConnectionConfiguration};
 
 use crate::credential_preferences::ProxmoxCredentialPreferences;
 
+/// How servers of a connection are grouped in the server tree.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub(super) enum ProxmoxGroupBy {
+    #[default]
+    Node,
+    Pool,
+    Tag,
+}
+
+impl ProxmoxGroupBy {
+    fn as_str(&self) -> &'static str {
+        match self {
+            ProxmoxGroupBy::Node => "node",
+            ProxmoxGroupBy::Pool => "pool",
+            ProxmoxGroupBy::Tag => "tag",
+        }
+    }
+
+    fn from_str(value: &str) -> Option<Self> {
+        match value {
+            "node" => Some(ProxmoxGroupBy::Node),
+            "pool" => Some(ProxmoxGroupBy::Pool),
+            "tag" => Some(ProxmoxGroupBy::Tag),
+            _ => None,
+        }
+    }
+
+    /// Position in the combo row of the preferences.
+    fn index(&self) -> u32 {
+        match self {
+            ProxmoxGroupBy::Node => 0,
+            ProxmoxGroupBy::Pool => 1,
+            ProxmoxGroupBy::Tag => 2,
+        }
+    }
+
+    fn from_index(index: u32) -> Self {
+        match index {
+            1 => ProxmoxGroupBy::Pool,
+            2 => ProxmoxGroupBy::Tag,
+            _ => ProxmoxGroupBy::Node,
+        }
+    }
+}
+
+/// Key of the cluster nodes found while connecting, see `discovered_endpoints`.
+pub(super) const DISCOVERED_ENDPOINTS: &str = "discovered-endpoints";
+
+/// Position of the proxy types in the combo row of the preferences.
+fn proxy_kind_index(kind: Option<ProxyKind>) -> u32 {
+    match kind {
+        None => 0,
+        Some(ProxyKind::Http) => 1,
+        Some(ProxyKind::Socks5) => 2,
+    }
+}
+
+fn proxy_kind_from_index(index: u32) -> Option<ProxyKind> {
+    match index {
+        1 => Some(ProxyKind::Http),
+        2 => Some(ProxyKind::Socks5),
+        _ => None,
+    }
+}
+
+/// Reads a list of strings stored in the configuration.
+fn string_list(config: &ConnectionConfiguration, key: &str) -> Vec<String> {
+    config
+        .get(key)
+        .and_then(|v| v.as_serde_value())
+        .and_then(|v| v.as_sequence())
+        .map(|seq| {
+            seq.iter()
+                .filter_map(|v| v.as_str())
+                .map(ToString::to_string)
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
 pub(super) trait ProxmoxConfiguration {
     fn title(&self) -> Option<&str>;
     fn set_title(&mut self, value: &str);
     fn ignore_ssl_cert_error(&self) -> bool;
     fn set_ignore_ssl_cert_error(&mut self, value: bool);
+    fn force_vnc_websocket(&self) -> bool;
+    fn set_force_vnc_websocket(&mut self, value: bool);
     fn hostname(&self) -> Option<&str>;
     fn set_hostname(&mut self, value: &str);
     fn port(&self) -> Option<NonZeroU32>;
     fn set_port(&mut self, value: NonZeroU32);
+    /// Further endpoints (`host` or `host:port`) to try if `hostname` can not be reached.
+    fn additional_endpoints(&self) -> Vec<String>;
+    fn set_additional_endpoints(&mut self, value: Vec<String>);
+    /// Whether to also try the other nodes of the cluster if no endpoint can be reached.
+    fn discover_endpoints(&self) -> bool;
+    fn set_discover_endpoints(&mut self, value: bool);
+    /// Nodes of the cluster (`host:port`) found the last time the connection was established,
+    /// tried after the `additional_endpoints` if `discover_endpoints` is set.
+    fn discovered_endpoints(&self) -> Vec<String>;
+    fn set_discovered_endpoints(&mut self, value: Vec<String>);
     fn use_apikey(&self) -> bool;
     fn set_use_apikey(&mut self, value: bool);
     fn username(&self) -> Option<&str>;
     fn set_username(&mut self, value: &str);
     fn tokenid(&self) -> Option<&str>;
     fn set_tokenid(&mut self, value: &str);
+    fn group_by(&self) -> ProxmoxGroupBy;
+    fn set_group_by(&mut self, value: ProxmoxGroupBy);
     fn password_or_apikey(&self) -> BoxFuture<anyhow::Result<Option<SecureString>>>;
     fn set_password_or_apikey(&mut self, value: Option<SecureString>);
     fn set_password_or_apikey_session(&mut self, value: Option<SecureString>);
+    fn proxy_kind(&self) -> Option<ProxyKind>;
+    fn set_proxy_kind(&mut self, value: Option<ProxyKind>);
+    fn proxy_hostname(&self) -> Option<&str>;
+    fn set_proxy_hostname(&mut self, value: &str);
+    fn proxy_port(&self) -> Option<u16>;
+    fn set_proxy_port(&mut self, value: u16);
+    fn proxy_username(&self) -> Option<&str>;
+    fn set_proxy_username(&mut self, value: &str);
+    fn proxy_password(&self) -> BoxFuture<anyhow::Result<Option<SecureString>>>;
+    fn set_proxy_password(&mut self, value: Option<SecureString>);
+    /// The proxy to use for all connections, if one is configured.
+    fn proxy(&self) -> BoxFuture<anyhow::Result<Option<ProxyConfig>>>;
 }
 
 impl ProxmoxConfiguration for ConnectionConfiguration {
@@ -69,6 +176,15 @@ impl ProxmoxConfiguration for ConnectionConfiguration {
         self.set_value("ignore-ssl-cert-error", value);
     }
 
+    fn force_vnc_websocket(&self) -> bool {
+        self.get_try_as_bool("vnc-over-websocket")
+            .unwrap_or_default()
+    }
+
+    fn set_force_vnc_websocket(&mut self, value: bool) {
+        self.set_value("vnc-over-websocket", value);
+    }
+
     fn hostname(&self) -> Option<&str> {
         self.get_try_as_str("hostname")
     }
@@ -91,6 +207,30 @@ impl ProxmoxConfiguration for ConnectionConfiguration {
         self.set_value("port", value.get());
     }
 
+    fn additional_endpoints(&self) -> Vec<String> {
+        string_list(self, "additional-endpoints")
+    }
+
+    fn set_additional_endpoints(&mut self, value: Vec<String>) {
+        self.set_value("additional-endpoints", value);
+    }
+
+    fn discover_endpoints(&self) -> bool {
+        self.get_try_as_bool("discover-endpoints").unwrap_or(true)
+    }
+
+    fn set_discover_endpoints(&mut self, value: bool) {
+        self.set_value("discover-endpoints", value);
+    }
+
+    fn discovered_endpoints(&self) -> Vec<String> {
+        string_list(self, DISCOVERED_ENDPOINTS)
+    }
+
+    fn set_discovered_endpoints(&mut self, value: Vec<String>) {
+        self.set_value(DISCOVERED_ENDPOINTS, value);
+    }
+
     fn use_apikey(&self) -> bool {
         self.get_try_as_bool("use-apikey").unwrap_or_default()
     }
@@ -115,6 +255,16 @@ impl ProxmoxConfiguration for ConnectionConfiguration {
         self.set_value("tokenid", value);
     }
 
+    fn group_by(&self) -> ProxmoxGroupBy {
+        self.get_try_as_str("group-by")
+            .and_then(ProxmoxGroupBy::from_str)
+            .unwrap_or_default()
+    }
+
+    fn set_group_by(&mut self, value: ProxmoxGroupBy) {
+        self.set_value("group-by", value.as_str());
+    }
+
     fn password_or_apikey(&self) -> BoxFuture<anyhow::Result<Option<SecureString>>> {
         Box::pin(async move {
             if let Some(pw) = self.get_try_as_sec_string("__session__password-or-apikey") {
@@ -141,6 +291,79 @@ impl ProxmoxConfiguration for ConnectionConfiguration {
             }
         }
     }
+
+    fn proxy_kind(&self) -> Option<ProxyKind> {
+        self.get_try_as_str("proxy-type")
+            .and_then(|v| v.parse().ok())
+    }
+
+    fn set_proxy_kind(&mut self, value: Option<ProxyKind>) {
+        self.set_value(
+            "proxy-type",
+            value.as_ref().map(ProxyKind::as_str).unwrap_or("none"),
+        );
+    }
+
+    fn proxy_hostname(&self) -> Option<&str> {
+        self.get_try_as_str("proxy-hostname")
+    }
+
+    fn set_proxy_hostname(&mut self, value: &str) {
+        self.set_value("proxy-hostname", value);
+    }
+
+    fn proxy_port(&self) -> Option<u16> {
+        self.get_try_as_u64("proxy-port")
+            .and_then(|v| u16::try_from(v).ok())
+            .filter(|v| *v != 0)
+    }
+
+    fn set_proxy_port(&mut self, value: u16) {
+        self.set_value("proxy-port", value);
+    }
+
+    fn proxy_username(&self) -> Option<&str> {
+        self.get_try_as_str("proxy-username")
+            .filter(|v| !v.is_empty())
+    }
+
+    fn set_proxy_username(&mut self, value: &str) {
+        self.set_value("proxy-username", value);
+    }
+
+    fn proxy_password(&self) -> BoxFuture<anyhow::Result<Option<SecureString>>> {
+        self.get_secret("proxy-password")
+    }
+
+    fn set_proxy_password(&mut self, value: Option<SecureString>) {
+        match value {
+            None => self.clear_secret("proxy-password"),
+            Some(value) => self.set_secret("proxy-password", value),
+        }
+    }
+
+    fn proxy(&self) -> BoxFuture<anyhow::Result<Option<ProxyConfig>>> {
+        Box::pin(async move {
+            let (Some(kind), Some(host), Some(port)) =
+                (self.proxy_kind(), self.proxy_hostname(), self.proxy_port())
+            else {
+                return Ok(None);
+            };
+            let username = self.proxy_username().map(ToString::to_string);
+            let password = if username.is_some() {
+                self.proxy_password().await?
+            } else {
+                None
+            };
+            Ok(Some(ProxyConfig {
+                kind,
+                host: host.to_string(),
+                port,
+                username,
+                password,
+            }))
+        })
+    }
 }
 
 mod imp {
@@ -154,6 +377,14 @@ mod imp {
         pub credentials: TemplateChild<ProxmoxCredentialPreferences>,
         #[template_child]
         pub port_entry: TemplateChild<adw::EntryRow>,
+        #[template_child]
+        pub proxy_hostname_entry: TemplateChild<adw::EntryRow>,
+        #[template_child]
+        pub proxy_port_entry: TemplateChild<adw::EntryRow>,
+        #[template_child]
+        pub proxy_username_entry: TemplateChild<adw::EntryRow>,
+        #[template_child]
+        pub proxy_password_entry: TemplateChild<adw::PasswordEntryRow>,
         #[property(get, set)]
         title: RefCell<String>,
         #[property(get, set)]
@@ -161,7 +392,25 @@ mod imp {
         #[property(get, set)]
         port: RefCell<String>,
         #[property(get, set)]
+        additional_endpoints: RefCell<String>,
+        #[property(get, set, default = true)]
+        discover_endpoints: Cell<bool>,
+        #[property(get, set)]
         ignore_ssl_cert_error: Cell<bool>,
+        #[property(get, set)]
+        force_vnc_websocket: Cell<bool>,
+        #[property(get, set)]
+        group_by: Cell<u32>,
+        #[property(get, set)]
+        proxy_type: Cell<u32>,
+        #[property(get, set)]
+        proxy_hostname: RefCell<String>,
+        #[property(get, set)]
+        proxy_port: RefCell<String>,
+        #[property(get, set)]
+        proxy_username: RefCell<String>,
+        #[property(get, set)]
+        proxy_password: RefCell<String>,
     }
 
     #[glib::object_subclass]
@@ -209,7 +458,30 @@ impl ProxmoxPreferences {
                             .map(ToString::to_string)
                             .unwrap_or_default(),
                     );
+                    slf.set_additional_endpoints(
+                        existing_configuration.additional_endpoints().join(", "),
+                    );
+                    slf.set_discover_endpoints(existing_configuration.discover_endpoints());
                     slf.set_ignore_ssl_cert_error(existing_configuration.ignore_ssl_cert_error());
+                    slf.set_force_vnc_websocket(existing_configuration.force_vnc_websocket());
+                    slf.set_group_by(existing_configuration.group_by().index());
+                    slf.set_proxy_type(proxy_kind_index(existing_configuration.proxy_kind()));
+                    slf.set_proxy_hostname(
+                        existing_configuration.proxy_hostname().unwrap_or_default(),
+                    );
+                    slf.set_proxy_port(
+                        existing_configuration
+                            .proxy_port()
+                            .as_ref()
+                            .map(ToString::to_string)
+                            .unwrap_or_default(),
+                    );
+                    slf.set_proxy_username(
+                        existing_configuration.proxy_username().unwrap_or_default(),
+                    );
+                    if let Ok(Some(v)) = existing_configuration.proxy_password().await {
+                        slf.set_proxy_password(v.unsecure());
+                    }
 
                     slf.imp()
                         .credentials
@@ -237,10 +509,46 @@ impl ProxmoxPreferences {
         };
         self.port_entry_error(false);
 
+        let proxy_kind = proxy_kind_from_index(self.proxy_type());
+        let proxy_port = self.proxy_port().parse::<u16>().ok().filter(|v| *v != 0);
+        if proxy_kind.is_some() {
+            let Some(proxy_port) = proxy_port else {
+                self.imp().proxy_port_entry.add_css_class("error");
+                return Err(anyhow!("invalid proxy port"));
+            };
+            self.imp().proxy_port_entry.remove_css_class("error");
+            if self.proxy_hostname().is_empty() {
+                self.imp().proxy_hostname_entry.add_css_class("error");
+                return Err(anyhow!("missing proxy hostname"));
+            }
+            self.imp().proxy_hostname_entry.remove_css_class("error");
+            config.set_proxy_port(proxy_port);
+        }
+
         config.set_title(&self.title());
         config.set_hostname(&self.hostname());
         config.set_port(port);
+        config.set_additional_endpoints(
+            self.additional_endpoints()
+                .split(',')
+                .map(str::trim)
+                .filter(|v| !v.is_empty())
+                .map(ToString::to_string)
+                .collect(),
+        );
+        config.set_discover_endpoints(self.discover_endpoints());
         config.set_ignore_ssl_cert_error(self.ignore_ssl_cert_error());
+        config.set_force_vnc_websocket(self.force_vnc_websocket());
+        config.set_group_by(ProxmoxGroupBy::from_index(self.group_by()));
+        config.set_proxy_kind(proxy_kind);
+        config.set_proxy_hostname(&self.proxy_hostname());
+        config.set_proxy_username(&self.proxy_username());
+        let proxy_password = self.proxy_password();
+        config.set_proxy_password(if proxy_password.is_empty() {
+            None
+        } else {
+            Some(SecureString::from(proxy_password))
+        });
 
         Ok(())
     }
@@ -259,4 +567,14 @@ impl ProxmoxPreferences {
 }
 
 #[gtk::template_callbacks]
-impl ProxmoxPreferences {}
+impl ProxmoxPreferences {
+    #[template_callback]
+    fn on_self_proxy_type_changed(&self) {
+        let imp = self.imp();
+        let has_proxy = proxy_kind_from_index(self.proxy_type()).is_some();
+        imp.proxy_hostname_entry.set_visible(has_proxy);
+        imp.proxy_port_entry.set_visible(has_proxy);
+        imp.proxy_username_entry.set_visible(has_proxy);
+        imp.proxy_password_entry.set_visible(has_proxy);
+    }
+}
@@ -0,0 +1,365 @@
+/* Copyright 2024 Marco Köpcke
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ *
+ * SPDX-License-Identifier: GPL-3.0-or-later
+ */
+//! Snapshot management actions for VMs and containers.
+
+use std::borrow::Cow;
+
+use adw::prelude::*;
+use gettextrs::gettext;
+use gtk::glib;
+use libfieldmonitor::connection::ServerAction;
+use libfieldmonitor::gtk::{FieldMonitorActionParametersDialog, SnapshotPrompt};
+use libfieldmonitor::i18n::gettext_f;
+use log::warn;
+use proxmox_api::{
+    VmSnapshot, VmSnapshotCreateInput, VmSnapshotDeleteInput, VmSnapshotRollbackInput, VmType,
+};
+
+use crate::permissions::{VM_AUDIT, VM_SNAPSHOT, VM_SNAPSHOT_ROLLBACK};
+use crate::tokiort::run_on_tokio;
+use crate::{exec_cmd, map_proxmox_error, show_toast, ExecParams, ProxmoxVm};
+
+impl ProxmoxVm {
+    pub(crate) fn snapshot_actions(&self) -> Vec<(Cow<'static, str>, Cow<'static, str>)> {
+        let mut actions = Vec::with_capacity(4);
+        if self.may(VM_AUDIT) {
+            actions.push(("vmsnapshotlist".into(), gettext("Snapshots").into()));
+        }
+        if self.may(VM_SNAPSHOT) {
+            actions.push((
+                "vmsnapshotcreate".into(),
+                gettext("Create Snapshot…").into(),
+            ));
+        }
+        if self.may(VM_SNAPSHOT_ROLLBACK) || self.may(VM_SNAPSHOT) {
+            actions.push((
+                "vmsnapshotrollback".into(),
+                gettext("Rollback to Snapshot…").into(),
+            ));
+        }
+        if self.may(VM_SNAPSHOT) {
+            actions.push((
+                "vmsnapshotdelete".into(),
+                gettext("Delete Snapshot…").into(),
+            ));
+        }
+        actions
+    }
+
+    pub(crate) fn act_snapshot_list<'a>(&self) -> ServerAction<'a> {
+        let title = self.title();
+        ServerAction::new(
+            Box::new(self.params()),
+            Box::new(move |params, window, toov| {
+                let title = title.clone();
+                Box::pin(async move {
+                    let params = params.downcast::<ExecParams>().unwrap();
+                    let Some(snapshots) = load_snapshots(&params, toov.as_ref()).await else {
+                        return false;
+                    };
+
+                    let dialog = FieldMonitorActionParametersDialog::new_informational(
+                        &gettext_f("Snapshots of {vm}", &[("vm", &title)]),
+                        snapshots
+                            .is_empty()
+                            .then(|| gettext("This VM has no snapshots."))
+                            .as_deref(),
+                    );
+                    for snapshot in &snapshots {
+                        dialog.add_info_row(&snapshot.name, &snapshot_subtitle(snapshot));
+                    }
+                    dialog.run(window.as_ref()).await;
+                    false
+                })
+            }),
+        )
+    }
+
+    pub(crate) fn act_snapshot_create<'a>(&self) -> ServerAction<'a> {
+        let title = self.title();
+        let can_save_ram = self.is_running() && matches!(self.vm_type, VmType::Qemu);
+        ServerAction::new(
+            Box::new(self.params()),
+            Box::new(move |params, window, toov| {
+                let title = title.clone();
+                Box::pin(async move {
+                    let params = params.downcast::<ExecParams>().unwrap();
+
+                    let dialog = FieldMonitorActionParametersDialog::new(
+                        &gettext_f("Create Snapshot of {vm}", &[("vm", &title)]),
+                        None,
+                        &gettext("Create"),
+                    );
+                    let default_name = glib::DateTime::now_local()
+                        .and_then(|now| now.format("snapshot_%Y%m%d_%H%M%S"))
+                        .map(|name| name.to_string())
+                        .unwrap_or_default();
+                    let name_row = dialog.add_entry_row(&gettext("Name"), &default_name);
+                    let description_row = dialog.add_entry_row(&gettext("Description"), "");
+                    let vmstate_row = can_save_ram.then(|| {
+                        dialog.add_switch_row(
+                            &gettext("Include RAM"),
+                            Some(&gettext(
+                                "Save the memory of the running VM, so it can be resumed as-is",
+                            )),
+                            false,
+                        )
+                    });
+
+                    if !dialog.run(window.as_ref()).await {
+                        return false;
+                    }
+
+                    let snapname = name_row.text().trim().to_string();
+                    if snapname.is_empty() {
+                        show_toast(toov.as_ref(), &gettext("A snapshot name is required."));
+                        return false;
+                    }
+                    let description = description_row.text().trim().to_string();
+                    let input = VmSnapshotCreateInput {
+                        snapname,
+                        description: (!description.is_empty()).then_some(description),
+                        vmstate: vmstate_row.map(|row| row.is_active()),
+                    };
+
+                    let (success, force_reload) = exec_cmd(
+                        params,
+                        move |params| {
+                            let input = input.clone();
+                            async move {
+                                params
+                                    .client
+                                    .vm_snapshot_create(
+                                        &params.node_id.unwrap(),
+                                        &params.vm_id.unwrap(),
+                                        params.vm_type,
+                                        input,
+                                    )
+                                    .await
+                            }
+                        },
+                        || gettext("Snapshot is being created."),
+                        |err| {
+                            warn!("failed snapshot creation: {err:?}");
+                            gettext("Failed to create snapshot.")
+                        },
+                        toov.as_ref(),
+                    )
+                    .await;
+                    success || force_reload
+                })
+            }),
+        )
+    }
+
+    pub(crate) fn act_snapshot_rollback<'a>(&self) -> ServerAction<'a> {
+        let title = self.title();
+        ServerAction::new(
+            Box::new(self.params()),
+            Box::new(move |params, window, toov| {
+                let title = title.clone();
+                Box::pin(async move {
+                    let params = params.downcast::<ExecParams>().unwrap();
+                    let prompt = SnapshotPrompt {
+                        heading: &gettext_f("Rollback {vm}?", &[("vm", &title)]),
+                        body: &gettext(
+                            "The current state of the VM will be lost. This can not be undone.",
+                        ),
+                        confirm_label: &gettext("Rollback"),
+                    };
+                    let Some((snapname, start_row)) = choose_snapshot(
+                        &params,
+                        window.as_ref(),
+                        toov.as_ref(),
+                        &prompt,
+                        |dialog| {
+                            dialog.add_switch_row(&gettext("Start After Rollback"), None, false)
+                        },
+                    )
+                    .await
+                    else {
+                        return false;
+                    };
+                    let input = VmSnapshotRollbackInput {
+                        start: start_row.is_active().then_some(1),
+                    };
+
+                    let (success, force_reload) = exec_cmd(
+                        params,
+                        move |params| {
+                            let snapname = snapname.clone();
+                            let input = input.clone();
+                            async move {
+                                params
+                                    .client
+                                    .vm_snapshot_rollback(
+                                        &params.node_id.unwrap(),
+                                        &params.vm_id.unwrap(),
+                                        params.vm_type,
+                                        &snapname,
+                                        input,
+                                    )
+                                    .await
+                            }
+                        },
+                        || gettext("VM is being rolled back."),
+                        |err| {
+                            warn!("failed snapshot rollback: {err:?}");
+                            gettext("Failed to roll back to snapshot.")
+                        },
+                        toov.as_ref(),
+                    )
+                    .await;
+                    success || force_reload
+                })
+            }),
+        )
+    }
+
+    pub(crate) fn act_snapshot_delete<'a>(&self) -> ServerAction<'a> {
+        let title = self.title();
+        ServerAction::new(
+            Box::new(self.params()),
+            Box::new(move |params, window, toov| {
+                let title = title.clone();
+                Box::pin(async move {
+                    let params = params.downcast::<ExecParams>().unwrap();
+                    let prompt = SnapshotPrompt {
+                        heading: &gettext_f("Delete Snapshot of {vm}?", &[("vm", &title)]),
+                        body: &gettext("The snapshot will be deleted permanently."),
+                        confirm_label: &gettext("Delete"),
+                    };
+                    let Some((snapname, ())) =
+                        choose_snapshot(&params, window.as_ref(), toov.as_ref(), &prompt, |_| ())
+                            .await
+                    else {
+                        return false;
+                    };
+
+                    let (success, force_reload) = exec_cmd(
+                        params,
+                        move |params| {
+                            let snapname = snapname.clone();
+                            async move {
+                                params
+                                    .client
+                                    .vm_snapshot_delete(
+                                        &params.node_id.unwrap(),
+                                        &params.vm_id.unwrap(),
+                                        params.vm_type,
+                                        &snapname,
+                                        VmSnapshotDeleteInput::default(),
+                                    )
+                                    .await
+                            }
+                        },
+                        || gettext("Snapshot is being deleted."),
+                        |err| {
+                            warn!("failed snapshot deletion: {err:?}");
+                            gettext("Failed to delete snapshot.")
+                        },
+                        toov.as_ref(),
+                    )
+                    .await;
+                    success || force_reload
+                })
+            }),
+        )
+    }
+}
+
+/// Loads the snapshots of a VM, newest first. Shows a toast and returns `None` on error.
+async fn load_snapshots(
+    params: &ExecParams,
+    toov: Option<&adw::ToastOverlay>,
+) -> Option<Vec<VmSnapshot>> {
+    let params = params.clone();
+    let result = run_on_tokio(async move {
+        params
+            .client
+            .vm_snapshots(
+                params.node_id.as_ref().unwrap(),
+                params.vm_id.as_ref().unwrap(),
+                params.vm_type,
+            )
+            .await
+            .map_err(map_proxmox_error)
+    })
+    .await;
+
+    match result {
+        Ok(snapshots) => {
+            let mut snapshots: Vec<_> = snapshots
+                .into_iter()
+                .filter(|snapshot| !snapshot.is_current())
+                .collect();
+            snapshots.sort_by_key(|snapshot| std::cmp::Reverse(snapshot.snaptime));
+            Some(snapshots)
+        }
+        Err(err) => {
+            warn!("failed to load snapshots: {err:?}");
+            show_toast(toov, &gettext("Failed to load snapshots."));
+            None
+        }
+    }
+}
+
+/// Asks the user to pick one of the snapshots of a VM, see
+/// `libfieldmonitor::gtk::choose_snapshot`. Returns `None` if there are no snapshots or the user
+/// cancelled.
+async fn choose_snapshot<T>(
+    params: &ExecParams,
+    window: Option<&gtk::Window>,
+    toov: Option<&adw::ToastOverlay>,
+    prompt: &SnapshotPrompt<'_>,
+    add_rows: impl FnOnce(&FieldMonitorActionParametersDialog) -> T,
+) -> Option<(String, T)> {
+    let snapshots = load_snapshots(params, toov).await?;
+    if snapshots.is_empty() {
+        show_toast(toov, &gettext("This VM has no snapshots."));
+        return None;
+    }
+
+    let names: Vec<&str> = snapshots.iter().map(|s| s.name.as_str()).collect();
+    let (index, extra) =
+        libfieldmonitor::gtk::choose_snapshot(window, prompt, &names, 0, add_rows).await?;
+    Some((snapshots[index].name.clone(), extra))
+}
+
+fn snapshot_subtitle(snapshot: &VmSnapshot) -> String {
+    let mut parts = Vec::with_capacity(3);
+    if let Some(snaptime) = snapshot
+        .snaptime
+        .and_then(|snaptime| glib::DateTime::from_unix_local(snaptime).ok())
+        .and_then(|snaptime| snaptime.format("%c").ok())
+    {
+        parts.push(snaptime.to_string());
+    }
+    if snapshot.vmstate.unwrap_or_default() {
+        parts.push(gettext("Includes RAM"));
+    }
+    if let Some(description) = snapshot
+        .description
+        .as_deref()
+        .map(str::trim)
+        .filter(|d| !d.is_empty())
+    {
+        parts.push(description.to_string());
+    }
+    parts.join(" · ")
+}
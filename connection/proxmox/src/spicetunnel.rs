@@ -0,0 +1,94 @@
+/* Copyright 2024 Marco Köpcke
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ *
+ * SPDX-License-Identifier: GPL-3.0-or-later
+ */
+//! SPICE through a user-configured proxy.
+//!
+//! SPICE sessions to Proxmox already go through the SPICE proxy of the cluster, and SPICE
+//! clients can only use a single proxy. To also use the proxy configured for the connection,
+//! a local forwarder is started that SPICE uses as its proxy instead. It tunnels every
+//! connection through the configured proxy to the SPICE proxy of the cluster.
+
+use std::str::FromStr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::anyhow;
+use http::Uri;
+use log::{debug, warn};
+use proxmox_api::ProxyConfig;
+use tokio::io::copy_bidirectional;
+use tokio::net::TcpListener;
+use tokio::time::timeout;
+
+/// The forwarder stops once no connection was active for this long.
+const IDLE_TIMEOUT: Duration = Duration::from_secs(30);
+/// Default port of the SPICE proxy of Proxmox.
+const DEFAULT_SPICE_PROXY_PORT: u16 = 3128;
+
+/// Starts forwarding connections to `spice_proxy` (as returned by the `spiceproxy` API
+/// endpoint) through `proxy`. Returns the proxy URL SPICE should use instead.
+///
+/// Must be run inside the Tokio runtime.
+pub(crate) async fn start_spice_tunnel(
+    proxy: ProxyConfig,
+    spice_proxy: &str,
+) -> anyhow::Result<String> {
+    let spice_proxy = Uri::from_str(spice_proxy)?;
+    let target_host = spice_proxy
+        .host()
+        .ok_or_else(|| anyhow!("SPICE proxy has no host"))?
+        .to_string();
+    let target_port = spice_proxy.port_u16().unwrap_or(DEFAULT_SPICE_PROXY_PORT);
+
+    let listener = TcpListener::bind(("127.0.0.1", 0)).await?;
+    let local_port = listener.local_addr()?.port();
+    let active = Arc::new(AtomicUsize::new(0));
+
+    tokio::spawn(async move {
+        loop {
+            let (mut stream, _) = match timeout(IDLE_TIMEOUT, listener.accept()).await {
+                Ok(Ok(accepted)) => accepted,
+                Ok(Err(err)) => {
+                    warn!("SPICE tunnel failed to accept connection: {err}");
+                    break;
+                }
+                Err(_) if active.load(Ordering::Acquire) == 0 => break,
+                Err(_) => continue,
+            };
+
+            let proxy = proxy.clone();
+            let target_host = target_host.clone();
+            let active = active.clone();
+            active.fetch_add(1, Ordering::AcqRel);
+            tokio::spawn(async move {
+                match proxy.connect(&target_host, target_port).await {
+                    Ok(mut upstream) => {
+                        if let Err(err) = copy_bidirectional(&mut stream, &mut upstream).await {
+                            debug!("SPICE tunnel connection closed with error: {err}");
+                        }
+                    }
+                    Err(err) => warn!("SPICE tunnel failed to connect through proxy: {err}"),
+                }
+                active.fetch_sub(1, Ordering::AcqRel);
+            });
+        }
+        debug!("SPICE tunnel stopped");
+    });
+
+    Ok(format!("http://127.0.0.1:{local_port}"))
+}
@@ -0,0 +1,91 @@
+/* Copyright 2024 Marco Köpcke
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ *
+ * SPDX-License-Identifier: GPL-3.0-or-later
+ */
+//! Tracking of long-running tasks started by actions.
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use async_std::task::sleep;
+use libfieldmonitor::connection::ConnectionResult;
+use log::warn;
+use proxmox_api::{NodeId, ProxmoxApiClient, TaskStatus, VmId, VmType};
+
+use crate::map_proxmox_error;
+use crate::tokiort::run_on_tokio;
+
+const TASK_POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Waits until the task with the given UPID, running on `node`, has stopped and returns its
+/// final status.
+pub(crate) async fn wait_for_task(
+    client: Arc<ProxmoxApiClient>,
+    node: NodeId,
+    upid: String,
+) -> ConnectionResult<TaskStatus> {
+    loop {
+        let client = client.clone();
+        let node = node.clone();
+        let upid = upid.clone();
+        let status = run_on_tokio(async move {
+            client
+                .node_task_status(&node, &upid)
+                .await
+                .map_err(map_proxmox_error)
+        })
+        .await?;
+
+        if !status.is_running() {
+            return Ok(status);
+        }
+        sleep(TASK_POLL_INTERVAL).await;
+    }
+}
+
+/// Starts the QEMU VM and waits until it was started. Returns whether that was successful.
+pub(crate) async fn start_vm(client: Arc<ProxmoxApiClient>, node_id: NodeId, vm_id: VmId) -> bool {
+    let upid = {
+        let client = client.clone();
+        let node_id = node_id.clone();
+        run_on_tokio(async move {
+            client
+                .vm_start(&node_id, &vm_id, Some(VmType::Qemu), Default::default())
+                .await
+                .map_err(map_proxmox_error)
+        })
+        .await
+    };
+    let upid = match upid {
+        Ok(upid) => upid,
+        Err(err) => {
+            warn!("failed to start VM: {err:?}");
+            return false;
+        }
+    };
+
+    match wait_for_task(client, node_id, upid).await {
+        Ok(status) if status.is_ok() => true,
+        Ok(status) => {
+            warn!("start failed: {:?}", status.exitstatus);
+            false
+        }
+        Err(err) => {
+            warn!("failed to track start: {err:?}");
+            false
+        }
+    }
+}
@@ -0,0 +1,460 @@
+/* Copyright 2024 Marco Köpcke
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ *
+ * SPDX-License-Identifier: GPL-3.0-or-later
+ */
+//! Tests of the connection against the local stand-in for the Proxmox API.
+
+use std::num::NonZeroU32;
+use std::str::FromStr;
+use std::sync::Arc;
+
+use futures::executor::block_on;
+use futures::future::BoxFuture;
+use http::StatusCode;
+use libfieldmonitor::connection::*;
+use libfieldmonitor::ManagesSecrets;
+use proxmox_api_mock::{MockProxmox, MockResponse, NODE, TICKET_RENEWED};
+use secure_string::SecureString;
+use serde_json::json;
+
+use crate::endpoints::api_roots;
+use crate::preferences::{ProxmoxConfiguration, ProxmoxGroupBy, DISCOVERED_ENDPOINTS};
+use crate::tokiort::tkruntime;
+use crate::vncwebsocket::vnc_adapter_creds;
+use crate::{map_proxmox_error, AdapterCreds, ProxmoxConnection, ProxmoxEntity};
+
+const USER: &str = "root@pam";
+const PASSWORD: &str = "hunter2";
+const TOKENID: &str = "root@pam!field-monitor";
+const APIKEY: &str = "8a0c7d36-3f2e-4b8e-9c41-5d2f1e6a7b90";
+
+/// The secrets are set for the session only, the secret service is never asked.
+struct NoSecrets;
+
+impl ManagesSecrets for NoSecrets {
+    fn lookup(
+        &self,
+        _connection_id: &str,
+        _field: &str,
+    ) -> BoxFuture<anyhow::Result<Option<SecureString>>> {
+        Box::pin(async { Ok(None) })
+    }
+
+    fn store(
+        &self,
+        _connection_id: &str,
+        _field: &str,
+        _password: SecureString,
+    ) -> BoxFuture<anyhow::Result<()>> {
+        Box::pin(async { Ok(()) })
+    }
+
+    fn clear(&self, _connection_id: &str, _field: &str) -> BoxFuture<anyhow::Result<()>> {
+        Box::pin(async { Ok(()) })
+    }
+}
+
+/// Starts an HTTPS mock serving the recorded cluster.
+fn start_mock() -> MockProxmox {
+    let mock = tkruntime().block_on(MockProxmox::start_tls());
+    mock.mock_cluster();
+    mock
+}
+
+/// Configuration connecting to the mock with username and password.
+fn config(mock: &MockProxmox, connection_id: &str) -> ConnectionConfiguration {
+    let mut config = ConnectionConfiguration::new(
+        connection_id.to_string(),
+        "proxmox".to_string(),
+        Arc::new(Box::new(NoSecrets)),
+    );
+    config.set_title("Homelab");
+    config.set_hostname("127.0.0.1");
+    config.set_port(NonZeroU32::new(mock.port().into()).unwrap());
+    config.set_ignore_ssl_cert_error(true);
+    config.set_username(USER);
+    config.set_password_or_apikey_session(Some(SecureString::from_str(PASSWORD).unwrap()));
+    config
+}
+
+fn connect(config: ConnectionConfiguration) -> ProxmoxConnection {
+    match tkruntime().block_on(ProxmoxConnection::connect(config)) {
+        Ok(connection) => connection,
+        Err(err) => panic!("failed to connect: {err:?}"),
+    }
+}
+
+fn servers(server: &dyn ServerConnection) -> ServerMap {
+    block_on(server.servers()).unwrap()
+}
+
+fn action_ids(server: &dyn ServerConnection) -> Vec<String> {
+    server
+        .actions()
+        .into_iter()
+        .map(|(id, _)| id.into_owned())
+        .collect()
+}
+
+#[test]
+fn proxmox_errors_are_mapped() {
+    assert!(matches!(
+        map_proxmox_error(proxmox_api::Error::AuthFailed),
+        ConnectionError::AuthFailed(None, _)
+    ));
+    assert!(matches!(
+        map_proxmox_error(proxmox_api::Error::MissingData),
+        ConnectionError::General(None, _)
+    ));
+    assert!(matches!(
+        map_proxmox_error(proxmox_api::Error::ApiUnknown(StatusCode::UNAUTHORIZED)),
+        ConnectionError::General(None, _)
+    ));
+    assert!(matches!(
+        map_proxmox_error(proxmox_api::Error::Api(
+            StatusCode::FORBIDDEN,
+            "Permission check failed".into()
+        )),
+        ConnectionError::General(None, _)
+    ));
+}
+
+#[test]
+fn connection_lists_nodes() {
+    let mock = start_mock();
+    mock.mock_ticket_auth();
+    let connection = connect(config(&mock, "lists-nodes"));
+
+    assert_eq!(connection.metadata().title, "Homelab");
+    let servers = block_on(connection.servers()).unwrap();
+    let ids: Vec<&str> = servers.keys().map(AsRef::as_ref).collect();
+    assert_eq!(ids, ["pve2", NODE]);
+
+    let offline = &servers["pve2"];
+    assert_eq!(offline.metadata().is_online, Some(false));
+    assert!(offline.supported_adapters().is_empty());
+    assert!(action_ids(offline.as_ref()).is_empty());
+
+    let online = &servers[NODE];
+    assert_eq!(online.metadata().is_online, Some(true));
+    let subtitle = online.metadata().subtitle.unwrap();
+    assert!(subtitle.contains(&format!(
+        "Disk {} of {}",
+        gtk::glib::format_size(12456120320),
+        gtk::glib::format_size(100861726720)
+    )));
+    assert_eq!(online.supported_adapters().len(), 3);
+    assert_eq!(action_ids(online.as_ref()), ["nodereboot", "nodeshutdown"]);
+
+    // One ticket is used for all requests, including those made while connecting.
+    assert_eq!(mock.requests_to("POST", "access/ticket").len(), 1);
+    assert_eq!(mock.requests_to("GET", "cluster/status").len(), 1);
+    assert_eq!(mock.requests_to("GET", "access/permissions").len(), 1);
+}
+
+#[test]
+fn node_lists_guests_with_permissions() {
+    let mock = start_mock();
+    mock.mock_ticket_auth();
+    let connection = connect(config(&mock, "lists-guests"));
+    let nodes = block_on(connection.servers()).unwrap();
+
+    let guests = servers(nodes[NODE].as_ref());
+    let ids: Vec<&str> = guests.keys().map(AsRef::as_ref).collect();
+    assert_eq!(ids, ["200", "201", "100", "101", "9000"]);
+
+    let container = guests["201"].metadata();
+    assert_eq!(container.title, "201 (dns)");
+    assert_eq!(container.is_online, Some(true));
+
+    // Power management is granted on the VM itself.
+    let webserver = &guests["100"];
+    assert_eq!(webserver.metadata().is_online, Some(true));
+    assert!(action_ids(webserver.as_ref()).contains(&"vmstop".to_string()));
+
+    // Power management is not granted at all.
+    let windows = &guests["101"];
+    assert_eq!(windows.metadata().is_online, Some(false));
+    assert!(!action_ids(windows.as_ref()).contains(&"vmstart".to_string()));
+
+    // Templates can not be started.
+    assert!(!action_ids(guests["9000"].as_ref()).contains(&"vmstart".to_string()));
+}
+
+#[test]
+fn guest_addresses_are_only_loaded_for_single_vms() {
+    let mock = start_mock();
+    mock.mock_ticket_auth();
+    let connection = connect(config(&mock, "loads-guest-addresses"));
+    let agent = format!("nodes/{NODE}/qemu/100/agent/network-get-interfaces");
+    let status = format!("nodes/{NODE}/qemu/100/status/current");
+    let path = |p: &[&str]| p.iter().map(ToString::to_string).collect::<Vec<_>>();
+
+    let nodes = block_on(connection.servers()).unwrap();
+    servers(nodes[NODE].as_ref());
+    assert!(mock.requests_to("GET", &agent).is_empty());
+
+    // The guest agent is not enabled in the configuration of the VM.
+    mock.mock_once(
+        "GET",
+        &status,
+        MockResponse::data(json!({ "ha": {}, "status": "running", "vmid": 100, "agent": 0 })),
+    );
+    block_on(connection.server(&path(&[NODE, "100"])))
+        .unwrap()
+        .unwrap();
+    assert!(mock.requests_to("GET", &agent).is_empty());
+
+    mock.mock(
+        "GET",
+        &status,
+        MockResponse::data(json!({ "ha": {}, "status": "running", "vmid": 100, "agent": 1 })),
+    );
+    block_on(connection.server(&path(&[NODE, "100"])))
+        .unwrap()
+        .unwrap();
+    assert_eq!(mock.requests_to("GET", &agent).len(), 1);
+}
+
+#[test]
+fn server_paths_resolve_vms_by_id() {
+    let mock = start_mock();
+    mock.mock_ticket_auth();
+    let connection = connect(config(&mock, "resolves-paths"));
+
+    let path = |p: &[&str]| p.iter().map(ToString::to_string).collect::<Vec<_>>();
+    let vm = block_on(connection.server(&path(&["pool:production", "201"])))
+        .unwrap()
+        .unwrap();
+    assert_eq!(vm.metadata().title, "201 (dns)");
+    // The VM is looked up on the node of the path.
+    let vm = block_on(connection.server(&path(&[NODE, "100"])))
+        .unwrap()
+        .unwrap();
+    assert_eq!(vm.metadata().title, "100 (webserver)");
+    assert_eq!(
+        mock.requests_to("GET", &format!("nodes/{NODE}/qemu")).len(),
+        1
+    );
+    // The VM is not on the node of the path anymore, it was migrated.
+    let vm = block_on(connection.server(&path(&["pve2", "100"])))
+        .unwrap()
+        .unwrap();
+    assert_eq!(vm.metadata().title, "100 (webserver)");
+    let node = block_on(connection.server(&path(&[NODE])))
+        .unwrap()
+        .unwrap();
+    assert_eq!(node.metadata().title, NODE);
+    assert!(block_on(connection.server(&path(&["pve3"])))
+        .unwrap()
+        .is_none());
+}
+
+#[test]
+fn server_paths_resolve_nodes_with_numeric_names() {
+    let mock = start_mock();
+    mock.mock_ticket_auth();
+    mock.mock(
+        "GET",
+        "nodes",
+        MockResponse::data(json!([
+            { "node": "2024", "status": "online", "type": "node", "id": "node/2024" }
+        ])),
+    );
+    let mut config = config(&mock, "resolves-numeric-nodes");
+    config.set_group_by(ProxmoxGroupBy::Pool);
+    let connection = connect(config);
+
+    let path = |p: &[&str]| p.iter().map(ToString::to_string).collect::<Vec<_>>();
+    let node = block_on(connection.server(&path(&["nodes", "2024"])))
+        .unwrap()
+        .unwrap();
+    assert_eq!(node.metadata().title, "2024");
+    let vm = block_on(connection.server(&path(&["pool:production", "100"])))
+        .unwrap()
+        .unwrap();
+    assert_eq!(vm.metadata().title, "100 (webserver)");
+}
+
+#[test]
+fn discovered_endpoints_are_stored_in_the_configuration() {
+    let mock = start_mock();
+    mock.mock_ticket_auth();
+    let mut config = config(&mock, "discovers-endpoints");
+    let connection = connect(config.clone());
+    let port = mock.port();
+    let discovered = vec![format!("127.0.0.1:{port}"), format!("127.0.0.2:{port}")];
+
+    assert_eq!(
+        connection.configuration_updates(),
+        vec![(DISCOVERED_ENDPOINTS.to_string(), discovered.clone().into())]
+    );
+
+    config.set_discovered_endpoints(discovered);
+    let roots: Vec<String> = api_roots(&config)
+        .unwrap()
+        .iter()
+        .map(ToString::to_string)
+        .collect();
+    assert_eq!(
+        roots,
+        vec![
+            format!("https://127.0.0.1:{port}/api2/json"),
+            format!("https://127.0.0.2:{port}/api2/json"),
+        ]
+    );
+
+    config.set_discover_endpoints(false);
+    assert_eq!(api_roots(&config).unwrap().len(), 1);
+}
+
+#[test]
+fn vnc_reachability_is_probed_once_per_node() {
+    let mock = start_mock();
+    mock.mock_ticket_auth();
+    let connection = connect(config(&mock, "probes-vnc-once"));
+    let vncshell = format!("nodes/{NODE}/vncshell");
+    // The mock itself stands in for the VNC port, so the probe succeeds.
+    mock.mock(
+        "POST",
+        &vncshell,
+        MockResponse::data(json!({
+            "cert": "",
+            "port": mock.port().to_string(),
+            "ticket": "PVEVNC:ticket",
+            "upid": "UPID:pve:vncshell",
+            "user": USER,
+        })),
+    );
+    let entity = ProxmoxEntity::Node(NODE.to_string().into());
+    let open_console = || {
+        tkruntime().block_on(vnc_adapter_creds(
+            &connection.client,
+            &entity,
+            &connection.vnc,
+        ))
+    };
+
+    assert!(matches!(open_console(), Ok(AdapterCreds::Vnc(_))));
+    assert_eq!(mock.requests_to("POST", &vncshell).len(), 2);
+    assert!(matches!(open_console(), Ok(AdapterCreds::Vnc(_))));
+    assert_eq!(mock.requests_to("POST", &vncshell).len(), 3);
+}
+
+#[test]
+fn connection_groups_by_pool() {
+    let mock = start_mock();
+    mock.mock_ticket_auth();
+    let mut config = config(&mock, "groups-by-pool");
+    config.set_group_by(ProxmoxGroupBy::Pool);
+    let connection = connect(config);
+
+    let groups = block_on(connection.servers()).unwrap();
+    let ids: Vec<&str> = groups.keys().map(AsRef::as_ref).collect();
+    assert_eq!(ids, ["nodes", "pool:production", "ungrouped"]);
+
+    let production = servers(groups["pool:production"].as_ref());
+    let ids: Vec<&str> = production.keys().map(AsRef::as_ref).collect();
+    assert_eq!(ids, ["100"]);
+    let ungrouped = servers(groups["ungrouped"].as_ref());
+    let ids: Vec<&str> = ungrouped.keys().map(AsRef::as_ref).collect();
+    assert_eq!(ids, ["201", "9000"]);
+}
+
+#[test]
+fn connection_uses_apikey() {
+    let mock = start_mock();
+    let mut config = config(&mock, "uses-apikey");
+    config.set_use_apikey(true);
+    config.set_tokenid(TOKENID);
+    config.set_password_or_apikey_session(Some(SecureString::from_str(APIKEY).unwrap()));
+    let connection = connect(config);
+
+    assert_eq!(block_on(connection.servers()).unwrap().len(), 2);
+    let request = &mock.requests_to("GET", "nodes")[0];
+    assert_eq!(
+        request.header("authorization"),
+        Some(format!("PVEAPIToken={TOKENID}={APIKEY}").as_str())
+    );
+    assert!(mock.requests_to("POST", "access/ticket").is_empty());
+}
+
+#[test]
+fn wrong_password_fails_auth() {
+    let mock = start_mock();
+    mock.mock("POST", "access/ticket", MockResponse::unauthorized());
+    // Discovery and permissions are optional, so connecting itself still succeeds.
+    let connection = connect(config(&mock, "wrong-password"));
+
+    assert!(matches!(
+        block_on(connection.servers()),
+        Err(ConnectionError::AuthFailed(..))
+    ));
+}
+
+#[test]
+fn expired_ticket_is_reissued() {
+    let mock = start_mock();
+    mock.mock_ticket_auth();
+    let connection = connect(config(&mock, "reissues-ticket"));
+    block_on(connection.servers()).unwrap();
+
+    mock.mock_once("GET", "nodes", MockResponse::unauthorized());
+    mock.mock_once(
+        "POST",
+        "access/ticket",
+        MockResponse::fixture("ticket_renewed"),
+    );
+    assert_eq!(block_on(connection.servers()).unwrap().len(), 2);
+
+    let requests = mock.requests_to("GET", "nodes");
+    assert_eq!(
+        requests.last().unwrap().header("authorization"),
+        Some(format!("PVEAuthCookie={TICKET_RENEWED}").as_str())
+    );
+}
+
+#[test]
+fn notes_and_tags_are_loaded() {
+    let mock = start_mock();
+    mock.mock_ticket_auth();
+    mock.mock(
+        "GET",
+        &format!("nodes/{NODE}/qemu/100/config"),
+        MockResponse::data(json!({ "description": "Do not reboot during business hours." })),
+    );
+    mock.mock(
+        "GET",
+        &format!("nodes/{NODE}/config"),
+        MockResponse::data(json!({})),
+    );
+    let connection = connect(config(&mock, "loads-notes"));
+    let nodes = block_on(connection.servers()).unwrap();
+    let guests = servers(nodes[NODE].as_ref());
+
+    let webserver = &guests["100"];
+    assert_eq!(webserver.metadata().tags, ["prod", "web"]);
+    assert_eq!(
+        block_on(webserver.notes()).unwrap().as_deref(),
+        Some("Do not reboot during business hours.")
+    );
+    // VM.Config.Options is not granted.
+    assert!(!action_ids(webserver.as_ref()).contains(&"vmeditnotes".to_string()));
+    assert_eq!(block_on(nodes[NODE].notes()).unwrap(), None);
+    // Notes of offline nodes can not be loaded.
+    assert_eq!(block_on(nodes["pve2"].notes()).unwrap(), None);
+    assert!(mock.requests_to("GET", "nodes/pve2/config").is_empty());
+}
@@ -0,0 +1,319 @@
+/* Copyright 2024 Marco Köpcke
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ *
+ * SPDX-License-Identifier: GPL-3.0-or-later
+ */
+//! VNC tunnelled through the `vncwebsocket` API endpoint.
+//!
+//! In many networks only the API port of Proxmox is reachable, not the ports `vncproxy` opens.
+//! In that case a local bridge is started: it accepts a single connection from the VNC client
+//! on localhost and relays it to the websocket, so the regular VNC adapter can be used.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use anyhow::anyhow;
+use async_tungstenite::tokio::client_async_tls_with_connector;
+use async_tungstenite::tungstenite;
+use async_tungstenite::tungstenite::client::IntoClientRequest;
+use async_tungstenite::tungstenite::handshake::client::generate_key;
+use async_tungstenite::tungstenite::Message;
+use futures::{Sink, SinkExt, Stream, StreamExt};
+use gettextrs::gettext;
+use http::HeaderValue;
+use libfieldmonitor::connection::{ConnectionError, ConnectionResult};
+use log::{debug, warn};
+use openssl::ssl::{SslConnector, SslMethod, SslVerifyMode};
+use proxmox_api::{
+    connect_tcp, NodeVncshellInput, ProxmoxApiClient, ProxyConfig, VmVncproxyInput, Vncproxy,
+    VncwebsocketInput,
+};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::select;
+use tokio::time::timeout;
+
+use crate::{map_proxmox_error, AdapterCreds, ProxmoxEntity};
+
+/// How long to wait when checking whether a port on the server can be reached directly.
+const PROBE_TIMEOUT: Duration = Duration::from_secs(3);
+/// How long to wait for the VNC client to connect to the bridge.
+const ACCEPT_TIMEOUT: Duration = Duration::from_secs(30);
+const BUFFER_SIZE: usize = 64 * 1024;
+
+/// How VNC consoles of a connection are opened.
+///
+/// Whether the ports `vncproxy` opens can be reached is remembered per node, so the check is only
+/// done for the first console opened on each node of the connection.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct VncOptions {
+    force_websocket: bool,
+    reachable: Arc<Mutex<HashMap<String, bool>>>,
+}
+
+impl VncOptions {
+    pub(crate) fn new(force_websocket: bool) -> Self {
+        Self {
+            force_websocket,
+            reachable: Default::default(),
+        }
+    }
+
+    fn reachable(&self, node: &str) -> Option<bool> {
+        self.reachable.lock().unwrap().get(node).copied()
+    }
+
+    fn set_reachable(&self, node: &str, reachable: bool) {
+        self.reachable
+            .lock()
+            .unwrap()
+            .insert(node.to_string(), reachable);
+    }
+}
+
+/// Requests a VNC console for the entity. The VNC port is connected to directly if it can be
+/// reached, otherwise (or if websockets are forced or a proxy is configured) it is tunnelled
+/// through the API.
+///
+/// Must be run inside the Tokio runtime.
+pub(crate) async fn vnc_adapter_creds(
+    client: &ProxmoxApiClient,
+    entity: &ProxmoxEntity,
+    options: &VncOptions,
+) -> ConnectionResult<AdapterCreds> {
+    if !options.force_websocket && client.clientconfig_proxy().is_none() {
+        let node = match entity {
+            ProxmoxEntity::Node(node_id) | ProxmoxEntity::Vm(_, node_id, _) => node_id.as_ref(),
+        };
+        match options.reachable(node) {
+            Some(true) => return Ok(AdapterCreds::Vnc(vncproxy(client, entity, false).await?)),
+            Some(false) => {}
+            None => {
+                let probe = vncproxy(client, entity, false).await?;
+                let reachable = match u16::try_from(probe.port.get()) {
+                    Ok(port) => is_reachable(&client.clientconfig_hostname(), port).await,
+                    Err(_) => false,
+                };
+                options.set_reachable(node, reachable);
+                if reachable {
+                    // The probe used up the single connection the proxy accepts, so request a new one.
+                    return Ok(AdapterCreds::Vnc(vncproxy(client, entity, false).await?));
+                }
+                debug!(
+                    "VNC port {} of node {node} is not reachable, falling back to websocket",
+                    probe.port
+                );
+            }
+        }
+    }
+
+    let vncproxy = vncproxy(client, entity, true).await?;
+    let input = VncwebsocketInput {
+        port: vncproxy.port,
+        vncticket: vncproxy.ticket.clone(),
+    };
+    let request = match entity {
+        ProxmoxEntity::Node(node_id) => client.node_vncwebsocket(node_id, &input).await,
+        ProxmoxEntity::Vm(vm_type, node_id, vm_id) => {
+            client
+                .vm_vncwebsocket(node_id, vm_id, *vm_type, &input)
+                .await
+        }
+    }
+    .map_err(map_proxmox_error)?;
+
+    let local_port = start_bridge(
+        request,
+        client.clientconfig_ignore_ssl_errors(),
+        client.clientconfig_proxy(),
+    )
+    .await
+    .map_err(|err| {
+        ConnectionError::General(
+            Some(gettext(
+                "Failed to connect to the VNC console through the Proxmox API.",
+            )),
+            err,
+        )
+    })?;
+    Ok(AdapterCreds::VncWebsocket(vncproxy, local_port))
+}
+
+async fn vncproxy(
+    client: &ProxmoxApiClient,
+    entity: &ProxmoxEntity,
+    websocket: bool,
+) -> ConnectionResult<Vncproxy> {
+    let websocket = websocket.then_some(1);
+    match entity {
+        ProxmoxEntity::Node(node_id) => {
+            client
+                .node_vncshell(
+                    node_id,
+                    NodeVncshellInput {
+                        websocket,
+                        ..Default::default()
+                    },
+                )
+                .await
+        }
+        ProxmoxEntity::Vm(vm_type, node_id, vm_id) => {
+            client
+                .vm_vncproxy(
+                    node_id,
+                    vm_id,
+                    Some(*vm_type),
+                    VmVncproxyInput {
+                        websocket,
+                        ..Default::default()
+                    },
+                )
+                .await
+        }
+    }
+    .map_err(map_proxmox_error)
+}
+
+/// Whether a TCP connection can be established to the given port of the server.
+/// Note that this consumes the connection a `vncproxy` call waits for.
+async fn is_reachable(host: &str, port: u16) -> bool {
+    matches!(
+        timeout(PROBE_TIMEOUT, TcpStream::connect((host, port))).await,
+        Ok(Ok(_))
+    )
+}
+
+/// Connects to the websocket described by `request` (see `ProxmoxApiClient::vm_vncwebsocket`)
+/// and starts relaying it to a local port, which is returned.
+///
+/// Must be run inside the Tokio runtime.
+async fn start_bridge(
+    request: http::Request<()>,
+    ignore_ssl_errors: bool,
+    proxy: Option<&ProxyConfig>,
+) -> anyhow::Result<u16> {
+    let mut request = request.into_client_request()?;
+    {
+        let request_headers = request.headers_mut();
+        request_headers.insert("Sec-WebSocket-Version", HeaderValue::from_str("13")?);
+        request_headers.insert("Sec-WebSocket-Key", HeaderValue::from_str(&generate_key())?);
+    }
+
+    let domain = domain(&request)?;
+    let port = port(&request)?;
+
+    let socket = connect_tcp(proxy, &domain, port).await?;
+    let mut connector_builder = SslConnector::builder(SslMethod::tls())?;
+    connector_builder.set_verify(if ignore_ssl_errors {
+        SslVerifyMode::NONE
+    } else {
+        SslVerifyMode::PEER
+    });
+    let connector = connector_builder.build().configure()?;
+    let (ws, _) = client_async_tls_with_connector(request, socket, Some(connector)).await?;
+    debug!("VNC websocket connected");
+
+    let listener = TcpListener::bind(("127.0.0.1", 0)).await?;
+    let local_port = listener.local_addr()?.port();
+
+    tokio::spawn(async move {
+        let result = async move {
+            let (stream, _) = timeout(ACCEPT_TIMEOUT, listener.accept())
+                .await
+                .map_err(|_| anyhow!("VNC client did not connect to bridge"))??;
+            drop(listener);
+            debug!("VNC client connected to bridge");
+            relay(stream, ws).await
+        }
+        .await;
+        match result {
+            Ok(()) => debug!("VNC websocket bridge closed"),
+            Err(err) => warn!("VNC websocket bridge closed with error: {err}"),
+        }
+    });
+
+    Ok(local_port)
+}
+
+async fn relay<S>(stream: TcpStream, ws: S) -> anyhow::Result<()>
+where
+    S: Stream<Item = Result<Message, tungstenite::Error>>
+        + Sink<Message, Error = tungstenite::Error>
+        + Unpin,
+{
+    let (mut tcp_read, mut tcp_write) = stream.into_split();
+    let (mut ws_sink, mut ws_stream) = ws.split();
+
+    let upstream = async move {
+        let mut buf = vec![0; BUFFER_SIZE];
+        loop {
+            let len = tcp_read.read(&mut buf).await?;
+            if len == 0 {
+                ws_sink.close().await.ok();
+                return anyhow::Ok(());
+            }
+            ws_sink.send(Message::binary(&buf[..len])).await?;
+        }
+    };
+
+    let downstream = async move {
+        while let Some(msg) = ws_stream.next().await {
+            match msg? {
+                Message::Binary(data) => tcp_write.write_all(&data).await?,
+                Message::Text(text) => tcp_write.write_all(text.as_bytes()).await?,
+                Message::Close(_) => break,
+                _ => {}
+            }
+        }
+        tcp_write.shutdown().await.ok();
+        anyhow::Ok(())
+    };
+
+    select!(
+        r = upstream => r,
+        r = downstream => r,
+    )
+}
+
+fn domain(request: &tungstenite::handshake::client::Request) -> Result<String, tungstenite::Error> {
+    request
+        .uri()
+        .host()
+        .map(|host| {
+            // IPv6 addresses may be surrounded by brackets, which are not part of the address.
+            host.strip_prefix('[')
+                .and_then(|host| host.strip_suffix(']'))
+                .unwrap_or(host)
+                .to_owned()
+        })
+        .ok_or(tungstenite::Error::Url(
+            tungstenite::error::UrlError::NoHostName,
+        ))
+}
+
+fn port(request: &tungstenite::handshake::client::Request) -> Result<u16, tungstenite::Error> {
+    request
+        .uri()
+        .port_u16()
+        .or_else(|| match request.uri().scheme_str() {
+            Some("wss") => Some(443),
+            Some("ws") => Some(80),
+            _ => None,
+        })
+        .ok_or(tungstenite::Error::Url(
+            tungstenite::error::UrlError::UnsupportedUrlScheme,
+        ))
+}
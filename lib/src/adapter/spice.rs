@@ -18,19 +18,20 @@
 use std::borrow::Cow;
 use std::cell::RefCell;
 use std::num::NonZeroU32;
+use std::os::fd::IntoRawFd;
 use std::rc::Rc;
 
 use anyhow::anyhow;
 use derive_builder::Builder;
 use gettextrs::gettext;
 use glib::prelude::*;
-use log::debug;
+use log::{debug, warn};
 use rdw_spice::spice;
 use rdw_spice::spice::prelude::ChannelExt;
 use rdw_spice::spice::{ChannelEvent, Session};
 use secure_string::SecureString;
 
-use crate::adapter::types::{Adapter, AdapterDisplay, AdapterDisplayWidget};
+use crate::adapter::types::{Adapter, AdapterDisplay, AdapterDisplayWidget, FdSource};
 use crate::connection::ConnectionError;
 
 #[derive(Builder, Debug, Clone, Default)]
@@ -53,6 +54,9 @@ pub struct SpiceSessionConfig {
     tls_port: Option<NonZeroU32>,
     #[builder(default = "None")]
     proxy: Option<String>,
+    /// Connect the channels through sockets opened by this instead of the network.
+    #[builder(default = "None")]
+    fd_source: Option<FdSource>,
 }
 
 impl SpiceSessionConfig {
@@ -101,6 +105,7 @@ impl SpiceAdapter {
             cert_subject: None,
             tls_port: None,
             proxy: None,
+            fd_source: None,
         })
     }
 
@@ -122,12 +127,29 @@ impl Adapter for SpiceAdapter {
         let spice = rdw_spice::Display::new();
 
         let mut session = spice.session();
+        let fd_source = self.0.fd_source.clone();
         self.0.apply(&mut session);
 
         let disconnect_error: Rc<RefCell<Option<glib::Error>>> = Default::default();
 
         let on_disconnected_cln = on_disconnected.clone();
+        let fd_source_cln = fd_source.clone();
         session.connect_channel_new(move |_, channel| {
+            if let Some(fd_source) = fd_source_cln.clone() {
+                // Every channel but the main channel asks for its own socket.
+                channel.connect_open_fd(move |channel, _with_tls| {
+                    let channel = channel.clone();
+                    let fd_source = fd_source.clone();
+                    glib::spawn_future_local(async move {
+                        match fd_source.open().await {
+                            Ok(fd) => {
+                                channel.open_fd(fd.into_raw_fd());
+                            }
+                            Err(err) => warn!("Failed to open SPICE socket: {err}"),
+                        }
+                    });
+                });
+            }
             if let Ok(main) = channel.clone().downcast::<spice::MainChannel>() {
                 let on_disconnected_cln_cln = on_disconnected_cln.clone();
                 main.connect_channel_event(move |channel, event| {
@@ -171,6 +193,7 @@ impl Adapter for SpiceAdapter {
             }
         ));
 
+        let on_disconnected_fd = on_disconnected.clone();
         session.connect_disconnected(glib::clone!(
             #[strong]
             disconnect_error,
@@ -196,7 +219,24 @@ impl Adapter for SpiceAdapter {
         ));
 
         glib::spawn_future_local(async move {
-            session.connect();
+            let fd = match &fd_source {
+                Some(fd_source) => Some(fd_source.open().await),
+                None => None,
+            };
+            match fd {
+                None => {
+                    session.connect();
+                }
+                // The session takes ownership of the socket.
+                Some(Ok(fd)) => {
+                    session.open_fd(fd.into_raw_fd());
+                }
+                Some(Err(err)) => {
+                    warn!("Failed to open SPICE socket: {err}");
+                    on_disconnected_fd(Err(ConnectionError::General(Some(err.to_string()), err)));
+                    return;
+                }
+            }
             on_connected();
         });
 
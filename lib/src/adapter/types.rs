@@ -15,7 +15,12 @@
  *
  * SPDX-License-Identifier: GPL-3.0-or-later
  */
+use std::fmt::{Debug, Formatter};
+use std::os::fd::OwnedFd;
 use std::rc::Rc;
+use std::sync::Arc;
+
+use futures::future::LocalBoxFuture;
 
 use crate::connection::ConnectionError;
 
@@ -48,3 +53,31 @@ pub trait Adapter: Send + Sync {
         on_disconnected: Rc<dyn Fn(Result<(), ConnectionError>)>,
     ) -> Box<dyn AdapterDisplay>;
 }
+
+/// Opens connections to a display server without going through the network, eg. sockets
+/// handed out by the hypervisor. Protocols that use multiple connections open one per
+/// connection.
+///
+/// Opening may take a while, implementations should not block the main thread.
+#[derive(Clone)]
+pub struct FdSource(Arc<FdSourceFn>);
+
+type FdSourceFn = dyn Fn() -> LocalBoxFuture<'static, anyhow::Result<OwnedFd>> + Send + Sync;
+
+impl FdSource {
+    pub fn new(
+        open: impl Fn() -> LocalBoxFuture<'static, anyhow::Result<OwnedFd>> + Send + Sync + 'static,
+    ) -> Self {
+        Self(Arc::new(open))
+    }
+
+    pub async fn open(&self) -> anyhow::Result<OwnedFd> {
+        (self.0)().await
+    }
+}
+
+impl Debug for FdSource {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str("FdSource")
+    }
+}
@@ -17,6 +17,7 @@
  */
 use std::borrow::Cow;
 use std::cell::RefCell;
+use std::os::fd::IntoRawFd;
 use std::rc::Rc;
 
 use anyhow::anyhow;
@@ -27,7 +28,7 @@ use log::{debug, warn};
 use rdw_vnc::gvnc;
 use secure_string::SecureString;
 
-use crate::adapter::types::{Adapter, AdapterDisplay, AdapterDisplayWidget};
+use crate::adapter::types::{Adapter, AdapterDisplay, AdapterDisplayWidget, FdSource};
 use crate::connection::ConnectionError;
 
 pub struct VncAdapter {
@@ -36,6 +37,7 @@ pub struct VncAdapter {
     user: String,
     password: SecureString,
     ca: Option<String>,
+    fd_source: Option<FdSource>,
 }
 
 impl VncAdapter {
@@ -48,6 +50,7 @@ impl VncAdapter {
             user,
             password,
             ca: None,
+            fd_source: None,
         }
     }
 
@@ -64,6 +67,24 @@ impl VncAdapter {
             user,
             password,
             ca: Some(ca),
+            fd_source: None,
+        }
+    }
+
+    /// Connects through a socket opened by `fd_source` instead of the network.
+    pub fn new_with_fd_source(
+        fd_source: FdSource,
+        user: String,
+        password: SecureString,
+        ca: Option<String>,
+    ) -> Self {
+        Self {
+            host: String::new(),
+            port: 0,
+            user,
+            password,
+            ca,
+            fd_source: Some(fd_source),
         }
     }
 
@@ -109,6 +130,7 @@ impl Adapter for VncAdapter {
                 )));
             });
 
+        let on_disconnected_fd = on_disconnected.clone();
         vnc.connection().connect_vnc_disconnected(move |_conn| {
             debug!("VNC connection disconnected");
             match error_container.borrow_mut().take() {
@@ -169,9 +191,29 @@ impl Adapter for VncAdapter {
             }
         ));
 
-        vnc.connection()
-            .open_host(&host, &format!("{}", port))
-            .unwrap();
+        match &self.fd_source {
+            None => vnc
+                .connection()
+                .open_host(&host, &format!("{}", port))
+                .unwrap(),
+            Some(fd_source) => {
+                let fd_source = fd_source.clone();
+                let vnc = vnc.clone();
+                glib::spawn_future_local(async move {
+                    match fd_source.open().await {
+                        // The connection takes ownership of the socket.
+                        Ok(fd) => vnc.connection().open_fd(fd.into_raw_fd()).unwrap(),
+                        Err(err) => {
+                            warn!("Failed to open VNC socket: {err}");
+                            on_disconnected_fd(Err(ConnectionError::General(
+                                Some(err.to_string()),
+                                err,
+                            )))
+                        }
+                    }
+                });
+            }
+        }
 
         Box::new(VncAdapterDisplay(vnc))
     }
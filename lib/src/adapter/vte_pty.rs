@@ -37,6 +37,12 @@ use field_monitor_vte_driver_lib::DBUS_KEY_ENV_VAR;
 use crate::adapter::types::{Adapter, AdapterDisplay, AdapterDisplayWidget};
 use crate::connection::ConnectionError;
 
+/// Driver for `VtePtyAdapter` that opens an SSH session. Its arguments are the hostname,
+/// the port and the username. Port and username may be empty.
+pub const SSH_DRIVER_BIN: &str = "de.capypara.FieldMonitor.PtyDrv.Ssh";
+/// Adapter tag connections should use for `VtePtyAdapter`s running `SSH_DRIVER_BIN`.
+pub const SSH_ADAPTER_TAG: &str = "ssh";
+
 pub struct VtePtyAdapter {
     connection_id: String,
     server_id: String,
@@ -16,16 +16,19 @@
  * SPDX-License-Identifier: GPL-3.0-or-later
  */
 use std::borrow::Cow;
-use std::cell::RefCell;
+use std::cell::{Cell, RefCell};
+use std::convert::Infallible;
 use std::rc::Rc;
-use std::sync::Arc;
+use std::sync::{Arc, OnceLock};
 
 use anyhow::anyhow;
 use futures::future::LocalBoxFuture;
+use futures::StreamExt;
 use gettextrs::gettext;
 use glib;
 use glib::prelude::*;
 use glib::subclass::prelude::*;
+use glib::subclass::Signal;
 use log::{debug, error};
 
 use crate::connection::types::{Connection, ConnectionProvider};
@@ -45,6 +48,10 @@ mod imp {
         pub provider: RefCell<Option<Rc<Box<dyn ConnectionProvider>>>>,
         pub implementation: RefCell<Option<Box<dyn Connection>>>,
         pub load_error: RefCell<Option<Arc<ConnectionError>>>,
+        /// Forwards the changes reported by the implementation as signals.
+        pub changes_watcher: RefCell<Option<glib::JoinHandle<()>>>,
+        /// Whether the implementation updated the configuration and it was not saved yet.
+        pub unsaved_configuration: Cell<bool>,
     }
 
     #[glib::object_subclass]
@@ -55,7 +62,27 @@ mod imp {
     }
 
     #[glib::derived_properties]
-    impl ObjectImpl for ConnectionInstance {}
+    impl ObjectImpl for ConnectionInstance {
+        fn signals() -> &'static [Signal] {
+            static SIGNALS: OnceLock<Vec<Signal>> = OnceLock::new();
+            SIGNALS.get_or_init(|| {
+                vec![
+                    // This signal is emitted when the implementation reports a change of its
+                    // servers. The parameter is the path of the server relative to the
+                    // connection, or empty if servers were added or removed.
+                    Signal::builder("server-changed")
+                        .param_types([Vec::<String>::static_type()])
+                        .build(),
+                ]
+            })
+        }
+
+        fn dispose(&self) {
+            if let Some(watcher) = self.changes_watcher.take() {
+                watcher.abort();
+            }
+        }
+    }
 }
 
 glib::wrapper! {
@@ -102,14 +129,17 @@ impl ConnectionInstance {
     }
 
     /// Changes the configuration and recreates the implementation.
-    pub async fn set_configuration(&self, value: DualScopedConnectionConfiguration) {
+    pub async fn set_configuration(&self, mut value: DualScopedConnectionConfiguration) {
         assert_eq!(value.session().id(), self.connection_id().as_str());
 
         let slf_imp = self.imp();
         let provider = slf_imp.provider.borrow().as_ref().expect(NOT_INIT).clone();
         match provider.load_connection(value.session().clone()).await {
             Ok(implementation) => {
+                value =
+                    self.apply_configuration_updates(value, implementation.configuration_updates());
                 self.set_title(implementation.metadata().title.as_str());
+                self.watch_changes(implementation.as_ref());
                 slf_imp.implementation.replace(Some(implementation));
             }
             Err(err) => {
@@ -124,6 +154,95 @@ impl ConnectionInstance {
         slf_imp.configuration.replace(Some(value));
     }
 
+    /// Stores the values the implementation learned in the configuration, see
+    /// `Connection::configuration_updates`.
+    fn apply_configuration_updates(
+        &self,
+        value: DualScopedConnectionConfiguration,
+        updates: Vec<(String, serde_yaml::Value)>,
+    ) -> DualScopedConnectionConfiguration {
+        let updates: Vec<_> = updates
+            .into_iter()
+            .filter(|(key, update)| {
+                value
+                    .persistent()
+                    .get(key)
+                    .and_then(|current| current.as_serde_value())
+                    != Some(update)
+            })
+            .collect();
+        if updates.is_empty() {
+            return value;
+        }
+        debug!(
+            "connection {} updated its configuration",
+            self.connection_id()
+        );
+        self.imp().unsaved_configuration.set(true);
+        value
+            .transform_update_unified(|config| {
+                for (key, update) in &updates {
+                    config.set_value(key, update.clone());
+                }
+                Ok::<_, Infallible>(())
+            })
+            .unwrap_or_else(|never| match never {})
+    }
+
+    /// Returns the configuration if the implementation updated it since it was last saved.
+    /// It is considered saved afterwards.
+    pub fn take_unsaved_configuration(&self) -> Option<DualScopedConnectionConfiguration> {
+        self.imp()
+            .unsaved_configuration
+            .replace(false)
+            .then(|| self.with_configuration(|config| config.explicit_clone()))
+    }
+
+    /// Connects to changes of the servers that the implementation reports on its own, see
+    /// `Connection::changes`.
+    pub fn connect_server_changed<F: Fn(&Self, &ServerChange) + 'static>(
+        &self,
+        f: F,
+    ) -> glib::SignalHandlerId {
+        self.connect_local("server-changed", false, move |values| {
+            let slf = values[0].get::<Self>().unwrap();
+            let path = values[1].get::<Vec<String>>().unwrap();
+            let change = if path.is_empty() {
+                ServerChange::ServersChanged
+            } else {
+                ServerChange::ServerChanged(path)
+            };
+            f(&slf, &change);
+            None
+        })
+    }
+
+    fn watch_changes(&self, implementation: &dyn Connection) {
+        let watcher = implementation.changes().map(|mut changes| {
+            // Only a weak reference is kept, the stream ends with the implementation.
+            let slf = self.downgrade();
+            glib::spawn_future_local(async move {
+                while let Some(change) = changes.next().await {
+                    let Some(slf) = slf.upgrade() else {
+                        break;
+                    };
+                    debug!(
+                        "connection {} reported change: {change:?}",
+                        slf.connection_id()
+                    );
+                    let path = match change {
+                        ServerChange::ServersChanged => Vec::new(),
+                        ServerChange::ServerChanged(path) => path,
+                    };
+                    slf.emit_by_name::<()>("server-changed", &[&path]);
+                }
+            })
+        });
+        if let Some(old_watcher) = self.imp().changes_watcher.replace(watcher) {
+            old_watcher.abort();
+        }
+    }
+
     pub fn with_configuration<T>(&self, cb: impl Fn(&DualScopedConnectionConfiguration) -> T) -> T {
         cb(self.imp().configuration.borrow().as_ref().unwrap())
     }
@@ -183,4 +302,22 @@ impl Connection for ConnectionInstance {
             }
         })
     }
+
+    // TODO: This SHOULD be okay, since we will never re-enter this function during loading servers.
+    #[allow(clippy::await_holding_refcell_ref)]
+    fn server<'a>(
+        &'a self,
+        path: &'a [String],
+    ) -> LocalBoxFuture<'a, ConnectionResult<Option<Box<dyn ServerConnection>>>> {
+        Box::pin(async move {
+            let brw = self.imp().implementation.borrow();
+            match brw.as_ref() {
+                Some(implementation) => implementation.server(path).await,
+                None => Err(match self.imp().load_error.borrow().as_ref() {
+                    Some(err) => err.clone_outside(),
+                    None => ConnectionError::General(None, anyhow!(gettext("Unknown error"))),
+                }),
+            }
+        })
+    }
 }
@@ -22,6 +22,7 @@ use std::sync::Arc;
 
 use derive_builder::Builder;
 use futures::future::LocalBoxFuture;
+use futures::stream::LocalBoxStream;
 use indexmap::IndexMap;
 use thiserror::Error;
 
@@ -124,6 +125,16 @@ pub struct ConnectionMetadata {
     pub icon: IconSpec<ConnectionMetadata>,
 }
 
+/// A change of the servers of a connection that happened outside of Field Monitor, eg. a VM
+/// that was started with another tool.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ServerChange {
+    /// Servers were added or removed.
+    ServersChanged,
+    /// The state of the server at the path (relative to the connection) changed.
+    ServerChanged(Vec<String>),
+}
+
 /// Metadata about a server. Only the title is required.
 ///
 /// On the builder type, build can be unwrapped as long as the title is set.
@@ -136,8 +147,60 @@ pub struct ServerMetadata {
     pub subtitle: Option<String>,
     #[builder(default = "None")]
     pub is_online: Option<bool>,
+    /// A state that is shown instead of online / offline, see `ServerState`.
+    #[builder(default = "None")]
+    pub state: Option<ServerState>,
     #[builder(default = "IconSpec::Default")]
     pub icon: IconSpec<ServerMetadata>,
+    /// Short labels the server is tagged with, shown next to it.
+    #[builder(default = "Vec::new()")]
+    pub tags: Vec<String>,
+}
+
+/// State of a server that is neither simply online nor offline.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ServerState {
+    /// The server is suspended and keeps its memory. It can still be connected to.
+    Paused,
+    /// The memory of the server was saved to disk and it was stopped. Starting it resumes
+    /// from the saved state.
+    Saved,
+}
+
+/// Unit of the values of a `ServerMetric`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MetricUnit {
+    /// A fraction between 0.0 and 1.0, shown as percentage.
+    Ratio,
+    /// An amount of bytes.
+    Bytes,
+    /// A transfer rate in bytes per second.
+    BytesPerSecond,
+}
+
+/// Recent history of a resource usage metric of a server, such as CPU load or memory use.
+///
+/// On the builder type, build can be unwrapped as long as the title and unit are set.
+#[derive(Builder, Debug, Clone)]
+#[builder(pattern = "owned")]
+#[non_exhaustive]
+pub struct ServerMetric {
+    pub title: String,
+    pub unit: MetricUnit,
+    /// Upper bound of the values, if known. Otherwise the largest value is used for scaling.
+    #[builder(default = "None")]
+    pub max: Option<f64>,
+    /// Data points as tuples (unix timestamp, value), oldest first.
+    #[builder(default = "Vec::new()")]
+    pub points: Vec<(i64, f64)>,
+}
+
+impl ServerMetric {
+    /// The most recent value, if any.
+    pub fn current(&self) -> Option<f64> {
+        self.points.last().map(|(_, v)| *v)
+    }
 }
 
 pub trait FieldMonitorApplication {}
@@ -242,11 +305,23 @@ pub type Parameters = Box<dyn Any>;
 /// methods on `Connection` and `ServerConnection`. See also `ActionMap`, `ServerAction`.
 /// Parameters: static parameters, parent window, toast overlay
 /// Return value: True if the connection should be reloaded, false otherwise.
+/// Actions which need further input from the user can ask for it using the parent window,
+/// see `crate::gtk::FieldMonitorActionParametersDialog`.
 pub type ActionExecuteFut<'a> =
     dyn Fn(Parameters, Option<gtk::Window>, Option<adw::ToastOverlay>) -> LocalBoxFuture<'a, bool>;
 pub type ServerMap = IndexMap<Cow<'static, str>, Box<dyn ServerConnection>>;
 pub type ServerMapSend = IndexMap<Cow<'static, str>, Box<dyn ServerConnection + Send>>;
 
+/// Action of the parent window that actions can activate to show a server afterwards, eg. one
+/// they created. Its parameter is `(server path, adapter ID)`: If the adapter ID is not empty,
+/// the server is connected to with that adapter, otherwise it is highlighted in the server list
+/// of its connection.
+pub const SHOW_SERVER_ACTION: &str = "win.show-server";
+
+/// Action of the parent window that actions can activate to reconnect all open views of a
+/// server, eg. after they reverted it to an earlier state. Its parameter is the server path.
+pub const RECONNECT_SERVER_ACTION: &str = "win.reconnect-server";
+
 pub struct ServerAction<'a> {
     static_parameters: Parameters,
     action_fn: Box<ActionExecuteFut<'a>>,
@@ -298,6 +373,48 @@ pub trait Connection: Actionable {
 
     /// Returns the servers managed by this connection.
     fn servers(&self) -> LocalBoxFuture<ConnectionResult<ServerMap>>;
+
+    /// Returns a stream of changes of the servers that happen outside of Field Monitor, if the
+    /// connection gets notified about them. Otherwise changes only show up after reloading.
+    ///
+    /// This is called once after the connection was loaded. The stream ends when the
+    /// connection is dropped.
+    fn changes(&self) -> Option<LocalBoxStream<'static, ServerChange>> {
+        None
+    }
+
+    /// Returns values the connection learned while it was loaded that should be kept in its
+    /// configuration, for example further endpoints of a cluster. They are stored in the
+    /// session and persistent configuration and saved, so they are known the next time.
+    ///
+    /// This is called once after the connection was loaded.
+    fn configuration_updates(&self) -> Vec<(String, serde_yaml::Value)> {
+        Vec::new()
+    }
+
+    /// Returns the server at the given path of server IDs, if it exists.
+    ///
+    /// The default implementation walks the tree returned by `servers`. Connections that
+    /// show the same server at different places in the tree (or move servers around) can
+    /// override this to keep previously used paths resolvable.
+    fn server<'a>(
+        &'a self,
+        path: &'a [String],
+    ) -> LocalBoxFuture<'a, ConnectionResult<Option<Box<dyn ServerConnection>>>> {
+        Box::pin(async move {
+            let Some((first, rest)) = path.split_first() else {
+                return Ok(None);
+            };
+            let mut server = self.servers().await?.swap_remove(first.as_str());
+            for part in rest {
+                let Some(parent) = server else {
+                    return Ok(None);
+                };
+                server = parent.servers().await?.swap_remove(part.as_str());
+            }
+            Ok(server)
+        })
+    }
 }
 
 /// A single instance of a server to connect to.
@@ -318,4 +435,21 @@ pub trait ServerConnection: Actionable {
     fn servers(&self) -> LocalBoxFuture<ConnectionResult<ServerMap>> {
         Box::pin(async move { Ok(IndexMap::new()) })
     }
+
+    /// Returns the recent history of resource usage of the server (if supported).
+    fn metrics(&self) -> LocalBoxFuture<ConnectionResult<Vec<ServerMetric>>> {
+        Box::pin(async move { Ok(Vec::new()) })
+    }
+
+    /// Returns the notes of the server, as Markdown (if supported and any are set).
+    fn notes(&self) -> LocalBoxFuture<ConnectionResult<Option<String>>> {
+        Box::pin(async move { Ok(None) })
+    }
+
+    /// Whether the server is currently being migrated to another host, or was migrated since
+    /// this instance was loaded. If the connection to a server is lost while this is true,
+    /// the server is loaded again and reconnected to once it is no longer migrating.
+    fn is_migrating(&self) -> LocalBoxFuture<ConnectionResult<bool>> {
+        Box::pin(async move { Ok(false) })
+    }
 }
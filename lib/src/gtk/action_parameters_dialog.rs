@@ -0,0 +1,171 @@
+/* Copyright 2024 Marco Köpcke
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ *
+ * SPDX-License-Identifier: GPL-3.0-or-later
+ */
+
+use adw::prelude::*;
+use adw::subclass::prelude::*;
+use gettextrs::gettext;
+use gtk::glib;
+use log::warn;
+
+mod imp {
+    use super::*;
+
+    #[derive(Default)]
+    pub struct FieldMonitorActionParametersDialog {
+        pub list_box: gtk::ListBox,
+    }
+
+    #[glib::object_subclass]
+    impl ObjectSubclass for FieldMonitorActionParametersDialog {
+        const NAME: &'static str = "FieldMonitorActionParametersDialog";
+        type Type = super::FieldMonitorActionParametersDialog;
+        type ParentType = adw::AlertDialog;
+    }
+
+    impl ObjectImpl for FieldMonitorActionParametersDialog {}
+    impl WidgetImpl for FieldMonitorActionParametersDialog {}
+    impl AdwDialogImpl for FieldMonitorActionParametersDialog {}
+    impl AdwAlertDialogImpl for FieldMonitorActionParametersDialog {}
+}
+
+glib::wrapper! {
+    /// Dialog that asks for the parameters of a server action before the action is run.
+    ///
+    /// Actions create this in their `ServerAction` closure, add rows for every parameter,
+    /// `run` it, and then read the values back from the rows they added.
+    pub struct FieldMonitorActionParametersDialog(ObjectSubclass<imp::FieldMonitorActionParametersDialog>)
+        @extends gtk::Widget, adw::Dialog, adw::AlertDialog;
+}
+
+impl FieldMonitorActionParametersDialog {
+    pub const RESPONSE_CANCEL: &'static str = "cancel";
+    pub const RESPONSE_CONFIRM: &'static str = "confirm";
+
+    /// Creates a new dialog. `confirm_label` is the label of the button that runs the action.
+    pub fn new(heading: &str, body: Option<&str>, confirm_label: &str) -> Self {
+        let slf: Self = glib::Object::builder()
+            .property("heading", heading)
+            .property("body", body.unwrap_or_default())
+            .build();
+
+        slf.setup_list_box();
+
+        slf.add_response(Self::RESPONSE_CANCEL, &gettext("Cancel"));
+        slf.add_response(Self::RESPONSE_CONFIRM, confirm_label);
+        slf.set_response_appearance(Self::RESPONSE_CONFIRM, adw::ResponseAppearance::Suggested);
+        slf.set_default_response(Some(Self::RESPONSE_CONFIRM));
+        slf.set_close_response(Self::RESPONSE_CANCEL);
+
+        slf
+    }
+
+    /// Creates a new dialog that only shows information and can only be closed.
+    pub fn new_informational(heading: &str, body: Option<&str>) -> Self {
+        let slf: Self = glib::Object::builder()
+            .property("heading", heading)
+            .property("body", body.unwrap_or_default())
+            .build();
+        slf.setup_list_box();
+
+        slf.add_response(Self::RESPONSE_CANCEL, &gettext("Close"));
+        slf.set_default_response(Some(Self::RESPONSE_CANCEL));
+        slf.set_close_response(Self::RESPONSE_CANCEL);
+
+        slf
+    }
+
+    fn setup_list_box(&self) {
+        let list_box = &self.imp().list_box;
+        list_box.add_css_class("boxed-list");
+        list_box.set_selection_mode(gtk::SelectionMode::None);
+        list_box.set_visible(false);
+        self.set_extra_child(Some(list_box));
+    }
+
+    /// Marks the confirm button as destructive. Use for actions that lose data.
+    pub fn set_destructive(&self) {
+        self.set_response_appearance(Self::RESPONSE_CONFIRM, adw::ResponseAppearance::Destructive);
+        self.set_default_response(Some(Self::RESPONSE_CANCEL));
+    }
+
+    /// Adds an arbitrary row.
+    pub fn add_row(&self, row: &impl IsA<gtk::Widget>) {
+        let list_box = &self.imp().list_box;
+        list_box.append(row);
+        list_box.set_visible(true);
+    }
+
+    /// Adds a free-form text parameter.
+    pub fn add_entry_row(&self, title: &str, text: &str) -> adw::EntryRow {
+        let row = adw::EntryRow::builder().title(title).text(text).build();
+        self.add_row(&row);
+        row
+    }
+
+    /// Adds a boolean parameter.
+    pub fn add_switch_row(
+        &self,
+        title: &str,
+        subtitle: Option<&str>,
+        active: bool,
+    ) -> adw::SwitchRow {
+        let row = adw::SwitchRow::builder()
+            .title(title)
+            .subtitle(subtitle.unwrap_or_default())
+            .active(active)
+            .build();
+        self.add_row(&row);
+        row
+    }
+
+    /// Adds a parameter that is one of `choices`. The selected index can be read with
+    /// `adw::ComboRow::selected`.
+    pub fn add_combo_row(&self, title: &str, choices: &[&str], selected: u32) -> adw::ComboRow {
+        let row = adw::ComboRow::builder()
+            .title(title)
+            .model(&gtk::StringList::new(choices))
+            .selected(selected)
+            .build();
+        self.add_row(&row);
+        row
+    }
+
+    /// Adds a read-only row, e.g. to list information the user should know before confirming.
+    /// The texts are shown as-is, they are not parsed as markup.
+    pub fn add_info_row(&self, title: &str, subtitle: &str) -> adw::ActionRow {
+        let row = adw::ActionRow::builder()
+            .title(title)
+            .subtitle(subtitle)
+            .use_markup(false)
+            .selectable(false)
+            .activatable(false)
+            .focusable(false)
+            .build();
+        self.add_row(&row);
+        row
+    }
+
+    /// Presents the dialog and waits for the user. Returns true if the action should be run.
+    pub async fn run(self, parent: Option<&gtk::Window>) -> bool {
+        let Some(parent) = parent else {
+            warn!("action parameters dialog requested without a parent window. Cancelling.");
+            return false;
+        };
+        self.choose_future(parent).await == Self::RESPONSE_CONFIRM
+    }
+}
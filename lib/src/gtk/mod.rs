@@ -1,3 +1,9 @@
+pub use action_parameters_dialog::FieldMonitorActionParametersDialog;
 pub use save_credentials_button::FieldMonitorSaveCredentialsButton;
+pub use snapshot_chooser::{choose_snapshot, SnapshotPrompt};
+pub use toast::show_toast;
 
+mod action_parameters_dialog;
 mod save_credentials_button;
+mod snapshot_chooser;
+mod toast;
@@ -0,0 +1,58 @@
+/* Copyright 2024 Marco Köpcke
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ *
+ * SPDX-License-Identifier: GPL-3.0-or-later
+ */
+//! Asking the user for a snapshot of a server to act on.
+
+use adw::prelude::*;
+use gettextrs::gettext;
+
+use crate::gtk::FieldMonitorActionParametersDialog;
+
+/// Texts of the dialog shown by `choose_snapshot`.
+pub struct SnapshotPrompt<'a> {
+    pub heading: &'a str,
+    pub body: &'a str,
+    /// Label of the button that runs the action.
+    pub confirm_label: &'a str,
+}
+
+/// Asks the user to pick one of the snapshots with the given `titles`, for an action that
+/// loses data. `add_rows` can add further parameters to the dialog. Returns the index of the
+/// chosen snapshot, or `None` if the user cancelled.
+pub async fn choose_snapshot<T>(
+    window: Option<&gtk::Window>,
+    prompt: &SnapshotPrompt<'_>,
+    titles: &[&str],
+    selected: u32,
+    add_rows: impl FnOnce(&FieldMonitorActionParametersDialog) -> T,
+) -> Option<(usize, T)> {
+    let dialog = FieldMonitorActionParametersDialog::new(
+        prompt.heading,
+        Some(prompt.body),
+        prompt.confirm_label,
+    );
+    dialog.set_destructive();
+    let snapshot_row = dialog.add_combo_row(&gettext("Snapshot"), titles, selected);
+    let extra = add_rows(&dialog);
+
+    if !dialog.run(window).await {
+        return None;
+    }
+
+    let index = snapshot_row.selected() as usize;
+    (index < titles.len()).then_some((index, extra))
+}
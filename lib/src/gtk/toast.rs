@@ -0,0 +1,27 @@
+/* Copyright 2024 Marco Köpcke
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ *
+ * SPDX-License-Identifier: GPL-3.0-or-later
+ */
+//! Feedback for the outcome of server actions.
+
+use adw::prelude::*;
+
+/// Shows `text` as a toast on `toov`, if the action was given an overlay.
+pub fn show_toast(toov: Option<&adw::ToastOverlay>, text: &str) {
+    if let Some(toov) = toov {
+        toov.add_toast(adw::Toast::builder().title(text).timeout(5).build());
+    }
+}
@@ -805,6 +805,12 @@ impl FieldMonitorApplication {
         };
         assert_eq!(&connection_id, instance.connection_id().as_str());
 
+        if let Some(mut configuration) = instance.take_unsaved_configuration() {
+            if let Err(err) = self.write_connection(&mut configuration).await {
+                error!("Failed to save the updated configuration of {connection_id}: {err}");
+            }
+        }
+
         self.emit_by_name::<()>("connection-updated", &[&instance]);
     }
 
@@ -950,6 +956,28 @@ impl FieldMonitorApplication {
         save_now: bool,
     ) -> anyhow::Result<Option<ConnectionInstance>> {
         let _busy = self.be_busy();
+        self.write_connection(&mut connection).await?;
+        if save_now {
+            let connection_id = connection.session().id().to_string();
+            self.update_connection(connection).await;
+            match self.connection(&connection_id) {
+                None => {
+                    warn!("connection was not updated properly after save.");
+                    Ok(None)
+                }
+                Some(connection_instance) => Ok(Some(connection_instance)),
+            }
+        } else {
+            self.update_connection_eventually(connection);
+            Ok(None)
+        }
+    }
+
+    /// Writes the persistent configuration of a connection to disk, without reloading it.
+    async fn write_connection(
+        &self,
+        connection: &mut DualScopedConnectionConfiguration,
+    ) -> anyhow::Result<()> {
         let mut filename = self.connections_dir().await;
 
         let c_persistent = connection.persistent_mut();
@@ -965,41 +993,17 @@ impl FieldMonitorApplication {
             .open(&filename)
             .await?;
 
-        match serde_yaml::to_string(&SavedConnectionConfiguration {
+        let result = match serde_yaml::to_string(&SavedConnectionConfiguration {
             tag: c_persistent.tag().to_string(),
             config,
         }) {
-            Ok(value) => match file.write_all(value.as_bytes()).await {
-                Ok(()) => {
-                    if save_now {
-                        let connection_id = connection.session().id().to_string();
-                        self.update_connection(connection).await;
-                        match self.connection(&connection_id) {
-                            None => {
-                                warn!("connection was not updated properly after save.");
-                                Ok(None)
-                            }
-                            Some(connection_instance) => Ok(Some(connection_instance)),
-                        }
-                    } else {
-                        self.update_connection_eventually(connection);
-                        Ok(None)
-                    }
-                }
-                Err(err) => {
-                    if !file_existed_before {
-                        remove_file(filename).await.ok();
-                    }
-                    Err(err.into())
-                }
-            },
-            Err(err) => {
-                if !file_existed_before {
-                    remove_file(filename).await.ok();
-                }
-                Err(err.into())
-            }
+            Ok(value) => file.write_all(value.as_bytes()).await.map_err(Into::into),
+            Err(err) => Err(err.into()),
+        };
+        if result.is_err() && !file_existed_before {
+            remove_file(filename).await.ok();
         }
+        result
     }
 }
 
@@ -28,4 +28,9 @@ pub static CONNECTION_PROVIDERS: &[&dyn ConnectionProviderConstructor] = &[
     &GenericConnectionProviderConstructor,
     &ProxmoxConnectionProviderConstructor,
     &LibvirtQemuConnectionProviderConstructor,
+    &LibvirtLxcConnectionProviderConstructor,
+    &LibvirtXenConnectionProviderConstructor,
+    &LibvirtCloudHypervisorConnectionProviderConstructor,
+    &LibvirtUriConnectionProviderConstructor,
+    &LibvirtTestConnectionProviderConstructor,
 ];
@@ -22,6 +22,7 @@ use std::ops::Deref;
 use std::rc::Rc;
 
 use adw::prelude::{AdwDialogExt, AlertDialogExt};
+use anyhow::anyhow;
 use futures::channel::oneshot;
 use futures::future::LocalBoxFuture;
 use gettextrs::gettext;
@@ -116,9 +117,6 @@ impl ConnectionLoader {
         try_reauth: bool,
     ) -> LocalBoxFuture<Option<Self>> {
         Box::pin(async move {
-            let path_parts_orig = path_parts;
-            let path_parts = path_parts_orig.iter();
-
             if !is_server {
                 Some(Self {
                     entity: Entity::Connection(connection.clone()),
@@ -128,18 +126,18 @@ impl ConnectionLoader {
                     server_path: vec![],
                 })
             } else {
-                // Get the servers from the connection. On auth failure, restart.
-                let mut servers = match Self::do_load_server(
-                    connection.servers(),
+                // Resolve the server from the connection. On auth failure, restart.
+                let server = match Self::do_load_server(
+                    connection.server(&path_parts),
                     connection.clone(),
-                    &path_parts_orig,
+                    &path_parts,
                     app.clone(),
                     active_window.cloned(),
                     try_reauth,
                 )
                 .await
                 {
-                    Ok(servers_new) => Some(servers_new),
+                    Ok(server) => server,
                     Err(None) => return None,
                     Err(Some(connection)) => {
                         // restart.
@@ -147,7 +145,7 @@ impl ConnectionLoader {
                             is_server,
                             connection,
                             active_window,
-                            path_parts_orig,
+                            path_parts,
                             app,
                             false,
                         )
@@ -155,51 +153,6 @@ impl ConnectionLoader {
                     }
                 };
 
-                let mut server: Option<Box<dyn ServerConnection>> = None;
-
-                for path_part in path_parts {
-                    // get subservers
-                    // this will only be relevant on the second+ loop, on the first we still have the list
-                    // from servers of the connection.
-                    if let Some(server) = server {
-                        match Self::do_load_server(
-                            server.servers(),
-                            connection.clone(),
-                            &path_parts_orig,
-                            app.clone(),
-                            active_window.cloned(),
-                            try_reauth,
-                        )
-                        .await
-                        {
-                            Ok(servers_new) => servers = Some(servers_new),
-                            Err(None) => servers = None,
-                            Err(Some(connection)) => {
-                                // restart.
-                                return Self::do_load_connection(
-                                    is_server,
-                                    connection,
-                                    active_window,
-                                    path_parts_orig,
-                                    app,
-                                    false,
-                                )
-                                .await;
-                            }
-                        }
-                    }
-
-                    // check if subserver list is even set.
-                    let Some(ref mut servers_rf) = servers else {
-                        warn!("servers were empty");
-                        Self::do_show_error(&gettext("Server not found."), None, active_window);
-                        return None;
-                    };
-
-                    // get subserver from server list.
-                    server = servers_rf.swap_remove(&Cow::Borrowed(path_part.as_str()));
-                }
-
                 match server {
                     None => {
                         warn!("server not found");
@@ -211,7 +164,7 @@ impl ConnectionLoader {
                         connection,
                         window: active_window.cloned(),
                         app,
-                        server_path: path_parts_orig,
+                        server_path: path_parts,
                     }),
                 }
             }
@@ -219,19 +172,19 @@ impl ConnectionLoader {
     }
 
     /// Tries to load a server.
-    /// Success: Ok(ServerMap)
+    /// Success: Ok(Option<Box<dyn ServerConnection>>)
     /// Auth Error: Err(Some(ConnectionInstance)) ( the instance to may try again with )
     /// General error: Err(None) ( give up; an error is already shown. )
     async fn do_load_server(
-        servers_fut: LocalBoxFuture<'_, ConnectionResult<ServerMap>>,
+        server_fut: LocalBoxFuture<'_, ConnectionResult<Option<Box<dyn ServerConnection>>>>,
         connection: ConnectionInstance,
         server_path: &[String],
         app: Option<FieldMonitorApplication>,
         active_window: Option<gtk::Window>,
         try_reauth: bool,
-    ) -> Result<ServerMap, Option<ConnectionInstance>> {
-        match servers_fut.await {
-            Ok(servers) => Ok(servers),
+    ) -> Result<Option<Box<dyn ServerConnection>>, Option<ConnectionInstance>> {
+        match server_fut.await {
+            Ok(server) => Ok(server),
             Err(ConnectionError::AuthFailed(_, _)) if try_reauth => {
                 warn!("auth failed, asking to re-auth");
                 let connection =
@@ -271,6 +224,24 @@ impl ConnectionLoader {
         self.connection.metadata().title
     }
 
+    /// The connection the server was loaded from.
+    pub fn connection(&self) -> &ConnectionInstance {
+        &self.connection
+    }
+
+    /// The path of the server, relative to the connection.
+    pub fn server_path(&self) -> &[String] {
+        &self.server_path
+    }
+
+    /// Whether the server is online, if known. Panics if this is not for a server.
+    pub fn server_is_online(&self) -> Option<bool> {
+        match &self.entity {
+            Entity::Server(server) => server.metadata().is_online,
+            _ => panic!("ConnectionLoader is not for server - but server state asked"),
+        }
+    }
+
     pub fn actions(&self) -> Vec<(Cow<'static, str>, Cow<'static, str>)> {
         match &self.entity {
             Entity::Connection(e) => e.actions(),
@@ -337,6 +308,50 @@ impl ConnectionLoader {
         Some(())
     }
 
+    /// Whether the server is being migrated (see `ServerConnection::is_migrating`).
+    /// Errors are treated as the server not migrating.
+    pub async fn server_is_migrating(&self) -> bool {
+        match &self.entity {
+            Entity::Connection(_) => false,
+            Entity::Server(e) => e.is_migrating().await.unwrap_or_else(|err| {
+                warn!("failed to check if server is migrating: {err:?}");
+                false
+            }),
+        }
+    }
+
+    /// Loads the server again, e.g. to pick up its new location after it was migrated.
+    pub async fn reload_server(&mut self) -> Option<()> {
+        debug!("reloading server");
+        *self = Self::do_load_connection(
+            true,
+            self.connection.clone(),
+            self.window.as_ref(),
+            self.server_path.clone(),
+            self.app.clone(),
+            false,
+        )
+        .await?;
+
+        Some(())
+    }
+
+    /// Like `reload_server`, but for background updates: errors are returned instead of being
+    /// shown, and the user is not asked to authenticate again.
+    pub async fn try_reload_server(&mut self) -> ConnectionResult<()> {
+        debug!("reloading server quietly");
+        match self.connection.server(&self.server_path).await? {
+            Some(server) => {
+                self.entity = Entity::Server(server);
+                Ok(())
+            }
+            None => Err(ConnectionError::General(
+                Some(gettext("Server not found.")),
+                anyhow!("server {:?} not found", self.server_path),
+            )),
+        }
+    }
+
     async fn create_adapter_internal(
         &self,
         tag: &str,
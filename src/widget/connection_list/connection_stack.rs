@@ -176,6 +176,20 @@ impl FieldMonitorConnectionStack {
         self.notify_visible_connection_id();
     }
 
+    /// Focuses a server in the info page of its connection, see
+    /// `FieldMonitorConnectionInfoPage::focus_server`.
+    pub fn focus_server(&self, path: &str) {
+        let connection = path.split('/').next().unwrap_or_default();
+        if let Some(info_page) = self
+            .imp()
+            .stack
+            .child_by_name(connection)
+            .and_downcast::<FieldMonitorConnectionInfoPage>()
+        {
+            info_page.focus_server(path);
+        }
+    }
+
     pub fn pages(&self) -> gtk::SelectionModel {
         self.imp().stack.pages()
     }
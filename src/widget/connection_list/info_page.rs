@@ -57,6 +57,11 @@ mod imp {
         #[property(get, construct_only)]
         pub application: RefCell<Option<FieldMonitorApplication>>,
         pub reload_connections_reentry_lock: Mutex<()>,
+        pub server_rows: RefCell<Vec<FieldMonitorServerRow>>,
+        /// Path of a server to focus as soon as it is listed, see `focus_server`.
+        pub pending_focus: RefCell<Option<Vec<String>>>,
+        /// Handler of `server-changed` of the connection, disconnected when the page is disposed.
+        pub server_changed_handler: RefCell<Option<(ConnectionInstance, glib::SignalHandlerId)>>,
     }
 
     #[glib::object_subclass]
@@ -76,7 +81,13 @@ mod imp {
     }
 
     #[glib::derived_properties]
-    impl ObjectImpl for FieldMonitorConnectionInfoPage {}
+    impl ObjectImpl for FieldMonitorConnectionInfoPage {
+        fn dispose(&self) {
+            if let Some((connection, handler)) = self.server_changed_handler.take() {
+                connection.disconnect(handler);
+            }
+        }
+    }
     impl WidgetImpl for FieldMonitorConnectionInfoPage {}
     impl BinImpl for FieldMonitorConnectionInfoPage {}
 }
@@ -105,9 +116,92 @@ impl FieldMonitorConnectionInfoPage {
             &connection_id,
         );
 
+        let handler = connection.connect_server_changed(glib::clone!(
+            #[weak]
+            slf,
+            move |connection, change| {
+                // The connection may have been replaced in the meantime.
+                if slf.connection().as_ref() != Some(connection) {
+                    return;
+                }
+                let change = change.clone();
+                glib::spawn_future_local(async move { slf.on_server_changed(change).await });
+            }
+        ));
+        imp.server_changed_handler
+            .replace(Some((connection.clone(), handler)));
+
         slf
     }
 
+    async fn on_server_changed(&self, change: ServerChange) {
+        match change {
+            ServerChange::ServersChanged => self.reload_connection().await,
+            ServerChange::ServerChanged(path) => {
+                if !self.refresh_server_row(&path).await {
+                    self.reload_connection().await
+                }
+            }
+        }
+    }
+
+    /// Replaces the row of the server with a freshly loaded one. Returns false if the server is
+    /// not listed as a row, in which case the entire connection needs to be reloaded.
+    async fn refresh_server_row(&self, path: &[String]) -> bool {
+        let imp = self.imp();
+        let _guard = imp.reload_connections_reentry_lock.lock().await;
+        let connection = imp.connection.borrow().clone().unwrap();
+        let full_path: Vec<String> = [connection.connection_id()]
+            .into_iter()
+            .chain(path.iter().cloned())
+            .collect();
+        // Connections may list a server under a different parent than the one in the path,
+        // see `focus_pending_server`.
+        let old_row = {
+            let rows = imp.server_rows.borrow();
+            rows.iter()
+                .find(|row| {
+                    let row_path = row.path();
+                    row_path == full_path
+                        || (row_path.len() == full_path.len()
+                            && row_path.first() == full_path.first()
+                            && row_path.last() == full_path.last())
+                })
+                .cloned()
+        };
+        let Some(old_row) = old_row else {
+            return false;
+        };
+        let Some(list_box) = old_row.parent().and_downcast::<gtk::ListBox>() else {
+            return false;
+        };
+
+        let server = match connection.server(path).await {
+            Ok(Some(server)) => server,
+            Ok(None) => return false,
+            Err(err) => {
+                warn!("failed to load changed server: {err:?}");
+                return true;
+            }
+        };
+        let row = match FieldMonitorServerRow::new(&old_row.path(), server).await {
+            Ok(row) => row,
+            Err(err) => {
+                warn!("failed to create row for changed server: {err:?}");
+                return true;
+            }
+        };
+        debug!("refreshing row of changed server {path:?}");
+        list_box.insert(&row, old_row.index());
+        list_box.remove(&old_row);
+        for server_row in imp.server_rows.borrow_mut().iter_mut() {
+            if *server_row == old_row {
+                *server_row = row.clone();
+            }
+        }
+        true
+    }
+
     async fn reload_connection(&self) {
         if let Err(err) = self.try_reload_connection().await {
             self.error(&err);
@@ -116,7 +210,7 @@ impl FieldMonitorConnectionInfoPage {
 
     async fn try_reload_connection(&self) -> ConnectionResult<()> {
         let imp = self.imp();
-        let _ = imp.reload_connections_reentry_lock.lock().await;
+        let _guard = imp.reload_connections_reentry_lock.lock().await;
         imp.status_stack.set_visible_child_name("loading");
         let connection = imp.connection.borrow().clone().unwrap();
         let connection_id = connection.connection_id();
@@ -125,6 +219,7 @@ impl FieldMonitorConnectionInfoPage {
         while let Some(child) = imp.group_box.last_child() {
             imp.group_box.remove(&child);
         }
+        imp.server_rows.borrow_mut().clear();
 
         let servers = connection.servers().await?;
         let no_servers = servers.is_empty();
@@ -155,13 +250,13 @@ impl FieldMonitorConnectionInfoPage {
         // Main group (servers with no children)
         let group = FieldMonitorServerGroup::new(&self.application().unwrap(), None).await?;
         for server in servers_with_no_children {
-            group.add(
-                &FieldMonitorServerRow::new(
-                    &[connection_id.clone(), server.key.to_string()],
-                    server.server,
-                )
-                .await?,
-            );
+            let row = FieldMonitorServerRow::new(
+                &[connection_id.clone(), server.key.to_string()],
+                server.server,
+            )
+            .await?;
+            group.add(&row);
+            imp.server_rows.borrow_mut().push(row);
         }
         // if servers is empty, we have no server at all, add a small note.
         if no_servers {
@@ -188,27 +283,61 @@ impl FieldMonitorConnectionInfoPage {
             )
             .await?;
             for (key, subserver) in server.subservers {
-                group.add(
-                    &FieldMonitorServerRow::new(
-                        &[
-                            connection_id.clone(),
-                            server.key.to_string(),
-                            key.to_string(),
-                        ],
-                        subserver,
-                    )
-                    .await?,
-                );
+                let row = FieldMonitorServerRow::new(
+                    &[
+                        connection_id.clone(),
+                        server.key.to_string(),
+                        key.to_string(),
+                    ],
+                    subserver,
+                )
+                .await?;
+                group.add(&row);
+                imp.server_rows.borrow_mut().push(row);
             }
+            group.load_details(server.server);
             imp.group_box.append(&group);
         }
 
         debug!("finished loading");
         imp.status_stack.set_visible_child_name("servers");
+        self.focus_pending_server();
 
         Ok(())
     }
 
+    /// Focuses the row of the server with the given full path. If the server is not listed
+    /// yet, it is focused once it is listed after a reload.
+    pub fn focus_server(&self, path: &str) {
+        self.imp()
+            .pending_focus
+            .replace(Some(path.split('/').map(ToString::to_string).collect()));
+        self.focus_pending_server();
+    }
+
+    fn focus_pending_server(&self) {
+        let imp = self.imp();
+        let Some(path) = imp.pending_focus.borrow().clone() else {
+            return;
+        };
+        let rows = imp.server_rows.borrow();
+        // Connections may list a server under a different parent than the one in the path
+        // (eg. in a group), so also accept rows that only differ in that.
+        let row = rows.iter().find(|row| row.path() == path).or_else(|| {
+            rows.iter().find(|row| {
+                let row_path = row.path();
+                row_path.len() == path.len()
+                    && row_path.first() == path.first()
+                    && row_path.last() == path.last()
+            })
+        });
+        if let Some(row) = row {
+            debug!("focusing server {path:?}");
+            row.grab_focus();
+            imp.pending_focus.replace(None);
+        }
+    }
+
     fn error(&self, err: &ConnectionError) {
         let imp = self.imp();
 
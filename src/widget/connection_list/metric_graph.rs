@@ -0,0 +1,147 @@
+/* Copyright 2024 Marco Köpcke
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ *
+ * SPDX-License-Identifier: GPL-3.0-or-later
+ */
+use std::cell::RefCell;
+
+use adw::prelude::*;
+use adw::subclass::prelude::*;
+use gtk::{cairo, glib};
+
+use libfieldmonitor::connection::*;
+use libfieldmonitor::i18n::gettext_f;
+
+mod imp {
+    use super::*;
+
+    #[derive(Debug, Default)]
+    pub struct FieldMonitorMetricGraph {
+        pub metric: RefCell<Option<ServerMetric>>,
+    }
+
+    #[glib::object_subclass]
+    impl ObjectSubclass for FieldMonitorMetricGraph {
+        const NAME: &'static str = "FieldMonitorMetricGraph";
+        type Type = super::FieldMonitorMetricGraph;
+        type ParentType = gtk::DrawingArea;
+    }
+
+    impl ObjectImpl for FieldMonitorMetricGraph {}
+    impl WidgetImpl for FieldMonitorMetricGraph {}
+    impl DrawingAreaImpl for FieldMonitorMetricGraph {}
+}
+
+glib::wrapper! {
+    /// Small graph of the recent history of a server metric.
+    pub struct FieldMonitorMetricGraph(ObjectSubclass<imp::FieldMonitorMetricGraph>)
+        @extends gtk::Widget, gtk::DrawingArea;
+}
+
+impl FieldMonitorMetricGraph {
+    pub fn new(metric: ServerMetric) -> Self {
+        let slf: Self = glib::Object::builder()
+            .property("content-width", 48)
+            .property("content-height", 20)
+            .property("valign", gtk::Align::Center)
+            .build();
+
+        let tooltip = match metric.current() {
+            Some(current) => gettext_f(
+                "{metric}: {value}",
+                &[
+                    ("metric", &metric.title),
+                    ("value", &format_metric_value(metric.unit, current)),
+                ],
+            ),
+            None => metric.title.clone(),
+        };
+        slf.set_tooltip_text(Some(&tooltip));
+        slf.imp().metric.replace(Some(metric));
+
+        slf.set_draw_func(|area, cr, width, height| {
+            if let Some(slf) = area.downcast_ref::<Self>() {
+                slf.draw(cr, width as f64, height as f64);
+            }
+        });
+
+        slf
+    }
+
+    fn draw(&self, cr: &cairo::Context, width: f64, height: f64) {
+        let metric = self.imp().metric.borrow();
+        let Some(metric) = metric.as_ref() else {
+            return;
+        };
+        let (Some((start, _)), Some((end, _))) = (metric.points.first(), metric.points.last())
+        else {
+            return;
+        };
+        let timespan = (end - start) as f64;
+        if timespan <= 0.0 {
+            return;
+        }
+        let max = metric
+            .max
+            .unwrap_or_else(|| metric.points.iter().map(|(_, v)| *v).fold(0.0, f64::max));
+        let max = if max > 0.0 { max } else { 1.0 };
+
+        let to_x = |time: i64| (time - start) as f64 / timespan * width;
+        let to_y = |value: f64| height - (value / max).clamp(0.0, 1.0) * height;
+
+        cr.move_to(0.0, height);
+        for (time, value) in &metric.points {
+            cr.line_to(to_x(*time), to_y(*value));
+        }
+        cr.line_to(width, height);
+        cr.close_path();
+
+        let color = self.color();
+        cr.set_source_rgba(
+            color.red() as f64,
+            color.green() as f64,
+            color.blue() as f64,
+            0.2,
+        );
+        cr.fill().ok();
+
+        for (i, (time, value)) in metric.points.iter().enumerate() {
+            if i == 0 {
+                cr.move_to(to_x(*time), to_y(*value));
+            } else {
+                cr.line_to(to_x(*time), to_y(*value));
+            }
+        }
+        cr.set_source_rgba(
+            color.red() as f64,
+            color.green() as f64,
+            color.blue() as f64,
+            0.8,
+        );
+        cr.set_line_width(1.5);
+        cr.stroke().ok();
+    }
+}
+
+pub fn format_metric_value(unit: MetricUnit, value: f64) -> String {
+    match unit {
+        MetricUnit::Ratio => format!("{:.0}\u{202F}%", value * 100.0),
+        MetricUnit::Bytes => glib::format_size(value.max(0.0) as u64).to_string(),
+        MetricUnit::BytesPerSecond => gettext_f(
+            "{size}/s",
+            &[("size", &glib::format_size(value.max(0.0) as u64))],
+        ),
+    }
+}
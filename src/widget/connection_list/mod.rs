@@ -20,6 +20,8 @@ pub const DEFAULT_GENERIC_ICON: &str = "network-server-symbolic";
 mod connection_list_navbar;
 mod connection_stack;
 mod info_page;
+mod metric_graph;
+mod notes;
 mod server_group;
 mod server_row;
 
@@ -31,7 +33,10 @@ use glib::object::Cast;
 use gtk::gio;
 use libfieldmonitor::connection::*;
 use libfieldmonitor::i18n::gettext_f;
+use log::debug;
+use metric_graph::FieldMonitorMetricGraph;
 use std::borrow::Cow;
+use std::rc::Rc;
 
 async fn make_server_prefix_suffix(
     server: &dyn ServerConnection,
@@ -79,14 +84,26 @@ fn add_status(child_wdgt: gtk::Widget, metadata: &ServerMetadata) -> gtk::Widget
 
     parent.append(&child_wdgt);
 
-    match metadata.is_online {
-        Some(status) => {
-            let (class, tooltip_text, icon_name) = if status {
-                ("success", "Online", "circle-filled-symbolic")
-            } else {
-                ("dim-label", "Offline", "circle-outline-thick-symbolic")
-            };
+    let status = match (metadata.state, metadata.is_online) {
+        (Some(ServerState::Paused), _) => Some((
+            "warning",
+            gettext("Paused"),
+            "media-playback-pause-symbolic",
+        )),
+        (Some(ServerState::Saved), _) => {
+            Some(("dim-label", gettext("Saved"), "document-save-symbolic"))
+        }
+        (_, Some(true)) => Some(("success", gettext("Online"), "circle-filled-symbolic")),
+        (_, Some(false)) => Some((
+            "dim-label",
+            gettext("Offline"),
+            "circle-outline-thick-symbolic",
+        )),
+        _ => None,
+    };
 
+    match status {
+        Some((class, tooltip_text, icon_name)) => {
             let status_icon = gtk::Image::builder()
                 .pixel_size(8)
                 .icon_name(icon_name)
@@ -167,6 +184,28 @@ fn maybe_add_actions_button(boxx: &gtk::Box, server_or_connection: ServerOrConne
     boxx.append(&button);
 }
 
+/// Loads the metrics of the server in the background and adds graphs for them to `boxx`.
+fn spawn_load_metrics(boxx: &gtk::Box, server: Rc<dyn ServerConnection>) {
+    let boxx = boxx.downgrade();
+    gtk::glib::spawn_future_local(async move {
+        let metrics = match server.metrics().await {
+            Ok(metrics) => metrics,
+            Err(err) => {
+                debug!("failed to load server metrics: {err:?}");
+                return;
+            }
+        };
+        let Some(boxx) = boxx.upgrade() else {
+            return;
+        };
+        for metric in metrics {
+            if metric.points.len() > 1 {
+                boxx.append(&FieldMonitorMetricGraph::new(metric));
+            }
+        }
+    });
+}
+
 fn make_multi_connection_button(path: &str, adapters: Vec<(Cow<str>, Cow<str>)>) -> gtk::Widget {
     let menu = gio::Menu::new();
     for (adapter_id, adapter_label) in adapters {
@@ -0,0 +1,234 @@
+/* Copyright 2024 Marco Köpcke
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ *
+ * SPDX-License-Identifier: GPL-3.0-or-later
+ */
+//! Notes and tags of servers.
+//!
+//! Notes are Markdown. Only the commonly used subset is rendered (emphasis, code, links,
+//! headings, lists and quotes), everything else is shown as written.
+
+use std::rc::Rc;
+
+use adw::prelude::*;
+use gettextrs::gettext;
+use gtk::glib;
+use libfieldmonitor::connection::ServerConnection;
+use log::debug;
+
+/// Loads the notes of the server in the background and, if there are any, adds a button
+/// showing them to `boxx`.
+pub(super) fn spawn_load_notes_button(boxx: &gtk::Box, server: Rc<dyn ServerConnection>) {
+    let boxx = boxx.downgrade();
+    glib::spawn_future_local(async move {
+        let Some(notes) = load_notes(server.as_ref()).await else {
+            return;
+        };
+        let Some(boxx) = boxx.upgrade() else {
+            return;
+        };
+
+        let label = make_notes_label(&notes);
+        label.set_max_width_chars(60);
+        label.set_margin_top(6);
+        label.set_margin_bottom(6);
+        label.set_margin_start(6);
+        label.set_margin_end(6);
+        let popover = gtk::Popover::builder().child(&label).build();
+
+        boxx.append(
+            &gtk::MenuButton::builder()
+                .popover(&popover)
+                .icon_name("text-x-generic-symbolic")
+                .tooltip_text(gettext("Notes"))
+                .valign(gtk::Align::Center)
+                .css_classes(["flat"])
+                .build(),
+        );
+    });
+}
+
+/// Loads the notes of the server in the background and, if there are any, shows them in
+/// `label`.
+pub(super) fn spawn_load_notes_label(label: &gtk::Label, server: Rc<dyn ServerConnection>) {
+    let label = label.downgrade();
+    glib::spawn_future_local(async move {
+        let Some(notes) = load_notes(server.as_ref()).await else {
+            return;
+        };
+        let Some(label) = label.upgrade() else {
+            return;
+        };
+        label.set_markup(&markdown_to_pango(&notes));
+        label.set_visible(true);
+    });
+}
+
+/// Chips for the tags of a server, or `None` if it has no tags.
+pub(super) fn make_tags_box(tags: &[String]) -> Option<gtk::Widget> {
+    if tags.is_empty() {
+        return None;
+    }
+    let boxx = gtk::Box::builder()
+        .spacing(3)
+        .orientation(gtk::Orientation::Horizontal)
+        .valign(gtk::Align::Center)
+        .build();
+    for tag in tags {
+        boxx.append(
+            &gtk::Label::builder()
+                .label(tag)
+                .css_classes(["tag-chip"])
+                .build(),
+        );
+    }
+    Some(boxx.upcast())
+}
+
+async fn load_notes(server: &dyn ServerConnection) -> Option<String> {
+    match server.notes().await {
+        Ok(notes) => notes.filter(|notes| !notes.trim().is_empty()),
+        Err(err) => {
+            debug!("failed to load server notes: {err:?}");
+            None
+        }
+    }
+}
+
+fn make_notes_label(notes: &str) -> gtk::Label {
+    gtk::Label::builder()
+        .label(markdown_to_pango(notes))
+        .use_markup(true)
+        .wrap(true)
+        .wrap_mode(gtk::pango::WrapMode::WordChar)
+        .selectable(true)
+        .xalign(0.0)
+        .build()
+}
+
+/// Converts Markdown to Pango markup.
+fn markdown_to_pango(markdown: &str) -> String {
+    let mut in_code_block = false;
+    let mut lines = Vec::new();
+    for line in markdown.lines() {
+        let trimmed = line.trim_start();
+        if trimmed.starts_with("```") {
+            in_code_block = !in_code_block;
+            continue;
+        }
+        if in_code_block {
+            lines.push(format!("<tt>{}</tt>", glib::markup_escape_text(line)));
+        } else if let Some(heading) = heading_text(trimmed) {
+            lines.push(format!("<b>{}</b>", inline_to_pango(heading)));
+        } else if let Some(item) = ["- ", "* ", "+ "]
+            .iter()
+            .find_map(|bullet| trimmed.strip_prefix(bullet))
+        {
+            let indent = &line[..line.len() - trimmed.len()];
+            lines.push(format!("{indent}• {}", inline_to_pango(item)));
+        } else if let Some(quote) = trimmed.strip_prefix('>') {
+            lines.push(format!("<i>{}</i>", inline_to_pango(quote.trim_start())));
+        } else {
+            lines.push(inline_to_pango(line));
+        }
+    }
+    lines.join("\n")
+}
+
+/// The text of the line, if it is a heading.
+fn heading_text(line: &str) -> Option<&str> {
+    let text = line.trim_start_matches('#');
+    let level = line.len() - text.len();
+    if (1..=6).contains(&level) && (text.is_empty() || text.starts_with(' ')) {
+        Some(text.trim())
+    } else {
+        None
+    }
+}
+
+/// Converts emphasis, code spans and links in a single line of Markdown to Pango markup.
+fn inline_to_pango(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    let mut prev = None;
+    while let Some(c) = rest.chars().next() {
+        let consumed = match inline_element(rest, prev) {
+            Some((markup, len)) => {
+                out.push_str(&markup);
+                len
+            }
+            None => {
+                out.push_str(&glib::markup_escape_text(&rest[..c.len_utf8()]));
+                c.len_utf8()
+            }
+        };
+        prev = rest[..consumed].chars().last();
+        rest = &rest[consumed..];
+    }
+    out
+}
+
+/// Markup for the inline element at the start of `text` and the length of the element in
+/// `text`, if there is a complete element. `prev` is the character before `text`.
+fn inline_element(text: &str, prev: Option<char>) -> Option<(String, usize)> {
+    if let Some(inner) = text.strip_prefix('`') {
+        let end = inner.find('`')?;
+        return Some((
+            format!("<tt>{}</tt>", glib::markup_escape_text(&inner[..end])),
+            end + 2,
+        ));
+    }
+    if let Some(inner) = text.strip_prefix("**") {
+        if let Some(end) = inner.find("**").filter(|end| *end > 0) {
+            return Some((
+                format!("<b>{}</b>", inline_to_pango(&inner[..end])),
+                end + 4,
+            ));
+        }
+    }
+    for delim in ['*', '_'] {
+        let Some(inner) = text.strip_prefix(delim) else {
+            continue;
+        };
+        // Underscores within words (eg. in snake_case) are not emphasis.
+        if delim == '_' && prev.is_some_and(char::is_alphanumeric) {
+            return None;
+        }
+        if inner.starts_with(char::is_whitespace) {
+            return None;
+        }
+        let end = inner.find(delim).filter(|end| *end > 0)?;
+        return Some((
+            format!("<i>{}</i>", inline_to_pango(&inner[..end])),
+            end + 2,
+        ));
+    }
+    if let Some(inner) = text.strip_prefix('[') {
+        let (label, rest) = inner.split_once("](")?;
+        let (url, _) = rest.split_once(')')?;
+        if label.is_empty() || url.is_empty() || url.contains(char::is_whitespace) {
+            return None;
+        }
+        return Some((
+            format!(
+                "<a href=\"{}\">{}</a>",
+                glib::markup_escape_text(url),
+                inline_to_pango(label)
+            ),
+            label.len() + url.len() + 4,
+        ));
+    }
+    None
+}
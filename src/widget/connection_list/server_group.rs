@@ -16,6 +16,7 @@
  * SPDX-License-Identifier: GPL-3.0-or-later
  */
 use std::cell::RefCell;
+use std::rc::Rc;
 
 use adw::prelude::*;
 use adw::subclass::prelude::*;
@@ -25,7 +26,8 @@ use gtk::glib;
 use libfieldmonitor::connection::*;
 
 use crate::application::FieldMonitorApplication;
-use crate::widget::connection_list::make_server_prefix_suffix;
+use crate::widget::connection_list::notes::spawn_load_notes_label;
+use crate::widget::connection_list::{make_server_prefix_suffix, spawn_load_metrics};
 
 mod imp {
     use super::*;
@@ -39,8 +41,12 @@ mod imp {
         #[template_child]
         pub prefix_box: TemplateChild<gtk::Box>,
         #[template_child]
+        pub metrics_box: TemplateChild<gtk::Box>,
+        #[template_child]
         pub suffix_box: TemplateChild<gtk::Box>,
         #[template_child]
+        pub notes: TemplateChild<gtk::Label>,
+        #[template_child]
         pub servers: TemplateChild<gtk::ListBox>,
         #[property(get, set)]
         pub application: RefCell<Option<FieldMonitorApplication>>,
@@ -105,4 +111,12 @@ impl FieldMonitorServerGroup {
     pub fn add(&self, row: &impl IsA<gtk::Widget>) {
         self.imp().servers.append(row);
     }
+
+    /// Loads the metrics and notes of the title server in the background and shows them next
+    /// to and below the title.
+    pub fn load_details(&self, title_server: Box<dyn ServerConnection>) {
+        let title_server: Rc<dyn ServerConnection> = title_server.into();
+        spawn_load_metrics(&self.imp().metrics_box, title_server.clone());
+        spawn_load_notes_label(&self.imp().notes, title_server);
+    }
 }
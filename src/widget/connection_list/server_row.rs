@@ -15,17 +15,22 @@
  *
  * SPDX-License-Identifier: GPL-3.0-or-later
  */
-use crate::widget::connection_list::make_server_prefix_suffix;
+use crate::widget::connection_list::notes::{make_tags_box, spawn_load_notes_button};
+use crate::widget::connection_list::{make_server_prefix_suffix, spawn_load_metrics};
 use adw::prelude::*;
 use adw::subclass::prelude::*;
 use gtk::glib;
 use libfieldmonitor::connection::*;
+use std::cell::RefCell;
+use std::rc::Rc;
 
 mod imp {
     use super::*;
 
     #[derive(Debug, Default)]
-    pub struct FieldMonitorServerRow {}
+    pub struct FieldMonitorServerRow {
+        pub path: RefCell<Vec<String>>,
+    }
 
     #[glib::object_subclass]
     impl ObjectSubclass for FieldMonitorServerRow {
@@ -57,12 +62,30 @@ impl FieldMonitorServerRow {
             .property("subtitle", &metadata.subtitle)
             .property("selectable", false)
             .build();
+        slf.imp().path.replace(full_path.to_vec());
 
         let (prefix, suffix) =
             make_server_prefix_suffix(server.as_ref(), full_path, Some(&slf)).await?;
         slf.add_prefix(&prefix);
+
+        let metrics_box = gtk::Box::builder()
+            .spacing(6)
+            .orientation(gtk::Orientation::Horizontal)
+            .build();
+        if let Some(tags) = make_tags_box(&metadata.tags) {
+            slf.add_suffix(&tags);
+        }
+        slf.add_suffix(&metrics_box);
         slf.add_suffix(&suffix);
+        let server: Rc<dyn ServerConnection> = server.into();
+        spawn_load_metrics(&metrics_box, server.clone());
+        spawn_load_notes_button(&metrics_box, server);
 
         Ok(slf)
     }
+
+    /// The full path of the server, including the connection ID.
+    pub fn path(&self) -> Vec<String> {
+        self.imp().path.borrow().clone()
+    }
 }
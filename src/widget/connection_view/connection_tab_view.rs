@@ -95,6 +95,18 @@ impl FieldMonitorConnectionTabView {
         false
     }
 
+    /// Reconnects all open connection views of the server.
+    pub fn reconnect(&self, server_path: &str) {
+        for page in self.imp().tab_view.pages().iter::<adw::TabPage>().flatten() {
+            let child = page.child().downcast::<FieldMonitorServerScreen>();
+            if let Ok(screen) = child {
+                if screen.server_path() == server_path {
+                    glib::spawn_future_local(async move { screen.reconnect().await });
+                }
+            }
+        }
+    }
+
     pub fn open(
         &self,
         window: &FieldMonitorWindow,
@@ -36,7 +36,9 @@ use std::time::Duration;
 use vte::TerminalExt;
 
 use libfieldmonitor::adapter::types::{AdapterDisplay, AdapterDisplayWidget};
-use libfieldmonitor::connection::{ConnectionError, ConnectionResult};
+use libfieldmonitor::connection::{
+    ConnectionError, ConnectionInstance, ConnectionResult, ServerChange,
+};
 use libfieldmonitor::i18n::gettext_f;
 
 use crate::application::FieldMonitorApplication;
@@ -46,6 +48,11 @@ use crate::widget::foucs_grabber::FieldMonitorFocusGrabber;
 use crate::widget::grab_note::FieldMonitorGrabNote;
 use crate::widget::window::FieldMonitorWindow;
 
+/// Interval in which a server that is being migrated is checked for whether it finished moving.
+const MIGRATION_POLL_INTERVAL: Duration = Duration::from_secs(2);
+/// How often to check a migrating server before giving up (30 minutes).
+const MIGRATION_MAX_POLLS: u32 = 900;
+
 mod imp {
     use super::*;
 
@@ -103,6 +110,10 @@ mod imp {
         // the connection / disconnection events.
         pub connection_generation: RefCell<u32>,
         pub close_cb: RefCell<Option<Box<dyn Fn()>>>,
+        /// Set once the screen was closed, to stop background work for it.
+        pub disposed: Cell<bool>,
+        /// Handler of `server-changed` of the connection, disconnected when the screen is closed.
+        pub server_changed_handler: RefCell<Option<(ConnectionInstance, glib::SignalHandlerId)>>,
     }
 
     #[glib::object_subclass]
@@ -230,7 +241,14 @@ mod imp {
     }
 
     #[glib::derived_properties]
-    impl ObjectImpl for FieldMonitorServerScreen {}
+    impl ObjectImpl for FieldMonitorServerScreen {
+        fn dispose(&self) {
+            self.disposed.set(true);
+            if let Some((connection, handler)) = self.server_changed_handler.take() {
+                connection.disconnect(handler);
+            }
+        }
+    }
     impl WidgetImpl for FieldMonitorServerScreen {}
     impl BinImpl for FieldMonitorServerScreen {}
 
@@ -282,6 +300,19 @@ impl FieldMonitorServerScreen {
             slf.on_window_fullscreened_changed(window);
         }
 
+        let connection = loader.connection();
+        let handler = connection.connect_server_changed(glib::clone!(
+            #[weak]
+            slf,
+            move |_, change| {
+                if let ServerChange::ServerChanged(path) = change {
+                    let path = path.clone();
+                    glib::spawn_future_local(async move { slf.on_server_changed(path).await });
+                }
+            }
+        ));
+        imp.server_changed_handler
+            .replace(Some((connection.clone(), handler)));
         imp.connection_loader.try_lock().unwrap().replace(loader);
         glib::spawn_future_local(glib::clone!(
             #[strong]
@@ -308,6 +339,21 @@ impl FieldMonitorServerScreen {
         }
     }
 
+    /// Reloads the server and connects to it again, eg. because it was changed by an action.
+    pub async fn reconnect(&self) {
+        info!("Reconnecting connection view");
+        {
+            let mut loader_brw = self.imp().connection_loader.lock().await;
+            let Some(loader) = loader_brw.as_mut() else {
+                return;
+            };
+            if loader.reload_server().await.is_none() {
+                return;
+            }
+        }
+        self.reset().await
+    }
+
     pub async fn reset(&self) {
         info!("Connection view reset");
         let imp = self.imp();
@@ -541,7 +587,110 @@ impl FieldMonitorServerScreen {
             }
         }
 
-        self.handle_error(result, true)
+        if let Err(ConnectionError::General(..)) = result {
+            // The server may have been lost because it is moving to another host.
+            let slf = self.downgrade();
+            glib::spawn_future_local(async move {
+                let reconnect = Self::wait_for_migration(&slf).await;
+                let Some(slf) = slf.upgrade().filter(|slf| !slf.imp().disposed.get()) else {
+                    return;
+                };
+                if reconnect {
+                    slf.reset().await
+                } else {
+                    slf.handle_error(result, true)
+                }
+            });
+        } else {
+            self.handle_error(result, true)
+        }
+    }
+
+    /// Called when the connection reports a change of a server that happened outside of Field
+    /// Monitor. If it is this server, its actions are updated. If it came online again after
+    /// the connection to it was lost, the view reconnects.
+    async fn on_server_changed(&self, path: Vec<String>) {
+        let imp = self.imp();
+        let mut loader_brw = imp.connection_loader.lock().await;
+        let Some(loader) = loader_brw.as_mut() else {
+            return;
+        };
+        if loader.server_path() != path.as_slice() {
+            return;
+        }
+        debug!("server changed outside of Field Monitor, reloading it");
+        if loader.reload_server().await.is_none() {
+            return;
+        }
+        let is_online = loader.server_is_online();
+        let actions = loader.actions();
+        drop(loader_brw);
+
+        let state = *imp.connection_state.borrow();
+        match state {
+            Some(true) => self.add_menu(self.menu_kind(), actions),
+            Some(false) if is_online == Some(true) => {
+                info!("Server is online again. Reconnecting.");
+                self.reset().await
+            }
+            _ => {}
+        }
+    }
+
+    /// The kind of menu for the current display.
+    fn menu_kind(&self) -> MenuKind {
+        let brw = self.imp().adapter.borrow();
+        match brw.as_ref().map(|adapter| adapter.widget()) {
+            Some(AdapterDisplayWidget::Rdw(_)) => MenuKind::Rdw,
+            Some(AdapterDisplayWidget::Vte(_)) => MenuKind::Vte,
+            _ => MenuKind::Other,
+        }
+    }
+
+    /// If the server is being migrated, waits until the migration has finished and the server
+    /// was loaded again at its new location. Returns true if the view should reconnect.
+    ///
+    /// Only holds a weak reference and the connection loader while checking, so the screen can
+    /// be used and closed in the meantime. Stops once the screen is closed.
+    async fn wait_for_migration(slf: &glib::WeakRef<Self>) -> bool {
+        let upgrade = || slf.upgrade().filter(|slf| !slf.imp().disposed.get());
+        {
+            let Some(slf) = upgrade() else {
+                return false;
+            };
+            let imp = slf.imp();
+            let loader_brw = imp.connection_loader.lock().await;
+            let Some(loader) = loader_brw.as_ref() else {
+                return false;
+            };
+            if !loader.server_is_migrating().await {
+                return false;
+            }
+
+            info!("Server is being migrated. Waiting to reconnect.");
+            imp.status_stack.set_visible_child_name("loading");
+            imp.outer_stack.set_visible_child_name("status");
+        }
+
+        for _ in 0..MIGRATION_MAX_POLLS {
+            sleep(MIGRATION_POLL_INTERVAL).await;
+            let Some(slf) = upgrade() else {
+                debug!("Server screen was closed, no longer waiting for migration.");
+                return false;
+            };
+            let mut loader_brw = slf.imp().connection_loader.lock().await;
+            let Some(loader) = loader_brw.as_mut() else {
+                return false;
+            };
+            // The server may be briefly unavailable while it is moving, keep trying.
+            match loader.try_reload_server().await {
+                Ok(()) if !loader.server_is_migrating().await => return true,
+                Ok(()) => {}
+                Err(err) => debug!("Failed to reload migrating server: {err:?}"),
+            }
+        }
+        warn!("Gave up waiting for migration of server.");
+        false
     }
 
     fn handle_error(&self, result: ConnectionResult<()>, allow_reauth: bool) {
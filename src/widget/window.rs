@@ -30,7 +30,7 @@ use adw::subclass::prelude::*;
 use async_std::task::sleep;
 use gettextrs::gettext;
 use gtk::{gdk, gio, glib};
-use log::debug;
+use log::{debug, warn};
 use std::cell::Cell;
 use std::cell::RefCell;
 use std::time::Duration;
@@ -211,6 +211,66 @@ impl FieldMonitorWindow {
                 }
             ))
             .build()]);
+        self.add_action_entries([
+            // See `libfieldmonitor::connection::SHOW_SERVER_ACTION`.
+            gio::ActionEntry::builder("show-server")
+                .parameter_type(Some(&*<(String, String)>::static_variant_type()))
+                .activate(|slf: &Self, _, param| {
+                    let Some((path, adapter_id)) = param.and_then(<(String, String)>::from_variant)
+                    else {
+                        warn!("Invalid parameters passed to win.show-server. Ignoring.");
+                        return;
+                    };
+                    slf.show_server(&path, &adapter_id);
+                })
+                .build(),
+            // See `libfieldmonitor::connection::RECONNECT_SERVER_ACTION`.
+            gio::ActionEntry::builder("reconnect-server")
+                .parameter_type(Some(&*String::static_variant_type()))
+                .activate(|slf: &Self, _, param| {
+                    let Some(path) = param.and_then(String::from_variant) else {
+                        warn!("Invalid parameters passed to win.reconnect-server. Ignoring.");
+                        return;
+                    };
+                    slf.reconnect_server(&path);
+                })
+                .build(),
+        ]);
+    }
+
+    /// Connects to the server with the adapter or, if no adapter is given, focuses it in the
+    /// server list of its connection.
+    pub fn show_server(&self, server_path: &str, adapter_id: &str) {
+        if !adapter_id.is_empty() {
+            let Some(app) = self.application().and_downcast::<FieldMonitorApplication>() else {
+                return;
+            };
+            // Not going through app.connect-to-server: this is usually requested by a
+            // connection action, which keeps the app busy until it is done.
+            let server_path = server_path.to_string();
+            let adapter_id = adapter_id.to_string();
+            glib::spawn_future_local(async move {
+                app.connect_to_server(&server_path, &adapter_id).await;
+            });
+            return;
+        }
+
+        let imp = self.imp();
+        let connection_id = server_path.split('/').next().unwrap_or_default();
+        imp.connection_list_stack.select_connection(connection_id);
+        imp.connection_list_stack.focus_server(server_path);
+    }
+
+    /// Reconnects the connection views of the server in all windows.
+    pub fn reconnect_server(&self, server_path: &str) {
+        let Some(app) = self.application() else {
+            return;
+        };
+        for window in app.windows() {
+            if let Ok(window) = window.downcast::<FieldMonitorWindow>() {
+                window.tab_view().reconnect(server_path);
+            }
+        }
     }
 
     pub fn toast(&self, msg: &str) {
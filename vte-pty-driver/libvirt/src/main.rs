@@ -41,8 +41,8 @@ use virt::stream::Stream;
 use virt::sys::{
     virEventAddTimeout, virEventRegisterDefaultImpl, virEventRunDefaultImpl,
     virStreamEventAddCallback, virStreamFlags, virStreamPtr, virStreamRecv, virStreamSend,
-    VIR_DOMAIN_CONSOLE_FORCE, VIR_STREAM_EVENT_READABLE, VIR_STREAM_EVENT_WRITABLE,
-    VIR_STREAM_NONBLOCK,
+    VIR_DOMAIN_CHANNEL_FORCE, VIR_DOMAIN_CONSOLE_FORCE, VIR_STREAM_EVENT_READABLE,
+    VIR_STREAM_EVENT_WRITABLE, VIR_STREAM_NONBLOCK,
 };
 
 use field_monitor_vte_driver_lib::{args, debug, debug_sync, error, setup_driver, PtyClient};
@@ -80,7 +80,7 @@ async fn main() -> ! {
 }
 
 async fn run_console(client: &Arc<PtyClient>) -> Result<(), anyhow::Error> {
-    args!(&client => (qemu_ui, domid));
+    args!(&client => (qemu_ui, domid, kind, alias, force));
 
     debug!(&client, "running console");
 
@@ -126,8 +126,18 @@ async fn run_console(client: &Arc<PtyClient>) -> Result<(), anyhow::Error> {
     let st = Stream::new(&connect, VIR_STREAM_NONBLOCK)?;
     debug!(&client, "opened stream");
 
-    domain.open_console(None, &st, VIR_DOMAIN_CONSOLE_FORCE)?;
-    debug!(&client, "opened console");
+    // An empty alias opens the first console of the domain.
+    let dev_name = (!alias.is_empty()).then_some(alias);
+    let force = force == "1";
+    if kind == "channel" {
+        let flags = if force { VIR_DOMAIN_CHANNEL_FORCE } else { 0 };
+        domain.open_channel(dev_name, &st, flags)?;
+        debug!(&client, "opened channel {alias}");
+    } else {
+        let flags = if force { VIR_DOMAIN_CONSOLE_FORCE } else { 0 };
+        domain.open_console(dev_name, &st, flags)?;
+        debug!(&client, "opened console {alias}");
+    }
 
     debug!(&client, "established domain connection");
 
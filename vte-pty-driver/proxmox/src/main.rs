@@ -29,7 +29,10 @@ use nix::libc;
 use nix::sys::signal::{sigaction, SaFlags, SigAction, SigHandler, SigSet, Signal};
 use nix::sys::termios::{cfmakeraw, tcgetattr, tcsetattr, SetArg};
 use openssl::ssl::{SslConnector, SslMethod, SslVerifyMode};
-use proxmox_api::{NodeId, ProxmoxApiClient, Termproxy, VmId, VmType, VncwebsocketInput};
+use proxmox_api::{
+    connect_tcp, NodeId, ProxmoxApiClient, ProxyConfig, Termproxy, VmId, VmTermproxySerial, VmType,
+    VncwebsocketInput,
+};
 use std::error::Error;
 use std::mem;
 use std::ops::Deref;
@@ -40,7 +43,6 @@ use std::str::FromStr;
 use std::sync::Arc;
 use std::time::Duration;
 use tokio::io::AsyncWriteExt;
-use tokio::net::TcpStream;
 use tokio::select;
 use tokio::sync::{Mutex, Notify};
 use tokio::time::sleep;
@@ -92,7 +94,9 @@ async fn run_console(client: &Arc<PtyClient>) -> Result<(), anyhow::Error> {
         node_id,
         vm_type,
         vm_id,
-        termproxy_str
+        termproxy_str,
+        serial,
+        proxy
     ));
     let (vncwebsocket_user, vncwebsocket) = {
         let termproxy: Termproxy = serde_json::from_str(termproxy_str)?;
@@ -104,7 +108,8 @@ async fn run_console(client: &Arc<PtyClient>) -> Result<(), anyhow::Error> {
             },
         )
     };
-    let root = Uri::from_str(root)?;
+    // The connection passes the endpoint it is currently using, no need to fail over here.
+    let roots = [Uri::from_str(root)?];
     let node_id = NodeId::from_str(node_id)?;
     let vm = if vm_id.is_empty() {
         None
@@ -117,8 +122,26 @@ async fn run_console(client: &Arc<PtyClient>) -> Result<(), anyhow::Error> {
         };
         Some((vm_id, vm_type))
     };
+    // The termproxy was already opened for this port, the driver only checks it is valid.
+    let serial = if serial.is_empty() {
+        None
+    } else {
+        Some(VmTermproxySerial::from_str(serial)?)
+    };
+    if serial.is_some() && !matches!(vm, Some((_, VmType::Qemu))) {
+        return Err(anyhow!("serial consoles are only supported for QEMU VMs"));
+    }
+    let proxy = if proxy.is_empty() {
+        None
+    } else {
+        Some(ProxyConfig::from_url(proxy)?)
+    };
 
-    debug!(&client, "running console");
+    debug!(
+        &client,
+        "running console (serial port: {serial:?}, proxy: {:?})",
+        proxy.as_ref().map(|proxy| proxy.kind)
+    );
 
     // Ignore signals, they will be processed via stdin and sent to the remote.
     let sighandler = SigAction::new(
@@ -157,19 +180,21 @@ async fn run_console(client: &Arc<PtyClient>) -> Result<(), anyhow::Error> {
     let proxmox_client = match connection_type.deref() {
         "apikey" => {
             ProxmoxApiClient::connect_with_apikey(
-                &root,
+                &roots,
                 user_tokenid,
                 pass_apikey.into(),
                 ignore_ssl_errors == "1",
+                proxy.clone(),
             )
             .await?
         }
         _ => {
             ProxmoxApiClient::connect_with_ticket(
-                &root,
+                &roots,
                 user_tokenid,
                 pass_apikey.into(),
                 ignore_ssl_errors == "1",
+                proxy.clone(),
             )
             .await?
         }
@@ -202,7 +227,7 @@ async fn run_console(client: &Arc<PtyClient>) -> Result<(), anyhow::Error> {
     let domain = domain(&request)?;
     let port = port(&request)?;
 
-    let socket = TcpStream::connect((domain.as_str(), port)).await?;
+    let socket = connect_tcp(proxy.as_ref(), &domain, port).await?;
     let mut connector_builder = SslConnector::builder(SslMethod::tls())?;
     connector_builder.set_verify(if ignore_ssl_errors == "1" {
         SslVerifyMode::NONE
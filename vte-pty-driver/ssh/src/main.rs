@@ -0,0 +1,107 @@
+/* Copyright 2024 Marco Köpcke
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ *
+ * SPDX-License-Identifier: GPL-3.0-or-later
+ */
+//! Opens an SSH session with the `ssh` client of the system.
+//!
+//! Arguments: hostname, port and username. Port and username may be empty to use the
+//! defaults of the SSH client (and its configuration).
+
+use std::process::exit;
+use std::sync::Arc;
+
+use anyhow::anyhow;
+use nix::libc;
+use nix::sys::signal::{sigaction, SaFlags, SigAction, SigHandler, SigSet, Signal};
+use tokio::process::Command;
+
+use field_monitor_vte_driver_lib::{args, debug, error, setup_driver, PtyClient};
+
+const SSH_BIN: &str = "ssh";
+/// Exit code of the SSH client if it failed, instead of the exit code of the remote shell.
+const SSH_ERROR_EXIT_CODE: i32 = 255;
+
+extern "C" fn handle_sig(_: libc::c_int, _: *mut libc::siginfo_t, _: *mut libc::c_void) {}
+
+#[tokio::main]
+async fn main() -> ! {
+    let client = Arc::new(setup_driver().await);
+
+    let result = run_ssh(&client).await;
+
+    client
+        .set_result(
+            result
+                .as_ref()
+                .map(|_| "exited normally")
+                .map_err(ToString::to_string),
+        )
+        .await
+        .ok();
+
+    if let Err(err) = &result {
+        error!(&client, "failed to run pty driver: {err}");
+    }
+    debug!(&client, "exiting");
+    exit(if result.is_err() { 1 } else { 0 });
+}
+
+async fn run_ssh(client: &Arc<PtyClient>) -> Result<(), anyhow::Error> {
+    args!(&client => (hostname, port, username));
+
+    // The signals are meant for the SSH client, which shares the terminal. Handlers are reset
+    // for the SSH client when it is executed, unlike ignored signals.
+    let sighandler = SigAction::new(
+        SigHandler::SigAction(handle_sig),
+        SaFlags::SA_SIGINFO,
+        SigSet::empty(),
+    );
+
+    // SAFETY: Our signal handler does nothing and (as far as we know) no invalid signal handler
+    //         was installed before.
+    unsafe {
+        sigaction(Signal::SIGQUIT, &sighandler)?;
+        sigaction(Signal::SIGINT, &sighandler)?;
+        sigaction(Signal::SIGTSTP, &sighandler)?;
+    }
+
+    let mut command = Command::new(SSH_BIN);
+    if !port.is_empty() {
+        command.arg("-p").arg(port);
+    }
+    if !username.is_empty() {
+        command.arg("-l").arg(username);
+    }
+    // The hostname must never be parsed as an option.
+    command.arg("--").arg(hostname);
+
+    debug!(
+        &client,
+        "running ssh (hostname: {hostname}, port: {port:?})"
+    );
+
+    let status = command
+        .kill_on_drop(true)
+        .status()
+        .await
+        .map_err(|err| anyhow!("failed to run {SSH_BIN}: {err}"))?;
+
+    debug!(&client, "ssh exited: {status}");
+    match status.code() {
+        Some(SSH_ERROR_EXIT_CODE) => Err(anyhow!("the SSH connection failed")),
+        _ => Ok(()),
+    }
+}